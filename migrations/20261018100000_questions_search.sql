@@ -0,0 +1,19 @@
+CREATE VIRTUAL TABLE questions_search USING fts5(
+    question,
+    answer,
+    content='questions',
+    content_rowid='id',
+    tokenize='unicode61 remove_diacritics 2'
+);
+INSERT INTO questions_search(questions_search) VALUES ('rebuild');
+
+CREATE TRIGGER questions_search_insert AFTER INSERT ON questions BEGIN
+    INSERT INTO questions_search(rowid, question, answer) VALUES (new.id, new.question, new.answer);
+END;
+CREATE TRIGGER questions_search_delete AFTER DELETE ON questions BEGIN
+    INSERT INTO questions_search(questions_search, rowid, question, answer) VALUES ('delete', old.id, old.question, old.answer);
+END;
+CREATE TRIGGER questions_search_update AFTER UPDATE OF question, answer ON questions BEGIN
+    INSERT INTO questions_search(questions_search, rowid, question, answer) VALUES ('delete', old.id, old.question, old.answer);
+    INSERT INTO questions_search(rowid, question, answer) VALUES (new.id, new.question, new.answer);
+END;
@@ -0,0 +1,12 @@
+ALTER TABLE categories ADD COLUMN parent INTEGER NULL REFERENCES categories(id) ON DELETE SET NULL;
+CREATE INDEX categories_parent ON categories(parent);
+
+-- Category is public when neither it nor any of its parents is hidden
+CREATE VIEW public_categories AS
+WITH RECURSIVE tree(id) AS (
+    SELECT id FROM categories WHERE parent IS NULL AND hidden = FALSE
+    UNION ALL
+    SELECT categories.id FROM categories JOIN tree ON categories.parent = tree.id
+    WHERE categories.hidden = FALSE
+)
+SELECT id FROM tree;
@@ -0,0 +1,7 @@
+-- CMS login sessions, only the sha256 of the cookie value is stored
+CREATE TABLE sessions(
+    token_hash TEXT PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    expires_at INTEGER NOT NULL
+);
+CREATE INDEX sessions_user_id ON sessions(user_id);
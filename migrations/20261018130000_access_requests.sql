@@ -0,0 +1,6 @@
+-- Requests of the unknown users to use the bot. Approved requests are deleted and the user is activated
+CREATE TABLE access_requests(
+    user_id INTEGER PRIMARY KEY NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'denied')),
+    requested_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,43 @@
+CREATE TABLE groups(
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL UNIQUE
+);
+CREATE TABLE user_groups(
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    group_id INTEGER NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    PRIMARY KEY (user_id, group_id)
+);
+-- Category with groups is available only to their members, category without groups - to everyone
+CREATE TABLE category_groups(
+    category_id INTEGER NOT NULL REFERENCES categories(id) ON DELETE CASCADE,
+    group_id INTEGER NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    PRIMARY KEY (category_id, group_id)
+);
+CREATE INDEX category_groups_group_id ON category_groups(group_id);
+
+-- Every category with itself and all of its parents. UNION stops on the cycles in the broken data
+CREATE VIEW category_ancestors AS
+WITH RECURSIVE ancestors(category_id, ancestor_id) AS (
+    SELECT id, id FROM categories
+    UNION
+    SELECT ancestors.category_id, categories.parent FROM ancestors
+    JOIN categories ON categories.id = ancestors.ancestor_id
+    WHERE categories.parent IS NOT NULL
+)
+SELECT category_id, ancestor_id FROM ancestors;
+
+-- Public categories the user can see: user should be a member of some group of every restricted
+-- category on the path, so restricting the parent restricts the whole subtree
+CREATE VIEW accessible_categories AS
+SELECT users.id AS user_id, public_categories.id AS category_id
+FROM users CROSS JOIN public_categories
+WHERE NOT EXISTS (
+    SELECT 1 FROM category_ancestors
+    JOIN category_groups ON category_groups.category_id = category_ancestors.ancestor_id
+    WHERE category_ancestors.category_id = public_categories.id
+    AND NOT EXISTS (
+        SELECT 1 FROM category_groups AS allowed
+        JOIN user_groups ON user_groups.group_id = allowed.group_id
+        WHERE allowed.category_id = category_ancestors.ancestor_id AND user_groups.user_id = users.id
+    )
+);
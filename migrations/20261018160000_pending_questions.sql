@@ -0,0 +1,15 @@
+-- Questions the users asked the support team, when nothing was found by the search.
+-- support_message_id is the message forwarded to the support chat, replies to it are the answers.
+-- question_id is set when the answer is added to the questions
+CREATE TABLE pending_questions(
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    question TEXT NOT NULL,
+    asked_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    support_message_id INTEGER NULL,
+    answer TEXT NULL,
+    answered_by TEXT NULL,
+    answered_at TEXT NULL,
+    question_id INTEGER NULL REFERENCES questions(id) ON DELETE SET NULL
+);
+CREATE INDEX pending_questions_support_message ON pending_questions(support_message_id);
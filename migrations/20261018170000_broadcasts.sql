@@ -0,0 +1,27 @@
+-- Users that blocked the bot, removed when the user starts the bot again
+CREATE TABLE blocked_users(
+    user_id INTEGER PRIMARY KEY NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    blocked_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Announcements sent by the admins. The text is Markdown, the attachments are stored
+-- in the broadcasts/<id> directory of the static files
+CREATE TABLE broadcasts(
+    id INTEGER PRIMARY KEY NOT NULL,
+    text TEXT NOT NULL,
+    attachments TEXT NOT NULL DEFAULT '[]',
+    -- description of the recipients, like "All active users"
+    audience TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    finished_at TEXT NULL
+);
+
+-- Recipients are chosen when the broadcast is created, so it can be resumed after the restart
+CREATE TABLE broadcast_deliveries(
+    broadcast_id INTEGER NOT NULL REFERENCES broadcasts(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'sent', 'blocked', 'failed')),
+    error TEXT NULL,
+    PRIMARY KEY (broadcast_id, user_id)
+);
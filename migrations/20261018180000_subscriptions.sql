@@ -0,0 +1,16 @@
+-- Users notified about the changes of the questions in the category and all of its subcategories
+CREATE TABLE subscriptions(
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    category_id INTEGER NOT NULL REFERENCES categories(id) ON DELETE CASCADE,
+    subscribed_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, category_id)
+);
+CREATE INDEX subscriptions_category_id ON subscriptions(category_id);
+
+-- Questions created or changed since the last digest was sent to the subscribers
+CREATE TABLE question_changes(
+    question_id INTEGER PRIMARY KEY NOT NULL REFERENCES questions(id) ON DELETE CASCADE,
+    created BOOLEAN NOT NULL,
+    -- unix time of the last change
+    changed_at INTEGER NOT NULL DEFAULT (unixepoch())
+);
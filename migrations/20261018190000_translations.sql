@@ -0,0 +1,45 @@
+-- Language chosen by the user with /language, and the language of the Telegram client
+-- that is used when nothing is chosen. The latter is remembered for the messages sent
+-- without an update from the user, like the digests
+CREATE TABLE user_languages(
+    user_id INTEGER PRIMARY KEY NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    language TEXT NULL,
+    telegram_language TEXT NULL
+);
+
+-- Content is written in the default language, the translations replace it for the users
+-- of the other languages
+CREATE TABLE category_translations(
+    category_id INTEGER NOT NULL REFERENCES categories(id) ON DELETE CASCADE,
+    language TEXT NOT NULL,
+    name TEXT NOT NULL,
+    PRIMARY KEY (category_id, language)
+);
+
+CREATE TABLE question_translations(
+    id INTEGER PRIMARY KEY NOT NULL,
+    question_id INTEGER NOT NULL REFERENCES questions(id) ON DELETE CASCADE,
+    language TEXT NOT NULL,
+    question TEXT NOT NULL,
+    answer TEXT NOT NULL,
+    UNIQUE (question_id, language)
+);
+
+CREATE VIRTUAL TABLE question_translations_search USING fts5(
+    question,
+    answer,
+    content='question_translations',
+    content_rowid='id',
+    tokenize='unicode61 remove_diacritics 2'
+);
+
+CREATE TRIGGER question_translations_search_insert AFTER INSERT ON question_translations BEGIN
+    INSERT INTO question_translations_search(rowid, question, answer) VALUES (new.id, new.question, new.answer);
+END;
+CREATE TRIGGER question_translations_search_delete AFTER DELETE ON question_translations BEGIN
+    INSERT INTO question_translations_search(question_translations_search, rowid, question, answer) VALUES ('delete', old.id, old.question, old.answer);
+END;
+CREATE TRIGGER question_translations_search_update AFTER UPDATE OF question, answer ON question_translations BEGIN
+    INSERT INTO question_translations_search(question_translations_search, rowid, question, answer) VALUES ('delete', old.id, old.question, old.answer);
+    INSERT INTO question_translations_search(rowid, question, answer) VALUES (new.id, new.question, new.answer);
+END;
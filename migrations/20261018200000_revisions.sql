@@ -0,0 +1,14 @@
+-- Every change of the questions and the categories. Values are JSON of the versioned fields,
+-- the created items have no old value and the deleted ones have no new value
+CREATE TABLE revisions(
+    id INTEGER PRIMARY KEY NOT NULL,
+    entity TEXT NOT NULL CHECK (entity IN ('question', 'category')),
+    entity_id INTEGER NOT NULL,
+    action TEXT NOT NULL CHECK (action IN ('create', 'update', 'delete')),
+    old_value TEXT NULL,
+    new_value TEXT NULL,
+    author TEXT NOT NULL,
+    restored_from INTEGER NULL REFERENCES revisions(id) ON DELETE SET NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX revisions_entity ON revisions(entity, entity_id);
@@ -0,0 +1,16 @@
+-- Deleted questions and categories stay in the trash until they are restored or purged
+ALTER TABLE questions ADD COLUMN deleted_at TEXT NULL;
+ALTER TABLE questions ADD COLUMN deleted_by TEXT NULL;
+ALTER TABLE categories ADD COLUMN deleted_at TEXT NULL;
+ALTER TABLE categories ADD COLUMN deleted_by TEXT NULL;
+
+-- Categories in the trash are not public
+DROP VIEW public_categories;
+CREATE VIEW public_categories AS
+WITH RECURSIVE tree(id) AS (
+    SELECT id FROM categories WHERE parent IS NULL AND hidden = FALSE AND deleted_at IS NULL
+    UNION ALL
+    SELECT categories.id FROM categories JOIN tree ON categories.parent = tree.id
+    WHERE categories.hidden = FALSE AND categories.deleted_at IS NULL
+)
+SELECT id FROM tree;
@@ -0,0 +1,21 @@
+-- Files of the questions. The content is stored once per hash in STATIC_DIR/blobs/<sha256>,
+-- the blob is removed when the last attachment referencing it is gone
+CREATE TABLE attachments(
+    id INTEGER PRIMARY KEY NOT NULL,
+    question_id INTEGER NOT NULL REFERENCES questions(id) ON DELETE CASCADE,
+    sha256 TEXT NOT NULL,
+    name TEXT NOT NULL,
+    mime_type TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    UNIQUE(question_id, name)
+);
+CREATE INDEX attachments_sha256 ON attachments(sha256);
+
+-- files of the old STATIC_DIR/<question id>/<name> layout have no hash yet,
+-- they are hashed and moved to the blobs on the start
+INSERT OR IGNORE INTO attachments (question_id, sha256, name, mime_type, size)
+SELECT questions.id, '', files.value, 'application/octet-stream', 0
+FROM questions, json_each(questions.attachments) AS files
+ORDER BY questions.id, files.key;
+
+ALTER TABLE questions DROP COLUMN attachments;
@@ -0,0 +1,20 @@
+-- Files of the broadcasts are stored in the blobs like the files of the questions
+CREATE TABLE broadcast_attachments(
+    id INTEGER PRIMARY KEY NOT NULL,
+    broadcast_id INTEGER NOT NULL REFERENCES broadcasts(id) ON DELETE CASCADE,
+    sha256 TEXT NOT NULL,
+    name TEXT NOT NULL,
+    mime_type TEXT NOT NULL,
+    size INTEGER NOT NULL
+);
+CREATE INDEX broadcast_attachments_broadcast ON broadcast_attachments(broadcast_id);
+CREATE INDEX broadcast_attachments_sha256 ON broadcast_attachments(sha256);
+
+-- files of the old STATIC_DIR/broadcasts/<id>/<name> layout have no hash yet,
+-- they are hashed and moved to the blobs on the start
+INSERT INTO broadcast_attachments (broadcast_id, sha256, name, mime_type, size)
+SELECT broadcasts.id, '', files.value, 'application/octet-stream', 0
+FROM broadcasts, json_each(broadcasts.attachments) AS files
+ORDER BY broadcasts.id, files.key;
+
+ALTER TABLE broadcasts DROP COLUMN attachments;
@@ -0,0 +1,4 @@
+-- Category the subcategory or the question was detached from when that category was moved to the trash,
+-- so restoring the category attaches them back
+ALTER TABLE categories ADD COLUMN detached_from INTEGER NULL REFERENCES categories(id) ON DELETE SET NULL;
+ALTER TABLE questions ADD COLUMN detached_from INTEGER NULL REFERENCES categories(id) ON DELETE SET NULL;
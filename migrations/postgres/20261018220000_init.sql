@@ -0,0 +1,214 @@
+-- Schema of the SQLite migrations up to the trash at once. Timestamps are kept as text
+-- in the format of CURRENT_TIMESTAMP of SQLite, so both databases return the same values
+CREATE FUNCTION utc_timestamp(at TIMESTAMPTZ DEFAULT now()) RETURNS TEXT AS $$
+    SELECT to_char(at AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI:SS')
+$$ LANGUAGE SQL STABLE;
+
+-- same as unixepoch() of SQLite
+CREATE FUNCTION unixepoch() RETURNS BIGINT AS $$
+    SELECT EXTRACT(EPOCH FROM now())::BIGINT
+$$ LANGUAGE SQL STABLE;
+
+CREATE TYPE entity AS ENUM ('question', 'category');
+CREATE TYPE revision_action AS ENUM ('create', 'update', 'delete');
+CREATE TYPE access_request_status AS ENUM ('pending', 'denied');
+CREATE TYPE delivery_status AS ENUM ('pending', 'sent', 'blocked', 'failed');
+
+-- Categories and questions are imported with their ids and the parents can come after
+-- the children, so the references are checked at the end of the transaction
+CREATE TABLE categories(
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    name TEXT NOT NULL,
+    hidden BOOLEAN NOT NULL DEFAULT FALSE,
+    ordering BIGINT NOT NULL DEFAULT 0,
+    parent BIGINT NULL REFERENCES categories(id) ON DELETE SET NULL DEFERRABLE,
+    deleted_at TEXT NULL,
+    deleted_by TEXT NULL
+);
+CREATE INDEX categories_parent ON categories(parent);
+
+CREATE TABLE questions(
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    category BIGINT NULL REFERENCES categories(id) ON DELETE SET NULL DEFERRABLE,
+    question TEXT NOT NULL,
+    answer TEXT NOT NULL,
+    hidden BOOLEAN NOT NULL DEFAULT FALSE,
+    ordering BIGINT NOT NULL DEFAULT 0,
+    attachments TEXT NOT NULL DEFAULT '[]',
+    deleted_at TEXT NULL,
+    deleted_by TEXT NULL,
+    -- the question weighs more than the answer, like in the SQLite full text search
+    search TSVECTOR GENERATED ALWAYS AS (
+        setweight(to_tsvector('simple', question), 'A') || setweight(to_tsvector('simple', answer), 'B')
+    ) STORED
+);
+CREATE INDEX questions_search ON questions USING GIN (search);
+
+CREATE TABLE users(
+    id BIGINT PRIMARY KEY,
+    username TEXT NULL,
+    first_name TEXT NOT NULL,
+    last_name TEXT NULL,
+    is_admin BOOLEAN NOT NULL DEFAULT FALSE,
+    active BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE VIEW public_categories AS
+WITH RECURSIVE tree(id) AS (
+    SELECT id FROM categories WHERE parent IS NULL AND hidden = FALSE AND deleted_at IS NULL
+    UNION ALL
+    SELECT categories.id FROM categories JOIN tree ON categories.parent = tree.id
+    WHERE categories.hidden = FALSE AND categories.deleted_at IS NULL
+)
+SELECT id FROM tree;
+
+CREATE TABLE sessions(
+    token_hash TEXT PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    expires_at BIGINT NOT NULL
+);
+CREATE INDEX sessions_user_id ON sessions(user_id);
+
+CREATE TABLE access_requests(
+    user_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    status access_request_status NOT NULL DEFAULT 'pending',
+    requested_at TEXT NOT NULL DEFAULT utc_timestamp()
+);
+
+CREATE TABLE groups(
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+CREATE TABLE user_groups(
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    group_id BIGINT NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    PRIMARY KEY (user_id, group_id)
+);
+CREATE TABLE category_groups(
+    category_id BIGINT NOT NULL REFERENCES categories(id) ON DELETE CASCADE DEFERRABLE,
+    group_id BIGINT NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+    PRIMARY KEY (category_id, group_id)
+);
+CREATE INDEX category_groups_group_id ON category_groups(group_id);
+
+CREATE VIEW category_ancestors AS
+WITH RECURSIVE ancestors(category_id, ancestor_id) AS (
+    SELECT id, id FROM categories
+    UNION
+    SELECT ancestors.category_id, categories.parent FROM ancestors
+    JOIN categories ON categories.id = ancestors.ancestor_id
+    WHERE categories.parent IS NOT NULL
+)
+SELECT category_id, ancestor_id FROM ancestors;
+
+CREATE VIEW accessible_categories AS
+SELECT users.id AS user_id, public_categories.id AS category_id
+FROM users CROSS JOIN public_categories
+WHERE NOT EXISTS (
+    SELECT 1 FROM category_ancestors
+    JOIN category_groups ON category_groups.category_id = category_ancestors.ancestor_id
+    WHERE category_ancestors.category_id = public_categories.id
+    AND NOT EXISTS (
+        SELECT 1 FROM category_groups AS allowed
+        JOIN user_groups ON user_groups.group_id = allowed.group_id
+        WHERE allowed.category_id = category_ancestors.ancestor_id AND user_groups.user_id = users.id
+    )
+);
+
+CREATE TABLE feedback(
+    question_id BIGINT NOT NULL REFERENCES questions(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    helpful BOOLEAN NOT NULL,
+    comment TEXT NULL,
+    updated_at TEXT NOT NULL DEFAULT utc_timestamp(),
+    PRIMARY KEY (question_id, user_id)
+);
+
+CREATE TABLE pending_questions(
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    question TEXT NOT NULL,
+    asked_at TEXT NOT NULL DEFAULT utc_timestamp(),
+    support_message_id BIGINT NULL,
+    answer TEXT NULL,
+    answered_by TEXT NULL,
+    answered_at TEXT NULL,
+    question_id BIGINT NULL REFERENCES questions(id) ON DELETE SET NULL
+);
+CREATE INDEX pending_questions_support_message ON pending_questions(support_message_id);
+
+CREATE TABLE blocked_users(
+    user_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    blocked_at TEXT NOT NULL DEFAULT utc_timestamp()
+);
+
+CREATE TABLE broadcasts(
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    text TEXT NOT NULL,
+    attachments TEXT NOT NULL DEFAULT '[]',
+    audience TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT utc_timestamp(),
+    finished_at TEXT NULL
+);
+
+CREATE TABLE broadcast_deliveries(
+    broadcast_id BIGINT NOT NULL REFERENCES broadcasts(id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    status delivery_status NOT NULL DEFAULT 'pending',
+    error TEXT NULL,
+    PRIMARY KEY (broadcast_id, user_id)
+);
+
+CREATE TABLE subscriptions(
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    category_id BIGINT NOT NULL REFERENCES categories(id) ON DELETE CASCADE,
+    subscribed_at TEXT NOT NULL DEFAULT utc_timestamp(),
+    PRIMARY KEY (user_id, category_id)
+);
+CREATE INDEX subscriptions_category_id ON subscriptions(category_id);
+
+CREATE TABLE question_changes(
+    question_id BIGINT PRIMARY KEY REFERENCES questions(id) ON DELETE CASCADE,
+    created BOOLEAN NOT NULL,
+    changed_at BIGINT NOT NULL DEFAULT unixepoch()
+);
+
+CREATE TABLE user_languages(
+    user_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    language TEXT NULL,
+    telegram_language TEXT NULL
+);
+
+CREATE TABLE category_translations(
+    category_id BIGINT NOT NULL REFERENCES categories(id) ON DELETE CASCADE DEFERRABLE,
+    language TEXT NOT NULL,
+    name TEXT NOT NULL,
+    PRIMARY KEY (category_id, language)
+);
+
+CREATE TABLE question_translations(
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    question_id BIGINT NOT NULL REFERENCES questions(id) ON DELETE CASCADE DEFERRABLE,
+    language TEXT NOT NULL,
+    question TEXT NOT NULL,
+    answer TEXT NOT NULL,
+    search TSVECTOR GENERATED ALWAYS AS (
+        setweight(to_tsvector('simple', question), 'A') || setweight(to_tsvector('simple', answer), 'B')
+    ) STORED,
+    UNIQUE (question_id, language)
+);
+CREATE INDEX question_translations_search ON question_translations USING GIN (search);
+
+CREATE TABLE revisions(
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    entity entity NOT NULL,
+    entity_id BIGINT NOT NULL,
+    action revision_action NOT NULL,
+    old_value TEXT NULL,
+    new_value TEXT NULL,
+    author TEXT NOT NULL,
+    restored_from BIGINT NULL REFERENCES revisions(id) ON DELETE SET NULL,
+    created_at TEXT NOT NULL DEFAULT utc_timestamp()
+);
+CREATE INDEX revisions_entity ON revisions(entity, entity_id);
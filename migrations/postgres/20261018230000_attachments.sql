@@ -0,0 +1,22 @@
+-- Files of the questions. The content is stored once per hash in STATIC_DIR/blobs/<sha256>,
+-- the blob is removed when the last attachment referencing it is gone
+CREATE TABLE attachments(
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    question_id BIGINT NOT NULL REFERENCES questions(id) ON DELETE CASCADE,
+    sha256 TEXT NOT NULL,
+    name TEXT NOT NULL,
+    mime_type TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    UNIQUE(question_id, name)
+);
+CREATE INDEX attachments_sha256 ON attachments(sha256);
+
+-- files of the old STATIC_DIR/<question id>/<name> layout have no hash yet,
+-- they are hashed and moved to the blobs on the start
+INSERT INTO attachments (question_id, sha256, name, mime_type, size)
+SELECT questions.id, '', files.value, 'application/octet-stream', 0
+FROM questions, json_array_elements_text(questions.attachments::json) WITH ORDINALITY AS files(value, position)
+ORDER BY questions.id, files.position
+ON CONFLICT DO NOTHING;
+
+ALTER TABLE questions DROP COLUMN attachments;
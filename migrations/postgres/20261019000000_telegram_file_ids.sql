@@ -0,0 +1,3 @@
+-- file_id returned by Telegram after the first upload of the attachment, so it is sent
+-- without uploading the content again. Replaced file is a new attachment without the id
+ALTER TABLE attachments ADD COLUMN telegram_file_id TEXT NULL;
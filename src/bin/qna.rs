@@ -1,8 +1,9 @@
 use std::{fs::create_dir_all, path::PathBuf};
 
 use anyhow::Context;
-use udv_qna_bot::db::run_migrations;
+use udv_qna_bot::db::{run_migrations, Repositories};
 use udv_qna_bot::server::app::run_server;
+use udv_qna_bot::storage::{self, migrate_broadcast_files, migrate_question_files};
 use udv_qna_bot::telemetry::init_tracing;
 use udv_qna_bot::{bot::run, db};
 
@@ -26,8 +27,8 @@ enum Runner {
 async fn main() -> anyhow::Result<()> {
     init_tracing();
     let cli = Cli::parse();
-    let path = dotenv::var("DB_PATH").expect("DB_PATH must be set");
-    let pool = db::establish_connection(&path).await.unwrap();
+    let url = db::database_url();
+    let pool = db::establish_connection(&url).await.unwrap();
     let static_dir =
         PathBuf::from(dotenv::var("STATIC_DIR").expect("Variable STATIC_DIR should be set"));
     if !static_dir.exists() {
@@ -37,16 +38,21 @@ async fn main() -> anyhow::Result<()> {
         anyhow::bail!("Variable STATIC_DIR should be a directory or not exist");
     }
 
+    let blobs = storage::from_env(&static_dir)?;
+
     tracing::info!("Running db migrations...");
-    run_migrations().await?;
+    run_migrations(&pool).await?;
+    migrate_question_files(&pool, &static_dir, blobs.as_ref()).await?;
+    migrate_broadcast_files(&pool, &static_dir, blobs.as_ref()).await?;
 
+    let repositories = Repositories::sql(pool.clone());
     match cli.runner {
-        Runner::Server => run_server(pool.clone(), static_dir.clone()).await?,
-        Runner::Bot => run(pool.clone(), &path, static_dir).await?,
+        Runner::Server => run_server(pool.clone(), repositories, static_dir, blobs).await?,
+        Runner::Bot => run(pool.clone(), repositories, &url, blobs).await?,
         Runner::All => {
             tokio::select! {
-               res = run(pool.clone(), &path, static_dir.clone()) => {tracing::warn!("Bot exited: {:#?}", res)}
-               res = run_server(pool.clone(), static_dir.clone()) => {tracing::warn!("Server exited: {:#?}", res)}
+               res = run(pool.clone(), repositories.clone(), &url, blobs.clone()) => {tracing::warn!("Bot exited: {:#?}", res)}
+               res = run_server(pool.clone(), repositories.clone(), static_dir.clone(), blobs.clone()) => {tracing::warn!("Server exited: {:#?}", res)}
             }
         }
     };
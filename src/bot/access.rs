@@ -0,0 +1,191 @@
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+};
+
+use crate::db::{
+    queries::access_requests::{
+        approve_access_request, create_access_request, deny_access_request,
+        get_access_request_status, AccessRequestStatus,
+    },
+    Pool, Repositories, User,
+};
+use crate::i18n::{Language, Texts};
+
+use super::callback_data::CallbackData;
+use super::language::{stored_language, user_language};
+
+pub fn display_name(user: &User) -> String {
+    let mut name = user.first_name.clone();
+    if let Some(last_name) = &user.last_name {
+        name = format!("{name} {last_name}");
+    }
+    if let Some(username) = &user.username {
+        name = format!("{name} (@{username})");
+    }
+    name
+}
+
+fn status_text(status: &AccessRequestStatus, texts: &'static Texts) -> &'static str {
+    match status {
+        AccessRequestStatus::Pending => texts.request_pending,
+        AccessRequestStatus::Denied => texts.request_denied,
+    }
+}
+
+// Text and the keyboard shown to the users that are not allowed to use the bot
+pub async fn not_authorized_reply(
+    conn: &Pool,
+    user_id: i64,
+    language: Language,
+) -> anyhow::Result<(&'static str, Option<InlineKeyboardMarkup>)> {
+    let texts = language.texts();
+    Ok(match get_access_request_status(conn, user_id).await? {
+        Some(status) => (status_text(&status, texts), None),
+        None => (
+            texts.not_authorized,
+            Some(InlineKeyboardMarkup::new(vec![vec![
+                InlineKeyboardButton::callback(
+                    texts.request_access,
+                    CallbackData::RequestAccess.to_string(),
+                ),
+            ]])),
+        ),
+    })
+}
+
+async fn notify_admins(
+    bot: &Bot,
+    conn: &Pool,
+    repos: &Repositories,
+    user: &User,
+) -> anyhow::Result<()> {
+    let admins = repos.users.get_admins().await?;
+    if admins.is_empty() {
+        tracing::warn!(
+            "User {} requested access, but there are no admins to approve it",
+            user.id
+        );
+    }
+    for admin in admins {
+        let texts = stored_language(conn, admin.id).await.texts();
+        let keyboard = InlineKeyboardMarkup::new(vec![vec![
+            InlineKeyboardButton::callback(
+                texts.approve,
+                CallbackData::ApproveAccess { user_id: user.id }.to_string(),
+            ),
+            InlineKeyboardButton::callback(
+                texts.deny,
+                CallbackData::DenyAccess { user_id: user.id }.to_string(),
+            ),
+        ]]);
+        // one admin that blocked the bot should not stop the others from being notified
+        if let Err(err) = bot
+            .send_message(
+                UserId(admin.id as u64),
+                (texts.requests_access)(&display_name(user)),
+            )
+            .reply_markup(keyboard)
+            .await
+        {
+            tracing::warn!(
+                "Unable to notify admin {} about access request: {err}",
+                admin.id
+            );
+        }
+    }
+    Ok(())
+}
+
+pub async fn on_request_access(
+    bot: Bot,
+    q: CallbackQuery,
+    conn: &Pool,
+    repos: &Repositories,
+) -> anyhow::Result<()> {
+    let user = repos.users.get_user(q.from.id.0 as i64).await?;
+    let texts = user_language(conn, &q.from).await.texts();
+    if !create_access_request(conn, user.id).await? {
+        let text = match get_access_request_status(conn, user.id).await? {
+            Some(status) => status_text(&status, texts),
+            None => texts.request_already_handled,
+        };
+        bot.answer_callback_query(q.id).text(text).await?;
+        return Ok(());
+    }
+    tracing::info!("User {} requested access", user.id);
+    bot.answer_callback_query(q.id.clone()).await?;
+    let text = texts.request_sent;
+    match q.regular_message() {
+        Some(msg) => bot.edit_message_text(msg.chat.id, msg.id, text).await?,
+        None => bot.send_message(q.from.id, text).await?,
+    };
+    notify_admins(&bot, conn, repos, &user).await
+}
+
+// Called by the admin pressing Approve/Deny under the access request notification
+pub async fn on_access_decision(
+    bot: Bot,
+    q: &CallbackQuery,
+    conn: &Pool,
+    repos: &Repositories,
+    user_id: i64,
+    approve: bool,
+) -> anyhow::Result<()> {
+    let admin = repos.users.get_user(q.from.id.0 as i64).await?;
+    if !(admin.is_admin && admin.active) {
+        tracing::warn!(
+            "User {} tried to handle access request without being an active admin",
+            admin.id
+        );
+        return Ok(());
+    }
+    let texts = user_language(conn, &q.from).await.texts();
+    let handled = if approve {
+        approve_access_request(conn, user_id).await?
+    } else {
+        deny_access_request(conn, user_id).await?
+    };
+    let user = repos.users.get_user(user_id).await?;
+    let result = if !handled {
+        texts.decision_already_made.to_string()
+    } else if approve {
+        (texts.approved_by)(&display_name(&admin))
+    } else {
+        (texts.denied_by)(&display_name(&admin))
+    };
+    if let Some(msg) = q.regular_message() {
+        // the keyboard is removed, so the request can't be handled twice from the same message
+        bot.edit_message_text(
+            msg.chat.id,
+            msg.id,
+            format!(
+                "{}\n\n{result}",
+                (texts.requests_access)(&display_name(&user))
+            ),
+        )
+        .await?;
+    }
+    if handled {
+        tracing::info!(
+            "Access request of user {user_id} is {} by {}",
+            if approve { "approved" } else { "denied" },
+            admin.id
+        );
+        notify_about_decision(&bot, conn, user_id, approve).await;
+    }
+    Ok(())
+}
+
+// Failures are only logged, the decision is already saved and the user may have blocked the bot
+pub async fn notify_about_decision(bot: &Bot, conn: &Pool, user_id: i64, approved: bool) {
+    let texts = stored_language(conn, user_id).await.texts();
+    let text = if approved {
+        texts.request_approved
+    } else {
+        status_text(&AccessRequestStatus::Denied, texts)
+    };
+    if let Err(err) = bot.send_message(UserId(user_id as u64), text).await {
+        tracing::warn!("Unable to notify user {user_id} about access decision: {err}");
+    }
+}
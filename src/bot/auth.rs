@@ -1,31 +1,54 @@
-use sqlx::SqlitePool;
 use teloxide::types::User;
 
-use crate::db::queries::users::{create_user, get_user};
+use crate::db::Repositories;
 
-pub async fn auth_user(conn: &SqlitePool, user: &User) -> anyhow::Result<bool> {
-    if dotenv::var("USE_AUTH")
+// Whether only the active users can use the bot
+pub fn use_auth() -> bool {
+    dotenv::var("USE_AUTH")
         .expect("Variable USE_AUTH should be set")
         .parse()
         .expect("Should be 'true' or 'false'")
-    {
-        // todo: return false only on RowNotFound
-        get_user(conn, user.id.0.try_into().unwrap())
-            .await
-            .map(|user| user.active)
-            .or(Ok(false))
+}
+
+pub async fn auth_user(repos: &Repositories, user: &User) -> anyhow::Result<bool> {
+    if use_auth() {
+        match repos.users.get_user(user.id.0.try_into().unwrap()).await {
+            Ok(user) => Ok(user.active),
+            // unknown users are recorded, so they can request access
+            Err(sqlx::Error::RowNotFound) => {
+                repos
+                    .users
+                    .create_user(
+                        user.id.0.try_into().unwrap(),
+                        user.username.as_deref(),
+                        &user.first_name,
+                        user.last_name.as_deref(),
+                        false,
+                        false,
+                    )
+                    .await?;
+                Ok(false)
+            }
+            Err(err) => Err(err.into()),
+        }
     } else {
-        if get_user(conn, user.id.0.try_into().unwrap()).await.is_err() {
-            create_user(
-                conn,
-                user.id.0.try_into().unwrap(),
-                user.username.as_deref(),
-                &user.first_name,
-                user.last_name.as_deref(),
-                false,
-                false,
-            )
-            .await?;
+        if repos
+            .users
+            .get_user(user.id.0.try_into().unwrap())
+            .await
+            .is_err()
+        {
+            repos
+                .users
+                .create_user(
+                    user.id.0.try_into().unwrap(),
+                    user.username.as_deref(),
+                    &user.first_name,
+                    user.last_name.as_deref(),
+                    false,
+                    false,
+                )
+                .await?;
         }
         Ok(true)
     }
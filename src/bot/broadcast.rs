@@ -0,0 +1,193 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use teloxide::{
+    prelude::*,
+    types::{InputFile, InputMedia, InputMediaDocument, ParseMode},
+    ApiError, RequestError,
+};
+use tokio::sync::Mutex;
+use tokio::time::{interval, Interval, MissedTickBehavior};
+use tokio_util::io::StreamReader;
+
+use crate::db::queries::{
+    broadcasts::{
+        finish_broadcast, get_broadcast, get_pending_deliveries, get_unfinished_broadcasts,
+        set_delivery_status, BroadcastAttachment, DeliveryStatus,
+    },
+    users::set_user_blocked,
+};
+use crate::db::Pool;
+use crate::markdown;
+use crate::storage::BlobStorage;
+
+use super::is_stale_file_id;
+
+// Telegram allows about 30 messages per second to the different chats,
+// broadcasts stay below it to leave the room for the answers of the bot
+const SEND_INTERVAL: Duration = Duration::from_millis(50);
+
+// Broadcasts are sent one at a time, so together they don't exceed the limit
+static SENDING: Mutex<()> = Mutex::const_new(());
+// ...by all the instances sharing the database, that also resume the same broadcasts
+const SENDING_LOCK: i64 = 1;
+
+// Waits for the rate limit before every request and retries when Telegram asks to
+async fn send_with_retry<T, F, Fut>(limiter: &mut Interval, request: F) -> Result<T, RequestError>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, RequestError>>,
+{
+    loop {
+        limiter.tick().await;
+        match request().await {
+            Err(RequestError::RetryAfter(after)) => {
+                tracing::warn!("Broadcast is rate limited, retrying in {after:?}");
+                tokio::time::sleep(after.duration()).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+async fn send_documents(
+    bot: &Bot,
+    blobs: &dyn BlobStorage,
+    limiter: &mut Interval,
+    chat_id: ChatId,
+    attachments: &[BroadcastAttachment],
+    file_ids: &[Option<String>],
+) -> Result<Vec<Message>, RequestError> {
+    send_with_retry(limiter, || async {
+        let mut media = vec![];
+        for (attachment, file_id) in attachments.iter().zip(file_ids.iter()) {
+            let file = match file_id {
+                Some(file_id) => InputFile::file_id(file_id),
+                None => {
+                    let blob = blobs.get(&attachment.sha256).await.map_err(Arc::new)?;
+                    InputFile::read(StreamReader::new(blob)).file_name(attachment.name.clone())
+                }
+            };
+            media.push(InputMedia::Document(InputMediaDocument::new(file)));
+        }
+        bot.send_media_group(chat_id, media).send().await
+    })
+    .await
+}
+
+async fn send_to_user(
+    bot: &Bot,
+    blobs: &dyn BlobStorage,
+    limiter: &mut Interval,
+    chat_id: ChatId,
+    messages: &[String],
+    attachments: &[BroadcastAttachment],
+    // files are uploaded once, then sent by the file_id Telegram returned for them
+    file_ids: &mut [Option<String>],
+) -> Result<(), RequestError> {
+    for message in messages {
+        send_with_retry(limiter, || {
+            bot.send_message(chat_id, message.clone())
+                .parse_mode(ParseMode::Html)
+                .send()
+        })
+        .await?;
+    }
+    if !attachments.is_empty() {
+        let sent = match send_documents(bot, blobs, limiter, chat_id, attachments, file_ids).await {
+            Ok(sent) => sent,
+            // the saved ids are rejected, the files are uploaded again
+            Err(err) if is_stale_file_id(&err) && file_ids.iter().any(Option::is_some) => {
+                tracing::warn!("Saved files are rejected, uploading them again: {err}");
+                file_ids.fill(None);
+                send_documents(bot, blobs, limiter, chat_id, attachments, file_ids).await?
+            }
+            Err(err) => return Err(err),
+        };
+        // messages are in the order of the media group
+        for (file_id, message) in file_ids.iter_mut().zip(sent.iter()) {
+            if let (None, Some(document)) = (&file_id, message.document()) {
+                *file_id = Some(document.file.id.to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+// Sends the broadcast to all the pending recipients. Deliveries are recorded one by one,
+// so the broadcast interrupted by the restart continues where it stopped
+pub async fn run_broadcast(
+    bot: Bot,
+    pool: Pool,
+    blobs: Arc<dyn BlobStorage>,
+    id: i64,
+) -> anyhow::Result<()> {
+    let broadcast = get_broadcast(&pool, id).await?.broadcast;
+    let messages = markdown::to_telegram_chunks(&broadcast.text, markdown::MESSAGE_MAX_LENGTH);
+    let mut attachments = vec![];
+    for attachment in broadcast.attachments {
+        if blobs.exists(&attachment.sha256).await? {
+            attachments.push(attachment);
+        } else {
+            tracing::error!("File {:?} is not found!", attachment.name);
+        }
+    }
+    let mut file_ids = vec![None; attachments.len()];
+
+    let _sending = SENDING.lock().await;
+    let _lock = pool.lock(SENDING_LOCK).await?;
+    let mut limiter = interval(SEND_INTERVAL);
+    limiter.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let recipients = get_pending_deliveries(&pool, id).await?;
+    tracing::info!("Sending broadcast {id} to {} users", recipients.len());
+    for user_id in recipients {
+        let result = send_to_user(
+            &bot,
+            blobs.as_ref(),
+            &mut limiter,
+            ChatId(user_id),
+            &messages,
+            &attachments,
+            &mut file_ids,
+        )
+        .await;
+        match result {
+            Ok(()) => set_delivery_status(&pool, id, user_id, DeliveryStatus::Sent, None).await?,
+            Err(RequestError::Api(ApiError::BotBlocked)) => {
+                set_user_blocked(&pool, user_id, true).await?;
+                set_delivery_status(&pool, id, user_id, DeliveryStatus::Blocked, None).await?;
+            }
+            Err(err) => {
+                tracing::warn!("Unable to send broadcast {id} to user {user_id}: {err}");
+                let error = err.to_string();
+                set_delivery_status(&pool, id, user_id, DeliveryStatus::Failed, Some(&error))
+                    .await?;
+            }
+        }
+    }
+    finish_broadcast(&pool, id).await?;
+    tracing::info!("Broadcast {id} is finished");
+    Ok(())
+}
+
+pub fn spawn_broadcast(bot: Bot, pool: Pool, blobs: Arc<dyn BlobStorage>, id: i64) {
+    tokio::spawn(async move {
+        if let Err(err) = run_broadcast(bot, pool, blobs, id).await {
+            tracing::error!("Broadcast {id} failed: {err:#}");
+        }
+    });
+}
+
+// Continues the broadcasts interrupted by the restart
+pub async fn resume_broadcasts(
+    bot: Bot,
+    pool: Pool,
+    blobs: Arc<dyn BlobStorage>,
+) -> anyhow::Result<()> {
+    for id in get_unfinished_broadcasts(&pool).await? {
+        tracing::info!("Resuming broadcast {id}");
+        spawn_broadcast(bot.clone(), pool.clone(), blobs.clone(), id);
+    }
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::i18n::Language;
+
+// Data attached to the inline keyboard buttons. Telegram limits it to 64 bytes,
+// so it is encoded as a short string like `cat:12:0`
+#[derive(Clone, Debug, PartialEq)]
+pub enum CallbackData {
+    // Page of the main menu with the list of categories
+    Categories { page: usize },
+    // Page of the questions of the category
+    Category { id: i64, page: usize },
+    Question { id: i64 },
+    // Sent by the user that is not authorized to use the bot
+    RequestAccess,
+    // Decisions of the admin on the access request of the user
+    ApproveAccess { user_id: i64 },
+    DenyAccess { user_id: i64 },
+    // Sends the question to the support team when nothing is found
+    AskHuman,
+    // Rating of the answer to the question
+    Feedback { question_id: i64, helpful: bool },
+    // Asks the user to write the comment on the rated answer
+    FeedbackComment { question_id: i64 },
+    // Notifications about the changes in the category, pressed on the page of the category menu
+    Subscribe { category_id: i64, page: usize },
+    Unsubscribe { category_id: i64, page: usize },
+    // Language of the bot chosen with /language, None goes back to the language of Telegram
+    SetLanguage { language: Option<Language> },
+}
+
+impl Display for CallbackData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Categories { page } => write!(f, "cats:{page}"),
+            Self::Category { id, page } => write!(f, "cat:{id}:{page}"),
+            Self::Question { id } => write!(f, "q:{id}"),
+            Self::RequestAccess => write!(f, "access"),
+            Self::ApproveAccess { user_id } => write!(f, "approve:{user_id}"),
+            Self::DenyAccess { user_id } => write!(f, "deny:{user_id}"),
+            Self::AskHuman => write!(f, "ask"),
+            Self::Feedback {
+                question_id,
+                helpful,
+            } => write!(f, "fb:{question_id}:{}", u8::from(*helpful)),
+            Self::FeedbackComment { question_id } => write!(f, "fbc:{question_id}"),
+            Self::Subscribe { category_id, page } => write!(f, "sub:{category_id}:{page}"),
+            Self::Unsubscribe { category_id, page } => write!(f, "unsub:{category_id}:{page}"),
+            Self::SetLanguage { language } => match language {
+                Some(language) => write!(f, "lang:{}", language.code()),
+                None => write!(f, "lang:auto"),
+            },
+        }
+    }
+}
+
+impl FromStr for CallbackData {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        let data = match parts.as_slice() {
+            ["cats", page] => Self::Categories {
+                page: page.parse()?,
+            },
+            ["cat", id, page] => Self::Category {
+                id: id.parse()?,
+                page: page.parse()?,
+            },
+            ["q", id] => Self::Question { id: id.parse()? },
+            ["access"] => Self::RequestAccess,
+            ["approve", user_id] => Self::ApproveAccess {
+                user_id: user_id.parse()?,
+            },
+            ["deny", user_id] => Self::DenyAccess {
+                user_id: user_id.parse()?,
+            },
+            ["ask"] => Self::AskHuman,
+            ["fb", question_id, helpful] => Self::Feedback {
+                question_id: question_id.parse()?,
+                helpful: match *helpful {
+                    "1" => true,
+                    "0" => false,
+                    _ => anyhow::bail!("Unknown callback data {s}"),
+                },
+            },
+            ["fbc", question_id] => Self::FeedbackComment {
+                question_id: question_id.parse()?,
+            },
+            ["sub", category_id, page] => Self::Subscribe {
+                category_id: category_id.parse()?,
+                page: page.parse()?,
+            },
+            ["unsub", category_id, page] => Self::Unsubscribe {
+                category_id: category_id.parse()?,
+                page: page.parse()?,
+            },
+            ["lang", "auto"] => Self::SetLanguage { language: None },
+            ["lang", code] => Self::SetLanguage {
+                language: Some(
+                    Language::from_code(code)
+                        .ok_or_else(|| anyhow::anyhow!("Unknown callback data {s}"))?,
+                ),
+            },
+            _ => anyhow::bail!("Unknown callback data {s}"),
+        };
+        Ok(data)
+    }
+}
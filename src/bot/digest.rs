@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup, ParseMode},
+    ApiError, RequestError,
+};
+
+use crate::db::queries::{
+    subscriptions::{get_pending_changes, take_digest, DigestItem},
+    users::set_user_blocked,
+};
+use crate::db::Pool;
+use crate::i18n::Texts;
+use crate::markdown;
+
+use super::auth::use_auth;
+use super::callback_data::CallbackData;
+use super::language::{stored_language, translate_digest};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+// digest is sent when nothing has changed for a while, so a batch of edits is sent at once
+const QUIET_PERIOD_SECONDS: i64 = 5 * 60;
+// ...but the subscribers don't wait forever while the content is being edited
+const MAX_DELAY_SECONDS: i64 = 30 * 60;
+const QUESTIONS_PER_DIGEST: usize = 20;
+// stays below the Telegram limit of about 30 messages per second
+const SEND_INTERVAL: Duration = Duration::from_millis(50);
+
+fn make_digest(items: &[DigestItem], texts: &Texts) -> (String, InlineKeyboardMarkup) {
+    let mut text = format!("{}\n", texts.digest_header);
+    for item in items.iter().take(QUESTIONS_PER_DIGEST) {
+        let mark = if item.created { "🆕" } else { "✏️" };
+        text.push_str(&format!(
+            "\n{mark} <b>{}</b>: {}",
+            markdown::escape(&item.category),
+            markdown::escape(&item.question)
+        ));
+    }
+    if items.len() > QUESTIONS_PER_DIGEST {
+        text.push_str(&format!(
+            "\n\n{}",
+            (texts.digest_more)(items.len() - QUESTIONS_PER_DIGEST)
+        ));
+    }
+    let keyboard = items
+        .iter()
+        .take(QUESTIONS_PER_DIGEST)
+        .map(|item| {
+            vec![InlineKeyboardButton::callback(
+                &item.question,
+                CallbackData::Question {
+                    id: item.question_id,
+                }
+                .to_string(),
+            )]
+        })
+        .collect::<Vec<_>>();
+    (text, InlineKeyboardMarkup::new(keyboard))
+}
+
+async fn send_digests(bot: &Bot, conn: &Pool) -> anyhow::Result<()> {
+    let Some(changes) = get_pending_changes(conn).await? else {
+        return Ok(());
+    };
+    if changes.since_last < QUIET_PERIOD_SECONDS && changes.since_first < MAX_DELAY_SECONDS {
+        return Ok(());
+    }
+    let mut digests: BTreeMap<i64, Vec<DigestItem>> = BTreeMap::new();
+    for item in take_digest(conn, changes.now, use_auth()).await? {
+        digests.entry(item.user_id).or_default().push(item);
+    }
+    tracing::info!("Sending digests to {} subscribers", digests.len());
+    for (user_id, mut items) in digests {
+        let language = stored_language(conn, user_id).await;
+        translate_digest(conn, language, &mut items).await?;
+        let (text, keyboard) = make_digest(&items, language.texts());
+        let result = bot
+            .send_message(ChatId(user_id), text)
+            .parse_mode(ParseMode::Html)
+            .reply_markup(keyboard)
+            .await;
+        match result {
+            Ok(_) => {}
+            Err(RequestError::Api(ApiError::BotBlocked)) => {
+                set_user_blocked(conn, user_id, true).await?;
+            }
+            Err(err) => tracing::warn!("Unable to send digest to user {user_id}: {err}"),
+        }
+        tokio::time::sleep(SEND_INTERVAL).await;
+    }
+    Ok(())
+}
+
+// Notifies the subscribers about the questions changed in their categories
+pub async fn run_digests(bot: Bot, conn: Arc<Pool>) {
+    loop {
+        tokio::time::sleep(CHECK_INTERVAL).await;
+        if let Err(err) = send_digests(&bot, &conn).await {
+            tracing::error!("Unable to send digests: {err:#}");
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use teloxide::{
+    prelude::*,
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+    ApiError, RequestError,
+};
+
+use crate::db::queries::feedback::{set_feedback, set_feedback_comment};
+use crate::db::Pool;
+use crate::i18n::Texts;
+
+use super::callback_data::CallbackData;
+
+// Buttons under the answer. Once the answer is rated, the rating is marked
+// and the user can add the comment
+pub fn feedback_keyboard(
+    question_id: i64,
+    rating: Option<bool>,
+    texts: &Texts,
+) -> InlineKeyboardMarkup {
+    let mark = |helpful: bool| if rating == Some(helpful) { " ✓" } else { "" };
+    let mut keyboard = vec![vec![
+        InlineKeyboardButton::callback(
+            format!("{}{}", texts.helpful, mark(true)),
+            CallbackData::Feedback {
+                question_id,
+                helpful: true,
+            }
+            .to_string(),
+        ),
+        InlineKeyboardButton::callback(
+            format!("{}{}", texts.not_helpful, mark(false)),
+            CallbackData::Feedback {
+                question_id,
+                helpful: false,
+            }
+            .to_string(),
+        ),
+    ]];
+    if rating.is_some() {
+        keyboard.push(vec![InlineKeyboardButton::callback(
+            texts.add_comment,
+            CallbackData::FeedbackComment { question_id }.to_string(),
+        )]);
+    }
+    InlineKeyboardMarkup::new(keyboard)
+}
+
+pub async fn on_feedback(
+    bot: &Bot,
+    q: &CallbackQuery,
+    conn: &Pool,
+    question_id: i64,
+    helpful: bool,
+    texts: &Texts,
+) -> anyhow::Result<()> {
+    match set_feedback(conn, question_id, q.from.id.0 as i64, helpful).await {
+        Ok(()) => {}
+        Err(sqlx::Error::Database(err)) if err.is_foreign_key_violation() => {
+            bot.send_message(q.from.id, texts.question_not_available)
+                .await?;
+            return Ok(());
+        }
+        Err(err) => return Err(err.into()),
+    }
+    if let Some(msg) = q.regular_message() {
+        let res = bot
+            .edit_message_reply_markup(msg.chat.id, msg.id)
+            .reply_markup(feedback_keyboard(question_id, Some(helpful), texts))
+            .await;
+        match res {
+            Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(())
+}
+
+// Called with the message sent after pressing "Add a comment".
+// Returns false when there is no text, so the user should send the comment again
+pub async fn on_comment(
+    bot: &Bot,
+    msg: &Message,
+    conn: &Pool,
+    question_id: i64,
+    texts: &Texts,
+) -> anyhow::Result<bool> {
+    let user_id = msg
+        .from
+        .as_ref()
+        .expect("Got message not from a user?")
+        .id
+        .0 as i64;
+    let text = match msg.text() {
+        Some(text) => text,
+        None => {
+            bot.send_message(msg.chat.id, texts.comment_not_text)
+                .await?;
+            return Ok(false);
+        }
+    };
+    let reply = if set_feedback_comment(conn, question_id, user_id, text).await? {
+        texts.comment_thanks
+    } else {
+        texts.question_not_available
+    };
+    bot.send_message(msg.chat.id, reply).await?;
+    Ok(true)
+}
@@ -0,0 +1,90 @@
+use std::borrow::Borrow;
+use std::sync::Arc;
+
+use teloxide::{
+    dispatching::DpHandlerDescription,
+    prelude::*,
+    types::{
+        InlineQueryResult, InlineQueryResultArticle, InlineQueryResultsButton,
+        InlineQueryResultsButtonKind, InputMessageContent, InputMessageContentText, ParseMode,
+    },
+};
+
+use crate::db::{Pool, Question, Repositories};
+use crate::markdown;
+
+use super::auth;
+use super::language::{translate_questions, user_language};
+
+const INLINE_RESULTS_LIMIT: i64 = 20;
+// results depend on the user permissions, so they should not live in telegram cache for long
+const INLINE_CACHE_TIME: u32 = 60;
+
+fn make_article(question: Question) -> InlineQueryResult {
+    // inline result is a single message, so only the first part of the long answer is sent
+    let header = format!("<b>{}</b>\n\n", markdown::escape(&question.question));
+    let limit = markdown::MESSAGE_MAX_LENGTH.saturating_sub(header.encode_utf16().count());
+    let text = markdown::to_telegram_chunks(&question.answer, limit)
+        .into_iter()
+        .next()
+        .map(|answer| header.clone() + &answer)
+        .unwrap_or(header);
+    let mut article = InlineQueryResultArticle::new(
+        question.id.to_string(),
+        question.question,
+        InputMessageContent::Text(InputMessageContentText::new(text).parse_mode(ParseMode::Html)),
+    );
+    if let Some(category) = question.category {
+        article = article.description(category.name);
+    }
+    InlineQueryResult::Article(article)
+}
+
+async fn on_inline_query(
+    bot: Bot,
+    q: InlineQuery,
+    conn: Arc<Pool>,
+    repos: Repositories,
+) -> anyhow::Result<()> {
+    let authenticated = auth::auth_user(&repos, &q.from)
+        .await
+        .map_err(|err| {
+            tracing::warn!("Unable to authenticate user {:?}: {}", q.from, err);
+            err
+        })
+        .unwrap_or(false);
+    let language = user_language(&conn, &q.from).await;
+    if !authenticated {
+        bot.answer_inline_query(q.id, vec![])
+            .is_personal(true)
+            .cache_time(INLINE_CACHE_TIME)
+            .button(InlineQueryResultsButton {
+                text: language.texts().not_authorized.to_string(),
+                kind: InlineQueryResultsButtonKind::StartParameter("auth".to_string()),
+            })
+            .await?;
+        return Ok(());
+    }
+
+    let mut questions = repos
+        .questions
+        .search_public_questions(
+            q.from.id.0 as i64,
+            language.code(),
+            &q.query,
+            INLINE_RESULTS_LIMIT,
+        )
+        .await?;
+    translate_questions(conn.borrow(), language, &mut questions).await?;
+    let results: Vec<InlineQueryResult> = questions.into_iter().map(make_article).collect();
+    bot.answer_inline_query(q.id, results)
+        .is_personal(true)
+        .cache_time(INLINE_CACHE_TIME)
+        .await?;
+    Ok(())
+}
+
+pub fn make_inline_query_branch(
+) -> Handler<'static, DependencyMap, anyhow::Result<()>, DpHandlerDescription> {
+    Update::filter_inline_query().endpoint(on_inline_query)
+}
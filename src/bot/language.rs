@@ -0,0 +1,117 @@
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, User};
+
+use crate::db::{
+    queries::{
+        subscriptions::DigestItem,
+        translations::{
+            self, get_category_names, get_translated_questions, get_user_language,
+            set_telegram_language,
+        },
+    },
+    Category, Pool, Question,
+};
+use crate::i18n::{default_language, Language};
+
+use super::callback_data::CallbackData;
+
+// Language of the user sending the update. The language of the Telegram client is
+// remembered, so the messages sent later are in the same language
+pub async fn user_language(conn: &Pool, user: &User) -> Language {
+    let user_id = user.id.0 as i64;
+    let telegram = user.language_code.as_deref();
+    let stored = get_user_language(conn, user_id)
+        .await
+        .unwrap_or_else(|err| {
+            tracing::warn!("Unable to get language of user {user_id}: {err}");
+            None
+        });
+    if stored.as_ref().map(|s| s.telegram_language.as_deref()) != Some(telegram) {
+        match set_telegram_language(conn, user_id, telegram).await {
+            Ok(()) => {}
+            // user is not recorded yet, the language is saved with the next update
+            Err(sqlx::Error::Database(err)) if err.is_foreign_key_violation() => {}
+            Err(err) => tracing::warn!("Unable to save language of user {user_id}: {err}"),
+        }
+    }
+    let chosen = stored.and_then(|s| s.language);
+    Language::resolve(chosen.as_deref(), telegram)
+}
+
+// Language for the messages sent without an update from the user, like the digests
+pub async fn stored_language(conn: &Pool, user_id: i64) -> Language {
+    match get_user_language(conn, user_id).await {
+        Ok(Some(stored)) => Language::resolve(
+            stored.language.as_deref(),
+            stored.telegram_language.as_deref(),
+        ),
+        Ok(None) => default_language(),
+        Err(err) => {
+            tracing::warn!("Unable to get language of user {user_id}: {err}");
+            default_language()
+        }
+    }
+}
+
+// Content is written in the default language, so only the other ones need the translations
+pub async fn translate_categories(
+    conn: &Pool,
+    language: Language,
+    categories: &mut [Category],
+) -> sqlx::Result<()> {
+    if language == default_language() {
+        return Ok(());
+    }
+    translations::translate_categories(conn, language.code(), categories).await
+}
+
+pub async fn translate_questions(
+    conn: &Pool,
+    language: Language,
+    questions: &mut [Question],
+) -> sqlx::Result<()> {
+    if language == default_language() {
+        return Ok(());
+    }
+    translations::translate_questions(conn, language.code(), questions).await
+}
+
+pub async fn translate_digest(
+    conn: &Pool,
+    language: Language,
+    items: &mut [DigestItem],
+) -> sqlx::Result<()> {
+    if language == default_language() {
+        return Ok(());
+    }
+    let questions = get_translated_questions(conn, language.code()).await?;
+    let categories = get_category_names(conn, language.code()).await?;
+    for item in items {
+        if let Some(translation) = questions.get(&item.question_id) {
+            item.question = translation.question.clone();
+        }
+        if let Some(name) = categories.get(&item.category_id) {
+            item.category = name.clone();
+        }
+    }
+    Ok(())
+}
+
+pub fn language_keyboard(language: Language) -> InlineKeyboardMarkup {
+    let mut keyboard: Vec<Vec<InlineKeyboardButton>> = Language::ALL
+        .into_iter()
+        .map(|option| {
+            vec![InlineKeyboardButton::callback(
+                option.name(),
+                CallbackData::SetLanguage {
+                    language: Some(option),
+                }
+                .to_string(),
+            )]
+        })
+        .collect();
+    keyboard.push(vec![InlineKeyboardButton::callback(
+        language.texts().language_auto,
+        CallbackData::SetLanguage { language: None }.to_string(),
+    )]);
+    InlineKeyboardMarkup::new(keyboard)
+}
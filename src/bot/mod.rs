@@ -1,24 +1,71 @@
+pub mod access;
 mod auth;
+pub mod broadcast;
+mod callback_data;
+mod digest;
+mod feedback;
+mod inline;
+mod language;
 mod private_chat;
+pub mod support;
 
-use std::path::PathBuf;
 use std::sync::Arc;
 
-use sqlx::SqlitePool;
-use teloxide::dispatching::dialogue::SqliteStorage;
-use teloxide::{dispatching::dialogue::serializer::Json, prelude::*};
+use teloxide::dispatching::dialogue::{ErasedStorage, PostgresStorage, SqliteStorage, Storage};
+use teloxide::{dispatching::dialogue::serializer::Json, prelude::*, ApiError, RequestError};
 
-pub async fn run(pool: SqlitePool, chat_db_path: &str, static_dir: PathBuf) -> anyhow::Result<()> {
+use crate::db::{self, Pool, Repositories};
+use crate::storage::BlobStorage;
+
+use private_chat::State;
+
+// Saved file_id is rejected by Telegram, e.g. after the token of the bot is changed.
+// The other errors, like timeouts, leave the saved ids valid
+fn is_stale_file_id(err: &RequestError) -> bool {
+    match err {
+        RequestError::Api(
+            ApiError::WrongFileId | ApiError::WrongFileIdOrUrl | ApiError::FileIdInvalid,
+        ) => true,
+        // sending by the file_id of another bot is reported with the varying text
+        RequestError::Api(ApiError::Unknown(text)) => text.contains("file identifier"),
+        _ => false,
+    }
+}
+
+// Dialogues are kept in the same database as the content
+async fn open_storage(database_url: &str) -> anyhow::Result<Arc<ErasedStorage<State>>> {
+    let storage = if db::is_postgres(database_url) {
+        PostgresStorage::open(database_url, 10, Json).await?.erase()
+    } else {
+        SqliteStorage::open(db::sqlite_path(database_url), Json)
+            .await?
+            .erase()
+    };
+    Ok(storage)
+}
+
+pub async fn run(
+    pool: Pool,
+    repositories: Repositories,
+    database_url: &str,
+    blobs: Arc<dyn BlobStorage>,
+) -> anyhow::Result<()> {
     let conn = Arc::new(pool);
-    let storage = SqliteStorage::open(chat_db_path, Json).await.unwrap();
+    let storage = open_storage(database_url).await?;
+
+    let handler = dptree::entry()
+        .branch(inline::make_inline_query_branch())
+        .branch(support::make_support_chat_branch())
+        .branch(
+            dptree::filter(private_chat::filter_private_chats)
+                .branch(private_chat::make_private_chat_branch()),
+        );
 
-    let handler = dptree::entry().branch(
-        dptree::filter(private_chat::filter_private_chats)
-            .branch(private_chat::make_private_chat_branch()),
-    );
+    let bot = Bot::from_env();
+    tokio::spawn(digest::run_digests(bot.clone(), conn.clone()));
 
-    let mut builder = Dispatcher::builder(Bot::from_env(), handler)
-        .dependencies(dptree::deps![conn, storage, Arc::new(static_dir)])
+    let mut builder = Dispatcher::builder(bot, handler)
+        .dependencies(dptree::deps![conn, repositories, storage, blobs])
         .default_handler(|upd| async move {
             dbg!(upd);
         })
@@ -29,3 +76,21 @@ pub async fn run(pool: SqlitePool, chat_db_path: &str, static_dir: PathBuf) -> a
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_stale_file_ids() {
+        let api = |err| RequestError::Api(err);
+        assert!(is_stale_file_id(&api(ApiError::WrongFileId)));
+        assert!(is_stale_file_id(&api(ApiError::FileIdInvalid)));
+        assert!(is_stale_file_id(&api(ApiError::Unknown(
+            "Bad Request: wrong remote file identifier specified: Wrong string length".to_string()
+        ))));
+        assert!(!is_stale_file_id(&api(ApiError::BotBlocked)));
+        let timeout = RequestError::Io(std::io::Error::from(std::io::ErrorKind::TimedOut).into());
+        assert!(!is_stale_file_id(&timeout));
+    }
+}
@@ -1,241 +1,727 @@
-use anyhow::bail;
 use itertools::Itertools;
-use sqlx::SqlitePool;
 use std::borrow::Borrow;
 
-use std::path::PathBuf;
 use std::sync::Arc;
 
 use teloxide::{
-    dispatching::dialogue::{serializer::Json, SqliteStorage},
+    dispatching::dialogue::ErasedStorage,
     dispatching::DpHandlerDescription,
     prelude::*,
     types::{
-        InputFile, InputMedia, InputMediaDocument, KeyboardButton, KeyboardMarkup, KeyboardRemove,
+        InlineKeyboardButton, InlineKeyboardMarkup, InputFile, InputMedia, InputMediaDocument,
+        KeyboardRemove, ParseMode, User,
     },
     utils::command::BotCommands,
+    ApiError, RequestError,
 };
 
 use serde::{Deserialize, Serialize};
 use teloxide::types::{ChatKind, ChatMemberKind};
+use tokio_util::io::StreamReader;
 
 use crate::db::{
     queries::{
-        categories::get_public_categories,
-        questions::{get_public_questions_for_public_category, get_question_by_category_name},
+        attachments::Attachment,
+        subscriptions::{is_subscribed, subscribe, unsubscribe},
+        translations::set_chosen_language,
+        users::set_user_blocked,
     },
-    Question,
+    Category, Pool, Question, Repositories,
 };
+use crate::i18n::{Language, Texts};
+use crate::markdown;
+use crate::storage::BlobStorage;
 use crate::telemetry::QUESTION_CNTR;
 
+use super::access;
 use super::auth;
+use super::callback_data::CallbackData;
+use super::feedback;
+use super::is_stale_file_id;
+use super::language::{self, translate_categories, translate_questions, user_language};
+use super::support;
 
-type MyDialogue = Dialogue<State, SqliteStorage<Json>>;
+type MyDialogue = Dialogue<State, ErasedStorage<State>>;
+
+const CATEGORIES_PER_PAGE: usize = 10;
+const QUESTIONS_PER_PAGE: usize = 8;
+const SEARCH_RESULTS_LIMIT: i64 = 10;
 
 #[derive(BotCommands, Clone)]
 #[command(rename_rule = "lowercase")]
 enum Command {
     Help,
     Start,
+    Ask,
+    Language,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Default, Serialize, Deserialize)]
 pub enum State {
-    // In this state list of Categories is displayed on the keyboard
+    // Navigation is done with inline keyboards, text messages are search queries
+    #[default]
     ShowingCategories,
-    // In this state list of Questions is displayed on the keyboard
-    ShowingQuestions { category: String },
+    // States of the reply keyboard navigation. Not used anymore, but kept so the dialogues
+    // stored by the previous versions can be loaded. Handled the same way as ShowingCategories
+    ShowingQuestions {
+        category: String,
+    },
+    SearchResults {
+        query: String,
+    },
+    // Next text message is the comment on the answer to the question
+    WritingFeedbackComment {
+        question_id: i64,
+    },
+    // Next text message is the question to the support team
+    AskingHuman,
+}
+
+struct Menu {
+    text: String,
+    keyboard: InlineKeyboardMarkup,
 }
 
-impl Default for State {
-    fn default() -> Self {
-        Self::ShowingCategories
+fn button(text: impl Into<String>, data: CallbackData) -> InlineKeyboardButton {
+    InlineKeyboardButton::callback(text, data.to_string())
+}
+
+// Buttons to switch between the pages, empty when everything fits on the single page
+fn navigation_row(
+    page: usize,
+    total: usize,
+    per_page: usize,
+    texts: &Texts,
+    to_page: impl Fn(usize) -> CallbackData,
+) -> Vec<InlineKeyboardButton> {
+    let mut row = vec![];
+    if page > 0 {
+        row.push(button(texts.prev_page, to_page(page - 1)));
+    }
+    if (page + 1) * per_page < total {
+        row.push(button(texts.next_page, to_page(page + 1)));
     }
+    row
 }
 
-async fn make_categories_keyboard(conn: &SqlitePool) -> anyhow::Result<KeyboardMarkup> {
-    let results: Vec<String> = get_public_categories(conn)
-        .await?
+async fn make_categories_menu(
+    conn: &Pool,
+    repos: &Repositories,
+    user_id: i64,
+    language: Language,
+    page: usize,
+) -> anyhow::Result<Menu> {
+    let texts = language.texts();
+    let mut categories = repos
+        .categories
+        .get_public_subcategories(user_id, None)
+        .await?;
+    translate_categories(conn, language, &mut categories).await?;
+    let mut keyboard: Vec<Vec<InlineKeyboardButton>> = categories
+        .iter()
+        .skip(page * CATEGORIES_PER_PAGE)
+        .take(CATEGORIES_PER_PAGE)
+        .map(|category| {
+            button(
+                &category.name,
+                CallbackData::Category {
+                    id: category.id,
+                    page: 0,
+                },
+            )
+        })
+        .chunks(2)
         .into_iter()
-        .map(|category_| category_.name)
+        .map(|row| row.collect())
         .collect();
-    make_keyboard(results, 2, false)
+    let navigation = navigation_row(page, categories.len(), CATEGORIES_PER_PAGE, texts, |page| {
+        CallbackData::Categories { page }
+    });
+    if !navigation.is_empty() {
+        keyboard.push(navigation);
+    }
+    Ok(Menu {
+        text: texts.main_menu.to_string(),
+        keyboard: InlineKeyboardMarkup::new(keyboard),
+    })
 }
 
-async fn make_questions_keyboard(
-    conn: &SqlitePool,
-    category: &str,
-) -> anyhow::Result<KeyboardMarkup> {
-    let results: Vec<String> = get_public_questions_for_public_category(conn, category)
-        .await?
-        .into_iter()
-        .map(|question| question.question)
+// Subcategories go first, then the questions, both share the pages
+async fn make_category_menu(
+    conn: &Pool,
+    repos: &Repositories,
+    user_id: i64,
+    language: Language,
+    category: &Category,
+    page: usize,
+) -> anyhow::Result<Menu> {
+    let texts = language.texts();
+    let mut subcategories = repos
+        .categories
+        .get_public_subcategories(user_id, Some(category.id))
+        .await?;
+    translate_categories(conn, language, &mut subcategories).await?;
+    let mut questions = repos
+        .questions
+        .get_public_questions_for_public_category(user_id, category.id)
+        .await?;
+    translate_questions(conn, language, &mut questions).await?;
+    let items: Vec<InlineKeyboardButton> = subcategories
+        .iter()
+        .map(|subcategory| {
+            button(
+                format!("📁 {}", subcategory.name),
+                CallbackData::Category {
+                    id: subcategory.id,
+                    page: 0,
+                },
+            )
+        })
+        .chain(questions.iter().map(|question| {
+            button(
+                &question.question,
+                CallbackData::Question { id: question.id },
+            )
+        }))
         .collect();
-    if results.is_empty() {
-        bail!(
-            "Category {} is unknown or has no available questions",
-            category
-        );
+    let mut keyboard: Vec<Vec<InlineKeyboardButton>> = items
+        .iter()
+        .skip(page * QUESTIONS_PER_PAGE)
+        .take(QUESTIONS_PER_PAGE)
+        .map(|item| vec![item.clone()])
+        .collect();
+    let navigation = navigation_row(page, items.len(), QUESTIONS_PER_PAGE, texts, |page| {
+        CallbackData::Category {
+            id: category.id,
+            page,
+        }
+    });
+    if !navigation.is_empty() {
+        keyboard.push(navigation);
     }
-    make_keyboard(results, 1, true)
+    let back = match category.parent {
+        Some(parent) => CallbackData::Category {
+            id: parent,
+            page: 0,
+        },
+        None => CallbackData::Categories { page: 0 },
+    };
+    let subscription = if is_subscribed(conn, user_id, category.id).await? {
+        button(
+            texts.unsubscribe,
+            CallbackData::Unsubscribe {
+                category_id: category.id,
+                page,
+            },
+        )
+    } else {
+        button(
+            texts.subscribe,
+            CallbackData::Subscribe {
+                category_id: category.id,
+                page,
+            },
+        )
+    };
+    keyboard.push(vec![subscription]);
+    keyboard.push(vec![button(texts.go_back, back)]);
+
+    let mut path = repos.categories.get_category_path(category.id).await?;
+    translate_categories(conn, language, &mut path).await?;
+    let path = path.into_iter().map(|c| c.name).join(" › ");
+    let text = if items.is_empty() {
+        (texts.category_empty)(&path)
+    } else {
+        (texts.category_select_question)(&path)
+    };
+    Ok(Menu {
+        text,
+        keyboard: InlineKeyboardMarkup::new(keyboard),
+    })
 }
 
-fn make_keyboard(
-    data: Vec<String>,
-    rows: usize,
-    with_exit: bool,
-) -> anyhow::Result<KeyboardMarkup> {
-    let mut keyboard: Vec<Vec<KeyboardButton>> = vec![];
+fn make_search_keyboard(questions: &[Question], texts: &Texts) -> InlineKeyboardMarkup {
+    let mut keyboard: Vec<Vec<InlineKeyboardButton>> = questions
+        .iter()
+        .map(|question| {
+            vec![button(
+                &question.question,
+                CallbackData::Question { id: question.id },
+            )]
+        })
+        .collect();
+    keyboard.push(vec![button(
+        texts.main_menu_button,
+        CallbackData::Categories { page: 0 },
+    )]);
+    InlineKeyboardMarkup::new(keyboard)
+}
 
-    for text in data.chunks(rows) {
-        let row = text.iter().map(KeyboardButton::new).collect();
+// Replaces the menu the button was pressed in, so the chat is not flooded with menus
+async fn show_menu(bot: &Bot, q: &CallbackQuery, menu: Menu) -> anyhow::Result<()> {
+    match q.regular_message() {
+        Some(msg) => {
+            let res = bot
+                .edit_message_text(msg.chat.id, msg.id, menu.text)
+                .reply_markup(menu.keyboard)
+                .await;
+            match res {
+                Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        // message is too old to be edited
+        None => {
+            bot.send_message(q.from.id, menu.text)
+                .reply_markup(menu.keyboard)
+                .await?;
+        }
+    }
+    Ok(())
+}
 
-        keyboard.push(row);
+// Files are uploaded once, then sent by the file_id Telegram returned for them. Attachments
+// without the blob are skipped, their saved ids are dropped when `upload` is set
+async fn make_media_group(
+    blobs: &dyn BlobStorage,
+    attachments: Vec<Attachment>,
+    upload: bool,
+) -> (Vec<InputMedia>, Vec<Attachment>) {
+    let mut media_group: Vec<InputMedia> = vec![];
+    let mut available: Vec<Attachment> = vec![];
+    for mut att in attachments {
+        if upload {
+            att.telegram_file_id = None;
+        }
+        let file = match &att.telegram_file_id {
+            Some(file_id) => InputFile::file_id(file_id),
+            None => match blobs.get(&att.sha256).await {
+                Ok(blob) => InputFile::read(StreamReader::new(blob)).file_name(att.name.clone()),
+                Err(err) => {
+                    tracing::error!("File {:?} is not available: {err}", att.name);
+                    continue;
+                }
+            },
+        };
+        media_group.push(InputMedia::Document(InputMediaDocument::new(file)));
+        available.push(att);
     }
-    if with_exit {
-        keyboard.push(vec![KeyboardButton::new("Go Back")]);
+    (media_group, available)
+}
+
+async fn send_attachments(
+    bot: &Bot,
+    chat_id: ChatId,
+    repos: &Repositories,
+    blobs: &dyn BlobStorage,
+    media_group: Vec<InputMedia>,
+    mut attachments: Vec<Attachment>,
+) -> anyhow::Result<()> {
+    let messages = match bot.send_media_group(chat_id, media_group).await {
+        Ok(messages) => messages,
+        Err(err)
+            if is_stale_file_id(&err)
+                && attachments.iter().any(|a| a.telegram_file_id.is_some()) =>
+        {
+            tracing::warn!("Saved files are rejected, uploading them again: {err}");
+            for att in attachments.iter().filter(|a| a.telegram_file_id.is_some()) {
+                repos.questions.set_telegram_file_id(att, None).await?;
+            }
+            let media_group;
+            (media_group, attachments) = make_media_group(blobs, attachments, true).await;
+            bot.send_media_group(chat_id, media_group).await?
+        }
+        Err(err) => return Err(err.into()),
+    };
+    // messages are in the order of the media group
+    for (att, message) in attachments.iter().zip(messages.iter()) {
+        if let (None, Some(document)) = (&att.telegram_file_id, message.document()) {
+            repos
+                .questions
+                .set_telegram_file_id(att, Some(&document.file.id))
+                .await?;
+        }
     }
-    Ok(KeyboardMarkup::new(keyboard))
+    Ok(())
 }
 
 async fn reply_with_answer(
     bot: Bot,
-    msg: Message,
-    static_dir: Arc<PathBuf>,
+    chat_id: ChatId,
+    repos: &Repositories,
+    blobs: Arc<dyn BlobStorage>,
     question: Question,
+    texts: &Texts,
 ) -> anyhow::Result<()> {
-    let data_v: Vec<String> = question
-        .answer
-        .chars()
-        .chunks(2048)
-        .into_iter()
-        .map(|chunk| chunk.collect::<String>())
-        .collect();
+    let data_v = markdown::to_telegram_chunks(&question.answer, markdown::MESSAGE_MAX_LENGTH);
 
-    let media_group: Vec<InputMedia> = question
-        .attachments
-        .iter()
-        .filter_map(|att| {
-            let filepath = static_dir.join(question.id.to_string()).join(att);
-            if filepath.is_file() {
-                Some(InputMedia::Document(InputMediaDocument::new(
-                    InputFile::file(filepath),
-                )))
-            } else {
-                tracing::error!("File {:#?} is not found!", filepath);
-                None
+    let attachments = repos.questions.get_attachments(question.id).await?;
+    let (media_group, attachments) = make_media_group(blobs.as_ref(), attachments, false).await;
+    // rating buttons go under the last message of the answer, media groups can't have them
+    let keyboard = feedback::feedback_keyboard(question.id, None, texts);
+    let last = data_v.len();
+    for (i, data) in data_v.into_iter().enumerate() {
+        let request = bot.send_message(chat_id, data).parse_mode(ParseMode::Html);
+        if i + 1 == last && media_group.is_empty() {
+            request.reply_markup(keyboard.clone()).await?;
+        } else {
+            request.await?;
+        }
+    }
+    if !media_group.is_empty() || last == 0 {
+        // the rating is asked even when the files are not sent
+        if !media_group.is_empty() {
+            let sent = send_attachments(
+                &bot,
+                chat_id,
+                repos,
+                blobs.as_ref(),
+                media_group,
+                attachments,
+            );
+            if let Err(err) = sent.await {
+                tracing::error!(
+                    "Unable to send the attachments of question {}: {err}",
+                    question.id
+                );
             }
-        })
-        .collect();
-    for data in data_v {
-        bot.send_message(msg.chat.id, data).await?;
+        }
+        bot.send_message(chat_id, texts.was_helpful)
+            .reply_markup(keyboard)
+            .await?;
     }
-    if !media_group.is_empty() {
-        bot.send_media_group(msg.chat.id, media_group).await?;
+    Ok(())
+}
+
+async fn search(
+    bot: Bot,
+    chat_id: ChatId,
+    conn: Arc<Pool>,
+    repos: Repositories,
+    user_id: i64,
+    language: Language,
+    query: &str,
+) -> anyhow::Result<()> {
+    let texts = language.texts();
+    let mut questions = repos
+        .questions
+        .search_public_questions(user_id, language.code(), query, SEARCH_RESULTS_LIMIT)
+        .await?;
+    if questions.is_empty() {
+        let mut menu = make_categories_menu(conn.borrow(), &repos, user_id, language, 0).await?;
+        menu.keyboard = menu
+            .keyboard
+            .append_row(vec![button(texts.ask_human, CallbackData::AskHuman)]);
+        bot.send_message(chat_id, (texts.nothing_found)(query))
+            .reply_markup(menu.keyboard)
+            .await?;
+        return Ok(());
     }
+    translate_questions(conn.borrow(), language, &mut questions).await?;
+    bot.send_message(chat_id, texts.search_results)
+        .reply_markup(make_search_keyboard(&questions, texts))
+        .await?;
     Ok(())
 }
 
-async fn on_question_select(
+async fn on_text(
     bot: Bot,
     msg: Message,
-    dialogue: MyDialogue,
-    category: String,
-    conn: Arc<SqlitePool>,
-    static_dir: Arc<PathBuf>,
+    conn: Arc<Pool>,
+    repos: Repositories,
 ) -> anyhow::Result<()> {
+    let user = msg.from.as_ref().expect("Got message not from a user?");
+    let user_id = user.id.0 as i64;
+    let language = user_language(conn.borrow(), user).await;
     let text = match msg.text() {
         Some(text) => text,
         None => {
-            bot.send_message(msg.chat.id, "Please select the question".to_string())
-                .reply_markup(make_questions_keyboard(conn.borrow(), category.as_str()).await?)
+            let menu = make_categories_menu(conn.borrow(), &repos, user_id, language, 0).await?;
+            bot.send_message(msg.chat.id, menu.text)
+                .reply_markup(menu.keyboard)
                 .await?;
             return Ok(());
         }
     };
-    match text {
-        "Go Back" => {
-            dialogue.reset().await?;
-            bot.send_message(msg.chat.id, "Main menu")
-                .reply_markup(make_categories_keyboard(conn.borrow()).await?)
+    // category names typed by hand (or sent by the old reply keyboard) open the category,
+    // both the default and the translated names are recognized
+    let categories = repos.categories.get_public_categories(user_id).await?;
+    let mut translated = categories.clone();
+    translate_categories(conn.borrow(), language, &mut translated).await?;
+    let category = categories
+        .into_iter()
+        .zip(translated)
+        .find(|(category, translated)| category.name == text || translated.name == text)
+        .map(|(category, _)| category);
+    match category {
+        Some(category) => {
+            let menu =
+                make_category_menu(conn.borrow(), &repos, user_id, language, &category, 0).await?;
+            bot.send_message(msg.chat.id, menu.text)
+                .reply_markup(menu.keyboard)
                 .await?;
+            Ok(())
         }
-        selected_question => {
-            QUESTION_CNTR
-                .with_label_values(&[category.as_str(), selected_question])
-                .inc();
-
-            match get_question_by_category_name(conn.borrow(), selected_question, &category).await {
-                Ok(question) => reply_with_answer(bot, msg, static_dir, question).await?,
-                Err(_) => {
-                    bot.send_message(
-                        msg.chat.id,
-                        format!("Unknown question: {}", selected_question),
-                    )
-                    .reply_markup(make_questions_keyboard(conn.borrow(), category.as_str()).await?)
-                    .await?;
-                }
-            };
+        None => search(bot, msg.chat.id, conn, repos, user_id, language, text).await,
+    }
+}
+
+// Category menu, or the main menu when the category is not available to the user anymore
+#[allow(clippy::too_many_arguments)]
+async fn show_category(
+    bot: &Bot,
+    q: &CallbackQuery,
+    conn: &Pool,
+    repos: &Repositories,
+    user_id: i64,
+    language: Language,
+    id: i64,
+    page: usize,
+) -> anyhow::Result<()> {
+    match repos.categories.get_public_category(user_id, id).await {
+        Ok(category) => {
+            let menu = make_category_menu(conn, repos, user_id, language, &category, page).await?;
+            show_menu(bot, q, menu).await?;
+        }
+        Err(sqlx::Error::RowNotFound) => {
+            let mut menu = make_categories_menu(conn, repos, user_id, language, 0).await?;
+            menu.text = language.texts().category_not_available.to_string();
+            show_menu(bot, q, menu).await?;
         }
+        Err(err) => return Err(err.into()),
     }
     Ok(())
 }
 
-async fn on_category_select(
+async fn on_callback(
     bot: Bot,
-    msg: Message,
-    dialogue: MyDialogue,
-    conn: Arc<SqlitePool>,
+    q: CallbackQuery,
+    data: CallbackData,
+    conn: Arc<Pool>,
+    repos: Repositories,
+    blobs: Arc<dyn BlobStorage>,
+    storage: Arc<ErasedStorage<State>>,
 ) -> anyhow::Result<()> {
-    let category = match msg.text() {
-        Some(text) => text,
-        None => {
-            bot.send_message(msg.chat.id, "Please select the category")
-                .reply_markup(make_categories_keyboard(conn.borrow()).await?)
+    let user_id = q.from.id.0 as i64;
+    let language = user_language(conn.borrow(), &q.from).await;
+    let texts = language.texts();
+    // subscription to the category the user can't see anymore is refused, the menu is updated
+    if let CallbackData::Subscribe { category_id, page } = data {
+        match repos
+            .categories
+            .get_public_category(user_id, category_id)
+            .await
+        {
+            Ok(_) => {}
+            Err(sqlx::Error::RowNotFound) => {
+                bot.answer_callback_query(q.id.clone())
+                    .text(texts.menu_outdated)
+                    .await?;
+                show_category(
+                    &bot,
+                    &q,
+                    conn.borrow(),
+                    &repos,
+                    user_id,
+                    language,
+                    category_id,
+                    page,
+                )
                 .await?;
-            return Ok(());
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
         }
-    };
-    match make_questions_keyboard(conn.borrow(), category).await {
-        Ok(keyboard) => {
-            bot.send_message(msg.chat.id, format!("You chose category {}", category))
-                .reply_markup(keyboard)
-                .await?;
+    }
+    bot.answer_callback_query(q.id.clone()).await?;
+    match data {
+        CallbackData::Categories { page } => {
+            let menu = make_categories_menu(conn.borrow(), &repos, user_id, language, page).await?;
+            show_menu(&bot, &q, menu).await?;
+        }
+        CallbackData::Category { id, page } => {
+            show_category(&bot, &q, conn.borrow(), &repos, user_id, language, id, page).await?;
         }
-        Err(e) => {
-            tracing::warn!("Exception getting category {}", e);
-            bot.send_message(
-                msg.chat.id,
-                format!("Category {} is unknown or has no questions", category),
+        CallbackData::Subscribe { category_id, page } => {
+            subscribe(conn.borrow(), user_id, category_id).await?;
+            show_category(
+                &bot,
+                &q,
+                conn.borrow(),
+                &repos,
+                user_id,
+                language,
+                category_id,
+                page,
             )
             .await?;
-            return Ok(());
+        }
+        CallbackData::Unsubscribe { category_id, page } => {
+            unsubscribe(conn.borrow(), user_id, category_id).await?;
+            show_category(
+                &bot,
+                &q,
+                conn.borrow(),
+                &repos,
+                user_id,
+                language,
+                category_id,
+                page,
+            )
+            .await?;
+        }
+        CallbackData::Question { id } => {
+            match repos.questions.get_public_question(user_id, id).await {
+                Ok(mut question) => {
+                    let category = question
+                        .category
+                        .as_ref()
+                        .map(|c| c.name.clone())
+                        .unwrap_or_default();
+                    // counted by the default text, so the translations don't split the stats
+                    QUESTION_CNTR
+                        .with_label_values(&[category.as_str(), question.question.as_str()])
+                        .inc();
+                    translate_questions(
+                        conn.borrow(),
+                        language,
+                        std::slice::from_mut(&mut question),
+                    )
+                    .await?;
+                    reply_with_answer(bot, q.from.id.into(), &repos, blobs, question, texts)
+                        .await?;
+                }
+                Err(sqlx::Error::RowNotFound) => {
+                    bot.send_message(q.from.id, texts.question_not_available)
+                        .await?;
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+        CallbackData::RequestAccess => {
+            bot.send_message(q.from.id, texts.already_have_access)
+                .await?;
+        }
+        CallbackData::ApproveAccess { user_id } => {
+            access::on_access_decision(bot, &q, conn.borrow(), &repos, user_id, true).await?;
+        }
+        CallbackData::DenyAccess { user_id } => {
+            access::on_access_decision(bot, &q, conn.borrow(), &repos, user_id, false).await?;
+        }
+        CallbackData::AskHuman => {
+            // private chat has the same id as the user
+            let dialogue: MyDialogue = Dialogue::new(storage, q.from.id.into());
+            ask_human(&bot, q.from.id.into(), dialogue, texts).await?;
+        }
+        CallbackData::Feedback {
+            question_id,
+            helpful,
+        } => {
+            feedback::on_feedback(&bot, &q, conn.borrow(), question_id, helpful, texts).await?;
+        }
+        CallbackData::FeedbackComment { question_id } => {
+            let dialogue: MyDialogue = Dialogue::new(storage, q.from.id.into());
+            dialogue
+                .update(State::WritingFeedbackComment { question_id })
+                .await
+                .map_err(anyhow::Error::from_boxed)?;
+            bot.send_message(q.from.id, texts.write_comment).await?;
+        }
+        CallbackData::SetLanguage { language: chosen } => {
+            set_chosen_language(conn.borrow(), user_id, chosen.map(|l| l.code())).await?;
+            let language =
+                Language::resolve(chosen.map(|l| l.code()), q.from.language_code.as_deref());
+            let mut menu =
+                make_categories_menu(conn.borrow(), &repos, user_id, language, 0).await?;
+            menu.text = format!(
+                "{}\n\n{}",
+                (language.texts().language_changed)(language.name()),
+                menu.text
+            );
+            show_menu(&bot, &q, menu).await?;
         }
     }
+    Ok(())
+}
+
+async fn ask_human(
+    bot: &Bot,
+    chat_id: ChatId,
+    dialogue: MyDialogue,
+    texts: &Texts,
+) -> anyhow::Result<()> {
     dialogue
-        .update(State::ShowingQuestions {
-            category: category.to_string(),
-        })
-        .await?;
+        .update(State::AskingHuman)
+        .await
+        .map_err(anyhow::Error::from_boxed)?;
+    bot.send_message(chat_id, texts.describe_question).await?;
     Ok(())
 }
 
+async fn on_human_question(
+    bot: Bot,
+    msg: Message,
+    dialogue: MyDialogue,
+    conn: Arc<Pool>,
+    repos: Repositories,
+) -> anyhow::Result<()> {
+    let user = msg.from.as_ref().expect("Got message not from a user?");
+    let texts = user_language(conn.borrow(), user).await.texts();
+    let Some(text) = msg.text() else {
+        bot.send_message(msg.chat.id, texts.question_not_text)
+            .await?;
+        return Ok(());
+    };
+    support::submit_question(&bot, conn.borrow(), &repos, user.id.0 as i64, text).await?;
+    dialogue.reset().await.map_err(anyhow::Error::from_boxed)?;
+    bot.send_message(msg.chat.id, texts.question_sent).await?;
+    Ok(())
+}
+
+async fn on_feedback_comment(
+    bot: Bot,
+    msg: Message,
+    dialogue: MyDialogue,
+    conn: Arc<Pool>,
+    question_id: i64,
+) -> anyhow::Result<()> {
+    let user = msg.from.as_ref().expect("Got message not from a user?");
+    let texts = user_language(conn.borrow(), user).await.texts();
+    if feedback::on_comment(&bot, &msg, conn.borrow(), question_id, texts).await? {
+        dialogue.reset().await.map_err(anyhow::Error::from_boxed)?;
+    }
+    Ok(())
+}
+
+// Blocked users are skipped by the broadcasts
+async fn set_blocked(conn: &Pool, user: &User, blocked: bool) {
+    match set_user_blocked(conn, user.id.0 as i64, blocked).await {
+        Ok(()) => {}
+        // user is not recorded yet, there is nothing to skip
+        Err(sqlx::Error::Database(err)) if err.is_foreign_key_violation() => {}
+        Err(err) => tracing::warn!("Unable to update blocked status of user {}: {err}", user.id),
+    }
+}
+
 async fn handle_private_chat_member(
     _bot: Bot,
     msg: ChatMemberUpdated,
-    storage: Arc<SqliteStorage<Json>>,
+    conn: Arc<Pool>,
+    storage: Arc<ErasedStorage<State>>,
 ) -> anyhow::Result<()> {
-    let dialogue: Dialogue<State, SqliteStorage<Json>> = Dialogue::new(storage, msg.chat.id);
+    let dialogue: Dialogue<State, ErasedStorage<State>> = Dialogue::new(storage, msg.chat.id);
     match msg.new_chat_member.kind {
         ChatMemberKind::Banned(_) => {
             tracing::info!(
                 "User {:?} has blocked the bot, deleting dialogue state",
                 msg.chat.username()
             );
-            dialogue.exit().await?;
+            dialogue.exit().await.map_err(anyhow::Error::from_boxed)?;
+            set_blocked(conn.borrow(), &msg.from, true).await;
         }
         ChatMemberKind::Member => {
             tracing::info!("New user {:?} connected", msg.from);
+            set_blocked(conn.borrow(), &msg.from, false).await;
         }
         kind => tracing::info!("Unsupported member kind{:?}", kind),
     }
@@ -247,13 +733,32 @@ async fn on_commands(
     msg: Message,
     cmd: Command,
     dialogue: MyDialogue,
-    conn: Arc<SqlitePool>,
+    conn: Arc<Pool>,
+    repos: Repositories,
 ) -> anyhow::Result<()> {
+    let user = msg.from.as_ref().expect("Got message not from a user?");
+    let language = user_language(conn.borrow(), user).await;
+    let texts = language.texts();
     match cmd {
         Command::Start => {
-            dialogue.reset().await?;
-            bot.send_message(msg.chat.id, "Main menu")
-                .reply_markup(make_categories_keyboard(conn.borrow()).await?)
+            dialogue.reset().await.map_err(anyhow::Error::from_boxed)?;
+            // removes the keyboard left by the previous versions of the bot
+            bot.send_message(msg.chat.id, texts.start)
+                .reply_markup(KeyboardRemove::new())
+                .await?;
+            let menu =
+                make_categories_menu(conn.borrow(), &repos, user.id.0 as i64, language, 0).await?;
+            bot.send_message(msg.chat.id, menu.text)
+                .reply_markup(menu.keyboard)
+                .await?
+        }
+        Command::Ask => {
+            ask_human(&bot, msg.chat.id, dialogue, texts).await?;
+            return Ok(());
+        }
+        Command::Language => {
+            bot.send_message(msg.chat.id, texts.select_language)
+                .reply_markup(language::language_keyboard(language))
                 .await?
         }
         Command::Help => {
@@ -265,20 +770,55 @@ async fn on_commands(
     Ok(())
 }
 
-async fn handle_not_authenticated(bot: Bot, msg: Message) -> anyhow::Result<()> {
-    bot.send_message(
-        msg.chat.id,
-        "You are not authorized to use this bot, contact the admin for authentication",
-    )
-    .reply_markup(KeyboardRemove::new())
-    .await?;
+async fn handle_not_authenticated(bot: Bot, msg: Message, conn: Arc<Pool>) -> anyhow::Result<()> {
+    let user = msg.from.expect("Got message not from a user?");
+    let language = user_language(conn.borrow(), &user).await;
+    let (text, keyboard) =
+        access::not_authorized_reply(conn.borrow(), user.id.0 as i64, language).await?;
+    // removes the keyboard left by the previous versions of the bot
+    let request = bot.send_message(msg.chat.id, text);
+    match keyboard {
+        Some(keyboard) => request.reply_markup(keyboard).await?,
+        None => request.reply_markup(KeyboardRemove::new()).await?,
+    };
+    Ok(())
+}
+
+async fn handle_callback_not_authenticated(
+    bot: Bot,
+    q: CallbackQuery,
+    conn: Arc<Pool>,
+    repos: Repositories,
+) -> anyhow::Result<()> {
+    if q.data.as_deref().and_then(|data| data.parse().ok()) == Some(CallbackData::RequestAccess) {
+        return access::on_request_access(bot, q, conn.borrow(), &repos).await;
+    }
+    let language = user_language(conn.borrow(), &q.from).await;
+    let (text, _) =
+        access::not_authorized_reply(conn.borrow(), q.from.id.0 as i64, language).await?;
+    bot.answer_callback_query(q.id)
+        .text(text)
+        .show_alert(true)
+        .await?;
+    Ok(())
+}
+
+// buttons of the menus sent by the older versions of the bot, the spinner is stopped anyway
+async fn handle_outdated_callback(
+    bot: Bot,
+    q: CallbackQuery,
+    conn: Arc<Pool>,
+) -> anyhow::Result<()> {
+    let texts = user_language(conn.borrow(), &q.from).await.texts();
+    bot.answer_callback_query(q.id)
+        .text(texts.menu_outdated)
+        .await?;
     Ok(())
 }
 
 // return true when user is _not_ authenticated
-async fn auth_failed(msg: Message, conn: Arc<SqlitePool>) -> bool {
-    let usr = &msg.from.expect("Got message not from a user?");
-    !auth::auth_user(&conn, usr)
+async fn auth_failed(usr: User, repos: Repositories) -> bool {
+    !auth::auth_user(&repos, &usr)
         .await
         .map_err(|err| {
             tracing::warn!("Unable to authenticate user {:?}: {}", usr, err);
@@ -293,25 +833,44 @@ pub fn make_private_chat_branch(
         .filter_command::<Command>()
         .endpoint(on_commands);
 
-    let dialogues_handler = dptree::entry()
-        .branch(dptree::case![State::ShowingCategories].endpoint(on_category_select))
-        .branch(dptree::case![State::ShowingQuestions { category }].endpoint(on_question_select));
-
     // if user is not authenticated - display "blocked" message
     let auth_handler = dptree::entry()
-        .filter_async(
-            |msg: Message, conn: Arc<SqlitePool>| async move { auth_failed(msg, conn).await },
-        )
+        .filter_async(|msg: Message, repos: Repositories| async move {
+            auth_failed(msg.from.expect("Got message not from a user?"), repos).await
+        })
         .endpoint(handle_not_authenticated);
 
+    // all the states display the inline menu, so the text is handled the same way
     let messages_handler = dptree::entry()
-        .enter_dialogue::<Message, SqliteStorage<Json>, State>()
+        .enter_dialogue::<Message, ErasedStorage<State>, State>()
         .branch(auth_handler)
         .branch(commands_handler)
-        .branch(dialogues_handler);
+        .branch(dptree::case![State::AskingHuman].endpoint(on_human_question))
+        .branch(
+            dptree::case![State::WritingFeedbackComment { question_id }]
+                .endpoint(on_feedback_comment),
+        )
+        .endpoint(on_text);
+
+    let callback_auth_handler = dptree::entry()
+        .filter_async(|q: CallbackQuery, repos: Repositories| async move {
+            auth_failed(q.from, repos).await
+        })
+        .endpoint(handle_callback_not_authenticated);
+
+    let callbacks_handler = dptree::entry()
+        .branch(callback_auth_handler)
+        .branch(
+            dptree::filter_map(|q: CallbackQuery| {
+                q.data.and_then(|data| data.parse::<CallbackData>().ok())
+            })
+            .endpoint(on_callback),
+        )
+        .endpoint(handle_outdated_callback);
 
     dptree::entry()
         .branch(Update::filter_message().chain(messages_handler))
+        .branch(Update::filter_callback_query().chain(callbacks_handler))
         .branch(Update::filter_my_chat_member().endpoint(handle_private_chat_member))
 }
 
@@ -0,0 +1,143 @@
+use std::sync::Arc;
+use teloxide::{
+    dispatching::DpHandlerDescription,
+    prelude::*,
+    types::{MessageId, ParseMode, ReplyParameters},
+};
+
+use crate::db::queries::pending_questions::{
+    answer_pending_question, create_pending_question, get_pending_question,
+    get_pending_question_by_support_message, set_support_message, PendingQuestion,
+};
+use crate::db::{Pool, Repositories};
+use crate::i18n::default_language;
+use crate::markdown;
+
+use super::access::display_name;
+use super::language::stored_language;
+
+// Chat of the support team the questions are forwarded to. Without it the questions
+// can be answered only in the CMS. Messages in the chat are in the default language
+pub fn support_chat_id() -> Option<ChatId> {
+    dotenv::var("SUPPORT_CHAT_ID")
+        .ok()
+        .and_then(|id| id.parse().ok())
+        .map(ChatId)
+}
+
+// Stores the question and forwards it to the support chat
+pub async fn submit_question(
+    bot: &Bot,
+    conn: &Pool,
+    repos: &Repositories,
+    user_id: i64,
+    question: &str,
+) -> anyhow::Result<()> {
+    let id = create_pending_question(conn, user_id, question).await?;
+    let Some(chat_id) = support_chat_id() else {
+        return Ok(());
+    };
+    let user = repos.users.get_user(user_id).await?;
+    let text = (default_language().texts().support_question)(id, &display_name(&user), question);
+    // the question is still available in the CMS
+    match bot.send_message(chat_id, text).await {
+        Ok(message) => set_support_message(conn, id, message.id.0.into()).await?,
+        Err(err) => tracing::error!("Unable to forward question {id} to the support chat: {err}"),
+    }
+    Ok(())
+}
+
+// Sends the answer to the user that asked the question
+pub async fn deliver_answer(
+    bot: &Bot,
+    conn: &Pool,
+    question: &PendingQuestion,
+) -> anyhow::Result<()> {
+    let chat_id = ChatId(question.user_id);
+    let answer = question.answer.as_deref().unwrap_or_default();
+    let texts = stored_language(conn, question.user_id).await.texts();
+    bot.send_message(
+        chat_id,
+        format!(
+            "{}\n<blockquote>{}</blockquote>",
+            texts.answer_header,
+            markdown::escape(&question.question)
+        ),
+    )
+    .parse_mode(ParseMode::Html)
+    .await?;
+    for chunk in markdown::to_telegram_chunks(answer, markdown::MESSAGE_MAX_LENGTH) {
+        bot.send_message(chat_id, chunk)
+            .parse_mode(ParseMode::Html)
+            .await?;
+    }
+    Ok(())
+}
+
+// Lets the support team know the question is answered somewhere else
+pub async fn notify_answered(bot: &Bot, question: &PendingQuestion, answered_by: &str) {
+    let (Some(chat_id), Some(message_id)) = (support_chat_id(), question.support_message_id) else {
+        return;
+    };
+    if let Err(err) = bot
+        .send_message(
+            chat_id,
+            (default_language().texts().support_answered_in_cms)(answered_by),
+        )
+        .reply_parameters(ReplyParameters::new(MessageId(message_id as i32)))
+        .await
+    {
+        tracing::warn!(
+            "Unable to notify the support chat about the answer to question {}: {err}",
+            question.id
+        );
+    }
+}
+
+async fn on_support_reply(bot: Bot, msg: Message, conn: Arc<Pool>) -> anyhow::Result<()> {
+    let Some(reply_to) = msg.reply_to_message() else {
+        return Ok(());
+    };
+    // replies to the other messages are the conversation of the support team
+    let Some(id) = get_pending_question_by_support_message(&conn, reply_to.id.0.into()).await?
+    else {
+        return Ok(());
+    };
+    let texts = default_language().texts();
+    let Some(answer) = msg.text() else {
+        bot.send_message(msg.chat.id, texts.support_answer_not_text)
+            .reply_parameters(ReplyParameters::new(msg.id))
+            .await?;
+        return Ok(());
+    };
+    let answered_by = msg
+        .from
+        .as_ref()
+        .map(|user| user.full_name())
+        .unwrap_or_else(|| "support".to_string());
+    let reply = if !answer_pending_question(&conn, id, answer, &answered_by).await? {
+        texts.support_already_answered.to_string()
+    } else {
+        let question = get_pending_question(&conn, id).await?;
+        match deliver_answer(&bot, &conn, &question).await {
+            Ok(()) => texts.support_answer_sent.to_string(),
+            Err(err) => {
+                tracing::warn!("Unable to deliver the answer to question {id}: {err}");
+                (texts.support_answer_not_delivered)(&err.to_string())
+            }
+        }
+    };
+    bot.send_message(msg.chat.id, reply)
+        .reply_parameters(ReplyParameters::new(msg.id))
+        .await?;
+    Ok(())
+}
+
+pub fn make_support_chat_branch(
+) -> Handler<'static, DependencyMap, anyhow::Result<()>, DpHandlerDescription> {
+    Update::filter_message()
+        .filter(|msg: Message| {
+            support_chat_id() == Some(msg.chat.id) && msg.reply_to_message().is_some()
+        })
+        .endpoint(on_support_reply)
+}
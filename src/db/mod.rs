@@ -1,10 +1,14 @@
 pub mod queries;
+pub mod repositories;
 
-use sqlx::sqlite::SqlitePool;
+use sqlx::migrate::MigrateError;
+use sqlx::postgres::{PgConnection, PgPool, Postgres};
+use sqlx::sqlite::{Sqlite, SqlitePool};
 
 pub use queries::categories::Category;
 pub use queries::questions::Question;
 pub use queries::users::User;
+pub use repositories::Repositories;
 
 use sqlx::Error;
 
@@ -13,17 +17,128 @@ pub struct Reorder {
     pub ordering: i64,
 }
 
-pub async fn establish_connection(path: &str) -> Result<SqlitePool, Error> {
-    SqlitePool::connect(format!("sqlite:{}", path).as_str()).await
+// Database chosen by the scheme of the url. SQLite queries are checked at compile time,
+// the Postgres ones are written next to them and checked by running against the database
+#[derive(Clone, Debug)]
+pub enum Pool {
+    Sqlite(SqlitePool),
+    Postgres(PgPool),
 }
 
-pub async fn run_migrations() -> Result<(), Error> {
-    dotenv::dotenv().ok();
-    let path = dotenv::var("DB_PATH").expect("DB_PATH must be set");
-    let pool = establish_connection(&path).await?;
-    sqlx::migrate!("./migrations").run(&pool).await?;
-    Ok(())
+// Advisory lock shared by the instances using the same Postgres database. It is held
+// by the connection detached from the pool, so it is released when the lock is dropped
+// and closes the connection. SQLite database is used by a single instance and needs no lock
+pub struct DatabaseLock {
+    _connection: Option<PgConnection>,
 }
 
-#[cfg(test)]
-mod tests {}
+// Lock of the blob while its references are counted and the blob is changed. The instances
+// sharing Postgres also take the advisory lock in the transaction, released with it on drop
+pub struct BlobLock {
+    _local: tokio::sync::MutexGuard<'static, ()>,
+    _transaction: Option<sqlx::Transaction<'static, Postgres>>,
+}
+
+// the blobs of the process share a few locks, only one is held at a time
+static BLOB_LOCKS: [tokio::sync::Mutex<()>; 64] = [const { tokio::sync::Mutex::const_new(()) }; 64];
+
+impl BlobLock {
+    pub(crate) async fn local(sha256: &str) -> Self {
+        let index = sha256.bytes().fold(0usize, |hash, b| {
+            hash.wrapping_mul(31).wrapping_add(b as usize)
+        });
+        BlobLock {
+            _local: BLOB_LOCKS[index % BLOB_LOCKS.len()].lock().await,
+            _transaction: None,
+        }
+    }
+}
+
+pub enum Transaction {
+    Sqlite(sqlx::Transaction<'static, Sqlite>),
+    Postgres(sqlx::Transaction<'static, Postgres>),
+}
+
+impl Pool {
+    pub async fn begin(&self) -> Result<Transaction, Error> {
+        Ok(match self {
+            Pool::Sqlite(pool) => Transaction::Sqlite(pool.begin().await?),
+            Pool::Postgres(pool) => Transaction::Postgres(pool.begin().await?),
+        })
+    }
+
+    // Waits until the other instances release the lock with the same key
+    pub async fn lock(&self, key: i64) -> Result<DatabaseLock, Error> {
+        match self {
+            Pool::Sqlite(_) => Ok(DatabaseLock { _connection: None }),
+            Pool::Postgres(pool) => {
+                let mut connection = pool.acquire().await?.detach();
+                sqlx::query("SELECT pg_advisory_lock($1)")
+                    .bind(key)
+                    .execute(&mut connection)
+                    .await?;
+                Ok(DatabaseLock {
+                    _connection: Some(connection),
+                })
+            }
+        }
+    }
+
+    // Waits until the blob is released by this process and the other instances
+    pub async fn lock_blob(&self, sha256: &str) -> Result<BlobLock, Error> {
+        let mut lock = BlobLock::local(sha256).await;
+        if let Pool::Postgres(pool) = self {
+            let mut transaction = pool.begin().await?;
+            // the key space of two keys does not overlap with the one of `lock`
+            sqlx::query("SELECT pg_advisory_xact_lock(1, hashtext($1))")
+                .bind(sha256)
+                .execute(&mut *transaction)
+                .await?;
+            lock._transaction = Some(transaction);
+        }
+        Ok(lock)
+    }
+}
+
+impl Transaction {
+    pub async fn commit(self) -> Result<(), Error> {
+        match self {
+            Transaction::Sqlite(transaction) => transaction.commit().await,
+            Transaction::Postgres(transaction) => transaction.commit().await,
+        }
+    }
+}
+
+pub fn is_postgres(url: &str) -> bool {
+    url.starts_with("postgres:") || url.starts_with("postgresql:")
+}
+
+// DATABASE_URL, or the SQLite database at DB_PATH as before the Postgres support
+pub fn database_url() -> String {
+    dotenv::var("DATABASE_URL").unwrap_or_else(|_| {
+        let path = dotenv::var("DB_PATH").expect("DATABASE_URL or DB_PATH must be set");
+        format!("sqlite:{path}")
+    })
+}
+
+// Path of the SQLite database file from the url, without the options
+pub fn sqlite_path(url: &str) -> &str {
+    let path = url.trim_start_matches("sqlite:").trim_start_matches("//");
+    path.split('?').next().unwrap_or(path)
+}
+
+pub async fn establish_connection(url: &str) -> Result<Pool, Error> {
+    if is_postgres(url) {
+        Ok(Pool::Postgres(PgPool::connect(url).await?))
+    } else {
+        Ok(Pool::Sqlite(SqlitePool::connect(url).await?))
+    }
+}
+
+// Postgres schema is created at once by its own migrations, SQLite one keeps the history
+pub async fn run_migrations(pool: &Pool) -> Result<(), MigrateError> {
+    match pool {
+        Pool::Sqlite(pool) => sqlx::migrate!("./migrations").run(pool).await,
+        Pool::Postgres(pool) => sqlx::migrate!("./migrations/postgres").run(pool).await,
+    }
+}
@@ -0,0 +1,178 @@
+use crate::db::{Pool, Transaction};
+
+#[derive(sqlx::Type, Debug, PartialEq)]
+#[sqlx(type_name = "access_request_status", rename_all = "lowercase")]
+pub enum AccessRequestStatus {
+    Pending,
+    Denied,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct PendingUser {
+    pub id: i64,
+    pub username: Option<String>,
+    pub first_name: String,
+    pub last_name: Option<String>,
+    pub requested_at: String,
+}
+
+pub async fn get_access_request_status(
+    pool: &Pool,
+    user_id: i64,
+) -> sqlx::Result<Option<AccessRequestStatus>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_scalar!(
+                r#"
+                SELECT status as "status: AccessRequestStatus" FROM access_requests WHERE user_id = ?1
+                "#,
+                user_id
+            )
+            .fetch_optional(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_scalar(
+                r#"
+                SELECT status FROM access_requests WHERE user_id = $1
+                "#,
+            )
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await
+        }
+    }
+}
+
+// Returns false when the user has already requested access
+pub async fn create_access_request(pool: &Pool, user_id: i64) -> sqlx::Result<bool> {
+    let result = match pool {
+        Pool::Sqlite(pool) => sqlx::query!(
+            r#"
+            INSERT INTO access_requests (user_id) VALUES (?1) ON CONFLICT(user_id) DO NOTHING
+            "#,
+            user_id
+        )
+        .execute(pool)
+        .await?
+        .rows_affected(),
+        Pool::Postgres(pool) => sqlx::query(
+            r#"
+            INSERT INTO access_requests (user_id) VALUES ($1) ON CONFLICT(user_id) DO NOTHING
+            "#,
+        )
+        .bind(user_id)
+        .execute(pool)
+        .await?
+        .rows_affected(),
+    };
+    Ok(result == 1)
+}
+
+// Activates the user. Returns false when there is no pending request
+pub async fn approve_access_request(pool: &Pool, user_id: i64) -> sqlx::Result<bool> {
+    let mut transaction = pool.begin().await?;
+    let deleted = match &mut transaction {
+        Transaction::Sqlite(transaction) => sqlx::query!(
+            r#"
+            DELETE FROM access_requests WHERE user_id = ?1 AND status = 'pending'
+            "#,
+            user_id
+        )
+        .execute(&mut **transaction)
+        .await?
+        .rows_affected(),
+        Transaction::Postgres(transaction) => sqlx::query(
+            r#"
+            DELETE FROM access_requests WHERE user_id = $1 AND status = 'pending'
+            "#,
+        )
+        .bind(user_id)
+        .execute(&mut **transaction)
+        .await?
+        .rows_affected(),
+    };
+    if deleted == 0 {
+        return Ok(false);
+    }
+    match &mut transaction {
+        Transaction::Sqlite(transaction) => {
+            sqlx::query!(
+                r#"
+                UPDATE users SET active = TRUE WHERE id = ?1
+                "#,
+                user_id
+            )
+            .execute(&mut **transaction)
+            .await?;
+        }
+        Transaction::Postgres(transaction) => {
+            sqlx::query(
+                r#"
+                UPDATE users SET active = TRUE WHERE id = $1
+                "#,
+            )
+            .bind(user_id)
+            .execute(&mut **transaction)
+            .await?;
+        }
+    }
+    transaction.commit().await?;
+    Ok(true)
+}
+
+// Denied request stays, so the user can't request access again.
+// Returns false when there is no pending request
+pub async fn deny_access_request(pool: &Pool, user_id: i64) -> sqlx::Result<bool> {
+    let result = match pool {
+        Pool::Sqlite(pool) => sqlx::query!(
+            r#"
+            UPDATE access_requests SET status = 'denied' WHERE user_id = ?1 AND status = 'pending'
+            "#,
+            user_id
+        )
+        .execute(pool)
+        .await?
+        .rows_affected(),
+        Pool::Postgres(pool) => sqlx::query(
+            r#"
+            UPDATE access_requests SET status = 'denied' WHERE user_id = $1 AND status = 'pending'
+            "#,
+        )
+        .bind(user_id)
+        .execute(pool)
+        .await?
+        .rows_affected(),
+    };
+    Ok(result == 1)
+}
+
+pub async fn get_pending_users(pool: &Pool) -> sqlx::Result<Vec<PendingUser>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                PendingUser,
+                r#"
+                SELECT users.id, users.username, users.first_name, users.last_name, access_requests.requested_at
+                FROM access_requests JOIN users ON users.id = access_requests.user_id
+                WHERE access_requests.status = 'pending' AND users.active = FALSE
+                ORDER BY access_requests.requested_at
+                "#
+            )
+            .fetch_all(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT users.id, users.username, users.first_name, users.last_name, access_requests.requested_at
+                FROM access_requests JOIN users ON users.id = access_requests.user_id
+                WHERE access_requests.status = 'pending' AND users.active = FALSE
+                ORDER BY access_requests.requested_at
+                "#,
+            )
+            .fetch_all(pool)
+            .await
+        }
+    }
+}
@@ -0,0 +1,427 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use utoipa::ToSchema;
+
+use crate::db::{Pool, Transaction};
+
+use super::subscriptions::record_question_change;
+
+// File of the question, the content is the blob with the sha256 hash
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, sqlx::FromRow)]
+pub struct Attachment {
+    pub id: i64,
+    pub question_id: i64,
+    pub sha256: String,
+    pub name: String,
+    pub mime_type: String,
+    pub size: i64,
+    // to send the file without uploading it again, known after the bot sent it once
+    #[serde(skip)]
+    pub telegram_file_id: Option<String>,
+}
+
+// Uploaded file, its content is already in the blobs
+#[derive(Debug, Clone)]
+pub struct NewAttachment {
+    pub name: String,
+    pub sha256: String,
+    pub mime_type: String,
+    pub size: i64,
+}
+
+pub async fn get_attachments(pool: &Pool, question_id: i64) -> sqlx::Result<Vec<Attachment>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                Attachment,
+                r#"
+                SELECT id, question_id, sha256, name, mime_type, size, telegram_file_id
+                FROM attachments
+                WHERE question_id = ?1
+                ORDER BY id
+                "#,
+                question_id
+            )
+            .fetch_all(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT id, question_id, sha256, name, mime_type, size, telegram_file_id
+                FROM attachments
+                WHERE question_id = $1
+                ORDER BY id
+                "#,
+            )
+            .bind(question_id)
+            .fetch_all(pool)
+            .await
+        }
+    }
+}
+
+pub async fn get_attachment(pool: &Pool, question_id: i64, name: &str) -> sqlx::Result<Attachment> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                Attachment,
+                r#"
+                SELECT id, question_id, sha256, name, mime_type, size, telegram_file_id
+                FROM attachments
+                WHERE question_id = ?1 AND name = ?2
+                "#,
+                question_id,
+                name
+            )
+            .fetch_one(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT id, question_id, sha256, name, mime_type, size, telegram_file_id
+                FROM attachments
+                WHERE question_id = $1 AND name = $2
+                "#,
+            )
+            .bind(question_id)
+            .bind(name)
+            .fetch_one(pool)
+            .await
+        }
+    }
+}
+
+pub async fn get_all_attachments(pool: &Pool) -> sqlx::Result<Vec<Attachment>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                Attachment,
+                r#"
+                SELECT id, question_id, sha256, name, mime_type, size, telegram_file_id
+                FROM attachments
+                ORDER BY question_id, id
+                "#
+            )
+            .fetch_all(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT id, question_id, sha256, name, mime_type, size, telegram_file_id
+                FROM attachments
+                ORDER BY question_id, id
+                "#,
+            )
+            .fetch_all(pool)
+            .await
+        }
+    }
+}
+
+async fn insert_attachment(
+    transaction: &mut Transaction,
+    question_id: i64,
+    attachment: &NewAttachment,
+) -> sqlx::Result<i64> {
+    // nothing is inserted when the question does not exist or is in the trash
+    let id: Option<i64> = match &mut *transaction {
+        Transaction::Sqlite(transaction) => {
+            sqlx::query_scalar!(
+                r#"
+                INSERT INTO attachments (question_id, sha256, name, mime_type, size)
+                SELECT id, ?2, ?3, ?4, ?5 FROM questions WHERE id = ?1 AND deleted_at IS NULL
+                RETURNING id
+                "#,
+                question_id,
+                attachment.sha256,
+                attachment.name,
+                attachment.mime_type,
+                attachment.size,
+            )
+            .fetch_optional(&mut **transaction)
+            .await?
+        }
+        Transaction::Postgres(transaction) => {
+            sqlx::query_scalar(
+                r#"
+                INSERT INTO attachments (question_id, sha256, name, mime_type, size)
+                SELECT id, $2, $3, $4, $5 FROM questions WHERE id = $1 AND deleted_at IS NULL
+                RETURNING id
+                "#,
+            )
+            .bind(question_id)
+            .bind(&attachment.sha256)
+            .bind(&attachment.name)
+            .bind(&attachment.mime_type)
+            .bind(attachment.size)
+            .fetch_optional(&mut **transaction)
+            .await?
+        }
+    };
+    id.ok_or(sqlx::Error::RowNotFound)
+}
+
+// Names are unique within the question, the second file with the same name is a conflict
+pub async fn add_attachment(
+    pool: &Pool,
+    question_id: i64,
+    attachment: NewAttachment,
+) -> sqlx::Result<Attachment> {
+    let mut transaction = pool.begin().await?;
+    let id = insert_attachment(&mut transaction, question_id, &attachment).await?;
+    record_question_change(&mut transaction, question_id, false).await?;
+    transaction.commit().await?;
+    Ok(Attachment {
+        id,
+        question_id,
+        sha256: attachment.sha256,
+        name: attachment.name,
+        mime_type: attachment.mime_type,
+        size: attachment.size,
+        telegram_file_id: None,
+    })
+}
+
+// Returns the hash of the blob when no other attachment references it
+pub async fn delete_attachment(
+    pool: &Pool,
+    question_id: i64,
+    name: &str,
+) -> sqlx::Result<Option<String>> {
+    let mut transaction = pool.begin().await?;
+    let sha256: Option<String> = match &mut transaction {
+        Transaction::Sqlite(transaction) => {
+            sqlx::query_scalar!(
+                r#"
+                DELETE FROM attachments WHERE question_id = ?1 AND name = ?2 RETURNING sha256
+                "#,
+                question_id,
+                name
+            )
+            .fetch_optional(&mut **transaction)
+            .await?
+        }
+        Transaction::Postgres(transaction) => {
+            sqlx::query_scalar(
+                r#"
+                DELETE FROM attachments WHERE question_id = $1 AND name = $2 RETURNING sha256
+                "#,
+            )
+            .bind(question_id)
+            .bind(name)
+            .fetch_optional(&mut **transaction)
+            .await?
+        }
+    };
+    let sha256 = sha256.ok_or(sqlx::Error::RowNotFound)?;
+    let unused = unused_blobs(&mut transaction, vec![sha256]).await?;
+    record_question_change(&mut transaction, question_id, false).await?;
+    transaction.commit().await?;
+    Ok(unused.into_iter().next())
+}
+
+// The id is kept only while the attachment has the same content, None forgets it
+pub async fn set_telegram_file_id(
+    pool: &Pool,
+    attachment: &Attachment,
+    file_id: Option<&str>,
+) -> sqlx::Result<()> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query!(
+                r#"
+                UPDATE attachments SET telegram_file_id = ?3 WHERE id = ?1 AND sha256 = ?2
+                "#,
+                attachment.id,
+                attachment.sha256,
+                file_id
+            )
+            .execute(pool)
+            .await?;
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query(
+                r#"
+                UPDATE attachments SET telegram_file_id = $3 WHERE id = $1 AND sha256 = $2
+                "#,
+            )
+            .bind(attachment.id)
+            .bind(&attachment.sha256)
+            .bind(file_id)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+// Hashes of the given blobs that are not referenced by any attachment of the questions
+// or the broadcasts anymore
+pub(crate) async fn unused_blobs(
+    transaction: &mut Transaction,
+    hashes: Vec<String>,
+) -> sqlx::Result<Vec<String>> {
+    let mut unused = vec![];
+    let hashes: HashSet<String> = hashes.into_iter().filter(|h| !h.is_empty()).collect();
+    for sha256 in hashes {
+        let references: i64 = match &mut *transaction {
+            Transaction::Sqlite(transaction) => {
+                sqlx::query_scalar!(
+                    r#"
+                    SELECT (SELECT COUNT(*) FROM attachments WHERE sha256 = ?1)
+                        + (SELECT COUNT(*) FROM broadcast_attachments WHERE sha256 = ?1) as "references!: i64"
+                    "#,
+                    sha256
+                )
+                .fetch_one(&mut **transaction)
+                .await?
+            }
+            Transaction::Postgres(transaction) => {
+                sqlx::query_scalar(
+                    r#"
+                    SELECT (SELECT COUNT(*) FROM attachments WHERE sha256 = $1)
+                        + (SELECT COUNT(*) FROM broadcast_attachments WHERE sha256 = $1)
+                    "#,
+                )
+                .bind(&sha256)
+                .fetch_one(&mut **transaction)
+                .await?
+            }
+        };
+        if references == 0 {
+            unused.push(sha256);
+        }
+    }
+    Ok(unused)
+}
+
+// Replaces the attachments of the imported questions. Returns the blobs that are not used anymore
+pub async fn import_attachments(
+    transaction: &mut Transaction,
+    question_ids: &HashSet<i64>,
+    attachments: Vec<(i64, NewAttachment)>,
+) -> sqlx::Result<Vec<String>> {
+    let mut replaced: Vec<String> = vec![];
+    for question_id in question_ids {
+        let hashes: Vec<String> = match &mut *transaction {
+            Transaction::Sqlite(transaction) => {
+                sqlx::query_scalar!(
+                    r#"
+                    DELETE FROM attachments WHERE question_id = ?1 RETURNING sha256
+                    "#,
+                    question_id
+                )
+                .fetch_all(&mut **transaction)
+                .await?
+            }
+            Transaction::Postgres(transaction) => {
+                sqlx::query_scalar(
+                    r#"
+                    DELETE FROM attachments WHERE question_id = $1 RETURNING sha256
+                    "#,
+                )
+                .bind(question_id)
+                .fetch_all(&mut **transaction)
+                .await?
+            }
+        };
+        replaced.extend(hashes);
+    }
+    for (question_id, attachment) in attachments.iter() {
+        insert_attachment(transaction, *question_id, attachment).await?;
+    }
+    unused_blobs(transaction, replaced).await
+}
+
+// Attachments added before the blobs, their files are still in the directories of the questions
+pub async fn get_unhashed_attachments(pool: &Pool) -> sqlx::Result<Vec<Attachment>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                Attachment,
+                r#"
+                SELECT id, question_id, sha256, name, mime_type, size, telegram_file_id
+                FROM attachments
+                WHERE sha256 = ''
+                "#
+            )
+            .fetch_all(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT id, question_id, sha256, name, mime_type, size, telegram_file_id
+                FROM attachments
+                WHERE sha256 = ''
+                "#,
+            )
+            .fetch_all(pool)
+            .await
+        }
+    }
+}
+
+pub async fn set_attachment_blob(pool: &Pool, id: i64, blob: &NewAttachment) -> sqlx::Result<()> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query!(
+                r#"
+                UPDATE attachments SET sha256 = ?2, mime_type = ?3, size = ?4, telegram_file_id = NULL
+                WHERE id = ?1
+                "#,
+                id,
+                blob.sha256,
+                blob.mime_type,
+                blob.size,
+            )
+            .execute(pool)
+            .await?;
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query(
+                r#"
+                UPDATE attachments SET sha256 = $2, mime_type = $3, size = $4, telegram_file_id = NULL
+                WHERE id = $1
+                "#,
+            )
+            .bind(id)
+            .bind(&blob.sha256)
+            .bind(&blob.mime_type)
+            .bind(blob.size)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+// Attachment which file was lost before it was moved to the blobs
+pub async fn delete_unhashed_attachment(pool: &Pool, id: i64) -> sqlx::Result<()> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query!(
+                r#"
+                DELETE FROM attachments WHERE id = ?1 AND sha256 = ''
+                "#,
+                id
+            )
+            .execute(pool)
+            .await?;
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query(
+                r#"
+                DELETE FROM attachments WHERE id = $1 AND sha256 = ''
+                "#,
+            )
+            .bind(id)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
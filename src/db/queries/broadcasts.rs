@@ -0,0 +1,544 @@
+use crate::db::{Pool, Transaction};
+
+use super::attachments::NewAttachment;
+
+#[derive(sqlx::Type, Debug, PartialEq)]
+#[sqlx(type_name = "delivery_status", rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    Pending,
+    Sent,
+    Blocked,
+    Failed,
+}
+
+// Who receives the broadcast
+pub enum Audience {
+    AllUsers,
+    Group(i64),
+    // subscribers of the category or any of its parents, that can see it
+    CategorySubscribers(i64),
+}
+
+pub struct Broadcast {
+    pub id: i64,
+    pub text: String,
+    pub attachments: Vec<BroadcastAttachment>,
+    pub audience: String,
+    pub created_by: String,
+    pub created_at: String,
+    pub finished_at: Option<String>,
+}
+
+// File of the broadcast, the content is the blob with the sha256 hash
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct BroadcastAttachment {
+    pub id: i64,
+    pub broadcast_id: i64,
+    pub sha256: String,
+    pub name: String,
+    pub mime_type: String,
+    pub size: i64,
+}
+
+// Broadcast with the number of the deliveries in each status
+pub struct BroadcastStats {
+    pub broadcast: Broadcast,
+    pub pending: i64,
+    pub sent: i64,
+    pub blocked: i64,
+    pub failed: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct BroadcastRow {
+    id: i64,
+    text: String,
+    audience: String,
+    created_by: String,
+    created_at: String,
+    finished_at: Option<String>,
+    pending: i64,
+    sent: i64,
+    blocked: i64,
+    failed: i64,
+}
+
+impl From<BroadcastRow> for BroadcastStats {
+    fn from(row: BroadcastRow) -> Self {
+        BroadcastStats {
+            broadcast: Broadcast {
+                id: row.id,
+                text: row.text,
+                attachments: vec![],
+                audience: row.audience,
+                created_by: row.created_by,
+                created_at: row.created_at,
+                finished_at: row.finished_at,
+            },
+            pending: row.pending,
+            sent: row.sent,
+            blocked: row.blocked,
+            failed: row.failed,
+        }
+    }
+}
+
+// Active users that have not blocked the bot
+pub async fn get_recipients(pool: &Pool, audience: &Audience) -> sqlx::Result<Vec<i64>> {
+    match (pool, audience) {
+        (Pool::Sqlite(pool), Audience::AllUsers) => {
+            sqlx::query_scalar!(
+                r#"
+                SELECT id FROM users
+                WHERE active = TRUE AND id NOT IN (SELECT user_id FROM blocked_users)
+                ORDER BY id
+                "#
+            )
+            .fetch_all(pool)
+            .await
+        }
+        (Pool::Sqlite(pool), Audience::Group(group_id)) => {
+            sqlx::query_scalar!(
+                r#"
+                SELECT users.id FROM users JOIN user_groups ON user_groups.user_id = users.id
+                WHERE user_groups.group_id = ?1 AND users.active = TRUE
+                    AND users.id NOT IN (SELECT user_id FROM blocked_users)
+                ORDER BY users.id
+                "#,
+                group_id
+            )
+            .fetch_all(pool)
+            .await
+        }
+        (Pool::Sqlite(pool), Audience::CategorySubscribers(category_id)) => {
+            sqlx::query_scalar!(
+                r#"
+                SELECT DISTINCT users.id FROM users
+                JOIN subscriptions ON subscriptions.user_id = users.id
+                JOIN category_ancestors ON category_ancestors.ancestor_id = subscriptions.category_id
+                JOIN accessible_categories ON accessible_categories.user_id = users.id
+                    AND accessible_categories.category_id = category_ancestors.category_id
+                WHERE category_ancestors.category_id = ?1 AND users.active = TRUE
+                    AND users.id NOT IN (SELECT user_id FROM blocked_users)
+                ORDER BY users.id
+                "#,
+                category_id
+            )
+            .fetch_all(pool)
+            .await
+        }
+        (Pool::Postgres(pool), Audience::AllUsers) => {
+            sqlx::query_scalar(
+                r#"
+                SELECT id FROM users
+                WHERE active = TRUE AND id NOT IN (SELECT user_id FROM blocked_users)
+                ORDER BY id
+                "#,
+            )
+            .fetch_all(pool)
+            .await
+        }
+        (Pool::Postgres(pool), Audience::Group(group_id)) => {
+            sqlx::query_scalar(
+                r#"
+                SELECT users.id FROM users JOIN user_groups ON user_groups.user_id = users.id
+                WHERE user_groups.group_id = $1 AND users.active = TRUE
+                    AND users.id NOT IN (SELECT user_id FROM blocked_users)
+                ORDER BY users.id
+                "#,
+            )
+            .bind(group_id)
+            .fetch_all(pool)
+            .await
+        }
+        (Pool::Postgres(pool), Audience::CategorySubscribers(category_id)) => {
+            sqlx::query_scalar(
+                r#"
+                SELECT DISTINCT users.id FROM users
+                JOIN subscriptions ON subscriptions.user_id = users.id
+                JOIN category_ancestors ON category_ancestors.ancestor_id = subscriptions.category_id
+                JOIN accessible_categories ON accessible_categories.user_id = users.id
+                    AND accessible_categories.category_id = category_ancestors.category_id
+                WHERE category_ancestors.category_id = $1 AND users.active = TRUE
+                    AND users.id NOT IN (SELECT user_id FROM blocked_users)
+                ORDER BY users.id
+                "#,
+            )
+            .bind(category_id)
+            .fetch_all(pool)
+            .await
+        }
+    }
+}
+
+// Creates the broadcast with the pending deliveries to all the recipients
+pub async fn create_broadcast(
+    pool: &Pool,
+    text: &str,
+    attachments: &[NewAttachment],
+    audience: &str,
+    created_by: &str,
+    recipients: &[i64],
+) -> sqlx::Result<i64> {
+    let mut transaction = pool.begin().await?;
+    let id = match &mut transaction {
+        Transaction::Sqlite(transaction) => {
+            let id = sqlx::query!(
+                r#"
+                INSERT INTO broadcasts (text, audience, created_by) VALUES (?1, ?2, ?3)
+                "#,
+                text,
+                audience,
+                created_by
+            )
+            .execute(&mut **transaction)
+            .await?
+            .last_insert_rowid();
+            for attachment in attachments {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO broadcast_attachments (broadcast_id, sha256, name, mime_type, size)
+                    VALUES (?1, ?2, ?3, ?4, ?5)
+                    "#,
+                    id,
+                    attachment.sha256,
+                    attachment.name,
+                    attachment.mime_type,
+                    attachment.size
+                )
+                .execute(&mut **transaction)
+                .await?;
+            }
+            for user_id in recipients {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO broadcast_deliveries (broadcast_id, user_id) VALUES (?1, ?2)
+                    "#,
+                    id,
+                    user_id
+                )
+                .execute(&mut **transaction)
+                .await?;
+            }
+            id
+        }
+        Transaction::Postgres(transaction) => {
+            let id = sqlx::query_scalar(
+                r#"
+                INSERT INTO broadcasts (text, audience, created_by) VALUES ($1, $2, $3)
+                RETURNING id
+                "#,
+            )
+            .bind(text)
+            .bind(audience)
+            .bind(created_by)
+            .fetch_one(&mut **transaction)
+            .await?;
+            for attachment in attachments {
+                sqlx::query(
+                    r#"
+                    INSERT INTO broadcast_attachments (broadcast_id, sha256, name, mime_type, size)
+                    VALUES ($1, $2, $3, $4, $5)
+                    "#,
+                )
+                .bind(id)
+                .bind(&attachment.sha256)
+                .bind(&attachment.name)
+                .bind(&attachment.mime_type)
+                .bind(attachment.size)
+                .execute(&mut **transaction)
+                .await?;
+            }
+            for user_id in recipients {
+                sqlx::query(
+                    r#"
+                    INSERT INTO broadcast_deliveries (broadcast_id, user_id) VALUES ($1, $2)
+                    "#,
+                )
+                .bind(id)
+                .bind(user_id)
+                .execute(&mut **transaction)
+                .await?;
+            }
+            id
+        }
+    };
+    transaction.commit().await?;
+    Ok(id)
+}
+
+async fn get_all_broadcast_attachments(pool: &Pool) -> sqlx::Result<Vec<BroadcastAttachment>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                BroadcastAttachment,
+                r#"
+                SELECT id, broadcast_id, sha256, name, mime_type, size
+                FROM broadcast_attachments
+                ORDER BY id
+                "#
+            )
+            .fetch_all(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT id, broadcast_id, sha256, name, mime_type, size
+                FROM broadcast_attachments
+                ORDER BY id
+                "#,
+            )
+            .fetch_all(pool)
+            .await
+        }
+    }
+}
+
+pub async fn get_broadcasts(pool: &Pool) -> sqlx::Result<Vec<BroadcastStats>> {
+    let rows: Vec<BroadcastRow> = match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                BroadcastRow,
+                r#"
+                SELECT broadcasts.*,
+                    COUNT(*) FILTER (WHERE status = 'pending') as "pending!: i64",
+                    COUNT(*) FILTER (WHERE status = 'sent') as "sent!: i64",
+                    COUNT(*) FILTER (WHERE status = 'blocked') as "blocked!: i64",
+                    COUNT(*) FILTER (WHERE status = 'failed') as "failed!: i64"
+                FROM broadcasts LEFT JOIN broadcast_deliveries ON broadcast_deliveries.broadcast_id = broadcasts.id
+                GROUP BY broadcasts.id
+                ORDER BY broadcasts.id DESC
+                "#
+            )
+            .fetch_all(pool)
+            .await?
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT broadcasts.*,
+                    COUNT(*) FILTER (WHERE status = 'pending') as pending,
+                    COUNT(*) FILTER (WHERE status = 'sent') as sent,
+                    COUNT(*) FILTER (WHERE status = 'blocked') as blocked,
+                    COUNT(*) FILTER (WHERE status = 'failed') as failed
+                FROM broadcasts LEFT JOIN broadcast_deliveries ON broadcast_deliveries.broadcast_id = broadcasts.id
+                GROUP BY broadcasts.id
+                ORDER BY broadcasts.id DESC
+                "#,
+            )
+            .fetch_all(pool)
+            .await?
+        }
+    };
+    let mut broadcasts: Vec<BroadcastStats> = rows.into_iter().map(BroadcastStats::from).collect();
+    for attachment in get_all_broadcast_attachments(pool).await? {
+        if let Some(stats) = broadcasts
+            .iter_mut()
+            .find(|stats| stats.broadcast.id == attachment.broadcast_id)
+        {
+            stats.broadcast.attachments.push(attachment);
+        }
+    }
+    Ok(broadcasts)
+}
+
+pub async fn get_broadcast(pool: &Pool, id: i64) -> sqlx::Result<BroadcastStats> {
+    get_broadcasts(pool)
+        .await?
+        .into_iter()
+        .find(|stats| stats.broadcast.id == id)
+        .ok_or(sqlx::Error::RowNotFound)
+}
+
+pub async fn get_unfinished_broadcasts(pool: &Pool) -> sqlx::Result<Vec<i64>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_scalar!(
+                r#"
+                SELECT id FROM broadcasts WHERE finished_at IS NULL ORDER BY id
+                "#
+            )
+            .fetch_all(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_scalar(
+                r#"
+                SELECT id FROM broadcasts WHERE finished_at IS NULL ORDER BY id
+                "#,
+            )
+            .fetch_all(pool)
+            .await
+        }
+    }
+}
+
+pub async fn get_pending_deliveries(pool: &Pool, broadcast_id: i64) -> sqlx::Result<Vec<i64>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_scalar!(
+                r#"
+                SELECT user_id FROM broadcast_deliveries
+                WHERE broadcast_id = ?1 AND status = 'pending'
+                ORDER BY user_id
+                "#,
+                broadcast_id
+            )
+            .fetch_all(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_scalar(
+                r#"
+                SELECT user_id FROM broadcast_deliveries
+                WHERE broadcast_id = $1 AND status = 'pending'
+                ORDER BY user_id
+                "#,
+            )
+            .bind(broadcast_id)
+            .fetch_all(pool)
+            .await
+        }
+    }
+}
+
+pub async fn set_delivery_status(
+    pool: &Pool,
+    broadcast_id: i64,
+    user_id: i64,
+    status: DeliveryStatus,
+    error: Option<&str>,
+) -> sqlx::Result<()> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query!(
+                r#"
+                UPDATE broadcast_deliveries SET status = ?1, error = ?2 WHERE broadcast_id = ?3 AND user_id = ?4
+                "#,
+                status,
+                error,
+                broadcast_id,
+                user_id
+            )
+            .execute(pool)
+            .await?;
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query(
+                r#"
+                UPDATE broadcast_deliveries SET status = $1, error = $2 WHERE broadcast_id = $3 AND user_id = $4
+                "#,
+            )
+            .bind(status)
+            .bind(error)
+            .bind(broadcast_id)
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+// Broadcast resumed by several instances is finished by the first of them
+pub async fn finish_broadcast(pool: &Pool, id: i64) -> sqlx::Result<()> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query!(
+                r#"
+                UPDATE broadcasts SET finished_at = CURRENT_TIMESTAMP WHERE id = ?1 AND finished_at IS NULL
+                "#,
+                id
+            )
+            .execute(pool)
+            .await?;
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query(
+                r#"
+                UPDATE broadcasts SET finished_at = utc_timestamp() WHERE id = $1 AND finished_at IS NULL
+                "#,
+            )
+            .bind(id)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+// Attachments of the broadcasts created before the blobs, see `storage::migrate_broadcast_files`
+pub async fn get_unhashed_broadcast_attachments(
+    pool: &Pool,
+) -> sqlx::Result<Vec<BroadcastAttachment>> {
+    Ok(get_all_broadcast_attachments(pool)
+        .await?
+        .into_iter()
+        .filter(|attachment| attachment.sha256.is_empty())
+        .collect())
+}
+
+pub async fn set_broadcast_attachment_blob(
+    pool: &Pool,
+    id: i64,
+    blob: &NewAttachment,
+) -> sqlx::Result<()> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query!(
+                r#"
+                UPDATE broadcast_attachments SET sha256 = ?2, mime_type = ?3, size = ?4
+                WHERE id = ?1
+                "#,
+                id,
+                blob.sha256,
+                blob.mime_type,
+                blob.size
+            )
+            .execute(pool)
+            .await?;
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query(
+                r#"
+                UPDATE broadcast_attachments SET sha256 = $2, mime_type = $3, size = $4
+                WHERE id = $1
+                "#,
+            )
+            .bind(id)
+            .bind(&blob.sha256)
+            .bind(&blob.mime_type)
+            .bind(blob.size)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn delete_unhashed_broadcast_attachment(pool: &Pool, id: i64) -> sqlx::Result<()> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query!(
+                r#"
+                DELETE FROM broadcast_attachments WHERE id = ?1 AND sha256 = ''
+                "#,
+                id
+            )
+            .execute(pool)
+            .await?;
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query(
+                r#"
+                DELETE FROM broadcast_attachments WHERE id = $1 AND sha256 = ''
+                "#,
+            )
+            .bind(id)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
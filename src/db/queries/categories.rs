@@ -1,145 +1,644 @@
 use serde::{Deserialize, Serialize};
-use sqlx::SqlitePool;
 use std::collections::HashSet;
+use utoipa::ToSchema;
 
-use crate::db::Reorder;
+use crate::db::{Pool, Reorder, Transaction};
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+use super::revisions::{get_state, record_revision, Entity};
+
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema, sqlx::FromRow)]
 pub struct Category {
     pub id: i64,
     pub name: String,
     pub hidden: bool,
     pub ordering: i64,
+    pub parent: Option<i64>,
 }
 
-pub async fn get_category(pool: &SqlitePool, id: i64) -> sqlx::Result<Category> {
-    sqlx::query_as!(
-        Category,
-        r#"
-        SELECT * FROM categories WHERE categories.id = ?1
-        "#,
-        id
-    )
-    .fetch_one(pool)
-    .await
+pub async fn get_category(pool: &Pool, id: i64) -> sqlx::Result<Category> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                Category,
+                r#"
+                SELECT id, name, hidden, ordering, parent FROM categories
+                WHERE categories.id = ?1 AND deleted_at IS NULL
+                "#,
+                id
+            )
+            .fetch_one(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT id, name, hidden, ordering, parent FROM categories
+                WHERE categories.id = $1 AND deleted_at IS NULL
+                "#,
+            )
+            .bind(id)
+            .fetch_one(pool)
+            .await
+        }
+    }
 }
 
 pub async fn create_category(
-    pool: &SqlitePool,
+    pool: &Pool,
     name: &str,
     hidden: bool,
     ordering: i64,
+    parent: Option<i64>,
+    author: &str,
 ) -> sqlx::Result<i64> {
-    let mut conn = pool.acquire().await?;
-
-    let id = sqlx::query!(
-        r#"
-INSERT INTO categories (name, hidden, ordering) VALUES (?1, ?2, ?3)
-        "#,
-        name,
-        hidden,
-        ordering,
-    )
-    .execute(&mut *conn)
-    .await?
-    .last_insert_rowid();
+    let mut transaction = pool.begin().await?;
+
+    let id = match &mut transaction {
+        Transaction::Sqlite(transaction) => sqlx::query!(
+            r#"
+            INSERT INTO categories (name, hidden, ordering, parent) VALUES (?1, ?2, ?3, ?4)
+            "#,
+            name,
+            hidden,
+            ordering,
+            parent,
+        )
+        .execute(&mut **transaction)
+        .await?
+        .last_insert_rowid(),
+        Transaction::Postgres(transaction) => {
+            sqlx::query_scalar(
+                r#"
+                INSERT INTO categories (name, hidden, ordering, parent) VALUES ($1, $2, $3, $4)
+                RETURNING id
+                "#,
+            )
+            .bind(name)
+            .bind(hidden)
+            .bind(ordering)
+            .bind(parent)
+            .fetch_one(&mut **transaction)
+            .await?
+        }
+    };
+    record_revision(&mut transaction, Entity::Category, id, None, author).await?;
+    transaction.commit().await?;
 
     Ok(id)
 }
 
 pub async fn update_category(
-    pool: &SqlitePool,
+    pool: &Pool,
     id: i64,
     name: String,
     hidden: bool,
+    parent: Option<i64>,
+    author: &str,
 ) -> sqlx::Result<()> {
-    let mut conn = pool.acquire().await?;
-
-    sqlx::query!(
-        r#"
-        UPDATE categories SET name=?1, hidden=?2 WHERE categories.id = ?3
-        "#,
-        name,
-        hidden,
-        id,
-    )
-    .execute(&mut *conn)
-    .await?;
+    let mut transaction = pool.begin().await?;
+    let old = get_state(&mut transaction, Entity::Category, id).await?;
+
+    match &mut transaction {
+        Transaction::Sqlite(transaction) => {
+            sqlx::query!(
+                r#"
+                UPDATE categories SET name=?1, hidden=?2, parent=?3 WHERE categories.id = ?4 AND deleted_at IS NULL
+                "#,
+                name,
+                hidden,
+                parent,
+                id,
+            )
+            .execute(&mut **transaction)
+            .await?;
+        }
+        Transaction::Postgres(transaction) => {
+            sqlx::query(
+                r#"
+                UPDATE categories SET name=$1, hidden=$2, parent=$3 WHERE categories.id = $4 AND deleted_at IS NULL
+                "#,
+            )
+            .bind(name)
+            .bind(hidden)
+            .bind(parent)
+            .bind(id)
+            .execute(&mut **transaction)
+            .await?;
+        }
+    }
+    record_revision(&mut transaction, Entity::Category, id, old, author).await?;
+    transaction.commit().await?;
     Ok(())
 }
 
-pub async fn get_all_categories(pool: &SqlitePool) -> sqlx::Result<Vec<Category>> {
-    sqlx::query_as!(
-        Category,
-        r#"
-        SELECT * FROM categories ORDER BY ordering, id DESC
-        "#
-    )
-    .fetch_all(pool)
-    .await
-}
-
-pub async fn get_public_categories(pool: &SqlitePool) -> sqlx::Result<Vec<Category>> {
-    sqlx::query_as!(
-        Category,
-        r#"
-        SELECT * FROM categories WHERE hidden = FALSE ORDER BY ordering, id DESC
-        "#
-    )
-    .fetch_all(pool)
-    .await
-}
-
-pub async fn delete_category(pool: &SqlitePool, category_id: i64) -> sqlx::Result<()> {
-    let mut conn = pool.acquire().await?;
-
-    sqlx::query!(
-        r#"
-        DELETE FROM categories WHERE categories.id = ?1
-        "#,
-        category_id,
-    )
-    .execute(&mut *conn)
-    .await?;
+pub async fn get_all_categories(pool: &Pool) -> sqlx::Result<Vec<Category>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                Category,
+                r#"
+                SELECT id, name, hidden, ordering, parent FROM categories WHERE deleted_at IS NULL
+                ORDER BY ordering, id DESC
+                "#
+            )
+            .fetch_all(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT id, name, hidden, ordering, parent FROM categories WHERE deleted_at IS NULL
+                ORDER BY ordering, id DESC
+                "#,
+            )
+            .fetch_all(pool)
+            .await
+        }
+    }
+}
+
+// Public categories available to the user according to the groups of the user
+pub async fn get_public_categories(pool: &Pool, user_id: i64) -> sqlx::Result<Vec<Category>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                Category,
+                r#"
+                SELECT categories.id, categories.name, categories.hidden, categories.ordering, categories.parent
+                FROM categories
+                JOIN accessible_categories ON categories.id = accessible_categories.category_id
+                WHERE accessible_categories.user_id = ?1
+                ORDER BY ordering, id DESC
+                "#,
+                user_id
+            )
+            .fetch_all(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT categories.id, categories.name, categories.hidden, categories.ordering, categories.parent
+                FROM categories
+                JOIN accessible_categories ON categories.id = accessible_categories.category_id
+                WHERE accessible_categories.user_id = $1
+                ORDER BY ordering, id DESC
+                "#,
+            )
+            .bind(user_id)
+            .fetch_all(pool)
+            .await
+        }
+    }
+}
+
+pub async fn get_public_subcategories(
+    pool: &Pool,
+    user_id: i64,
+    parent: Option<i64>,
+) -> sqlx::Result<Vec<Category>> {
+    Ok(get_public_categories(pool, user_id)
+        .await?
+        .into_iter()
+        .filter(|category| category.parent == parent)
+        .collect())
+}
+
+pub async fn get_public_category(pool: &Pool, user_id: i64, id: i64) -> sqlx::Result<Category> {
+    get_public_categories(pool, user_id)
+        .await?
+        .into_iter()
+        .find(|category| category.id == id)
+        .ok_or(sqlx::Error::RowNotFound)
+}
+
+// Category itself and all of its parents, starting from the top level one
+pub async fn get_category_path(pool: &Pool, id: i64) -> sqlx::Result<Vec<Category>> {
+    let path = match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                Category,
+                r#"
+                WITH RECURSIVE path(id, depth) AS (
+                    SELECT id, 0 FROM categories WHERE id = ?1 AND deleted_at IS NULL
+                    UNION ALL
+                    SELECT categories.parent, path.depth + 1 FROM categories JOIN path ON categories.id = path.id
+                    WHERE categories.parent IS NOT NULL
+                )
+                SELECT categories.id, categories.name, categories.hidden, categories.ordering, categories.parent
+                FROM categories JOIN path ON categories.id = path.id
+                ORDER BY path.depth DESC
+                "#,
+                id
+            )
+            .fetch_all(pool)
+            .await?
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                WITH RECURSIVE path(id, depth) AS (
+                    SELECT id, 0 FROM categories WHERE id = $1 AND deleted_at IS NULL
+                    UNION ALL
+                    SELECT categories.parent, path.depth + 1 FROM categories JOIN path ON categories.id = path.id
+                    WHERE categories.parent IS NOT NULL
+                )
+                SELECT categories.id, categories.name, categories.hidden, categories.ordering, categories.parent
+                FROM categories JOIN path ON categories.id = path.id
+                ORDER BY path.depth DESC
+                "#,
+            )
+            .bind(id)
+            .fetch_all(pool)
+            .await?
+        }
+    };
+    if path.is_empty() {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    Ok(path)
+}
+
+// Ids of the category and everything nested in it
+pub async fn get_subtree_ids(pool: &Pool, id: i64) -> sqlx::Result<HashSet<i64>> {
+    let ids: Vec<i64> = match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_scalar!(
+                r#"
+                WITH RECURSIVE subtree(id) AS (
+                    SELECT id FROM categories WHERE id = ?1
+                    UNION ALL
+                    SELECT categories.id FROM categories JOIN subtree ON categories.parent = subtree.id
+                )
+                SELECT id as "id!" FROM subtree
+                "#,
+                id
+            )
+            .fetch_all(pool)
+            .await?
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_scalar(
+                r#"
+                WITH RECURSIVE subtree(id) AS (
+                    SELECT id FROM categories WHERE id = $1
+                    UNION ALL
+                    SELECT categories.id FROM categories JOIN subtree ON categories.parent = subtree.id
+                )
+                SELECT id FROM subtree
+                "#,
+            )
+            .bind(id)
+            .fetch_all(pool)
+            .await?
+        }
+    };
+    Ok(ids.into_iter().collect())
+}
+
+// Orders categories so every category is followed by its children, each one paired with
+// its depth in the tree. Siblings keep the order they had in the input
+pub fn flatten_tree(categories: Vec<Category>) -> Vec<(usize, Category)> {
+    fn visit(
+        parent: i64,
+        depth: usize,
+        categories: &[Category],
+        result: &mut Vec<(usize, Category)>,
+    ) {
+        for category in categories.iter().filter(|c| c.parent == Some(parent)) {
+            result.push((depth, category.clone()));
+            visit(category.id, depth + 1, categories, result);
+        }
+    }
+    let ids: HashSet<i64> = categories.iter().map(|c| c.id).collect();
+    let mut result = Vec::with_capacity(categories.len());
+    // categories with the parent missing from the input are displayed at the top level
+    for category in categories
+        .iter()
+        .filter(|c| c.parent.is_none_or(|p| !ids.contains(&p)))
+    {
+        result.push((0, category.clone()));
+        visit(category.id, 1, &categories, &mut result);
+    }
+    result
+}
+
+// Finds the category by its name and the names of its parents, creating the missing ones
+pub async fn get_or_create_category_by_path(
+    transaction: &mut Transaction,
+    path: &[&str],
+    author: &str,
+) -> sqlx::Result<i64> {
+    let mut parent: Option<i64> = None;
+    for name in path {
+        let existing = match &mut *transaction {
+            Transaction::Sqlite(transaction) => {
+                sqlx::query_scalar!(
+                    r#"
+                    SELECT id FROM categories WHERE name = ?1 AND parent IS ?2 AND deleted_at IS NULL
+                    ORDER BY id LIMIT 1
+                    "#,
+                    name,
+                    parent,
+                )
+                .fetch_optional(&mut **transaction)
+                .await?
+            }
+            Transaction::Postgres(transaction) => {
+                sqlx::query_scalar(
+                    r#"
+                    SELECT id FROM categories WHERE name = $1 AND parent IS NOT DISTINCT FROM $2 AND deleted_at IS NULL
+                    ORDER BY id LIMIT 1
+                    "#,
+                )
+                .bind(name)
+                .bind(parent)
+                .fetch_optional(&mut **transaction)
+                .await?
+            }
+        };
+        let id = match existing {
+            Some(id) => id,
+            None => {
+                let id = match &mut *transaction {
+                    Transaction::Sqlite(transaction) => sqlx::query!(
+                        r#"
+                        INSERT INTO categories (name, parent) VALUES (?1, ?2)
+                        "#,
+                        name,
+                        parent,
+                    )
+                    .execute(&mut **transaction)
+                    .await?
+                    .last_insert_rowid(),
+                    Transaction::Postgres(transaction) => {
+                        sqlx::query_scalar(
+                            r#"
+                            INSERT INTO categories (name, parent) VALUES ($1, $2) RETURNING id
+                            "#,
+                        )
+                        .bind(name)
+                        .bind(parent)
+                        .fetch_one(&mut **transaction)
+                        .await?
+                    }
+                };
+                record_revision(transaction, Entity::Category, id, None, author).await?;
+                id
+            }
+        };
+        parent = Some(id);
+    }
+    parent.ok_or(sqlx::Error::RowNotFound)
+}
+
+pub async fn delete_category(pool: &Pool, category_id: i64, author: &str) -> sqlx::Result<()> {
+    let mut transaction = pool.begin().await?;
+    let old = delete_with_revisions(&mut transaction, category_id, author).await?;
+    record_revision(&mut transaction, Entity::Category, category_id, old, author).await?;
+    transaction.commit().await?;
     Ok(())
 }
 
-pub async fn reorder_categories(pool: &SqlitePool, categories: Vec<Reorder>) -> sqlx::Result<()> {
+// Moves the category to the trash. Its subcategories are moved to the top level and its questions
+// are left without the category until it is restored, the revisions of them are recorded.
+// Returns the state of the category before the deletion
+async fn delete_with_revisions(
+    transaction: &mut Transaction,
+    category_id: i64,
+    author: &str,
+) -> sqlx::Result<Option<String>> {
+    let old = get_state(transaction, Entity::Category, category_id).await?;
+    let (subcategories, questions): (Vec<i64>, Vec<i64>) = match &mut *transaction {
+        Transaction::Sqlite(transaction) => {
+            let subcategories = sqlx::query_scalar!(
+                r#"
+                SELECT id FROM categories WHERE parent = ?1
+                "#,
+                category_id
+            )
+            .fetch_all(&mut **transaction)
+            .await?;
+            let questions = sqlx::query_scalar!(
+                r#"
+                SELECT id FROM questions WHERE category = ?1
+                "#,
+                category_id
+            )
+            .fetch_all(&mut **transaction)
+            .await?;
+            (subcategories, questions)
+        }
+        Transaction::Postgres(transaction) => {
+            let subcategories = sqlx::query_scalar(
+                r#"
+                SELECT id FROM categories WHERE parent = $1
+                "#,
+            )
+            .bind(category_id)
+            .fetch_all(&mut **transaction)
+            .await?;
+            let questions = sqlx::query_scalar(
+                r#"
+                SELECT id FROM questions WHERE category = $1
+                "#,
+            )
+            .bind(category_id)
+            .fetch_all(&mut **transaction)
+            .await?;
+            (subcategories, questions)
+        }
+    };
+    let mut affected = vec![];
+    for id in subcategories {
+        affected.push((Entity::Category, id));
+    }
+    for id in questions {
+        affected.push((Entity::Question, id));
+    }
+    let mut states = vec![];
+    for (entity, id) in affected {
+        states.push((entity, id, get_state(transaction, entity, id).await?));
+    }
+
+    match &mut *transaction {
+        Transaction::Sqlite(transaction) => {
+            sqlx::query!(
+                r#"
+                UPDATE categories SET parent = NULL, detached_from = ?1 WHERE parent = ?1
+                "#,
+                category_id,
+            )
+            .execute(&mut **transaction)
+            .await?;
+            sqlx::query!(
+                r#"
+                UPDATE questions SET category = NULL, detached_from = ?1 WHERE category = ?1
+                "#,
+                category_id,
+            )
+            .execute(&mut **transaction)
+            .await?;
+            sqlx::query!(
+                r#"
+                UPDATE categories SET deleted_at = CURRENT_TIMESTAMP, deleted_by = ?2
+                WHERE categories.id = ?1 AND deleted_at IS NULL
+                "#,
+                category_id,
+                author,
+            )
+            .execute(&mut **transaction)
+            .await?;
+        }
+        Transaction::Postgres(transaction) => {
+            sqlx::query(
+                r#"
+                UPDATE categories SET parent = NULL, detached_from = $1 WHERE parent = $1
+                "#,
+            )
+            .bind(category_id)
+            .execute(&mut **transaction)
+            .await?;
+            sqlx::query(
+                r#"
+                UPDATE questions SET category = NULL, detached_from = $1 WHERE category = $1
+                "#,
+            )
+            .bind(category_id)
+            .execute(&mut **transaction)
+            .await?;
+            sqlx::query(
+                r#"
+                UPDATE categories SET deleted_at = utc_timestamp(), deleted_by = $2
+                WHERE categories.id = $1 AND deleted_at IS NULL
+                "#,
+            )
+            .bind(category_id)
+            .bind(author)
+            .execute(&mut **transaction)
+            .await?;
+        }
+    }
+    for (entity, id, state) in states {
+        record_revision(transaction, entity, id, state, author).await?;
+    }
+    Ok(old)
+}
+
+pub async fn reorder_categories(pool: &Pool, categories: Vec<Reorder>) -> sqlx::Result<()> {
     let mut transaction = pool.begin().await?;
     for category in categories {
-        sqlx::query!(
-            r#"
-            UPDATE categories SET ordering=?1 WHERE categories.id = ?2
-            "#,
-            category.ordering,
-            category.id,
-        )
-        .execute(&mut *transaction)
-        .await?;
+        match &mut transaction {
+            Transaction::Sqlite(transaction) => {
+                sqlx::query!(
+                    r#"
+                    UPDATE categories SET ordering=?1 WHERE categories.id = ?2
+                    "#,
+                    category.ordering,
+                    category.id,
+                )
+                .execute(&mut **transaction)
+                .await?;
+            }
+            Transaction::Postgres(transaction) => {
+                sqlx::query(
+                    r#"
+                    UPDATE categories SET ordering=$1 WHERE categories.id = $2
+                    "#,
+                )
+                .bind(category.ordering)
+                .bind(category.id)
+                .execute(&mut **transaction)
+                .await?;
+            }
+        }
     }
     transaction.commit().await?;
     Ok(())
 }
 
-pub async fn import_categories(pool: &SqlitePool, categories: Vec<Category>) -> sqlx::Result<()> {
-    let existing_categories = get_all_categories(pool).await?;
-    let existing_categories_ids: HashSet<i64> = existing_categories.iter().map(|c| c.id).collect();
+// Replaces all the categories with the given ones, ids are preserved and the missing ones
+// are moved to the trash
+pub async fn import_categories(
+    transaction: &mut Transaction,
+    categories: Vec<Category>,
+    author: &str,
+) -> sqlx::Result<()> {
     let new_categories_ids: HashSet<i64> = categories.iter().map(|c| c.id).collect();
-    for category_id in existing_categories_ids.difference(&new_categories_ids) {
-        delete_category(pool, *category_id).await?;
+    let existing_categories_ids: Vec<i64> = match &mut *transaction {
+        Transaction::Sqlite(transaction) => {
+            sqlx::query_scalar!(
+                r#"
+                SELECT id FROM categories WHERE deleted_at IS NULL
+                "#
+            )
+            .fetch_all(&mut **transaction)
+            .await?
+        }
+        Transaction::Postgres(transaction) => {
+            sqlx::query_scalar(
+                r#"
+                SELECT id FROM categories WHERE deleted_at IS NULL
+                "#,
+            )
+            .fetch_all(&mut **transaction)
+            .await?
+        }
+    };
+    for category_id in existing_categories_ids
+        .into_iter()
+        .filter(|id| !new_categories_ids.contains(id))
+    {
+        let old = delete_with_revisions(transaction, category_id, author).await?;
+        record_revision(transaction, Entity::Category, category_id, old, author).await?;
     }
     for category in categories {
-        if existing_categories_ids.contains(&category.id) {
-            update_category(pool, category.id, category.name, category.hidden).await?;
-        } else {
-            create_category(
-                pool,
-                category.name.as_str(),
-                category.hidden,
-                category.ordering,
-            )
-            .await?;
+        let old = get_state(transaction, Entity::Category, category.id).await?;
+        match &mut *transaction {
+            Transaction::Sqlite(transaction) => {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO categories (id, name, hidden, ordering, parent) VALUES (?1, ?2, ?3, ?4, ?5)
+                    ON CONFLICT(id) DO UPDATE SET name=excluded.name, hidden=excluded.hidden, ordering=excluded.ordering, parent=excluded.parent,
+                        deleted_at=NULL, deleted_by=NULL
+                    "#,
+                    category.id,
+                    category.name,
+                    category.hidden,
+                    category.ordering,
+                    category.parent,
+                )
+                .execute(&mut **transaction)
+                .await?;
+            }
+            Transaction::Postgres(transaction) => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO categories (id, name, hidden, ordering, parent) VALUES ($1, $2, $3, $4, $5)
+                    ON CONFLICT(id) DO UPDATE SET name=excluded.name, hidden=excluded.hidden, ordering=excluded.ordering, parent=excluded.parent,
+                        deleted_at=NULL, deleted_by=NULL
+                    "#,
+                )
+                .bind(category.id)
+                .bind(category.name)
+                .bind(category.hidden)
+                .bind(category.ordering)
+                .bind(category.parent)
+                .execute(&mut **transaction)
+                .await?;
+            }
         }
+        record_revision(transaction, Entity::Category, category.id, old, author).await?;
+    }
+    // the ids are set explicitly, so the new categories continue after the imported ones
+    if let Transaction::Postgres(transaction) = transaction {
+        sqlx::query(
+            r#"
+            SELECT setval(pg_get_serial_sequence('categories', 'id'), COALESCE(MAX(id), 0) + 1, false) FROM categories
+            "#,
+        )
+        .execute(&mut **transaction)
+        .await?;
     }
     Ok(())
 }
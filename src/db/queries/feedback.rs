@@ -0,0 +1,185 @@
+use crate::db::Pool;
+
+// Feedback on the answer to the question, aggregated over all the users
+#[derive(sqlx::FromRow)]
+pub struct FeedbackStats {
+    pub question_id: i64,
+    pub question: String,
+    pub category: Option<String>,
+    pub helpful: i64,
+    pub unhelpful: i64,
+    pub comments: i64,
+    pub updated_at: String,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct FeedbackComment {
+    pub first_name: String,
+    pub last_name: Option<String>,
+    pub username: Option<String>,
+    pub helpful: bool,
+    pub comment: String,
+    pub updated_at: String,
+}
+
+// Rating replaces the previous one of the user, the comment is kept
+pub async fn set_feedback(
+    pool: &Pool,
+    question_id: i64,
+    user_id: i64,
+    helpful: bool,
+) -> sqlx::Result<()> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query!(
+                r#"
+                INSERT INTO feedback (question_id, user_id, helpful) VALUES (?1, ?2, ?3)
+                ON CONFLICT(question_id, user_id) DO UPDATE SET helpful=excluded.helpful, updated_at=CURRENT_TIMESTAMP
+                "#,
+                question_id,
+                user_id,
+                helpful
+            )
+            .execute(pool)
+            .await?;
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query(
+                r#"
+                INSERT INTO feedback (question_id, user_id, helpful) VALUES ($1, $2, $3)
+                ON CONFLICT(question_id, user_id) DO UPDATE SET helpful=excluded.helpful, updated_at=utc_timestamp()
+                "#,
+            )
+            .bind(question_id)
+            .bind(user_id)
+            .bind(helpful)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+// Returns false when the user has not rated the answer
+pub async fn set_feedback_comment(
+    pool: &Pool,
+    question_id: i64,
+    user_id: i64,
+    comment: &str,
+) -> sqlx::Result<bool> {
+    let result = match pool {
+        Pool::Sqlite(pool) => sqlx::query!(
+            r#"
+            UPDATE feedback SET comment = ?1, updated_at = CURRENT_TIMESTAMP
+            WHERE question_id = ?2 AND user_id = ?3
+            "#,
+            comment,
+            question_id,
+            user_id
+        )
+        .execute(pool)
+        .await?
+        .rows_affected(),
+        Pool::Postgres(pool) => sqlx::query(
+            r#"
+            UPDATE feedback SET comment = $1, updated_at = utc_timestamp()
+            WHERE question_id = $2 AND user_id = $3
+            "#,
+        )
+        .bind(comment)
+        .bind(question_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?
+        .rows_affected(),
+    };
+    Ok(result == 1)
+}
+
+// Questions with the feedback, the ones with the most negative ratings first
+pub async fn get_feedback_report(pool: &Pool) -> sqlx::Result<Vec<FeedbackStats>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                FeedbackStats,
+                r#"
+                SELECT
+                    questions.id as "question_id!",
+                    questions.question as "question!",
+                    categories.name as "category?",
+                    SUM(feedback.helpful) as "helpful!: i64",
+                    SUM(NOT feedback.helpful) as "unhelpful!: i64",
+                    COUNT(feedback.comment) as "comments!: i64",
+                    MAX(feedback.updated_at) as "updated_at!: String"
+                FROM feedback
+                JOIN questions ON questions.id = feedback.question_id
+                LEFT JOIN categories ON categories.id = questions.category
+                WHERE questions.deleted_at IS NULL
+                GROUP BY questions.id
+                ORDER BY 5 DESC, 4, questions.id
+                "#
+            )
+            .fetch_all(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT
+                    questions.id as question_id,
+                    questions.question,
+                    categories.name as category,
+                    COUNT(*) FILTER (WHERE feedback.helpful) as helpful,
+                    COUNT(*) FILTER (WHERE NOT feedback.helpful) as unhelpful,
+                    COUNT(feedback.comment) as comments,
+                    MAX(feedback.updated_at) as updated_at
+                FROM feedback
+                JOIN questions ON questions.id = feedback.question_id
+                LEFT JOIN categories ON categories.id = questions.category
+                WHERE questions.deleted_at IS NULL
+                GROUP BY questions.id, categories.id
+                ORDER BY 5 DESC, 4, questions.id
+                "#,
+            )
+            .fetch_all(pool)
+            .await
+        }
+    }
+}
+
+pub async fn get_feedback_comments(
+    pool: &Pool,
+    question_id: i64,
+) -> sqlx::Result<Vec<FeedbackComment>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                FeedbackComment,
+                r#"
+                SELECT users.first_name, users.last_name, users.username, feedback.helpful,
+                    feedback.comment as "comment!", feedback.updated_at
+                FROM feedback JOIN users ON users.id = feedback.user_id
+                WHERE feedback.question_id = ?1 AND feedback.comment IS NOT NULL
+                ORDER BY feedback.updated_at DESC
+                "#,
+                question_id
+            )
+            .fetch_all(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT users.first_name, users.last_name, users.username, feedback.helpful,
+                    feedback.comment, feedback.updated_at
+                FROM feedback JOIN users ON users.id = feedback.user_id
+                WHERE feedback.question_id = $1 AND feedback.comment IS NOT NULL
+                ORDER BY feedback.updated_at DESC
+                "#,
+            )
+            .bind(question_id)
+            .fetch_all(pool)
+            .await
+        }
+    }
+}
@@ -0,0 +1,351 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::db::{Pool, Transaction};
+
+// Group of users with the categories only they can see
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Group {
+    pub id: i64,
+    pub name: String,
+    pub users: Vec<i64>,
+    pub categories: Vec<i64>,
+}
+
+// Pairs of the group id and the id of the user or the category
+type Links = Vec<(i64, i64)>;
+
+pub async fn get_groups(pool: &Pool) -> sqlx::Result<Vec<Group>> {
+    let (rows, members, categories): (Vec<(i64, String)>, Links, Links) = match pool {
+        Pool::Sqlite(pool) => {
+            let mut conn = pool.acquire().await?;
+            let rows = sqlx::query!(
+                r#"
+                    SELECT id, name FROM groups ORDER BY name
+                    "#
+            )
+            .fetch_all(&mut *conn)
+            .await?
+            .into_iter()
+            .map(|row| (row.id, row.name))
+            .collect();
+            let members = sqlx::query!(
+                r#"
+                    SELECT group_id, user_id FROM user_groups ORDER BY user_id
+                    "#
+            )
+            .fetch_all(&mut *conn)
+            .await?
+            .into_iter()
+            .map(|row| (row.group_id, row.user_id))
+            .collect();
+            let categories = sqlx::query!(
+                r#"
+                    SELECT group_id, category_id FROM category_groups ORDER BY category_id
+                    "#
+            )
+            .fetch_all(&mut *conn)
+            .await?
+            .into_iter()
+            .map(|row| (row.group_id, row.category_id))
+            .collect();
+            (rows, members, categories)
+        }
+        Pool::Postgres(pool) => {
+            let mut conn = pool.acquire().await?;
+            let rows = sqlx::query_as(
+                r#"
+                    SELECT id, name FROM groups ORDER BY name
+                    "#,
+            )
+            .fetch_all(&mut *conn)
+            .await?;
+            let members = sqlx::query_as(
+                r#"
+                    SELECT group_id, user_id FROM user_groups ORDER BY user_id
+                    "#,
+            )
+            .fetch_all(&mut *conn)
+            .await?;
+            let categories = sqlx::query_as(
+                r#"
+                    SELECT group_id, category_id FROM category_groups ORDER BY category_id
+                    "#,
+            )
+            .fetch_all(&mut *conn)
+            .await?;
+            (rows, members, categories)
+        }
+    };
+    let mut groups: Vec<Group> = rows
+        .into_iter()
+        .map(|(id, name)| Group {
+            id,
+            name,
+            users: vec![],
+            categories: vec![],
+        })
+        .collect();
+    let index: HashMap<i64, usize> = groups
+        .iter()
+        .enumerate()
+        .map(|(i, group)| (group.id, i))
+        .collect();
+
+    for (group_id, user_id) in members {
+        if let Some(i) = index.get(&group_id) {
+            groups[*i].users.push(user_id);
+        }
+    }
+    for (group_id, category_id) in categories {
+        if let Some(i) = index.get(&group_id) {
+            groups[*i].categories.push(category_id);
+        }
+    }
+    Ok(groups)
+}
+
+pub async fn get_group(pool: &Pool, id: i64) -> sqlx::Result<Group> {
+    get_groups(pool)
+        .await?
+        .into_iter()
+        .find(|group| group.id == id)
+        .ok_or(sqlx::Error::RowNotFound)
+}
+
+pub async fn create_group(pool: &Pool, name: &str) -> sqlx::Result<i64> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            let id = sqlx::query!(
+                r#"
+                INSERT INTO groups (name) VALUES (?1)
+                "#,
+                name
+            )
+            .execute(pool)
+            .await?
+            .last_insert_rowid();
+            Ok(id)
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_scalar(
+                r#"
+                INSERT INTO groups (name) VALUES ($1) RETURNING id
+                "#,
+            )
+            .bind(name)
+            .fetch_one(pool)
+            .await
+        }
+    }
+}
+
+async fn set_members(transaction: &mut Transaction, group: &Group) -> sqlx::Result<()> {
+    let users = group.users.iter().collect::<HashSet<_>>();
+    let categories = group.categories.iter().collect::<HashSet<_>>();
+    match transaction {
+        Transaction::Sqlite(transaction) => {
+            sqlx::query!(
+                r#"
+                DELETE FROM user_groups WHERE group_id = ?1
+                "#,
+                group.id
+            )
+            .execute(&mut **transaction)
+            .await?;
+            sqlx::query!(
+                r#"
+                DELETE FROM category_groups WHERE group_id = ?1
+                "#,
+                group.id
+            )
+            .execute(&mut **transaction)
+            .await?;
+            for user_id in users {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO user_groups (user_id, group_id) VALUES (?1, ?2)
+                    "#,
+                    user_id,
+                    group.id
+                )
+                .execute(&mut **transaction)
+                .await?;
+            }
+            for category_id in categories {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO category_groups (category_id, group_id) VALUES (?1, ?2)
+                    "#,
+                    category_id,
+                    group.id
+                )
+                .execute(&mut **transaction)
+                .await?;
+            }
+        }
+        Transaction::Postgres(transaction) => {
+            sqlx::query(
+                r#"
+                DELETE FROM user_groups WHERE group_id = $1
+                "#,
+            )
+            .bind(group.id)
+            .execute(&mut **transaction)
+            .await?;
+            sqlx::query(
+                r#"
+                DELETE FROM category_groups WHERE group_id = $1
+                "#,
+            )
+            .bind(group.id)
+            .execute(&mut **transaction)
+            .await?;
+            for user_id in users {
+                sqlx::query(
+                    r#"
+                    INSERT INTO user_groups (user_id, group_id) VALUES ($1, $2)
+                    "#,
+                )
+                .bind(user_id)
+                .bind(group.id)
+                .execute(&mut **transaction)
+                .await?;
+            }
+            for category_id in categories {
+                sqlx::query(
+                    r#"
+                    INSERT INTO category_groups (category_id, group_id) VALUES ($1, $2)
+                    "#,
+                )
+                .bind(category_id)
+                .bind(group.id)
+                .execute(&mut **transaction)
+                .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Updates the name and replaces the members and the categories of the group
+pub async fn update_group(pool: &Pool, group: &Group) -> sqlx::Result<()> {
+    let mut transaction = pool.begin().await?;
+    let updated = match &mut transaction {
+        Transaction::Sqlite(transaction) => sqlx::query!(
+            r#"
+            UPDATE groups SET name = ?1 WHERE id = ?2
+            "#,
+            group.name,
+            group.id
+        )
+        .execute(&mut **transaction)
+        .await?
+        .rows_affected(),
+        Transaction::Postgres(transaction) => sqlx::query(
+            r#"
+            UPDATE groups SET name = $1 WHERE id = $2
+            "#,
+        )
+        .bind(&group.name)
+        .bind(group.id)
+        .execute(&mut **transaction)
+        .await?
+        .rows_affected(),
+    };
+    if updated == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    set_members(&mut transaction, group).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+pub async fn delete_group(pool: &Pool, id: i64) -> sqlx::Result<()> {
+    let deleted = match pool {
+        Pool::Sqlite(pool) => sqlx::query!(
+            r#"
+            DELETE FROM groups WHERE id = ?1
+            "#,
+            id
+        )
+        .execute(pool)
+        .await?
+        .rows_affected(),
+        Pool::Postgres(pool) => sqlx::query(
+            r#"
+            DELETE FROM groups WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(pool)
+        .await?
+        .rows_affected(),
+    };
+    if deleted == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    Ok(())
+}
+
+// Replaces all the groups with the given ones, ids are preserved
+pub async fn import_groups(transaction: &mut Transaction, groups: Vec<Group>) -> sqlx::Result<()> {
+    match &mut *transaction {
+        Transaction::Sqlite(transaction) => {
+            sqlx::query!(
+                r#"
+                DELETE FROM groups
+                "#
+            )
+            .execute(&mut **transaction)
+            .await?;
+        }
+        Transaction::Postgres(transaction) => {
+            sqlx::query(
+                r#"
+                DELETE FROM groups
+                "#,
+            )
+            .execute(&mut **transaction)
+            .await?;
+        }
+    }
+    for group in groups {
+        match &mut *transaction {
+            Transaction::Sqlite(transaction) => {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO groups (id, name) VALUES (?1, ?2)
+                    "#,
+                    group.id,
+                    group.name
+                )
+                .execute(&mut **transaction)
+                .await?;
+            }
+            Transaction::Postgres(transaction) => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO groups (id, name) VALUES ($1, $2)
+                    "#,
+                )
+                .bind(group.id)
+                .bind(&group.name)
+                .execute(&mut **transaction)
+                .await?;
+            }
+        }
+        set_members(transaction, &group).await?;
+    }
+    // the ids are set explicitly, so the new groups continue after the imported ones
+    if let Transaction::Postgres(transaction) = transaction {
+        sqlx::query(
+            r#"
+            SELECT setval(pg_get_serial_sequence('groups', 'id'), COALESCE(MAX(id), 0) + 1, false) FROM groups
+            "#,
+        )
+        .execute(&mut **transaction)
+        .await?;
+    }
+    Ok(())
+}
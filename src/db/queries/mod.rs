@@ -1,3 +1,15 @@
+pub mod access_requests;
+pub mod attachments;
+pub mod broadcasts;
 pub mod categories;
+pub mod feedback;
+pub mod groups;
+pub mod pending_questions;
 pub mod questions;
+pub mod revisions;
+pub mod sessions;
+pub mod snapshot;
+pub mod subscriptions;
+pub mod translations;
+pub mod trash;
 pub mod users;
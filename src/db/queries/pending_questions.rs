@@ -0,0 +1,274 @@
+use crate::db::Pool;
+
+// Question of the user to the support team with the name of the user
+#[derive(sqlx::FromRow)]
+pub struct PendingQuestion {
+    pub id: i64,
+    pub user_id: i64,
+    pub first_name: String,
+    pub last_name: Option<String>,
+    pub username: Option<String>,
+    pub question: String,
+    pub asked_at: String,
+    pub support_message_id: Option<i64>,
+    pub answer: Option<String>,
+    pub answered_by: Option<String>,
+    pub answered_at: Option<String>,
+    pub question_id: Option<i64>,
+}
+
+pub async fn create_pending_question(
+    pool: &Pool,
+    user_id: i64,
+    question: &str,
+) -> sqlx::Result<i64> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            let id = sqlx::query!(
+                r#"
+                INSERT INTO pending_questions (user_id, question) VALUES (?1, ?2)
+                "#,
+                user_id,
+                question
+            )
+            .execute(pool)
+            .await?
+            .last_insert_rowid();
+            Ok(id)
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_scalar(
+                r#"
+                INSERT INTO pending_questions (user_id, question) VALUES ($1, $2) RETURNING id
+                "#,
+            )
+            .bind(user_id)
+            .bind(question)
+            .fetch_one(pool)
+            .await
+        }
+    }
+}
+
+pub async fn set_support_message(
+    pool: &Pool,
+    id: i64,
+    support_message_id: i64,
+) -> sqlx::Result<()> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query!(
+                r#"
+                UPDATE pending_questions SET support_message_id = ?1 WHERE id = ?2
+                "#,
+                support_message_id,
+                id
+            )
+            .execute(pool)
+            .await?;
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query(
+                r#"
+                UPDATE pending_questions SET support_message_id = $1 WHERE id = $2
+                "#,
+            )
+            .bind(support_message_id)
+            .bind(id)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn get_pending_question(pool: &Pool, id: i64) -> sqlx::Result<PendingQuestion> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                PendingQuestion,
+                r#"
+                SELECT pending_questions.id, pending_questions.user_id, users.first_name, users.last_name,
+                    users.username, pending_questions.question, pending_questions.asked_at,
+                    pending_questions.support_message_id, pending_questions.answer,
+                    pending_questions.answered_by, pending_questions.answered_at, pending_questions.question_id
+                FROM pending_questions JOIN users ON users.id = pending_questions.user_id
+                WHERE pending_questions.id = ?1
+                "#,
+                id
+            )
+            .fetch_one(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT pending_questions.id, pending_questions.user_id, users.first_name, users.last_name,
+                    users.username, pending_questions.question, pending_questions.asked_at,
+                    pending_questions.support_message_id, pending_questions.answer,
+                    pending_questions.answered_by, pending_questions.answered_at, pending_questions.question_id
+                FROM pending_questions JOIN users ON users.id = pending_questions.user_id
+                WHERE pending_questions.id = $1
+                "#,
+            )
+            .bind(id)
+            .fetch_one(pool)
+            .await
+        }
+    }
+}
+
+pub async fn get_pending_question_by_support_message(
+    pool: &Pool,
+    support_message_id: i64,
+) -> sqlx::Result<Option<i64>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_scalar!(
+                r#"
+                SELECT id FROM pending_questions WHERE support_message_id = ?1
+                "#,
+                support_message_id
+            )
+            .fetch_optional(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_scalar(
+                r#"
+                SELECT id FROM pending_questions WHERE support_message_id = $1
+                "#,
+            )
+            .bind(support_message_id)
+            .fetch_optional(pool)
+            .await
+        }
+    }
+}
+
+// Unanswered questions first, the oldest ones on top
+pub async fn get_pending_questions(pool: &Pool) -> sqlx::Result<Vec<PendingQuestion>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                PendingQuestion,
+                r#"
+                SELECT pending_questions.id, pending_questions.user_id, users.first_name, users.last_name,
+                    users.username, pending_questions.question, pending_questions.asked_at,
+                    pending_questions.support_message_id, pending_questions.answer,
+                    pending_questions.answered_by, pending_questions.answered_at, pending_questions.question_id
+                FROM pending_questions JOIN users ON users.id = pending_questions.user_id
+                ORDER BY pending_questions.answer IS NOT NULL, pending_questions.asked_at, pending_questions.id
+                "#
+            )
+            .fetch_all(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT pending_questions.id, pending_questions.user_id, users.first_name, users.last_name,
+                    users.username, pending_questions.question, pending_questions.asked_at,
+                    pending_questions.support_message_id, pending_questions.answer,
+                    pending_questions.answered_by, pending_questions.answered_at, pending_questions.question_id
+                FROM pending_questions JOIN users ON users.id = pending_questions.user_id
+                ORDER BY pending_questions.answer IS NOT NULL, pending_questions.asked_at, pending_questions.id
+                "#,
+            )
+            .fetch_all(pool)
+            .await
+        }
+    }
+}
+
+// Returns false when the question is already answered
+pub async fn answer_pending_question(
+    pool: &Pool,
+    id: i64,
+    answer: &str,
+    answered_by: &str,
+) -> sqlx::Result<bool> {
+    let result = match pool {
+        Pool::Sqlite(pool) => sqlx::query!(
+            r#"
+            UPDATE pending_questions SET answer = ?1, answered_by = ?2, answered_at = CURRENT_TIMESTAMP
+            WHERE id = ?3 AND answer IS NULL
+            "#,
+            answer,
+            answered_by,
+            id
+        )
+        .execute(pool)
+        .await?
+        .rows_affected(),
+        Pool::Postgres(pool) => sqlx::query(
+            r#"
+            UPDATE pending_questions SET answer = $1, answered_by = $2, answered_at = utc_timestamp()
+            WHERE id = $3 AND answer IS NULL
+            "#,
+        )
+        .bind(answer)
+        .bind(answered_by)
+        .bind(id)
+        .execute(pool)
+        .await?
+        .rows_affected(),
+    };
+    Ok(result == 1)
+}
+
+// Links the question created from the answer
+pub async fn set_promoted_question(pool: &Pool, id: i64, question_id: i64) -> sqlx::Result<()> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query!(
+                r#"
+                UPDATE pending_questions SET question_id = ?1 WHERE id = ?2
+                "#,
+                question_id,
+                id
+            )
+            .execute(pool)
+            .await?;
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query(
+                r#"
+                UPDATE pending_questions SET question_id = $1 WHERE id = $2
+                "#,
+            )
+            .bind(question_id)
+            .bind(id)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn delete_pending_question(pool: &Pool, id: i64) -> sqlx::Result<()> {
+    let deleted = match pool {
+        Pool::Sqlite(pool) => sqlx::query!(
+            r#"
+            DELETE FROM pending_questions WHERE id = ?1
+            "#,
+            id
+        )
+        .execute(pool)
+        .await?
+        .rows_affected(),
+        Pool::Postgres(pool) => sqlx::query(
+            r#"
+            DELETE FROM pending_questions WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(pool)
+        .await?
+        .rows_affected(),
+    };
+    if deleted == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    Ok(())
+}
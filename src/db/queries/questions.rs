@@ -1,10 +1,13 @@
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use sqlx::SqlitePool;
 use std::collections::{HashMap, HashSet};
+use utoipa::ToSchema;
 
-use crate::db::Category;
+use crate::db::{Category, Pool, Transaction};
 
 use super::categories::{get_all_categories, get_category};
+use super::revisions::{get_state, record_revision, Entity};
+use super::subscriptions::record_question_change;
 use crate::db::Reorder;
 
 #[derive(Serialize, Deserialize, sqlx::FromRow)]
@@ -20,6 +23,7 @@ struct QuestionRowJoined {
     category_name: String,
     category_hidden: bool,
     category_ordering: i64,
+    category_parent: Option<i64>,
 }
 #[derive(Serialize, Deserialize, sqlx::FromRow)]
 struct QuestionRow {
@@ -32,12 +36,13 @@ struct QuestionRow {
     ordering: i64,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct Question {
     pub id: i64,
     pub category: Option<Category>,
     pub question: String,
     pub answer: String,
+    // names of the files, in the order they were attached
     pub attachments: Vec<String>,
     pub hidden: bool,
     pub ordering: i64,
@@ -52,6 +57,7 @@ impl From<QuestionRowJoined> for Question {
                 name: value.category_name,
                 hidden: value.category_hidden,
                 ordering: value.category_ordering,
+                parent: value.category_parent,
             }),
             question: value.question,
             answer: value.answer,
@@ -63,68 +69,232 @@ impl From<QuestionRowJoined> for Question {
 }
 
 pub async fn get_public_questions_for_public_category(
-    pool: &SqlitePool,
-    category: &str,
+    pool: &Pool,
+    user_id: i64,
+    category_id: i64,
 ) -> sqlx::Result<Vec<Question>> {
-    sqlx::query_as!(
-        QuestionRowJoined,
-        r#"
-        SELECT categories.id as category_id, categories.name as category_name, categories.hidden as category_hidden, categories.ordering as category_ordering,
-         questions.id, questions.category, questions.question, questions.answer, questions.attachments, questions.hidden, questions.ordering 
-        FROM questions JOIN categories on questions.category = categories.id WHERE categories.name = ?1 AND categories.hidden = FALSE AND questions.hidden = FALSE
-        ORDER BY questions.ordering, questions.id DESC
-        "#,
-        category
-    ).fetch_all(pool).await
-    .map(|questions| questions.into_iter().map(|q| q.into()).collect())
+    let questions: Vec<QuestionRowJoined> = match pool {
+        Pool::Sqlite(pool) => sqlx::query_as!(
+            QuestionRowJoined,
+            r#"
+            SELECT categories.id as category_id, categories.name as category_name, categories.hidden as category_hidden, categories.ordering as category_ordering, categories.parent as category_parent,
+             questions.id, questions.category, questions.question, questions.answer, (SELECT json_group_array(name ORDER BY id) FROM attachments WHERE question_id = questions.id) as "attachments!: String", questions.hidden, questions.ordering
+            FROM questions JOIN categories on questions.category = categories.id
+            JOIN accessible_categories on categories.id = accessible_categories.category_id AND accessible_categories.user_id = ?2
+            WHERE categories.id = ?1 AND questions.hidden = FALSE AND questions.deleted_at IS NULL
+            ORDER BY questions.ordering, questions.id DESC
+            "#,
+            category_id,
+            user_id
+        ).fetch_all(pool).await?,
+        Pool::Postgres(pool) => sqlx::query_as(
+            r#"
+            SELECT categories.id as category_id, categories.name as category_name, categories.hidden as category_hidden, categories.ordering as category_ordering, categories.parent as category_parent,
+             questions.id, questions.category, questions.question, questions.answer, (SELECT COALESCE(json_agg(name ORDER BY id), '[]')::TEXT FROM attachments WHERE question_id = questions.id) as attachments, questions.hidden, questions.ordering
+            FROM questions JOIN categories on questions.category = categories.id
+            JOIN accessible_categories on categories.id = accessible_categories.category_id AND accessible_categories.user_id = $2
+            WHERE categories.id = $1 AND questions.hidden = FALSE AND questions.deleted_at IS NULL
+            ORDER BY questions.ordering, questions.id DESC
+            "#,
+        )
+        .bind(category_id)
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?,
+    };
+    Ok(questions.into_iter().map(|q| q.into()).collect())
 }
 
 pub async fn get_questions_by_category_id(
-    pool: &SqlitePool,
+    pool: &Pool,
     category_id: i64,
 ) -> sqlx::Result<Vec<Question>> {
-    sqlx::query_as!(
-        QuestionRowJoined,
-        r#"
-        SELECT categories.id as category_id, categories.name as category_name, categories.hidden as category_hidden, categories.ordering as category_ordering,
-            questions.*  
-        FROM questions JOIN categories on questions.category = categories.id 
-        WHERE questions.category = ?1 
-        ORDER BY questions.ordering, questions.id DESC
-        "#,
-        category_id
-    ).fetch_all(pool).await
-    .map(|questions| questions.into_iter().map(|q| q.into()).collect())
+    let questions: Vec<QuestionRowJoined> = match pool {
+        Pool::Sqlite(pool) => sqlx::query_as!(
+            QuestionRowJoined,
+            r#"
+            SELECT categories.id as category_id, categories.name as category_name, categories.hidden as category_hidden, categories.ordering as category_ordering, categories.parent as category_parent,
+                questions.id, questions.category, questions.question, questions.answer, (SELECT json_group_array(name ORDER BY id) FROM attachments WHERE question_id = questions.id) as "attachments!: String", questions.hidden, questions.ordering
+            FROM questions JOIN categories on questions.category = categories.id
+            WHERE questions.category = ?1 AND questions.deleted_at IS NULL
+            ORDER BY questions.ordering, questions.id DESC
+            "#,
+            category_id
+        ).fetch_all(pool).await?,
+        Pool::Postgres(pool) => sqlx::query_as(
+            r#"
+            SELECT categories.id as category_id, categories.name as category_name, categories.hidden as category_hidden, categories.ordering as category_ordering, categories.parent as category_parent,
+                questions.id, questions.category, questions.question, questions.answer, (SELECT COALESCE(json_agg(name ORDER BY id), '[]')::TEXT FROM attachments WHERE question_id = questions.id) as attachments, questions.hidden, questions.ordering
+            FROM questions JOIN categories on questions.category = categories.id
+            WHERE questions.category = $1 AND questions.deleted_at IS NULL
+            ORDER BY questions.ordering, questions.id DESC
+            "#,
+        )
+        .bind(category_id)
+        .fetch_all(pool)
+        .await?,
+    };
+    Ok(questions.into_iter().map(|q| q.into()).collect())
 }
-pub async fn get_question_by_category_name(
-    pool: &SqlitePool,
-    question: &str,
-    category: &str,
-) -> sqlx::Result<Question> {
-    sqlx::query_as!(
-        QuestionRowJoined,
-        r#"
-        SELECT categories.id as category_id, categories.name as category_name, categories.hidden as category_hidden, categories.ordering as category_ordering,
-            questions.* 
-        FROM questions JOIN categories on questions.category = categories.id WHERE categories.name = ?1 AND questions.question = ?2
-        "#,
-        category,
-        question,
-    )
-    .fetch_one(pool)
-    .await.map(|x| x.into())
+pub async fn get_public_question(pool: &Pool, user_id: i64, id: i64) -> sqlx::Result<Question> {
+    let question: QuestionRowJoined = match pool {
+        Pool::Sqlite(pool) => sqlx::query_as!(
+            QuestionRowJoined,
+            r#"
+            SELECT categories.id as category_id, categories.name as category_name, categories.hidden as category_hidden, categories.ordering as category_ordering, categories.parent as category_parent,
+             questions.id, questions.category, questions.question, questions.answer, (SELECT json_group_array(name ORDER BY id) FROM attachments WHERE question_id = questions.id) as "attachments!: String", questions.hidden, questions.ordering
+            FROM questions JOIN categories on questions.category = categories.id
+            JOIN accessible_categories on categories.id = accessible_categories.category_id AND accessible_categories.user_id = ?2
+            WHERE questions.id = ?1 AND questions.hidden = FALSE AND questions.deleted_at IS NULL
+            "#,
+            id,
+            user_id
+        )
+        .fetch_one(pool)
+        .await?,
+        Pool::Postgres(pool) => sqlx::query_as(
+            r#"
+            SELECT categories.id as category_id, categories.name as category_name, categories.hidden as category_hidden, categories.ordering as category_ordering, categories.parent as category_parent,
+             questions.id, questions.category, questions.question, questions.answer, (SELECT COALESCE(json_agg(name ORDER BY id), '[]')::TEXT FROM attachments WHERE question_id = questions.id) as attachments, questions.hidden, questions.ordering
+            FROM questions JOIN categories on questions.category = categories.id
+            JOIN accessible_categories on categories.id = accessible_categories.category_id AND accessible_categories.user_id = $2
+            WHERE questions.id = $1 AND questions.hidden = FALSE AND questions.deleted_at IS NULL
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?,
+    };
+    Ok(question.into())
+}
+
+pub(crate) fn search_words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+}
+
+// turns user input into FTS5 query, every word is quoted (so FTS syntax can't leak in)
+// and matched as a prefix, questions matching more words are ranked higher
+fn search_pattern(text: &str) -> String {
+    search_words(text)
+        .map(|word| format!("\"{}\"*", word))
+        .join(" OR ")
+}
+
+// same for the tsquery of Postgres, the words have nothing but letters and digits
+fn search_tsquery(text: &str) -> String {
+    search_words(text)
+        .map(|word| format!("{}:*", word))
+        .join(" | ")
+}
+
+// Questions are found by the default text and by the translation to the language of the user
+pub async fn search_public_questions(
+    pool: &Pool,
+    user_id: i64,
+    language: &str,
+    text: &str,
+    limit: i64,
+) -> sqlx::Result<Vec<Question>> {
+    let questions: Vec<QuestionRowJoined> = match pool {
+        Pool::Sqlite(pool) => {
+            let pattern = search_pattern(text);
+            if pattern.is_empty() {
+                return Ok(vec![]);
+            }
+            sqlx::query_as!(
+                QuestionRowJoined,
+                r#"
+                WITH matches(id, rank) AS (
+                    SELECT rowid, bm25(questions_search, 10.0, 1.0) FROM questions_search
+                    WHERE questions_search MATCH ?1
+                    UNION ALL
+                    SELECT question_translations.question_id, bm25(question_translations_search, 10.0, 1.0)
+                    FROM question_translations_search
+                    JOIN question_translations ON question_translations.id = question_translations_search.rowid
+                    WHERE question_translations_search MATCH ?1 AND question_translations.language = ?4
+                )
+                SELECT categories.id as category_id, categories.name as category_name, categories.hidden as category_hidden, categories.ordering as category_ordering, categories.parent as category_parent,
+                 questions.id, questions.category, questions.question, questions.answer, (SELECT json_group_array(name ORDER BY id) FROM attachments WHERE question_id = questions.id) as "attachments!: String", questions.hidden, questions.ordering
+                FROM (SELECT id, MIN(rank) AS rank FROM matches GROUP BY id) AS best
+                JOIN questions on questions.id = best.id
+                JOIN categories on questions.category = categories.id
+                JOIN accessible_categories on categories.id = accessible_categories.category_id AND accessible_categories.user_id = ?3
+                WHERE questions.hidden = FALSE AND questions.deleted_at IS NULL
+                ORDER BY best.rank
+                LIMIT ?2
+                "#,
+                pattern,
+                limit,
+                user_id,
+                language
+            ).fetch_all(pool).await?
+        }
+        Pool::Postgres(pool) => {
+            let query = search_tsquery(text);
+            if query.is_empty() {
+                return Ok(vec![]);
+            }
+            // the question weighs ten times more than the answer, higher rank is better
+            sqlx::query_as(
+                r#"
+                WITH matches(id, rank) AS (
+                    SELECT id, ts_rank('{0.1, 0.1, 0.1, 1.0}', search, to_tsquery('simple', $1)) FROM questions
+                    WHERE search @@ to_tsquery('simple', $1)
+                    UNION ALL
+                    SELECT question_id, ts_rank('{0.1, 0.1, 0.1, 1.0}', search, to_tsquery('simple', $1))
+                    FROM question_translations
+                    WHERE search @@ to_tsquery('simple', $1) AND language = $4
+                )
+                SELECT categories.id as category_id, categories.name as category_name, categories.hidden as category_hidden, categories.ordering as category_ordering, categories.parent as category_parent,
+                 questions.id, questions.category, questions.question, questions.answer, (SELECT COALESCE(json_agg(name ORDER BY id), '[]')::TEXT FROM attachments WHERE question_id = questions.id) as attachments, questions.hidden, questions.ordering
+                FROM (SELECT id, MAX(rank) AS rank FROM matches GROUP BY id) AS best
+                JOIN questions on questions.id = best.id
+                JOIN categories on questions.category = categories.id
+                JOIN accessible_categories on categories.id = accessible_categories.category_id AND accessible_categories.user_id = $3
+                WHERE questions.hidden = FALSE AND questions.deleted_at IS NULL
+                ORDER BY best.rank DESC
+                LIMIT $2
+                "#,
+            )
+            .bind(query)
+            .bind(limit)
+            .bind(user_id)
+            .bind(language)
+            .fetch_all(pool)
+            .await?
+        }
+    };
+    Ok(questions.into_iter().map(|q| q.into()).collect())
 }
 
-pub async fn get_question_by_id(pool: &SqlitePool, id: i64) -> sqlx::Result<Question> {
-    let question_row = sqlx::query_as!(
-        QuestionRow,
-        r#"
-        SELECT * FROM questions WHERE questions.id = ?1
-        "#,
-        id
-    )
-    .fetch_one(pool)
-    .await?;
+pub async fn get_question_by_id(pool: &Pool, id: i64) -> sqlx::Result<Question> {
+    let question_row: QuestionRow = match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                QuestionRow,
+                r#"
+                SELECT id, category, question, answer, (SELECT json_group_array(name ORDER BY id) FROM attachments WHERE question_id = questions.id) as "attachments!: String", hidden, ordering FROM questions
+                WHERE questions.id = ?1 AND deleted_at IS NULL
+                "#,
+                id
+            )
+            .fetch_one(pool)
+            .await?
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT id, category, question, answer, (SELECT COALESCE(json_agg(name ORDER BY id), '[]')::TEXT FROM attachments WHERE question_id = questions.id) as attachments, hidden, ordering FROM questions
+                WHERE questions.id = $1 AND deleted_at IS NULL
+                "#,
+            )
+            .bind(id)
+            .fetch_one(pool)
+            .await?
+        }
+    };
 
     let category = if let Some(c) = question_row.category {
         get_category(pool, c).await.ok()
@@ -143,15 +313,30 @@ pub async fn get_question_by_id(pool: &SqlitePool, id: i64) -> sqlx::Result<Ques
     })
 }
 
-pub async fn get_all_questions(pool: &SqlitePool) -> sqlx::Result<Vec<Question>> {
-    let questions_rows = sqlx::query_as!(
-        QuestionRow,
-        r#"
-        SELECT * FROM questions ORDER BY questions.ordering, questions.id DESC
-        "#,
-    )
-    .fetch_all(pool)
-    .await?;
+pub async fn get_all_questions(pool: &Pool) -> sqlx::Result<Vec<Question>> {
+    let questions_rows: Vec<QuestionRow> = match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                QuestionRow,
+                r#"
+                SELECT id, category, question, answer, (SELECT json_group_array(name ORDER BY id) FROM attachments WHERE question_id = questions.id) as "attachments!: String", hidden, ordering FROM questions WHERE deleted_at IS NULL
+                ORDER BY questions.ordering, questions.id DESC
+                "#,
+            )
+            .fetch_all(pool)
+            .await?
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT id, category, question, answer, (SELECT COALESCE(json_agg(name ORDER BY id), '[]')::TEXT FROM attachments WHERE question_id = questions.id) as attachments, hidden, ordering FROM questions WHERE deleted_at IS NULL
+                ORDER BY questions.ordering, questions.id DESC
+                "#,
+            )
+            .fetch_all(pool)
+            .await?
+        }
+    };
     let categories: HashMap<i64, Category> = get_all_categories(pool)
         .await?
         .into_iter()
@@ -171,15 +356,30 @@ pub async fn get_all_questions(pool: &SqlitePool) -> sqlx::Result<Vec<Question>>
         .collect())
 }
 
-pub async fn get_public_questions(pool: &SqlitePool) -> sqlx::Result<Vec<Question>> {
-    let questions_rows = sqlx::query_as!(
-        QuestionRow,
-        r#"
-        SELECT * FROM questions WHERE hidden = FALSE ORDER BY ordering, questions.id DESC
-        "#,
-    )
-    .fetch_all(pool)
-    .await?;
+pub async fn get_public_questions(pool: &Pool) -> sqlx::Result<Vec<Question>> {
+    let questions_rows: Vec<QuestionRow> = match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                QuestionRow,
+                r#"
+                SELECT id, category, question, answer, (SELECT json_group_array(name ORDER BY id) FROM attachments WHERE question_id = questions.id) as "attachments!: String", hidden, ordering FROM questions WHERE hidden = FALSE AND deleted_at IS NULL
+                ORDER BY ordering, questions.id DESC
+                "#,
+            )
+            .fetch_all(pool)
+            .await?
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT id, category, question, answer, (SELECT COALESCE(json_agg(name ORDER BY id), '[]')::TEXT FROM attachments WHERE question_id = questions.id) as attachments, hidden, ordering FROM questions WHERE hidden = FALSE AND deleted_at IS NULL
+                ORDER BY ordering, questions.id DESC
+                "#,
+            )
+            .fetch_all(pool)
+            .await?
+        }
+    };
     let categories: HashMap<i64, Category> = get_all_categories(pool)
         .await?
         .into_iter()
@@ -199,124 +399,402 @@ pub async fn get_public_questions(pool: &SqlitePool) -> sqlx::Result<Vec<Questio
         .collect())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn create_question(
-    pool: &SqlitePool,
+    pool: &Pool,
     question: &str,
     answer: &str,
     category: Option<i64>,
-    attachments: Vec<&str>,
     hidden: bool,
     ordering: i64,
+    author: &str,
 ) -> sqlx::Result<i64> {
-    let mut conn = pool.acquire().await?;
-    let att = serde_json::to_string(&attachments).unwrap();
-    let id = sqlx::query!(
-        r#"
-        INSERT INTO questions (category, question, answer, attachments, hidden, ordering) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
-        "#,
-        category,
-        question,
-        answer,
-        att,
-        hidden,
-        ordering,
-    )
-    .execute(&mut *conn)
-    .await?
-    .last_insert_rowid();
+    let mut transaction = pool.begin().await?;
+    let id = match &mut transaction {
+        Transaction::Sqlite(transaction) => sqlx::query!(
+            r#"
+            INSERT INTO questions (category, question, answer, hidden, ordering) VALUES (?1, ?2, ?3, ?4, ?5)
+            "#,
+            category,
+            question,
+            answer,
+            hidden,
+            ordering,
+        )
+        .execute(&mut **transaction)
+        .await?
+        .last_insert_rowid(),
+        Transaction::Postgres(transaction) => {
+            sqlx::query_scalar(
+                r#"
+                INSERT INTO questions (category, question, answer, hidden, ordering) VALUES ($1, $2, $3, $4, $5)
+                RETURNING id
+                "#,
+            )
+            .bind(category)
+            .bind(question)
+            .bind(answer)
+            .bind(hidden)
+            .bind(ordering)
+            .fetch_one(&mut **transaction)
+            .await?
+        }
+    };
+    record_question_change(&mut transaction, id, true).await?;
+    record_revision(&mut transaction, Entity::Question, id, None, author).await?;
+    transaction.commit().await?;
 
     Ok(id)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn update_question(
-    pool: &SqlitePool,
+    pool: &Pool,
     id: i64,
     category: Option<i64>,
     question: String,
     answer: String,
-    attachments: Vec<&str>,
     hidden: bool,
+    author: &str,
 ) -> sqlx::Result<()> {
-    let mut conn = pool.acquire().await?;
-    let att = serde_json::to_string(&attachments).unwrap();
-    sqlx::query!(
-        r#"
-        UPDATE questions SET category=?1, question=?2, answer=?3, attachments=?4, hidden=?5 WHERE questions.id = ?6
-        "#,
-        category,
-        question,
-        answer,
-        att,
-        hidden,
-        id,
-    )
-    .execute(&mut *conn)
-    .await?;
+    let mut transaction = pool.begin().await?;
+    let old = get_state(&mut transaction, Entity::Question, id).await?;
+    // saving the question without the changes does not notify the subscribers
+    let changed = match &mut transaction {
+        Transaction::Sqlite(transaction) => sqlx::query!(
+            r#"
+            UPDATE questions SET category=?1, question=?2, answer=?3, hidden=?4
+            WHERE questions.id = ?5 AND deleted_at IS NULL
+            AND (category IS NOT ?1 OR question IS NOT ?2 OR answer IS NOT ?3 OR hidden IS NOT ?4)
+            "#,
+            category,
+            question,
+            answer,
+            hidden,
+            id,
+        )
+        .execute(&mut **transaction)
+        .await?
+        .rows_affected(),
+        Transaction::Postgres(transaction) => sqlx::query(
+            r#"
+            UPDATE questions SET category=$1, question=$2, answer=$3, hidden=$4
+            WHERE questions.id = $5 AND deleted_at IS NULL
+            AND (category IS DISTINCT FROM $1 OR question IS DISTINCT FROM $2 OR answer IS DISTINCT FROM $3
+                OR hidden IS DISTINCT FROM $4)
+            "#,
+        )
+        .bind(category)
+        .bind(question)
+        .bind(answer)
+        .bind(hidden)
+        .bind(id)
+        .execute(&mut **transaction)
+        .await?
+        .rows_affected(),
+    };
+    if changed > 0 {
+        record_question_change(&mut transaction, id, false).await?;
+        record_revision(&mut transaction, Entity::Question, id, old, author).await?;
+    }
+    transaction.commit().await?;
     Ok(())
 }
 
-pub async fn delete_question(pool: &SqlitePool, question_id: i64) -> sqlx::Result<()> {
-    let mut conn = pool.acquire().await?;
+// Updates the question with the same text in the category or creates a new one.
+// Returns true when the question is created
+pub async fn upsert_question(
+    transaction: &mut Transaction,
+    category: Option<i64>,
+    question: &str,
+    answer: &str,
+    hidden: bool,
+    ordering: i64,
+    author: &str,
+) -> sqlx::Result<bool> {
+    let existing = match &mut *transaction {
+        Transaction::Sqlite(transaction) => {
+            sqlx::query_scalar!(
+                r#"
+                SELECT id FROM questions WHERE category IS ?1 AND question = ?2 AND deleted_at IS NULL
+                ORDER BY id LIMIT 1
+                "#,
+                category,
+                question,
+            )
+            .fetch_optional(&mut **transaction)
+            .await?
+        }
+        Transaction::Postgres(transaction) => {
+            sqlx::query_scalar(
+                r#"
+                SELECT id FROM questions WHERE category IS NOT DISTINCT FROM $1 AND question = $2 AND deleted_at IS NULL
+                ORDER BY id LIMIT 1
+                "#,
+            )
+            .bind(category)
+            .bind(question)
+            .fetch_optional(&mut **transaction)
+            .await?
+        }
+    };
+    match existing {
+        Some(id) => {
+            let old = get_state(transaction, Entity::Question, id).await?;
+            // new ordering alone does not notify the subscribers
+            let changed = match &mut *transaction {
+                Transaction::Sqlite(transaction) => {
+                    let changed = sqlx::query_scalar!(
+                        r#"
+                        SELECT answer IS NOT ?1 OR hidden IS NOT ?2 as "changed!: bool" FROM questions WHERE id = ?3
+                        "#,
+                        answer,
+                        hidden,
+                        id,
+                    )
+                    .fetch_one(&mut **transaction)
+                    .await?;
+                    sqlx::query!(
+                        r#"
+                        UPDATE questions SET answer=?1, hidden=?2, ordering=?3 WHERE questions.id = ?4
+                        "#,
+                        answer,
+                        hidden,
+                        ordering,
+                        id,
+                    )
+                    .execute(&mut **transaction)
+                    .await?;
+                    changed
+                }
+                Transaction::Postgres(transaction) => {
+                    let changed = sqlx::query_scalar(
+                        r#"
+                        SELECT answer IS DISTINCT FROM $1 OR hidden IS DISTINCT FROM $2 FROM questions WHERE id = $3
+                        "#,
+                    )
+                    .bind(answer)
+                    .bind(hidden)
+                    .bind(id)
+                    .fetch_one(&mut **transaction)
+                    .await?;
+                    sqlx::query(
+                        r#"
+                        UPDATE questions SET answer=$1, hidden=$2, ordering=$3 WHERE questions.id = $4
+                        "#,
+                    )
+                    .bind(answer)
+                    .bind(hidden)
+                    .bind(ordering)
+                    .bind(id)
+                    .execute(&mut **transaction)
+                    .await?;
+                    changed
+                }
+            };
+            if changed {
+                record_question_change(transaction, id, false).await?;
+                record_revision(transaction, Entity::Question, id, old, author).await?;
+            }
+            Ok(false)
+        }
+        None => {
+            let id = match &mut *transaction {
+                Transaction::Sqlite(transaction) => sqlx::query!(
+                    r#"
+                    INSERT INTO questions (category, question, answer, hidden, ordering) VALUES (?1, ?2, ?3, ?4, ?5)
+                    "#,
+                    category,
+                    question,
+                    answer,
+                    hidden,
+                    ordering,
+                )
+                .execute(&mut **transaction)
+                .await?
+                .last_insert_rowid(),
+                Transaction::Postgres(transaction) => {
+                    sqlx::query_scalar(
+                        r#"
+                        INSERT INTO questions (category, question, answer, hidden, ordering) VALUES ($1, $2, $3, $4, $5)
+                        RETURNING id
+                        "#,
+                    )
+                    .bind(category)
+                    .bind(question)
+                    .bind(answer)
+                    .bind(hidden)
+                    .bind(ordering)
+                    .fetch_one(&mut **transaction)
+                    .await?
+                }
+            };
+            record_question_change(transaction, id, true).await?;
+            record_revision(transaction, Entity::Question, id, None, author).await?;
+            Ok(true)
+        }
+    }
+}
 
-    sqlx::query!(
-        r#"
-        DELETE FROM questions WHERE questions.id = ?1
-        "#,
-        question_id,
-    )
-    .execute(&mut *conn)
-    .await?;
+// Moves the question to the trash, its attachments are kept until it is purged
+pub async fn delete_question(pool: &Pool, question_id: i64, author: &str) -> sqlx::Result<()> {
+    let mut transaction = pool.begin().await?;
+    move_to_trash(&mut transaction, question_id, author).await?;
+    transaction.commit().await?;
     Ok(())
 }
 
-pub async fn reorder_questions(pool: &SqlitePool, questions: Vec<Reorder>) -> sqlx::Result<()> {
+async fn move_to_trash(
+    transaction: &mut Transaction,
+    question_id: i64,
+    author: &str,
+) -> sqlx::Result<()> {
+    let old = get_state(transaction, Entity::Question, question_id).await?;
+    match &mut *transaction {
+        Transaction::Sqlite(transaction) => {
+            sqlx::query!(
+                r#"
+                UPDATE questions SET deleted_at = CURRENT_TIMESTAMP, deleted_by = ?2
+                WHERE questions.id = ?1 AND deleted_at IS NULL
+                "#,
+                question_id,
+                author,
+            )
+            .execute(&mut **transaction)
+            .await?;
+        }
+        Transaction::Postgres(transaction) => {
+            sqlx::query(
+                r#"
+                UPDATE questions SET deleted_at = utc_timestamp(), deleted_by = $2
+                WHERE questions.id = $1 AND deleted_at IS NULL
+                "#,
+            )
+            .bind(question_id)
+            .bind(author)
+            .execute(&mut **transaction)
+            .await?;
+        }
+    }
+    record_revision(transaction, Entity::Question, question_id, old, author).await
+}
+
+pub async fn reorder_questions(pool: &Pool, questions: Vec<Reorder>) -> sqlx::Result<()> {
     let mut transaction = pool.begin().await?;
     for question in questions {
-        sqlx::query!(
-            r#"
-            UPDATE questions SET ordering=?1 WHERE questions.id = ?2
-            "#,
-            question.ordering,
-            question.id,
-        )
-        .execute(&mut *transaction)
-        .await?;
+        match &mut transaction {
+            Transaction::Sqlite(transaction) => {
+                sqlx::query!(
+                    r#"
+                    UPDATE questions SET ordering=?1 WHERE questions.id = ?2
+                    "#,
+                    question.ordering,
+                    question.id,
+                )
+                .execute(&mut **transaction)
+                .await?;
+            }
+            Transaction::Postgres(transaction) => {
+                sqlx::query(
+                    r#"
+                    UPDATE questions SET ordering=$1 WHERE questions.id = $2
+                    "#,
+                )
+                .bind(question.ordering)
+                .bind(question.id)
+                .execute(&mut **transaction)
+                .await?;
+            }
+        }
     }
     transaction.commit().await?;
     Ok(())
 }
 
-pub async fn import_questions(pool: &SqlitePool, questions: Vec<Question>) -> sqlx::Result<()> {
-    let existing_questions = get_all_questions(pool).await?;
-    let existing_questions_ids: HashSet<i64> = existing_questions.iter().map(|q| q.id).collect();
+// Replaces all the questions with the given ones, ids are preserved and the missing ones
+// are moved to the trash. The attachments are imported separately, with their blobs
+pub async fn import_questions(
+    transaction: &mut Transaction,
+    questions: Vec<Question>,
+    author: &str,
+) -> sqlx::Result<()> {
     let new_questions_ids: HashSet<i64> = questions.iter().map(|q| q.id).collect();
-    for question_id in existing_questions_ids.difference(&new_questions_ids) {
-        delete_question(pool, *question_id).await?;
-    }
-    for question in questions {
-        if existing_questions_ids.contains(&question.id) {
-            update_question(
-                pool,
-                question.id,
-                question.category.map(|c| c.id),
-                question.question,
-                question.answer,
-                question.attachments.iter().map(|x| x.as_str()).collect(),
-                question.hidden,
+    let existing_questions_ids: Vec<i64> = match &mut *transaction {
+        Transaction::Sqlite(transaction) => {
+            sqlx::query_scalar!(
+                r#"
+                SELECT id FROM questions WHERE deleted_at IS NULL
+                "#
             )
-            .await?;
-        } else {
-            create_question(
-                pool,
-                question.question.as_str(),
-                question.answer.as_str(),
-                question.category.map(|c| c.id),
-                question.attachments.iter().map(|x| x.as_str()).collect(),
-                question.hidden,
-                question.ordering,
+            .fetch_all(&mut **transaction)
+            .await?
+        }
+        Transaction::Postgres(transaction) => {
+            sqlx::query_scalar(
+                r#"
+                SELECT id FROM questions WHERE deleted_at IS NULL
+                "#,
             )
-            .await?;
+            .fetch_all(&mut **transaction)
+            .await?
         }
+    };
+    for question_id in existing_questions_ids
+        .into_iter()
+        .filter(|id| !new_questions_ids.contains(id))
+    {
+        move_to_trash(transaction, question_id, author).await?;
+    }
+    for question in questions {
+        let old = get_state(transaction, Entity::Question, question.id).await?;
+        let category = question.category.map(|c| c.id);
+        match &mut *transaction {
+            Transaction::Sqlite(transaction) => {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO questions (id, category, question, answer, hidden, ordering) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                    ON CONFLICT(id) DO UPDATE SET category=excluded.category, question=excluded.question, answer=excluded.answer,
+                        hidden=excluded.hidden, ordering=excluded.ordering, deleted_at=NULL, deleted_by=NULL
+                    "#,
+                    question.id,
+                    category,
+                    question.question,
+                    question.answer,
+                    question.hidden,
+                    question.ordering,
+                )
+                .execute(&mut **transaction)
+                .await?;
+            }
+            Transaction::Postgres(transaction) => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO questions (id, category, question, answer, hidden, ordering) VALUES ($1, $2, $3, $4, $5, $6)
+                    ON CONFLICT(id) DO UPDATE SET category=excluded.category, question=excluded.question, answer=excluded.answer,
+                        hidden=excluded.hidden, ordering=excluded.ordering, deleted_at=NULL, deleted_by=NULL
+                    "#,
+                )
+                .bind(question.id)
+                .bind(category)
+                .bind(question.question)
+                .bind(question.answer)
+                .bind(question.hidden)
+                .bind(question.ordering)
+                .execute(&mut **transaction)
+                .await?;
+            }
+        }
+        record_revision(transaction, Entity::Question, question.id, old, author).await?;
+    }
+    // the ids are set explicitly, so the new questions continue after the imported ones
+    if let Transaction::Postgres(transaction) = transaction {
+        sqlx::query(
+            r#"
+            SELECT setval(pg_get_serial_sequence('questions', 'id'), COALESCE(MAX(id), 0) + 1, false) FROM questions
+            "#,
+        )
+        .execute(&mut **transaction)
+        .await?;
     }
     Ok(())
 }
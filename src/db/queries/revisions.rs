@@ -0,0 +1,409 @@
+use serde::{Deserialize, Serialize};
+
+use crate::db::{Pool, Transaction};
+
+use super::subscriptions::record_question_change;
+
+#[derive(sqlx::Type, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(type_name = "entity", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Entity {
+    Question,
+    Category,
+}
+
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(type_name = "revision_action", rename_all = "lowercase")]
+pub enum RevisionAction {
+    Create,
+    Update,
+    Delete,
+}
+
+impl std::fmt::Display for Entity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Question => write!(f, "question"),
+            Self::Category => write!(f, "category"),
+        }
+    }
+}
+
+impl std::fmt::Display for RevisionAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Create => write!(f, "created"),
+            Self::Update => write!(f, "updated"),
+            Self::Delete => write!(f, "deleted"),
+        }
+    }
+}
+
+// Versioned fields of the question. Attachments are files, so they are not versioned
+#[derive(Serialize, Deserialize, PartialEq, sqlx::FromRow)]
+pub struct QuestionState {
+    pub category: Option<i64>,
+    pub question: String,
+    pub answer: String,
+    pub hidden: bool,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, sqlx::FromRow)]
+pub struct CategoryState {
+    pub name: String,
+    pub hidden: bool,
+    pub parent: Option<i64>,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct Revision {
+    pub id: i64,
+    pub entity: Entity,
+    pub entity_id: i64,
+    pub action: RevisionAction,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub author: String,
+    pub restored_from: Option<i64>,
+    pub created_at: String,
+}
+
+impl Revision {
+    // Values the item has after the revision, the deleted item is restored to the values
+    // it had before the deletion
+    pub fn restorable_value(&self) -> Option<&str> {
+        self.new_value.as_deref().or(self.old_value.as_deref())
+    }
+}
+
+// JSON of the current values of the item, None when it does not exist.
+// Taken before the change and passed to `record_revision` after it
+pub async fn get_state(
+    transaction: &mut Transaction,
+    entity: Entity,
+    id: i64,
+) -> sqlx::Result<Option<String>> {
+    let state = match (entity, transaction) {
+        (Entity::Question, Transaction::Sqlite(transaction)) => sqlx::query_as!(
+            QuestionState,
+            r#"
+            SELECT category, question, answer, hidden FROM questions WHERE id = ?1 AND deleted_at IS NULL
+            "#,
+            id
+        )
+        .fetch_optional(&mut **transaction)
+        .await?
+        .map(|state| serde_json::to_string(&state)),
+        (Entity::Question, Transaction::Postgres(transaction)) => sqlx::query_as::<_, QuestionState>(
+            r#"
+            SELECT category, question, answer, hidden FROM questions WHERE id = $1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&mut **transaction)
+        .await?
+        .map(|state| serde_json::to_string(&state)),
+        (Entity::Category, Transaction::Sqlite(transaction)) => sqlx::query_as!(
+            CategoryState,
+            r#"
+            SELECT name, hidden, parent FROM categories WHERE id = ?1 AND deleted_at IS NULL
+            "#,
+            id
+        )
+        .fetch_optional(&mut **transaction)
+        .await?
+        .map(|state| serde_json::to_string(&state)),
+        (Entity::Category, Transaction::Postgres(transaction)) => sqlx::query_as::<_, CategoryState>(
+            r#"
+            SELECT name, hidden, parent FROM categories WHERE id = $1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&mut **transaction)
+        .await?
+        .map(|state| serde_json::to_string(&state)),
+    };
+    Ok(state.transpose().expect("State is always serializable"))
+}
+
+async fn insert_revision(
+    transaction: &mut Transaction,
+    entity: Entity,
+    id: i64,
+    old: Option<String>,
+    author: &str,
+    restored_from: Option<i64>,
+) -> sqlx::Result<()> {
+    let new = get_state(transaction, entity, id).await?;
+    let action = match (&old, &new) {
+        (None, None) => return Ok(()),
+        (None, Some(_)) => RevisionAction::Create,
+        (Some(_), None) => RevisionAction::Delete,
+        // saving the item without the changes is not a revision
+        (Some(old), Some(new)) if old == new => return Ok(()),
+        (Some(_), Some(_)) => RevisionAction::Update,
+    };
+    match transaction {
+        Transaction::Sqlite(transaction) => {
+            sqlx::query!(
+                r#"
+                INSERT INTO revisions (entity, entity_id, action, old_value, new_value, author, restored_from)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                "#,
+                entity,
+                id,
+                action,
+                old,
+                new,
+                author,
+                restored_from
+            )
+            .execute(&mut **transaction)
+            .await?;
+        }
+        Transaction::Postgres(transaction) => {
+            sqlx::query(
+                r#"
+                INSERT INTO revisions (entity, entity_id, action, old_value, new_value, author, restored_from)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                "#,
+            )
+            .bind(entity)
+            .bind(id)
+            .bind(action)
+            .bind(old)
+            .bind(new)
+            .bind(author)
+            .bind(restored_from)
+            .execute(&mut **transaction)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+// Records the change of the item from the `old` state to the current one
+pub async fn record_revision(
+    transaction: &mut Transaction,
+    entity: Entity,
+    id: i64,
+    old: Option<String>,
+    author: &str,
+) -> sqlx::Result<()> {
+    insert_revision(transaction, entity, id, old, author, None).await
+}
+
+pub async fn get_revision(pool: &Pool, id: i64) -> sqlx::Result<Revision> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                Revision,
+                r#"
+                SELECT id, entity as "entity: Entity", entity_id, action as "action: RevisionAction",
+                    old_value, new_value, author, restored_from, created_at
+                FROM revisions WHERE id = ?1
+                "#,
+                id
+            )
+            .fetch_one(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT id, entity, entity_id, action, old_value, new_value, author, restored_from, created_at
+                FROM revisions WHERE id = $1
+                "#,
+            )
+            .bind(id)
+            .fetch_one(pool)
+            .await
+        }
+    }
+}
+
+// Revisions of the item, the latest first
+pub async fn get_revisions(
+    pool: &Pool,
+    entity: Entity,
+    entity_id: i64,
+) -> sqlx::Result<Vec<Revision>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                Revision,
+                r#"
+                SELECT id, entity as "entity: Entity", entity_id, action as "action: RevisionAction",
+                    old_value, new_value, author, restored_from, created_at
+                FROM revisions WHERE entity = ?1 AND entity_id = ?2
+                ORDER BY id DESC
+                "#,
+                entity,
+                entity_id
+            )
+            .fetch_all(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT id, entity, entity_id, action, old_value, new_value, author, restored_from, created_at
+                FROM revisions WHERE entity = $1 AND entity_id = $2
+                ORDER BY id DESC
+                "#,
+            )
+            .bind(entity)
+            .bind(entity_id)
+            .fetch_all(pool)
+            .await
+        }
+    }
+}
+
+pub async fn get_latest_revisions(pool: &Pool, limit: i64) -> sqlx::Result<Vec<Revision>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                Revision,
+                r#"
+                SELECT id, entity as "entity: Entity", entity_id, action as "action: RevisionAction",
+                    old_value, new_value, author, restored_from, created_at
+                FROM revisions ORDER BY id DESC LIMIT ?1
+                "#,
+                limit
+            )
+            .fetch_all(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT id, entity, entity_id, action, old_value, new_value, author, restored_from, created_at
+                FROM revisions ORDER BY id DESC LIMIT $1
+                "#,
+            )
+            .bind(limit)
+            .fetch_all(pool)
+            .await
+        }
+    }
+}
+
+// Brings the item back to the values of the revision, taking it from the trash or creating it
+// again when it is deleted. References to the categories that don't exist anymore are cleared
+pub async fn restore_revision(pool: &Pool, id: i64, author: &str) -> sqlx::Result<()> {
+    let revision = get_revision(pool, id).await?;
+    let value = revision
+        .restorable_value()
+        .ok_or(sqlx::Error::RowNotFound)?;
+    let decode = |err| sqlx::Error::Decode(Box::new(err));
+    let mut transaction = pool.begin().await?;
+    let old = get_state(&mut transaction, revision.entity, revision.entity_id).await?;
+    match revision.entity {
+        Entity::Question => {
+            let state: QuestionState = serde_json::from_str(value).map_err(decode)?;
+            match &mut transaction {
+                Transaction::Sqlite(transaction) => {
+                    sqlx::query!(
+                        r#"
+                        INSERT INTO questions (id, category, question, answer, hidden)
+                        VALUES (?1, (SELECT id FROM categories WHERE id = ?2 AND deleted_at IS NULL), ?3, ?4, ?5)
+                        ON CONFLICT(id) DO UPDATE SET category=excluded.category, question=excluded.question,
+                            answer=excluded.answer, hidden=excluded.hidden, deleted_at=NULL, deleted_by=NULL
+                        "#,
+                        revision.entity_id,
+                        state.category,
+                        state.question,
+                        state.answer,
+                        state.hidden
+                    )
+                    .execute(&mut **transaction)
+                    .await?;
+                }
+                Transaction::Postgres(transaction) => {
+                    sqlx::query(
+                        r#"
+                        INSERT INTO questions (id, category, question, answer, hidden)
+                        VALUES ($1, (SELECT id FROM categories WHERE id = $2 AND deleted_at IS NULL), $3, $4, $5)
+                        ON CONFLICT(id) DO UPDATE SET category=excluded.category, question=excluded.question,
+                            answer=excluded.answer, hidden=excluded.hidden, deleted_at=NULL, deleted_by=NULL
+                        "#,
+                    )
+                    .bind(revision.entity_id)
+                    .bind(state.category)
+                    .bind(state.question)
+                    .bind(state.answer)
+                    .bind(state.hidden)
+                    .execute(&mut **transaction)
+                    .await?;
+                }
+            }
+            record_question_change(&mut transaction, revision.entity_id, old.is_none()).await?;
+        }
+        Entity::Category => {
+            let state: CategoryState = serde_json::from_str(value).map_err(decode)?;
+            // the parent can't be the category itself or one of its children
+            match &mut transaction {
+                Transaction::Sqlite(transaction) => {
+                    sqlx::query!(
+                        r#"
+                        INSERT INTO categories (id, name, hidden, parent)
+                        VALUES (?1, ?2, ?3, (
+                            WITH RECURSIVE subtree(id) AS (
+                                SELECT ?1
+                                UNION ALL
+                                SELECT categories.id FROM categories JOIN subtree ON categories.parent = subtree.id
+                            )
+                            SELECT id FROM categories
+                            WHERE id = ?4 AND deleted_at IS NULL AND id NOT IN (SELECT id FROM subtree)
+                        ))
+                        ON CONFLICT(id) DO UPDATE SET name=excluded.name, hidden=excluded.hidden, parent=excluded.parent,
+                            deleted_at=NULL, deleted_by=NULL
+                        "#,
+                        revision.entity_id,
+                        state.name,
+                        state.hidden,
+                        state.parent
+                    )
+                    .execute(&mut **transaction)
+                    .await?;
+                }
+                Transaction::Postgres(transaction) => {
+                    sqlx::query(
+                        r#"
+                        INSERT INTO categories (id, name, hidden, parent)
+                        VALUES ($1, $2, $3, (
+                            WITH RECURSIVE subtree(id) AS (
+                                SELECT $1::BIGINT
+                                UNION ALL
+                                SELECT categories.id FROM categories JOIN subtree ON categories.parent = subtree.id
+                            )
+                            SELECT id FROM categories
+                            WHERE id = $4 AND deleted_at IS NULL AND id NOT IN (SELECT id FROM subtree)
+                        ))
+                        ON CONFLICT(id) DO UPDATE SET name=excluded.name, hidden=excluded.hidden, parent=excluded.parent,
+                            deleted_at=NULL, deleted_by=NULL
+                        "#,
+                    )
+                    .bind(revision.entity_id)
+                    .bind(state.name)
+                    .bind(state.hidden)
+                    .bind(state.parent)
+                    .execute(&mut **transaction)
+                    .await?;
+                }
+            }
+        }
+    }
+    insert_revision(
+        &mut transaction,
+        revision.entity,
+        revision.entity_id,
+        old,
+        author,
+        Some(revision.id),
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
@@ -0,0 +1,121 @@
+use crate::db::Pool;
+
+use super::users::User;
+
+pub async fn create_session(
+    pool: &Pool,
+    token_hash: &str,
+    user_id: i64,
+    ttl_seconds: i64,
+) -> sqlx::Result<()> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query!(
+                r#"
+                INSERT INTO sessions (token_hash, user_id, expires_at) VALUES (?1, ?2, unixepoch() + ?3)
+                "#,
+                token_hash,
+                user_id,
+                ttl_seconds
+            )
+            .execute(pool)
+            .await?;
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query(
+                r#"
+                INSERT INTO sessions (token_hash, user_id, expires_at) VALUES ($1, $2, unixepoch() + $3)
+                "#,
+            )
+            .bind(token_hash)
+            .bind(user_id)
+            .bind(ttl_seconds)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+// Session is valid only while the user stays an active admin
+pub async fn get_session_admin(pool: &Pool, token_hash: &str) -> sqlx::Result<Option<User>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                User,
+                r#"
+                SELECT users.* FROM sessions
+                JOIN users ON users.id = sessions.user_id
+                WHERE sessions.token_hash = ?1 AND sessions.expires_at > unixepoch()
+                    AND users.is_admin AND users.active
+                "#,
+                token_hash
+            )
+            .fetch_optional(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT users.* FROM sessions
+                JOIN users ON users.id = sessions.user_id
+                WHERE sessions.token_hash = $1 AND sessions.expires_at > unixepoch()
+                    AND users.is_admin AND users.active
+                "#,
+            )
+            .bind(token_hash)
+            .fetch_optional(pool)
+            .await
+        }
+    }
+}
+
+pub async fn delete_session(pool: &Pool, token_hash: &str) -> sqlx::Result<()> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query!(
+                r#"
+                DELETE FROM sessions WHERE token_hash = ?1
+                "#,
+                token_hash
+            )
+            .execute(pool)
+            .await?;
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query(
+                r#"
+                DELETE FROM sessions WHERE token_hash = $1
+                "#,
+            )
+            .bind(token_hash)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn delete_expired_sessions(pool: &Pool) -> sqlx::Result<()> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query!(
+                r#"
+                DELETE FROM sessions WHERE expires_at <= unixepoch()
+                "#
+            )
+            .execute(pool)
+            .await?;
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query(
+                r#"
+                DELETE FROM sessions WHERE expires_at <= unixepoch()
+                "#,
+            )
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
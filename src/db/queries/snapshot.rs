@@ -0,0 +1,79 @@
+use crate::db::{Category, Pool, Question, Transaction, User};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use super::{
+    attachments::{self, NewAttachment},
+    categories, groups,
+    groups::Group,
+    questions,
+    translations::{self, CategoryTranslation, QuestionTranslation},
+    users,
+};
+
+// Whole content of the bot, used to move it between the instances
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub categories: Vec<Category>,
+    pub questions: Vec<Question>,
+    pub users: Vec<User>,
+    // missing in the archives made before the groups were added
+    #[serde(default)]
+    pub groups: Vec<Group>,
+    // missing in the archives made before the translations were added
+    #[serde(default)]
+    pub category_translations: Vec<CategoryTranslation>,
+    #[serde(default)]
+    pub question_translations: Vec<QuestionTranslation>,
+}
+
+pub async fn get_snapshot(pool: &Pool) -> sqlx::Result<Snapshot> {
+    Ok(Snapshot {
+        categories: categories::get_all_categories(pool).await?,
+        questions: questions::get_all_questions(pool).await?,
+        users: users::get_users(pool).await?,
+        groups: groups::get_groups(pool).await?,
+        category_translations: translations::get_all_category_translations(pool).await?,
+        question_translations: translations::get_all_question_translations(pool).await?,
+    })
+}
+
+// Replaces everything in the database with the snapshot content in a single transaction.
+// The attachments are the files of the archive already stored in the blobs.
+// Returns the blobs of the replaced attachments that are not used anymore
+pub async fn restore_snapshot(
+    pool: &Pool,
+    snapshot: Snapshot,
+    attachments: Vec<(i64, NewAttachment)>,
+    author: &str,
+) -> sqlx::Result<Vec<String>> {
+    let mut transaction = pool.begin().await?;
+    // categories reference each other, so the order of insertion can't satisfy the constraints
+    match &mut transaction {
+        Transaction::Sqlite(transaction) => {
+            sqlx::query!("PRAGMA defer_foreign_keys = ON")
+                .execute(&mut **transaction)
+                .await?;
+        }
+        Transaction::Postgres(transaction) => {
+            sqlx::query("SET CONSTRAINTS ALL DEFERRED")
+                .execute(&mut **transaction)
+                .await?;
+        }
+    }
+    categories::import_categories(&mut transaction, snapshot.categories, author).await?;
+    let question_ids: HashSet<i64> = snapshot.questions.iter().map(|q| q.id).collect();
+    questions::import_questions(&mut transaction, snapshot.questions, author).await?;
+    let unused =
+        attachments::import_attachments(&mut transaction, &question_ids, attachments).await?;
+    users::import_users(&mut transaction, snapshot.users).await?;
+    groups::import_groups(&mut transaction, snapshot.groups).await?;
+    translations::import_translations(
+        &mut transaction,
+        snapshot.category_translations,
+        snapshot.question_translations,
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(unused)
+}
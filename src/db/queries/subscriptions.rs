@@ -0,0 +1,256 @@
+use crate::db::{Pool, Transaction};
+
+// Question changed since the last digest, that the subscriber can see
+#[derive(sqlx::FromRow)]
+pub struct DigestItem {
+    pub user_id: i64,
+    pub question_id: i64,
+    pub question: String,
+    pub category_id: i64,
+    pub category: String,
+    pub created: bool,
+}
+
+// Seconds passed since the first and the last change waiting for the digest
+pub struct PendingChanges {
+    pub now: i64,
+    pub since_first: i64,
+    pub since_last: i64,
+}
+
+pub async fn is_subscribed(pool: &Pool, user_id: i64, category_id: i64) -> sqlx::Result<bool> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            let subscribed = sqlx::query_scalar!(
+                r#"
+                SELECT EXISTS(SELECT 1 FROM subscriptions WHERE user_id = ?1 AND category_id = ?2)
+                "#,
+                user_id,
+                category_id
+            )
+            .fetch_one(pool)
+            .await?;
+            Ok(subscribed == 1)
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_scalar(
+                r#"
+                SELECT EXISTS(SELECT 1 FROM subscriptions WHERE user_id = $1 AND category_id = $2)
+                "#,
+            )
+            .bind(user_id)
+            .bind(category_id)
+            .fetch_one(pool)
+            .await
+        }
+    }
+}
+
+pub async fn subscribe(pool: &Pool, user_id: i64, category_id: i64) -> sqlx::Result<()> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query!(
+                r#"
+                INSERT INTO subscriptions (user_id, category_id) VALUES (?1, ?2)
+                ON CONFLICT(user_id, category_id) DO NOTHING
+                "#,
+                user_id,
+                category_id
+            )
+            .execute(pool)
+            .await?;
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query(
+                r#"
+                INSERT INTO subscriptions (user_id, category_id) VALUES ($1, $2)
+                ON CONFLICT(user_id, category_id) DO NOTHING
+                "#,
+            )
+            .bind(user_id)
+            .bind(category_id)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn unsubscribe(pool: &Pool, user_id: i64, category_id: i64) -> sqlx::Result<()> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query!(
+                r#"
+                DELETE FROM subscriptions WHERE user_id = ?1 AND category_id = ?2
+                "#,
+                user_id,
+                category_id
+            )
+            .execute(pool)
+            .await?;
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query(
+                r#"
+                DELETE FROM subscriptions WHERE user_id = $1 AND category_id = $2
+                "#,
+            )
+            .bind(user_id)
+            .bind(category_id)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+// Hidden questions are not recorded, subscribers can't see them anyway.
+// Repeated changes keep the question in the same digest
+pub async fn record_question_change(
+    transaction: &mut Transaction,
+    question_id: i64,
+    created: bool,
+) -> sqlx::Result<()> {
+    match transaction {
+        Transaction::Sqlite(transaction) => {
+            sqlx::query!(
+                r#"
+                INSERT INTO question_changes (question_id, created)
+                SELECT id, ?2 FROM questions WHERE id = ?1 AND hidden = FALSE AND deleted_at IS NULL
+                ON CONFLICT(question_id) DO UPDATE SET changed_at = unixepoch()
+                "#,
+                question_id,
+                created
+            )
+            .execute(&mut **transaction)
+            .await?;
+        }
+        Transaction::Postgres(transaction) => {
+            sqlx::query(
+                r#"
+                INSERT INTO question_changes (question_id, created)
+                SELECT id, $2 FROM questions WHERE id = $1 AND hidden = FALSE AND deleted_at IS NULL
+                ON CONFLICT(question_id) DO UPDATE SET changed_at = unixepoch()
+                "#,
+            )
+            .bind(question_id)
+            .bind(created)
+            .execute(&mut **transaction)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn get_pending_changes(pool: &Pool) -> sqlx::Result<Option<PendingChanges>> {
+    let (now, since_first, since_last): (i64, Option<i64>, Option<i64>) = match pool {
+        Pool::Sqlite(pool) => {
+            let row = sqlx::query!(
+                r#"
+                SELECT unixepoch() as "now!: i64",
+                    unixepoch() - MIN(changed_at) as "since_first: i64",
+                    unixepoch() - MAX(changed_at) as "since_last: i64"
+                FROM question_changes
+                "#
+            )
+            .fetch_one(pool)
+            .await?;
+            (row.now, row.since_first, row.since_last)
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT unixepoch(), unixepoch() - MIN(changed_at), unixepoch() - MAX(changed_at)
+                FROM question_changes
+                "#,
+            )
+            .fetch_one(pool)
+            .await?
+        }
+    };
+    Ok(match (since_first, since_last) {
+        (Some(since_first), Some(since_last)) => Some(PendingChanges {
+            now,
+            since_first,
+            since_last,
+        }),
+        _ => None,
+    })
+}
+
+// Removes the changes made before `until` and returns them for every subscriber
+// that can see the question. Subscription to the category includes its subcategories
+pub async fn take_digest(
+    pool: &Pool,
+    until: i64,
+    only_active: bool,
+) -> sqlx::Result<Vec<DigestItem>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            let mut transaction = pool.begin().await?;
+            let items = sqlx::query_as!(
+                DigestItem,
+                r#"
+                SELECT DISTINCT subscriptions.user_id, questions.id as "question_id!", questions.question,
+                    categories.id as category_id, categories.name as category, question_changes.created
+                FROM question_changes
+                JOIN questions ON questions.id = question_changes.question_id
+                JOIN categories ON categories.id = questions.category
+                JOIN category_ancestors ON category_ancestors.category_id = questions.category
+                JOIN subscriptions ON subscriptions.category_id = category_ancestors.ancestor_id
+                JOIN accessible_categories ON accessible_categories.user_id = subscriptions.user_id
+                    AND accessible_categories.category_id = questions.category
+                JOIN users ON users.id = subscriptions.user_id
+                WHERE question_changes.changed_at <= ?1 AND questions.hidden = FALSE AND questions.deleted_at IS NULL
+                    AND (users.active OR NOT ?2)
+                    AND subscriptions.user_id NOT IN (SELECT user_id FROM blocked_users)
+                ORDER BY subscriptions.user_id, categories.name, questions.question
+                "#,
+                until,
+                only_active
+            )
+            .fetch_all(&mut *transaction)
+            .await?;
+            sqlx::query!(
+                r#"
+                DELETE FROM question_changes WHERE changed_at <= ?1
+                "#,
+                until
+            )
+            .execute(&mut *transaction)
+            .await?;
+            transaction.commit().await?;
+            Ok(items)
+        }
+        // changes are deleted and returned by the single statement, so the instances
+        // sharing the database don't take the same changes and send the digest twice
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                WITH taken AS (
+                    DELETE FROM question_changes WHERE changed_at <= $1
+                    RETURNING question_id, created
+                )
+                SELECT DISTINCT subscriptions.user_id, questions.id as question_id, questions.question,
+                    categories.id as category_id, categories.name as category, taken.created
+                FROM taken
+                JOIN questions ON questions.id = taken.question_id
+                JOIN categories ON categories.id = questions.category
+                JOIN category_ancestors ON category_ancestors.category_id = questions.category
+                JOIN subscriptions ON subscriptions.category_id = category_ancestors.ancestor_id
+                JOIN accessible_categories ON accessible_categories.user_id = subscriptions.user_id
+                    AND accessible_categories.category_id = questions.category
+                JOIN users ON users.id = subscriptions.user_id
+                WHERE questions.hidden = FALSE AND questions.deleted_at IS NULL
+                    AND (users.active OR NOT $2)
+                    AND subscriptions.user_id NOT IN (SELECT user_id FROM blocked_users)
+                ORDER BY subscriptions.user_id, categories.name, questions.question
+                "#,
+            )
+            .bind(until)
+            .bind(only_active)
+            .fetch_all(pool)
+            .await
+        }
+    }
+}
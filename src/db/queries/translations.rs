@@ -0,0 +1,559 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::db::{Category, Pool, Question, Transaction};
+
+#[derive(sqlx::FromRow)]
+pub struct UserLanguage {
+    pub language: Option<String>,
+    pub telegram_language: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, sqlx::FromRow)]
+pub struct CategoryTranslation {
+    pub category_id: i64,
+    pub language: String,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, sqlx::FromRow)]
+pub struct QuestionTranslation {
+    pub question_id: i64,
+    pub language: String,
+    pub question: String,
+    pub answer: String,
+}
+
+pub async fn get_user_language(pool: &Pool, user_id: i64) -> sqlx::Result<Option<UserLanguage>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                UserLanguage,
+                r#"
+                SELECT language, telegram_language FROM user_languages WHERE user_id = ?1
+                "#,
+                user_id
+            )
+            .fetch_optional(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT language, telegram_language FROM user_languages WHERE user_id = $1
+                "#,
+            )
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await
+        }
+    }
+}
+
+pub async fn set_telegram_language(
+    pool: &Pool,
+    user_id: i64,
+    language: Option<&str>,
+) -> sqlx::Result<()> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query!(
+                r#"
+                INSERT INTO user_languages (user_id, telegram_language) VALUES (?1, ?2)
+                ON CONFLICT(user_id) DO UPDATE SET telegram_language = excluded.telegram_language
+                "#,
+                user_id,
+                language
+            )
+            .execute(pool)
+            .await?;
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query(
+                r#"
+                INSERT INTO user_languages (user_id, telegram_language) VALUES ($1, $2)
+                ON CONFLICT(user_id) DO UPDATE SET telegram_language = excluded.telegram_language
+                "#,
+            )
+            .bind(user_id)
+            .bind(language)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+// None goes back to the language of the Telegram client
+pub async fn set_chosen_language(
+    pool: &Pool,
+    user_id: i64,
+    language: Option<&str>,
+) -> sqlx::Result<()> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query!(
+                r#"
+                INSERT INTO user_languages (user_id, language) VALUES (?1, ?2)
+                ON CONFLICT(user_id) DO UPDATE SET language = excluded.language
+                "#,
+                user_id,
+                language
+            )
+            .execute(pool)
+            .await?;
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query(
+                r#"
+                INSERT INTO user_languages (user_id, language) VALUES ($1, $2)
+                ON CONFLICT(user_id) DO UPDATE SET language = excluded.language
+                "#,
+            )
+            .bind(user_id)
+            .bind(language)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn get_category_translations(
+    pool: &Pool,
+    category_id: i64,
+) -> sqlx::Result<Vec<CategoryTranslation>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                CategoryTranslation,
+                r#"
+                SELECT category_id, language, name FROM category_translations WHERE category_id = ?1
+                "#,
+                category_id
+            )
+            .fetch_all(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT category_id, language, name FROM category_translations WHERE category_id = $1
+                "#,
+            )
+            .bind(category_id)
+            .fetch_all(pool)
+            .await
+        }
+    }
+}
+
+pub async fn set_category_translation(
+    pool: &Pool,
+    category_id: i64,
+    language: &str,
+    name: &str,
+) -> sqlx::Result<()> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query!(
+                r#"
+                INSERT INTO category_translations (category_id, language, name) VALUES (?1, ?2, ?3)
+                ON CONFLICT(category_id, language) DO UPDATE SET name = excluded.name
+                "#,
+                category_id,
+                language,
+                name
+            )
+            .execute(pool)
+            .await?;
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query(
+                r#"
+                INSERT INTO category_translations (category_id, language, name) VALUES ($1, $2, $3)
+                ON CONFLICT(category_id, language) DO UPDATE SET name = excluded.name
+                "#,
+            )
+            .bind(category_id)
+            .bind(language)
+            .bind(name)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn delete_category_translation(
+    pool: &Pool,
+    category_id: i64,
+    language: &str,
+) -> sqlx::Result<()> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query!(
+                r#"
+                DELETE FROM category_translations WHERE category_id = ?1 AND language = ?2
+                "#,
+                category_id,
+                language
+            )
+            .execute(pool)
+            .await?;
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query(
+                r#"
+                DELETE FROM category_translations WHERE category_id = $1 AND language = $2
+                "#,
+            )
+            .bind(category_id)
+            .bind(language)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn get_question_translations(
+    pool: &Pool,
+    question_id: i64,
+) -> sqlx::Result<Vec<QuestionTranslation>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                QuestionTranslation,
+                r#"
+                SELECT question_id, language, question, answer FROM question_translations WHERE question_id = ?1
+                "#,
+                question_id
+            )
+            .fetch_all(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT question_id, language, question, answer FROM question_translations WHERE question_id = $1
+                "#,
+            )
+            .bind(question_id)
+            .fetch_all(pool)
+            .await
+        }
+    }
+}
+
+pub async fn set_question_translation(
+    pool: &Pool,
+    question_id: i64,
+    language: &str,
+    question: &str,
+    answer: &str,
+) -> sqlx::Result<()> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query!(
+                r#"
+                INSERT INTO question_translations (question_id, language, question, answer) VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT(question_id, language) DO UPDATE SET question = excluded.question, answer = excluded.answer
+                "#,
+                question_id,
+                language,
+                question,
+                answer
+            )
+            .execute(pool)
+            .await?;
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query(
+                r#"
+                INSERT INTO question_translations (question_id, language, question, answer) VALUES ($1, $2, $3, $4)
+                ON CONFLICT(question_id, language) DO UPDATE SET question = excluded.question, answer = excluded.answer
+                "#,
+            )
+            .bind(question_id)
+            .bind(language)
+            .bind(question)
+            .bind(answer)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn delete_question_translation(
+    pool: &Pool,
+    question_id: i64,
+    language: &str,
+) -> sqlx::Result<()> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query!(
+                r#"
+                DELETE FROM question_translations WHERE question_id = ?1 AND language = ?2
+                "#,
+                question_id,
+                language
+            )
+            .execute(pool)
+            .await?;
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query(
+                r#"
+                DELETE FROM question_translations WHERE question_id = $1 AND language = $2
+                "#,
+            )
+            .bind(question_id)
+            .bind(language)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+// Names of the categories translated to the language by the category id
+pub async fn get_category_names(pool: &Pool, language: &str) -> sqlx::Result<HashMap<i64, String>> {
+    let names: Vec<(i64, String)> = match pool {
+        Pool::Sqlite(pool) => sqlx::query!(
+            r#"
+            SELECT category_id, name FROM category_translations WHERE language = ?1
+            "#,
+            language
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| (row.category_id, row.name))
+        .collect(),
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT category_id, name FROM category_translations WHERE language = $1
+                "#,
+            )
+            .bind(language)
+            .fetch_all(pool)
+            .await?
+        }
+    };
+    Ok(names.into_iter().collect())
+}
+
+// Questions translated to the language by the question id
+pub async fn get_translated_questions(
+    pool: &Pool,
+    language: &str,
+) -> sqlx::Result<HashMap<i64, QuestionTranslation>> {
+    let translations: Vec<QuestionTranslation> = match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                QuestionTranslation,
+                r#"
+                SELECT question_id, language, question, answer FROM question_translations WHERE language = ?1
+                "#,
+                language
+            )
+            .fetch_all(pool)
+            .await?
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT question_id, language, question, answer FROM question_translations WHERE language = $1
+                "#,
+            )
+            .bind(language)
+            .fetch_all(pool)
+            .await?
+        }
+    };
+    Ok(translations
+        .into_iter()
+        .map(|translation| (translation.question_id, translation))
+        .collect())
+}
+
+// Replaces the names of the categories translated to the language, the rest keep the default one
+pub async fn translate_categories(
+    pool: &Pool,
+    language: &str,
+    categories: &mut [Category],
+) -> sqlx::Result<()> {
+    let names = get_category_names(pool, language).await?;
+    for category in categories {
+        if let Some(name) = names.get(&category.id) {
+            category.name = name.clone();
+        }
+    }
+    Ok(())
+}
+
+// Same for the questions and their categories
+pub async fn translate_questions(
+    pool: &Pool,
+    language: &str,
+    questions: &mut [Question],
+) -> sqlx::Result<()> {
+    let translations = get_translated_questions(pool, language).await?;
+    let names = get_category_names(pool, language).await?;
+    for question in questions {
+        if let Some(translation) = translations.get(&question.id) {
+            question.question = translation.question.clone();
+            question.answer = translation.answer.clone();
+        }
+        if let Some(category) = question.category.as_mut() {
+            if let Some(name) = names.get(&category.id) {
+                category.name = name.clone();
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn get_all_category_translations(pool: &Pool) -> sqlx::Result<Vec<CategoryTranslation>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                CategoryTranslation,
+                r#"
+                SELECT category_id, language, name FROM category_translations ORDER BY category_id, language
+                "#
+            )
+            .fetch_all(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT category_id, language, name FROM category_translations ORDER BY category_id, language
+                "#,
+            )
+            .fetch_all(pool)
+            .await
+        }
+    }
+}
+
+pub async fn get_all_question_translations(pool: &Pool) -> sqlx::Result<Vec<QuestionTranslation>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                QuestionTranslation,
+                r#"
+                SELECT question_id, language, question, answer FROM question_translations ORDER BY question_id, language
+                "#
+            )
+            .fetch_all(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT question_id, language, question, answer FROM question_translations ORDER BY question_id, language
+                "#,
+            )
+            .fetch_all(pool)
+            .await
+        }
+    }
+}
+
+// Replaces all the translations with the given ones
+pub async fn import_translations(
+    transaction: &mut Transaction,
+    categories: Vec<CategoryTranslation>,
+    questions: Vec<QuestionTranslation>,
+) -> sqlx::Result<()> {
+    match transaction {
+        Transaction::Sqlite(transaction) => {
+            sqlx::query!(
+                r#"
+                DELETE FROM category_translations
+                "#
+            )
+            .execute(&mut **transaction)
+            .await?;
+            sqlx::query!(
+                r#"
+                DELETE FROM question_translations
+                "#
+            )
+            .execute(&mut **transaction)
+            .await?;
+            for translation in categories {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO category_translations (category_id, language, name) VALUES (?1, ?2, ?3)
+                    "#,
+                    translation.category_id,
+                    translation.language,
+                    translation.name
+                )
+                .execute(&mut **transaction)
+                .await?;
+            }
+            for translation in questions {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO question_translations (question_id, language, question, answer) VALUES (?1, ?2, ?3, ?4)
+                    "#,
+                    translation.question_id,
+                    translation.language,
+                    translation.question,
+                    translation.answer
+                )
+                .execute(&mut **transaction)
+                .await?;
+            }
+        }
+        Transaction::Postgres(transaction) => {
+            sqlx::query(
+                r#"
+                DELETE FROM category_translations
+                "#,
+            )
+            .execute(&mut **transaction)
+            .await?;
+            sqlx::query(
+                r#"
+                DELETE FROM question_translations
+                "#,
+            )
+            .execute(&mut **transaction)
+            .await?;
+            for translation in categories {
+                sqlx::query(
+                    r#"
+                    INSERT INTO category_translations (category_id, language, name) VALUES ($1, $2, $3)
+                    "#,
+                )
+                .bind(translation.category_id)
+                .bind(translation.language)
+                .bind(translation.name)
+                .execute(&mut **transaction)
+                .await?;
+            }
+            for translation in questions {
+                sqlx::query(
+                    r#"
+                    INSERT INTO question_translations (question_id, language, question, answer) VALUES ($1, $2, $3, $4)
+                    "#,
+                )
+                .bind(translation.question_id)
+                .bind(translation.language)
+                .bind(translation.question)
+                .bind(translation.answer)
+                .execute(&mut **transaction)
+                .await?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,491 @@
+use std::collections::HashSet;
+
+use crate::db::{Pool, Transaction};
+
+use super::attachments::unused_blobs;
+use super::revisions::{get_state, record_revision, Entity};
+use super::subscriptions::record_question_change;
+
+#[derive(sqlx::FromRow)]
+pub struct DeletedQuestion {
+    pub id: i64,
+    pub question: String,
+    pub attachments: String,
+    pub deleted_at: String,
+    pub deleted_by: Option<String>,
+}
+
+impl DeletedQuestion {
+    pub fn attachments(&self) -> Vec<String> {
+        serde_json::from_str(&self.attachments).unwrap_or_default()
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub struct DeletedCategory {
+    pub id: i64,
+    pub name: String,
+    pub deleted_at: String,
+    pub deleted_by: Option<String>,
+}
+
+// Ids of the items purged from the trash and the blobs that are not used anymore
+pub struct Purged {
+    pub questions: Vec<i64>,
+    pub categories: Vec<i64>,
+    pub blobs: Vec<String>,
+}
+
+pub async fn get_deleted_questions(pool: &Pool) -> sqlx::Result<Vec<DeletedQuestion>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                DeletedQuestion,
+                r#"
+                SELECT id, question, (SELECT json_group_array(name ORDER BY id) FROM attachments WHERE question_id = questions.id) as "attachments!: String", deleted_at as "deleted_at!", deleted_by
+                FROM questions
+                WHERE deleted_at IS NOT NULL
+                ORDER BY deleted_at DESC, id DESC
+                "#
+            )
+            .fetch_all(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT id, question, (SELECT COALESCE(json_agg(name ORDER BY id), '[]')::TEXT FROM attachments WHERE question_id = questions.id) as attachments, deleted_at, deleted_by
+                FROM questions
+                WHERE deleted_at IS NOT NULL
+                ORDER BY deleted_at DESC, id DESC
+                "#,
+            )
+            .fetch_all(pool)
+            .await
+        }
+    }
+}
+
+pub async fn get_deleted_categories(pool: &Pool) -> sqlx::Result<Vec<DeletedCategory>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                DeletedCategory,
+                r#"
+                SELECT id, name, deleted_at as "deleted_at!", deleted_by FROM categories
+                WHERE deleted_at IS NOT NULL
+                ORDER BY deleted_at DESC, id DESC
+                "#
+            )
+            .fetch_all(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT id, name, deleted_at, deleted_by FROM categories
+                WHERE deleted_at IS NOT NULL
+                ORDER BY deleted_at DESC, id DESC
+                "#,
+            )
+            .fetch_all(pool)
+            .await
+        }
+    }
+}
+
+// Takes the question out of the trash, subscribers get it as a new one
+pub async fn restore_question(pool: &Pool, id: i64, author: &str) -> sqlx::Result<()> {
+    let mut transaction = pool.begin().await?;
+    let restored = match &mut transaction {
+        Transaction::Sqlite(transaction) => sqlx::query!(
+            r#"
+            UPDATE questions SET deleted_at = NULL, deleted_by = NULL
+            WHERE id = ?1 AND deleted_at IS NOT NULL
+            "#,
+            id
+        )
+        .execute(&mut **transaction)
+        .await?
+        .rows_affected(),
+        Transaction::Postgres(transaction) => sqlx::query(
+            r#"
+            UPDATE questions SET deleted_at = NULL, deleted_by = NULL
+            WHERE id = $1 AND deleted_at IS NOT NULL
+            "#,
+        )
+        .bind(id)
+        .execute(&mut **transaction)
+        .await?
+        .rows_affected(),
+    };
+    if restored == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    record_question_change(&mut transaction, id, true).await?;
+    record_revision(&mut transaction, Entity::Question, id, None, author).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+// Categories above the given one, to keep the restored tree without the cycles
+async fn get_ancestors(transaction: &mut Transaction, id: i64) -> sqlx::Result<HashSet<i64>> {
+    let mut ancestors = HashSet::new();
+    let mut current = id;
+    loop {
+        let parent: Option<i64> = match &mut *transaction {
+            Transaction::Sqlite(transaction) => sqlx::query_scalar!(
+                r#"
+                SELECT parent FROM categories WHERE id = ?1
+                "#,
+                current
+            )
+            .fetch_optional(&mut **transaction)
+            .await?
+            .flatten(),
+            Transaction::Postgres(transaction) => sqlx::query_scalar(
+                r#"
+                SELECT parent FROM categories WHERE id = $1
+                "#,
+            )
+            .bind(current)
+            .fetch_optional(&mut **transaction)
+            .await?
+            .flatten(),
+        };
+        match parent {
+            Some(parent) if ancestors.insert(parent) => current = parent,
+            _ => return Ok(ancestors),
+        }
+    }
+}
+
+// Subcategories and questions detached from the category when it was deleted are attached
+// back, unless they were moved to another category since then
+pub async fn restore_category(pool: &Pool, id: i64, author: &str) -> sqlx::Result<()> {
+    let mut transaction = pool.begin().await?;
+    let restored = match &mut transaction {
+        Transaction::Sqlite(transaction) => sqlx::query!(
+            r#"
+            UPDATE categories SET deleted_at = NULL, deleted_by = NULL
+            WHERE id = ?1 AND deleted_at IS NOT NULL
+            "#,
+            id
+        )
+        .execute(&mut **transaction)
+        .await?
+        .rows_affected(),
+        Transaction::Postgres(transaction) => sqlx::query(
+            r#"
+            UPDATE categories SET deleted_at = NULL, deleted_by = NULL
+            WHERE id = $1 AND deleted_at IS NOT NULL
+            "#,
+        )
+        .bind(id)
+        .execute(&mut **transaction)
+        .await?
+        .rows_affected(),
+    };
+    if restored == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    record_revision(&mut transaction, Entity::Category, id, None, author).await?;
+
+    let (subcategories, questions): (Vec<i64>, Vec<i64>) = match &mut transaction {
+        Transaction::Sqlite(transaction) => {
+            let subcategories = sqlx::query_scalar!(
+                r#"
+                SELECT id FROM categories WHERE detached_from = ?1 AND parent IS NULL
+                "#,
+                id
+            )
+            .fetch_all(&mut **transaction)
+            .await?;
+            let questions = sqlx::query_scalar!(
+                r#"
+                SELECT id FROM questions WHERE detached_from = ?1 AND category IS NULL
+                "#,
+                id
+            )
+            .fetch_all(&mut **transaction)
+            .await?;
+            (subcategories, questions)
+        }
+        Transaction::Postgres(transaction) => {
+            let subcategories = sqlx::query_scalar(
+                r#"
+                SELECT id FROM categories WHERE detached_from = $1 AND parent IS NULL
+                "#,
+            )
+            .bind(id)
+            .fetch_all(&mut **transaction)
+            .await?;
+            let questions = sqlx::query_scalar(
+                r#"
+                SELECT id FROM questions WHERE detached_from = $1 AND category IS NULL
+                "#,
+            )
+            .bind(id)
+            .fetch_all(&mut **transaction)
+            .await?;
+            (subcategories, questions)
+        }
+    };
+    let ancestors = get_ancestors(&mut transaction, id).await?;
+    // the items in the trash are attached without the revisions
+    for subcategory in subcategories.into_iter().filter(|c| !ancestors.contains(c)) {
+        let old = get_state(&mut transaction, Entity::Category, subcategory).await?;
+        match &mut transaction {
+            Transaction::Sqlite(transaction) => {
+                sqlx::query!(
+                    r#"
+                    UPDATE categories SET parent = ?1 WHERE id = ?2
+                    "#,
+                    id,
+                    subcategory
+                )
+                .execute(&mut **transaction)
+                .await?;
+            }
+            Transaction::Postgres(transaction) => {
+                sqlx::query(
+                    r#"
+                    UPDATE categories SET parent = $1 WHERE id = $2
+                    "#,
+                )
+                .bind(id)
+                .bind(subcategory)
+                .execute(&mut **transaction)
+                .await?;
+            }
+        }
+        if old.is_some() {
+            record_revision(&mut transaction, Entity::Category, subcategory, old, author).await?;
+        }
+    }
+    for question in questions {
+        let old = get_state(&mut transaction, Entity::Question, question).await?;
+        match &mut transaction {
+            Transaction::Sqlite(transaction) => {
+                sqlx::query!(
+                    r#"
+                    UPDATE questions SET category = ?1 WHERE id = ?2
+                    "#,
+                    id,
+                    question
+                )
+                .execute(&mut **transaction)
+                .await?;
+            }
+            Transaction::Postgres(transaction) => {
+                sqlx::query(
+                    r#"
+                    UPDATE questions SET category = $1 WHERE id = $2
+                    "#,
+                )
+                .bind(id)
+                .bind(question)
+                .execute(&mut **transaction)
+                .await?;
+            }
+        }
+        if old.is_some() {
+            record_revision(&mut transaction, Entity::Question, question, old, author).await?;
+        }
+    }
+    match &mut transaction {
+        Transaction::Sqlite(transaction) => {
+            sqlx::query!(
+                r#"
+                UPDATE categories SET detached_from = NULL WHERE detached_from = ?1
+                "#,
+                id
+            )
+            .execute(&mut **transaction)
+            .await?;
+            sqlx::query!(
+                r#"
+                UPDATE questions SET detached_from = NULL WHERE detached_from = ?1
+                "#,
+                id
+            )
+            .execute(&mut **transaction)
+            .await?;
+        }
+        Transaction::Postgres(transaction) => {
+            sqlx::query(
+                r#"
+                UPDATE categories SET detached_from = NULL WHERE detached_from = $1
+                "#,
+            )
+            .bind(id)
+            .execute(&mut **transaction)
+            .await?;
+            sqlx::query(
+                r#"
+                UPDATE questions SET detached_from = NULL WHERE detached_from = $1
+                "#,
+            )
+            .bind(id)
+            .execute(&mut **transaction)
+            .await?;
+        }
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+// Removes the question for good, with its attachments, translations, feedback and subscriptions.
+// Only the questions in the trash can be purged. Returns the blobs that are not used anymore
+pub async fn purge_question(pool: &Pool, id: i64) -> sqlx::Result<Vec<String>> {
+    let mut transaction = pool.begin().await?;
+    let (hashes, purged) = match &mut transaction {
+        Transaction::Sqlite(transaction) => {
+            let hashes = sqlx::query_scalar!(
+                r#"
+                DELETE FROM attachments WHERE question_id = ?1 RETURNING sha256
+                "#,
+                id
+            )
+            .fetch_all(&mut **transaction)
+            .await?;
+            let purged = sqlx::query!(
+                r#"
+                DELETE FROM questions WHERE id = ?1 AND deleted_at IS NOT NULL
+                "#,
+                id
+            )
+            .execute(&mut **transaction)
+            .await?
+            .rows_affected();
+            (hashes, purged)
+        }
+        Transaction::Postgres(transaction) => {
+            let hashes = sqlx::query_scalar(
+                r#"
+                DELETE FROM attachments WHERE question_id = $1 RETURNING sha256
+                "#,
+            )
+            .bind(id)
+            .fetch_all(&mut **transaction)
+            .await?;
+            let purged = sqlx::query(
+                r#"
+                DELETE FROM questions WHERE id = $1 AND deleted_at IS NOT NULL
+                "#,
+            )
+            .bind(id)
+            .execute(&mut **transaction)
+            .await?
+            .rows_affected();
+            (hashes, purged)
+        }
+    };
+    // the attachments of the question that is not in the trash are rolled back
+    if purged == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    let blobs = unused_blobs(&mut transaction, hashes).await?;
+    transaction.commit().await?;
+    Ok(blobs)
+}
+
+pub async fn purge_category(pool: &Pool, id: i64) -> sqlx::Result<()> {
+    let purged = match pool {
+        Pool::Sqlite(pool) => sqlx::query!(
+            r#"
+            DELETE FROM categories WHERE id = ?1 AND deleted_at IS NOT NULL
+            "#,
+            id
+        )
+        .execute(pool)
+        .await?
+        .rows_affected(),
+        Pool::Postgres(pool) => sqlx::query(
+            r#"
+            DELETE FROM categories WHERE id = $1 AND deleted_at IS NOT NULL
+            "#,
+        )
+        .bind(id)
+        .execute(pool)
+        .await?
+        .rows_affected(),
+    };
+    if purged == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    Ok(())
+}
+
+// Purges the items that are in the trash for more than the given number of days
+pub async fn purge_expired(pool: &Pool, retention_days: i64) -> sqlx::Result<Purged> {
+    let mut transaction = pool.begin().await?;
+    let (hashes, questions, categories) = match &mut transaction {
+        Transaction::Sqlite(transaction) => {
+            let modifier = format!("-{retention_days} days");
+            let hashes = sqlx::query_scalar!(
+                r#"
+                DELETE FROM attachments WHERE question_id IN (
+                    SELECT id FROM questions WHERE deleted_at < datetime('now', ?1)
+                ) RETURNING sha256
+                "#,
+                modifier
+            )
+            .fetch_all(&mut **transaction)
+            .await?;
+            let questions = sqlx::query_scalar!(
+                r#"
+                DELETE FROM questions WHERE deleted_at < datetime('now', ?1) RETURNING id
+                "#,
+                modifier
+            )
+            .fetch_all(&mut **transaction)
+            .await?;
+            let categories = sqlx::query_scalar!(
+                r#"
+                DELETE FROM categories WHERE deleted_at < datetime('now', ?1) RETURNING id
+                "#,
+                modifier
+            )
+            .fetch_all(&mut **transaction)
+            .await?;
+            (hashes, questions, categories)
+        }
+        Transaction::Postgres(transaction) => {
+            let hashes = sqlx::query_scalar(
+                r#"
+                DELETE FROM attachments WHERE question_id IN (
+                    SELECT id FROM questions WHERE deleted_at < utc_timestamp(now() - make_interval(days => $1))
+                ) RETURNING sha256
+                "#,
+            )
+            .bind(retention_days as i32)
+            .fetch_all(&mut **transaction)
+            .await?;
+            let questions = sqlx::query_scalar(
+                r#"
+                DELETE FROM questions WHERE deleted_at < utc_timestamp(now() - make_interval(days => $1)) RETURNING id
+                "#,
+            )
+            .bind(retention_days as i32)
+            .fetch_all(&mut **transaction)
+            .await?;
+            let categories = sqlx::query_scalar(
+                r#"
+                DELETE FROM categories WHERE deleted_at < utc_timestamp(now() - make_interval(days => $1)) RETURNING id
+                "#,
+            )
+            .bind(retention_days as i32)
+            .fetch_all(&mut **transaction)
+            .await?;
+            (hashes, questions, categories)
+        }
+    };
+    let blobs = unused_blobs(&mut transaction, hashes).await?;
+    transaction.commit().await?;
+    Ok(Purged {
+        questions,
+        categories,
+        blobs,
+    })
+}
@@ -1,8 +1,10 @@
 use serde::{Deserialize, Serialize};
-use sqlx::SqlitePool;
 use std::collections::HashSet;
+use utoipa::ToSchema;
 
-#[derive(Serialize, Deserialize)]
+use crate::db::{Pool, Transaction};
+
+#[derive(Serialize, Deserialize, Clone, ToSchema, sqlx::FromRow)]
 pub struct User {
     pub id: i64,
     pub username: Option<String>,
@@ -12,30 +14,82 @@ pub struct User {
     pub active: bool,
 }
 
-pub async fn get_user(pool: &SqlitePool, id: i64) -> sqlx::Result<User> {
-    sqlx::query_as!(
-        User,
-        r#"
-        SELECT * FROM users WHERE users.id = ?1
-        "#,
-        id
-    )
-    .fetch_one(pool)
-    .await
+pub async fn get_user(pool: &Pool, id: i64) -> sqlx::Result<User> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                User,
+                r#"
+                SELECT * FROM users WHERE users.id = ?1
+                "#,
+                id
+            )
+            .fetch_one(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT * FROM users WHERE users.id = $1
+                "#,
+            )
+            .bind(id)
+            .fetch_one(pool)
+            .await
+        }
+    }
 }
 
-pub async fn get_users(pool: &SqlitePool) -> sqlx::Result<Vec<User>> {
-    sqlx::query_as!(
-        User,
-        r#"
-        SELECT * FROM users
-        "#,
-    )
-    .fetch_all(pool)
-    .await
+pub async fn get_users(pool: &Pool) -> sqlx::Result<Vec<User>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                User,
+                r#"
+                SELECT * FROM users
+                "#,
+            )
+            .fetch_all(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT * FROM users ORDER BY id
+                "#,
+            )
+            .fetch_all(pool)
+            .await
+        }
+    }
 }
+
+pub async fn get_admins(pool: &Pool) -> sqlx::Result<Vec<User>> {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query_as!(
+                User,
+                r#"
+                SELECT * FROM users WHERE is_admin = TRUE AND active = TRUE
+                "#,
+            )
+            .fetch_all(pool)
+            .await
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_as(
+                r#"
+                SELECT * FROM users WHERE is_admin = TRUE AND active = TRUE ORDER BY id
+                "#,
+            )
+            .fetch_all(pool)
+            .await
+        }
+    }
+}
+
 pub async fn create_user(
-    pool: &SqlitePool,
+    pool: &Pool,
     id: i64,
     username: Option<&str>,
     first_name: &str,
@@ -43,78 +97,239 @@ pub async fn create_user(
     is_admin: bool,
     active: bool,
 ) -> sqlx::Result<i64> {
-    let mut conn = pool.acquire().await?;
-    let user_id = sqlx::query!(
-        r#"
-        INSERT INTO users (id, username, first_name, last_name, is_admin, active) VALUES(?1, ?2, ?3, ?4, ?5, ?6)
-        "#,
-        id,
-        username,
-        first_name,
-        last_name,
-        is_admin,
-        active
-    )
-    .execute(&mut *conn)
-    .await?
-    .last_insert_rowid();
-
-    Ok(user_id)
+    match pool {
+        Pool::Sqlite(pool) => {
+            let user_id = sqlx::query!(
+                r#"
+                INSERT INTO users (id, username, first_name, last_name, is_admin, active) VALUES(?1, ?2, ?3, ?4, ?5, ?6)
+                "#,
+                id,
+                username,
+                first_name,
+                last_name,
+                is_admin,
+                active
+            )
+            .execute(pool)
+            .await?
+            .last_insert_rowid();
+            Ok(user_id)
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query_scalar(
+                r#"
+                INSERT INTO users (id, username, first_name, last_name, is_admin, active) VALUES($1, $2, $3, $4, $5, $6)
+                RETURNING id
+                "#,
+            )
+            .bind(id)
+            .bind(username)
+            .bind(first_name)
+            .bind(last_name)
+            .bind(is_admin)
+            .bind(active)
+            .fetch_one(pool)
+            .await
+        }
+    }
 }
-pub async fn update_user(pool: &SqlitePool, user: User) -> sqlx::Result<()> {
-    get_user(pool, user.id).await?;
-    let mut conn = pool.acquire().await?;
 
-    sqlx::query!(
-        r#"
-        UPDATE users SET username=?1, first_name=?2, last_name=?3, is_admin=?4, active=?5 WHERE users.id = ?6
-        "#,
-        user.username,
-        user.first_name,
-        user.last_name,
-        user.is_admin,
-        user.active,
-        user.id
-    )
-    .execute(&mut *conn)
-    .await?;
+pub async fn update_user(pool: &Pool, user: User) -> sqlx::Result<()> {
+    get_user(pool, user.id).await?;
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query!(
+                r#"
+                UPDATE users SET username=?1, first_name=?2, last_name=?3, is_admin=?4, active=?5 WHERE users.id = ?6
+                "#,
+                user.username,
+                user.first_name,
+                user.last_name,
+                user.is_admin,
+                user.active,
+                user.id
+            )
+            .execute(pool)
+            .await?;
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query(
+                r#"
+                UPDATE users SET username=$1, first_name=$2, last_name=$3, is_admin=$4, active=$5 WHERE users.id = $6
+                "#,
+            )
+            .bind(user.username)
+            .bind(user.first_name)
+            .bind(user.last_name)
+            .bind(user.is_admin)
+            .bind(user.active)
+            .bind(user.id)
+            .execute(pool)
+            .await?;
+        }
+    }
     Ok(())
 }
-pub async fn delete_user(pool: &SqlitePool, user_id: i64) -> sqlx::Result<()> {
-    get_user(pool, user_id).await?;
-    let mut conn = pool.acquire().await?;
 
-    sqlx::query!(
-        r#"
-        DELETE FROM users WHERE users.id = ?1
-        "#,
-        user_id,
-    )
-    .execute(&mut *conn)
-    .await?;
+pub async fn delete_user(pool: &Pool, user_id: i64) -> sqlx::Result<()> {
+    get_user(pool, user_id).await?;
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query!(
+                r#"
+                DELETE FROM users WHERE users.id = ?1
+                "#,
+                user_id,
+            )
+            .execute(pool)
+            .await?;
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query(
+                r#"
+                DELETE FROM users WHERE users.id = $1
+                "#,
+            )
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+        }
+    }
     Ok(())
 }
 
-pub async fn import_users(pool: &SqlitePool, users: Vec<User>) -> sqlx::Result<()> {
-    let existing_users = get_users(pool).await?;
-    let existing_users_ids: HashSet<i64> = existing_users.iter().map(|c| c.id).collect();
-    let new_users_ids: HashSet<i64> = users.iter().map(|c| c.id).collect();
-    for user_id in existing_users_ids.difference(&new_users_ids) {
-        delete_user(pool, *user_id).await?;
+// Replaces all the users with the given ones
+pub async fn import_users(transaction: &mut Transaction, users: Vec<User>) -> sqlx::Result<()> {
+    let new_users_ids: HashSet<i64> = users.iter().map(|u| u.id).collect();
+    let existing_users_ids: Vec<i64> = match transaction {
+        Transaction::Sqlite(transaction) => {
+            sqlx::query_scalar!(
+                r#"
+                SELECT id FROM users
+                "#
+            )
+            .fetch_all(&mut **transaction)
+            .await?
+        }
+        Transaction::Postgres(transaction) => {
+            sqlx::query_scalar(
+                r#"
+                SELECT id FROM users
+                "#,
+            )
+            .fetch_all(&mut **transaction)
+            .await?
+        }
+    };
+    for user_id in existing_users_ids
+        .into_iter()
+        .filter(|id| !new_users_ids.contains(id))
+    {
+        match transaction {
+            Transaction::Sqlite(transaction) => {
+                sqlx::query!(
+                    r#"
+                    DELETE FROM users WHERE users.id = ?1
+                    "#,
+                    user_id,
+                )
+                .execute(&mut **transaction)
+                .await?;
+            }
+            Transaction::Postgres(transaction) => {
+                sqlx::query(
+                    r#"
+                    DELETE FROM users WHERE users.id = $1
+                    "#,
+                )
+                .bind(user_id)
+                .execute(&mut **transaction)
+                .await?;
+            }
+        }
     }
     for user in users {
-        if existing_users_ids.contains(&user.id) {
-            update_user(pool, user).await?;
-        } else {
-            create_user(
-                pool,
-                user.id,
-                user.username.as_deref(),
-                &user.first_name,
-                user.last_name.as_deref(),
-                user.is_admin,
-                user.active,
+        match transaction {
+            Transaction::Sqlite(transaction) => {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO users (id, username, first_name, last_name, is_admin, active) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                    ON CONFLICT(id) DO UPDATE SET username=excluded.username, first_name=excluded.first_name,
+                        last_name=excluded.last_name, is_admin=excluded.is_admin, active=excluded.active
+                    "#,
+                    user.id,
+                    user.username,
+                    user.first_name,
+                    user.last_name,
+                    user.is_admin,
+                    user.active,
+                )
+                .execute(&mut **transaction)
+                .await?;
+            }
+            Transaction::Postgres(transaction) => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO users (id, username, first_name, last_name, is_admin, active) VALUES ($1, $2, $3, $4, $5, $6)
+                    ON CONFLICT(id) DO UPDATE SET username=excluded.username, first_name=excluded.first_name,
+                        last_name=excluded.last_name, is_admin=excluded.is_admin, active=excluded.active
+                    "#,
+                )
+                .bind(user.id)
+                .bind(user.username)
+                .bind(user.first_name)
+                .bind(user.last_name)
+                .bind(user.is_admin)
+                .bind(user.active)
+                .execute(&mut **transaction)
+                .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Users that blocked the bot don't get the broadcasts
+pub async fn set_user_blocked(pool: &Pool, user_id: i64, blocked: bool) -> sqlx::Result<()> {
+    match (pool, blocked) {
+        (Pool::Sqlite(pool), true) => {
+            sqlx::query!(
+                r#"
+                INSERT INTO blocked_users (user_id) VALUES (?1) ON CONFLICT(user_id) DO NOTHING
+                "#,
+                user_id
+            )
+            .execute(pool)
+            .await?;
+        }
+        (Pool::Sqlite(pool), false) => {
+            sqlx::query!(
+                r#"
+                DELETE FROM blocked_users WHERE user_id = ?1
+                "#,
+                user_id
+            )
+            .execute(pool)
+            .await?;
+        }
+        (Pool::Postgres(pool), true) => {
+            sqlx::query(
+                r#"
+                INSERT INTO blocked_users (user_id) VALUES ($1) ON CONFLICT(user_id) DO NOTHING
+                "#,
+            )
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+        }
+        (Pool::Postgres(pool), false) => {
+            sqlx::query(
+                r#"
+                DELETE FROM blocked_users WHERE user_id = $1
+                "#,
             )
+            .bind(user_id)
+            .execute(pool)
             .await?;
         }
     }
@@ -0,0 +1,726 @@
+use async_trait::async_trait;
+use sqlx::error::{DatabaseError, ErrorKind};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Mutex;
+
+use crate::db::queries::attachments::{Attachment, NewAttachment};
+use crate::db::queries::groups::Group;
+use crate::db::queries::questions::search_words;
+use crate::db::{BlobLock, Category, Question, Reorder, User};
+
+use super::{CategoryRepository, GroupRepository, QuestionRepository, UserRepository};
+
+struct StoredQuestion {
+    id: i64,
+    category: Option<i64>,
+    question: String,
+    answer: String,
+    hidden: bool,
+    ordering: i64,
+}
+
+#[derive(Default)]
+struct Data {
+    categories: BTreeMap<i64, Category>,
+    questions: BTreeMap<i64, StoredQuestion>,
+    attachments: BTreeMap<i64, Attachment>,
+    users: BTreeMap<i64, User>,
+    groups: BTreeMap<i64, Group>,
+}
+
+impl Data {
+    fn question(&self, question: &StoredQuestion) -> Question {
+        Question {
+            id: question.id,
+            category: question
+                .category
+                .and_then(|id| self.categories.get(&id))
+                .cloned(),
+            question: question.question.clone(),
+            answer: question.answer.clone(),
+            attachments: self
+                .attachments
+                .values()
+                .filter(|a| a.question_id == question.id)
+                .map(|a| a.name.clone())
+                .collect(),
+            hidden: question.hidden,
+            ordering: question.ordering,
+        }
+    }
+
+    fn sorted_questions<'a>(
+        &self,
+        questions: impl Iterator<Item = &'a StoredQuestion>,
+    ) -> Vec<Question> {
+        let mut questions: Vec<&StoredQuestion> = questions.collect();
+        questions.sort_by_key(|q| (q.ordering, Reverse(q.id)));
+        questions.into_iter().map(|q| self.question(q)).collect()
+    }
+
+    // same as the public_categories view: the category and all of its parents are not hidden
+    fn is_public(&self, id: i64) -> bool {
+        let mut current = self.categories.get(&id);
+        let mut depth = 0;
+        while let Some(category) = current.filter(|_| depth <= self.categories.len()) {
+            if category.hidden {
+                return false;
+            }
+            match category.parent {
+                Some(parent) => current = self.categories.get(&parent),
+                None => return true,
+            }
+            depth += 1;
+        }
+        false
+    }
+
+    // same as the accessible_categories view: the user is a member of some group of every
+    // restricted category on the path
+    fn is_accessible(&self, user_id: i64, id: i64) -> bool {
+        if !self.users.contains_key(&user_id) || !self.is_public(id) {
+            return false;
+        }
+        let mut current = self.categories.get(&id);
+        while let Some(category) = current {
+            let mut groups = self
+                .groups
+                .values()
+                .filter(|g| g.categories.contains(&category.id))
+                .peekable();
+            if groups.peek().is_some() && !groups.any(|g| g.users.contains(&user_id)) {
+                return false;
+            }
+            current = category
+                .parent
+                .and_then(|parent| self.categories.get(&parent));
+        }
+        true
+    }
+
+    fn is_public_question(&self, user_id: i64, question: &StoredQuestion) -> bool {
+        !question.hidden
+            && question
+                .category
+                .is_some_and(|category| self.is_accessible(user_id, category))
+    }
+}
+
+fn next_id<T>(items: &BTreeMap<i64, T>) -> i64 {
+    items.keys().next_back().map_or(1, |id| id + 1)
+}
+
+#[derive(Debug)]
+enum Violation {
+    Unique(String),
+    ForeignKey(String),
+}
+
+impl Violation {
+    fn error(self) -> sqlx::Error {
+        sqlx::Error::Database(Box::new(self))
+    }
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for Violation {}
+
+// so the duplicates and the unknown ids are reported the same way as by the database
+impl DatabaseError for Violation {
+    fn message(&self) -> &str {
+        match self {
+            Violation::Unique(message) | Violation::ForeignKey(message) => message,
+        }
+    }
+
+    fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+        self
+    }
+
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Violation::Unique(_) => ErrorKind::UniqueViolation,
+            Violation::ForeignKey(_) => ErrorKind::ForeignKeyViolation,
+        }
+    }
+}
+
+// Keeps everything in memory, for running the handlers without a database. The revisions,
+// the trash and the translations are not kept, deleted items are removed at once
+#[derive(Default)]
+pub struct MemoryRepository {
+    data: Mutex<Data>,
+}
+
+impl MemoryRepository {
+    fn data(&self) -> std::sync::MutexGuard<'_, Data> {
+        self.data
+            .lock()
+            .expect("Memory repository lock is poisoned")
+    }
+}
+
+#[async_trait]
+impl CategoryRepository for MemoryRepository {
+    async fn get_category(&self, id: i64) -> sqlx::Result<Category> {
+        self.data()
+            .categories
+            .get(&id)
+            .cloned()
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn get_all_categories(&self) -> sqlx::Result<Vec<Category>> {
+        let mut categories: Vec<Category> = self.data().categories.values().cloned().collect();
+        categories.sort_by_key(|c| (c.ordering, Reverse(c.id)));
+        Ok(categories)
+    }
+
+    async fn get_public_categories(&self, user_id: i64) -> sqlx::Result<Vec<Category>> {
+        let data = self.data();
+        let mut categories: Vec<Category> = data
+            .categories
+            .values()
+            .filter(|c| data.is_accessible(user_id, c.id))
+            .cloned()
+            .collect();
+        categories.sort_by_key(|c| (c.ordering, Reverse(c.id)));
+        Ok(categories)
+    }
+
+    async fn get_public_subcategories(
+        &self,
+        user_id: i64,
+        parent: Option<i64>,
+    ) -> sqlx::Result<Vec<Category>> {
+        Ok(self
+            .get_public_categories(user_id)
+            .await?
+            .into_iter()
+            .filter(|category| category.parent == parent)
+            .collect())
+    }
+
+    async fn get_public_category(&self, user_id: i64, id: i64) -> sqlx::Result<Category> {
+        let data = self.data();
+        data.categories
+            .get(&id)
+            .filter(|c| data.is_accessible(user_id, c.id))
+            .cloned()
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn get_category_path(&self, id: i64) -> sqlx::Result<Vec<Category>> {
+        let data = self.data();
+        let mut path = vec![];
+        let mut current = data.categories.get(&id);
+        while let Some(category) = current.filter(|_| path.len() < data.categories.len()) {
+            path.push(category.clone());
+            current = category
+                .parent
+                .and_then(|parent| data.categories.get(&parent));
+        }
+        if path.is_empty() {
+            return Err(sqlx::Error::RowNotFound);
+        }
+        path.reverse();
+        Ok(path)
+    }
+
+    async fn get_subtree_ids(&self, id: i64) -> sqlx::Result<HashSet<i64>> {
+        let data = self.data();
+        let mut ids = HashSet::new();
+        if data.categories.contains_key(&id) {
+            ids.insert(id);
+        }
+        let mut added = true;
+        while added {
+            added = false;
+            for category in data.categories.values() {
+                if category.parent.is_some_and(|parent| ids.contains(&parent)) {
+                    added |= ids.insert(category.id);
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    async fn create_category(
+        &self,
+        name: &str,
+        hidden: bool,
+        ordering: i64,
+        parent: Option<i64>,
+        _author: &str,
+    ) -> sqlx::Result<i64> {
+        let mut data = self.data();
+        let id = next_id(&data.categories);
+        data.categories.insert(
+            id,
+            Category {
+                id,
+                name: name.to_string(),
+                hidden,
+                ordering,
+                parent,
+            },
+        );
+        Ok(id)
+    }
+
+    async fn update_category(
+        &self,
+        id: i64,
+        name: String,
+        hidden: bool,
+        parent: Option<i64>,
+        _author: &str,
+    ) -> sqlx::Result<()> {
+        let mut data = self.data();
+        let category = data
+            .categories
+            .get_mut(&id)
+            .ok_or(sqlx::Error::RowNotFound)?;
+        category.name = name;
+        category.hidden = hidden;
+        category.parent = parent;
+        Ok(())
+    }
+
+    // subcategories are moved to the top level and the questions are left without the category
+    async fn delete_category(&self, id: i64, _author: &str) -> sqlx::Result<()> {
+        let mut data = self.data();
+        data.categories
+            .remove(&id)
+            .ok_or(sqlx::Error::RowNotFound)?;
+        for category in data.categories.values_mut() {
+            if category.parent == Some(id) {
+                category.parent = None;
+            }
+        }
+        for question in data.questions.values_mut() {
+            if question.category == Some(id) {
+                question.category = None;
+            }
+        }
+        for group in data.groups.values_mut() {
+            group.categories.retain(|category| *category != id);
+        }
+        Ok(())
+    }
+
+    async fn reorder_categories(&self, categories: Vec<Reorder>) -> sqlx::Result<()> {
+        let mut data = self.data();
+        for reorder in categories {
+            if let Some(category) = data.categories.get_mut(&reorder.id) {
+                category.ordering = reorder.ordering;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl QuestionRepository for MemoryRepository {
+    async fn get_question_by_id(&self, id: i64) -> sqlx::Result<Question> {
+        let data = self.data();
+        data.questions
+            .get(&id)
+            .map(|q| data.question(q))
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn get_all_questions(&self) -> sqlx::Result<Vec<Question>> {
+        let data = self.data();
+        Ok(data.sorted_questions(data.questions.values()))
+    }
+
+    async fn get_questions_by_category_id(&self, category_id: i64) -> sqlx::Result<Vec<Question>> {
+        let data = self.data();
+        Ok(data.sorted_questions(
+            data.questions
+                .values()
+                .filter(|q| q.category == Some(category_id)),
+        ))
+    }
+
+    async fn get_public_questions_for_public_category(
+        &self,
+        user_id: i64,
+        category_id: i64,
+    ) -> sqlx::Result<Vec<Question>> {
+        let data = self.data();
+        Ok(data.sorted_questions(
+            data.questions
+                .values()
+                .filter(|q| q.category == Some(category_id) && data.is_public_question(user_id, q)),
+        ))
+    }
+
+    async fn get_public_question(&self, user_id: i64, id: i64) -> sqlx::Result<Question> {
+        let data = self.data();
+        data.questions
+            .get(&id)
+            .filter(|q| data.is_public_question(user_id, q))
+            .map(|q| data.question(q))
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    // words are matched by the prefix, the matches in the question weigh more than in the answer
+    async fn search_public_questions(
+        &self,
+        user_id: i64,
+        _language: &str,
+        text: &str,
+        limit: i64,
+    ) -> sqlx::Result<Vec<Question>> {
+        let data = self.data();
+        let query: Vec<String> = search_words(text).map(|w| w.to_lowercase()).collect();
+        let matches = |text: &str| {
+            let words: Vec<String> = search_words(text).map(|w| w.to_lowercase()).collect();
+            query
+                .iter()
+                .filter(|q| words.iter().any(|w| w.starts_with(q.as_str())))
+                .count()
+        };
+        let mut found: Vec<(usize, &StoredQuestion)> = data
+            .questions
+            .values()
+            .filter(|q| data.is_public_question(user_id, q))
+            .map(|q| (matches(&q.question) * 10 + matches(&q.answer), q))
+            .filter(|(rank, _)| *rank > 0)
+            .collect();
+        found.sort_by_key(|(rank, q)| (Reverse(*rank), q.id));
+        Ok(found
+            .into_iter()
+            .take(limit.try_into().unwrap_or_default())
+            .map(|(_, q)| data.question(q))
+            .collect())
+    }
+
+    async fn create_question(
+        &self,
+        question: &str,
+        answer: &str,
+        category: Option<i64>,
+        hidden: bool,
+        ordering: i64,
+        _author: &str,
+    ) -> sqlx::Result<i64> {
+        let mut data = self.data();
+        let id = next_id(&data.questions);
+        data.questions.insert(
+            id,
+            StoredQuestion {
+                id,
+                category,
+                question: question.to_string(),
+                answer: answer.to_string(),
+                hidden,
+                ordering,
+            },
+        );
+        Ok(id)
+    }
+
+    async fn update_question(
+        &self,
+        id: i64,
+        category: Option<i64>,
+        question: String,
+        answer: String,
+        hidden: bool,
+        _author: &str,
+    ) -> sqlx::Result<()> {
+        let mut data = self.data();
+        let stored = data
+            .questions
+            .get_mut(&id)
+            .ok_or(sqlx::Error::RowNotFound)?;
+        stored.category = category;
+        stored.question = question;
+        stored.answer = answer;
+        stored.hidden = hidden;
+        Ok(())
+    }
+
+    async fn delete_question(&self, id: i64, _author: &str) -> sqlx::Result<()> {
+        let mut data = self.data();
+        data.questions.remove(&id).ok_or(sqlx::Error::RowNotFound)?;
+        data.attachments.retain(|_, a| a.question_id != id);
+        Ok(())
+    }
+
+    async fn reorder_questions(&self, questions: Vec<Reorder>) -> sqlx::Result<()> {
+        let mut data = self.data();
+        for reorder in questions {
+            if let Some(question) = data.questions.get_mut(&reorder.id) {
+                question.ordering = reorder.ordering;
+            }
+        }
+        Ok(())
+    }
+    async fn get_attachments(&self, question_id: i64) -> sqlx::Result<Vec<Attachment>> {
+        Ok(self
+            .data()
+            .attachments
+            .values()
+            .filter(|a| a.question_id == question_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_attachment(&self, question_id: i64, name: &str) -> sqlx::Result<Attachment> {
+        self.data()
+            .attachments
+            .values()
+            .find(|a| a.question_id == question_id && a.name == name)
+            .cloned()
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn add_attachment(
+        &self,
+        question_id: i64,
+        attachment: NewAttachment,
+    ) -> sqlx::Result<Attachment> {
+        let mut data = self.data();
+        if !data.questions.contains_key(&question_id) {
+            return Err(sqlx::Error::RowNotFound);
+        }
+        if data
+            .attachments
+            .values()
+            .any(|a| a.question_id == question_id && a.name == attachment.name)
+        {
+            return Err(Violation::Unique(format!(
+                "Attachment {} already exists",
+                attachment.name
+            ))
+            .error());
+        }
+        let id = next_id(&data.attachments);
+        let attachment = Attachment {
+            id,
+            question_id,
+            sha256: attachment.sha256,
+            name: attachment.name,
+            mime_type: attachment.mime_type,
+            size: attachment.size,
+            telegram_file_id: None,
+        };
+        data.attachments.insert(id, attachment.clone());
+        Ok(attachment)
+    }
+
+    async fn delete_attachment(
+        &self,
+        question_id: i64,
+        name: &str,
+    ) -> sqlx::Result<Option<String>> {
+        let mut data = self.data();
+        let id = data
+            .attachments
+            .values()
+            .find(|a| a.question_id == question_id && a.name == name)
+            .map(|a| a.id)
+            .ok_or(sqlx::Error::RowNotFound)?;
+        let removed = data.attachments.remove(&id).expect("Attachment is found");
+        let used = data
+            .attachments
+            .values()
+            .any(|a| a.sha256 == removed.sha256);
+        Ok((!used).then_some(removed.sha256))
+    }
+
+    async fn set_telegram_file_id(
+        &self,
+        attachment: &Attachment,
+        file_id: Option<&str>,
+    ) -> sqlx::Result<()> {
+        let mut data = self.data();
+        if let Some(stored) = data
+            .attachments
+            .get_mut(&attachment.id)
+            .filter(|a| a.sha256 == attachment.sha256)
+        {
+            stored.telegram_file_id = file_id.map(String::from);
+        }
+        Ok(())
+    }
+
+    async fn lock_blob(&self, sha256: &str) -> sqlx::Result<BlobLock> {
+        Ok(BlobLock::local(sha256).await)
+    }
+
+    async fn unused_blobs(&self, hashes: Vec<String>) -> sqlx::Result<Vec<String>> {
+        let data = self.data();
+        let mut unused: Vec<String> = hashes
+            .into_iter()
+            .filter(|h| !h.is_empty() && data.attachments.values().all(|a| &a.sha256 != h))
+            .collect();
+        unused.sort();
+        unused.dedup();
+        Ok(unused)
+    }
+}
+
+#[async_trait]
+impl UserRepository for MemoryRepository {
+    async fn get_user(&self, id: i64) -> sqlx::Result<User> {
+        self.data()
+            .users
+            .get(&id)
+            .cloned()
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn get_users(&self) -> sqlx::Result<Vec<User>> {
+        Ok(self.data().users.values().cloned().collect())
+    }
+
+    async fn get_admins(&self) -> sqlx::Result<Vec<User>> {
+        Ok(self
+            .data()
+            .users
+            .values()
+            .filter(|u| u.is_admin && u.active)
+            .cloned()
+            .collect())
+    }
+
+    async fn create_user(
+        &self,
+        id: i64,
+        username: Option<&str>,
+        first_name: &str,
+        last_name: Option<&str>,
+        is_admin: bool,
+        active: bool,
+    ) -> sqlx::Result<i64> {
+        let mut data = self.data();
+        if data.users.contains_key(&id) {
+            return Err(Violation::Unique(format!("User {id} already exists")).error());
+        }
+        data.users.insert(
+            id,
+            User {
+                id,
+                username: username.map(String::from),
+                first_name: first_name.to_string(),
+                last_name: last_name.map(String::from),
+                is_admin,
+                active,
+            },
+        );
+        Ok(id)
+    }
+
+    async fn update_user(&self, user: User) -> sqlx::Result<()> {
+        let mut data = self.data();
+        let stored = data
+            .users
+            .get_mut(&user.id)
+            .ok_or(sqlx::Error::RowNotFound)?;
+        *stored = user;
+        Ok(())
+    }
+
+    async fn delete_user(&self, id: i64) -> sqlx::Result<()> {
+        let mut data = self.data();
+        data.users.remove(&id).ok_or(sqlx::Error::RowNotFound)?;
+        for group in data.groups.values_mut() {
+            group.users.retain(|user| *user != id);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl GroupRepository for MemoryRepository {
+    async fn get_groups(&self) -> sqlx::Result<Vec<Group>> {
+        let mut groups: Vec<Group> = self.data().groups.values().cloned().collect();
+        groups.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(groups)
+    }
+
+    async fn get_group(&self, id: i64) -> sqlx::Result<Group> {
+        self.data()
+            .groups
+            .get(&id)
+            .cloned()
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    async fn create_group(&self, name: &str) -> sqlx::Result<i64> {
+        let mut data = self.data();
+        if data.groups.values().any(|g| g.name == name) {
+            return Err(Violation::Unique(format!("Group {name} already exists")).error());
+        }
+        let id = next_id(&data.groups);
+        data.groups.insert(
+            id,
+            Group {
+                id,
+                name: name.to_string(),
+                users: vec![],
+                categories: vec![],
+            },
+        );
+        Ok(id)
+    }
+
+    async fn update_group(&self, group: &Group) -> sqlx::Result<()> {
+        let mut data = self.data();
+        if !data.groups.contains_key(&group.id) {
+            return Err(sqlx::Error::RowNotFound);
+        }
+        if data
+            .groups
+            .values()
+            .any(|g| g.id != group.id && g.name == group.name)
+        {
+            return Err(Violation::Unique(format!("Group {} already exists", group.name)).error());
+        }
+        if let Some(id) = group.users.iter().find(|id| !data.users.contains_key(id)) {
+            return Err(Violation::ForeignKey(format!("User {id} does not exist")).error());
+        }
+        if let Some(id) = group
+            .categories
+            .iter()
+            .find(|id| !data.categories.contains_key(id))
+        {
+            return Err(Violation::ForeignKey(format!("Category {id} does not exist")).error());
+        }
+        // links are unique and ordered by id, as they are read from the database
+        let mut group = group.clone();
+        group.users.sort();
+        group.users.dedup();
+        group.categories.sort();
+        group.categories.dedup();
+        data.groups.insert(group.id, group);
+        Ok(())
+    }
+
+    async fn delete_group(&self, id: i64) -> sqlx::Result<()> {
+        self.data()
+            .groups
+            .remove(&id)
+            .map(|_| ())
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+}
@@ -0,0 +1,170 @@
+mod memory;
+mod sql;
+
+use async_trait::async_trait;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::db::queries::attachments::{Attachment, NewAttachment};
+use crate::db::queries::groups::Group;
+use crate::db::{BlobLock, Category, Pool, Question, Reorder, User};
+
+pub use memory::MemoryRepository;
+pub use sql::SqlRepository;
+
+// Content the bot and the CMS work with. The handlers of the JSON API and of the categories,
+// questions, users and groups pages use only these, so they run against the in-memory implementation
+
+#[async_trait]
+pub trait CategoryRepository: Send + Sync {
+    async fn get_category(&self, id: i64) -> sqlx::Result<Category>;
+    async fn get_all_categories(&self) -> sqlx::Result<Vec<Category>>;
+    async fn get_public_categories(&self, user_id: i64) -> sqlx::Result<Vec<Category>>;
+    async fn get_public_subcategories(
+        &self,
+        user_id: i64,
+        parent: Option<i64>,
+    ) -> sqlx::Result<Vec<Category>>;
+    async fn get_public_category(&self, user_id: i64, id: i64) -> sqlx::Result<Category>;
+    async fn get_category_path(&self, id: i64) -> sqlx::Result<Vec<Category>>;
+    async fn get_subtree_ids(&self, id: i64) -> sqlx::Result<HashSet<i64>>;
+    async fn create_category(
+        &self,
+        name: &str,
+        hidden: bool,
+        ordering: i64,
+        parent: Option<i64>,
+        author: &str,
+    ) -> sqlx::Result<i64>;
+    async fn update_category(
+        &self,
+        id: i64,
+        name: String,
+        hidden: bool,
+        parent: Option<i64>,
+        author: &str,
+    ) -> sqlx::Result<()>;
+    async fn delete_category(&self, id: i64, author: &str) -> sqlx::Result<()>;
+    async fn reorder_categories(&self, categories: Vec<Reorder>) -> sqlx::Result<()>;
+}
+
+#[async_trait]
+pub trait QuestionRepository: Send + Sync {
+    async fn get_question_by_id(&self, id: i64) -> sqlx::Result<Question>;
+    async fn get_all_questions(&self) -> sqlx::Result<Vec<Question>>;
+    async fn get_questions_by_category_id(&self, category_id: i64) -> sqlx::Result<Vec<Question>>;
+    async fn get_public_questions_for_public_category(
+        &self,
+        user_id: i64,
+        category_id: i64,
+    ) -> sqlx::Result<Vec<Question>>;
+    async fn get_public_question(&self, user_id: i64, id: i64) -> sqlx::Result<Question>;
+    async fn search_public_questions(
+        &self,
+        user_id: i64,
+        language: &str,
+        text: &str,
+        limit: i64,
+    ) -> sqlx::Result<Vec<Question>>;
+    #[allow(clippy::too_many_arguments)]
+    async fn create_question(
+        &self,
+        question: &str,
+        answer: &str,
+        category: Option<i64>,
+        hidden: bool,
+        ordering: i64,
+        author: &str,
+    ) -> sqlx::Result<i64>;
+    #[allow(clippy::too_many_arguments)]
+    async fn update_question(
+        &self,
+        id: i64,
+        category: Option<i64>,
+        question: String,
+        answer: String,
+        hidden: bool,
+        author: &str,
+    ) -> sqlx::Result<()>;
+    async fn delete_question(&self, id: i64, author: &str) -> sqlx::Result<()>;
+    async fn reorder_questions(&self, questions: Vec<Reorder>) -> sqlx::Result<()>;
+    async fn get_attachments(&self, question_id: i64) -> sqlx::Result<Vec<Attachment>>;
+    async fn get_attachment(&self, question_id: i64, name: &str) -> sqlx::Result<Attachment>;
+    async fn add_attachment(
+        &self,
+        question_id: i64,
+        attachment: NewAttachment,
+    ) -> sqlx::Result<Attachment>;
+    // returns the hash of the blob when it is not used anymore
+    async fn delete_attachment(&self, question_id: i64, name: &str)
+        -> sqlx::Result<Option<String>>;
+    // file_id of Telegram is kept until the content of the attachment is changed
+    async fn set_telegram_file_id(
+        &self,
+        attachment: &Attachment,
+        file_id: Option<&str>,
+    ) -> sqlx::Result<()>;
+    // held while the references of the blob are counted and the blob is added or removed
+    async fn lock_blob(&self, sha256: &str) -> sqlx::Result<BlobLock>;
+    async fn unused_blobs(&self, hashes: Vec<String>) -> sqlx::Result<Vec<String>>;
+}
+
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    async fn get_user(&self, id: i64) -> sqlx::Result<User>;
+    async fn get_users(&self) -> sqlx::Result<Vec<User>>;
+    async fn get_admins(&self) -> sqlx::Result<Vec<User>>;
+    async fn create_user(
+        &self,
+        id: i64,
+        username: Option<&str>,
+        first_name: &str,
+        last_name: Option<&str>,
+        is_admin: bool,
+        active: bool,
+    ) -> sqlx::Result<i64>;
+    async fn update_user(&self, user: User) -> sqlx::Result<()>;
+    async fn delete_user(&self, id: i64) -> sqlx::Result<()>;
+}
+
+#[async_trait]
+pub trait GroupRepository: Send + Sync {
+    async fn get_groups(&self) -> sqlx::Result<Vec<Group>>;
+    async fn get_group(&self, id: i64) -> sqlx::Result<Group>;
+    async fn create_group(&self, name: &str) -> sqlx::Result<i64>;
+    // replaces the name, the members and the categories of the group
+    async fn update_group(&self, group: &Group) -> sqlx::Result<()>;
+    async fn delete_group(&self, id: i64) -> sqlx::Result<()>;
+}
+
+// Passed to the handlers through the state of the server and the dependencies of the bot
+#[derive(Clone)]
+pub struct Repositories {
+    pub categories: Arc<dyn CategoryRepository>,
+    pub questions: Arc<dyn QuestionRepository>,
+    pub users: Arc<dyn UserRepository>,
+    pub groups: Arc<dyn GroupRepository>,
+}
+
+impl Repositories {
+    pub fn new<R>(repository: R) -> Self
+    where
+        R: CategoryRepository + QuestionRepository + UserRepository + GroupRepository + 'static,
+    {
+        let repository = Arc::new(repository);
+        Self {
+            categories: repository.clone(),
+            questions: repository.clone(),
+            users: repository.clone(),
+            groups: repository,
+        }
+    }
+
+    pub fn sql(pool: Pool) -> Self {
+        Self::new(SqlRepository::new(pool))
+    }
+
+    pub fn memory() -> Self {
+        Self::new(MemoryRepository::default())
+    }
+}
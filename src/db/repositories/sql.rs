@@ -0,0 +1,259 @@
+use async_trait::async_trait;
+use std::collections::HashSet;
+
+use crate::db::queries::attachments::{self, Attachment, NewAttachment};
+use crate::db::queries::groups::{self, Group};
+use crate::db::queries::{categories, questions, users};
+use crate::db::{BlobLock, Category, Pool, Question, Reorder, User};
+
+use super::{CategoryRepository, GroupRepository, QuestionRepository, UserRepository};
+
+// Repositories backed by the database, both SQLite and Postgres through the queries
+pub struct SqlRepository {
+    pool: Pool,
+}
+
+impl SqlRepository {
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl CategoryRepository for SqlRepository {
+    async fn get_category(&self, id: i64) -> sqlx::Result<Category> {
+        categories::get_category(&self.pool, id).await
+    }
+
+    async fn get_all_categories(&self) -> sqlx::Result<Vec<Category>> {
+        categories::get_all_categories(&self.pool).await
+    }
+
+    async fn get_public_categories(&self, user_id: i64) -> sqlx::Result<Vec<Category>> {
+        categories::get_public_categories(&self.pool, user_id).await
+    }
+
+    async fn get_public_subcategories(
+        &self,
+        user_id: i64,
+        parent: Option<i64>,
+    ) -> sqlx::Result<Vec<Category>> {
+        categories::get_public_subcategories(&self.pool, user_id, parent).await
+    }
+
+    async fn get_public_category(&self, user_id: i64, id: i64) -> sqlx::Result<Category> {
+        categories::get_public_category(&self.pool, user_id, id).await
+    }
+
+    async fn get_category_path(&self, id: i64) -> sqlx::Result<Vec<Category>> {
+        categories::get_category_path(&self.pool, id).await
+    }
+
+    async fn get_subtree_ids(&self, id: i64) -> sqlx::Result<HashSet<i64>> {
+        categories::get_subtree_ids(&self.pool, id).await
+    }
+
+    async fn create_category(
+        &self,
+        name: &str,
+        hidden: bool,
+        ordering: i64,
+        parent: Option<i64>,
+        author: &str,
+    ) -> sqlx::Result<i64> {
+        categories::create_category(&self.pool, name, hidden, ordering, parent, author).await
+    }
+
+    async fn update_category(
+        &self,
+        id: i64,
+        name: String,
+        hidden: bool,
+        parent: Option<i64>,
+        author: &str,
+    ) -> sqlx::Result<()> {
+        categories::update_category(&self.pool, id, name, hidden, parent, author).await
+    }
+
+    async fn delete_category(&self, id: i64, author: &str) -> sqlx::Result<()> {
+        categories::delete_category(&self.pool, id, author).await
+    }
+
+    async fn reorder_categories(&self, categories: Vec<Reorder>) -> sqlx::Result<()> {
+        categories::reorder_categories(&self.pool, categories).await
+    }
+}
+
+#[async_trait]
+impl QuestionRepository for SqlRepository {
+    async fn get_question_by_id(&self, id: i64) -> sqlx::Result<Question> {
+        questions::get_question_by_id(&self.pool, id).await
+    }
+
+    async fn get_all_questions(&self) -> sqlx::Result<Vec<Question>> {
+        questions::get_all_questions(&self.pool).await
+    }
+
+    async fn get_questions_by_category_id(&self, category_id: i64) -> sqlx::Result<Vec<Question>> {
+        questions::get_questions_by_category_id(&self.pool, category_id).await
+    }
+
+    async fn get_public_questions_for_public_category(
+        &self,
+        user_id: i64,
+        category_id: i64,
+    ) -> sqlx::Result<Vec<Question>> {
+        questions::get_public_questions_for_public_category(&self.pool, user_id, category_id).await
+    }
+
+    async fn get_public_question(&self, user_id: i64, id: i64) -> sqlx::Result<Question> {
+        questions::get_public_question(&self.pool, user_id, id).await
+    }
+
+    async fn search_public_questions(
+        &self,
+        user_id: i64,
+        language: &str,
+        text: &str,
+        limit: i64,
+    ) -> sqlx::Result<Vec<Question>> {
+        questions::search_public_questions(&self.pool, user_id, language, text, limit).await
+    }
+
+    async fn create_question(
+        &self,
+        question: &str,
+        answer: &str,
+        category: Option<i64>,
+        hidden: bool,
+        ordering: i64,
+        author: &str,
+    ) -> sqlx::Result<i64> {
+        questions::create_question(
+            &self.pool, question, answer, category, hidden, ordering, author,
+        )
+        .await
+    }
+
+    async fn update_question(
+        &self,
+        id: i64,
+        category: Option<i64>,
+        question: String,
+        answer: String,
+        hidden: bool,
+        author: &str,
+    ) -> sqlx::Result<()> {
+        questions::update_question(&self.pool, id, category, question, answer, hidden, author).await
+    }
+
+    async fn delete_question(&self, id: i64, author: &str) -> sqlx::Result<()> {
+        questions::delete_question(&self.pool, id, author).await
+    }
+
+    async fn reorder_questions(&self, questions: Vec<Reorder>) -> sqlx::Result<()> {
+        questions::reorder_questions(&self.pool, questions).await
+    }
+
+    async fn get_attachments(&self, question_id: i64) -> sqlx::Result<Vec<Attachment>> {
+        attachments::get_attachments(&self.pool, question_id).await
+    }
+
+    async fn get_attachment(&self, question_id: i64, name: &str) -> sqlx::Result<Attachment> {
+        attachments::get_attachment(&self.pool, question_id, name).await
+    }
+
+    async fn add_attachment(
+        &self,
+        question_id: i64,
+        attachment: NewAttachment,
+    ) -> sqlx::Result<Attachment> {
+        attachments::add_attachment(&self.pool, question_id, attachment).await
+    }
+
+    async fn delete_attachment(
+        &self,
+        question_id: i64,
+        name: &str,
+    ) -> sqlx::Result<Option<String>> {
+        attachments::delete_attachment(&self.pool, question_id, name).await
+    }
+
+    async fn set_telegram_file_id(
+        &self,
+        attachment: &Attachment,
+        file_id: Option<&str>,
+    ) -> sqlx::Result<()> {
+        attachments::set_telegram_file_id(&self.pool, attachment, file_id).await
+    }
+
+    async fn lock_blob(&self, sha256: &str) -> sqlx::Result<BlobLock> {
+        self.pool.lock_blob(sha256).await
+    }
+
+    async fn unused_blobs(&self, hashes: Vec<String>) -> sqlx::Result<Vec<String>> {
+        let mut transaction = self.pool.begin().await?;
+        attachments::unused_blobs(&mut transaction, hashes).await
+    }
+}
+
+#[async_trait]
+impl UserRepository for SqlRepository {
+    async fn get_user(&self, id: i64) -> sqlx::Result<User> {
+        users::get_user(&self.pool, id).await
+    }
+
+    async fn get_users(&self) -> sqlx::Result<Vec<User>> {
+        users::get_users(&self.pool).await
+    }
+
+    async fn get_admins(&self) -> sqlx::Result<Vec<User>> {
+        users::get_admins(&self.pool).await
+    }
+
+    async fn create_user(
+        &self,
+        id: i64,
+        username: Option<&str>,
+        first_name: &str,
+        last_name: Option<&str>,
+        is_admin: bool,
+        active: bool,
+    ) -> sqlx::Result<i64> {
+        users::create_user(
+            &self.pool, id, username, first_name, last_name, is_admin, active,
+        )
+        .await
+    }
+
+    async fn update_user(&self, user: User) -> sqlx::Result<()> {
+        users::update_user(&self.pool, user).await
+    }
+
+    async fn delete_user(&self, id: i64) -> sqlx::Result<()> {
+        users::delete_user(&self.pool, id).await
+    }
+}
+
+#[async_trait]
+impl GroupRepository for SqlRepository {
+    async fn get_groups(&self) -> sqlx::Result<Vec<Group>> {
+        groups::get_groups(&self.pool).await
+    }
+
+    async fn get_group(&self, id: i64) -> sqlx::Result<Group> {
+        groups::get_group(&self.pool, id).await
+    }
+
+    async fn create_group(&self, name: &str) -> sqlx::Result<i64> {
+        groups::create_group(&self.pool, name).await
+    }
+
+    async fn update_group(&self, group: &Group) -> sqlx::Result<()> {
+        groups::update_group(&self.pool, group).await
+    }
+
+    async fn delete_group(&self, id: i64) -> sqlx::Result<()> {
+        groups::delete_group(&self.pool, id).await
+    }
+}
@@ -0,0 +1,243 @@
+// Languages of the bot interface. The content is written in the default language
+// and can be translated to the other ones in the CMS
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    En,
+    Ru,
+}
+
+impl Language {
+    pub const ALL: [Language; 2] = [Language::En, Language::Ru];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::En => "en",
+            Language::Ru => "ru",
+        }
+    }
+
+    // name of the language in the language itself, shown in the language menu
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::En => "English",
+            Language::Ru => "Русский",
+        }
+    }
+
+    // Telegram sends IETF language tags, so the region part like in `en-US` is ignored
+    pub fn from_code(code: &str) -> Option<Language> {
+        let code = code.split(['-', '_']).next().unwrap_or_default();
+        Language::ALL
+            .into_iter()
+            .find(|language| language.code().eq_ignore_ascii_case(code))
+    }
+
+    // Language chosen by the user, then the one of the Telegram client, then the default one
+    pub fn resolve(chosen: Option<&str>, telegram: Option<&str>) -> Language {
+        chosen
+            .and_then(Language::from_code)
+            .or_else(|| telegram.and_then(Language::from_code))
+            .unwrap_or_else(default_language)
+    }
+
+    pub fn texts(self) -> &'static Texts {
+        match self {
+            Language::En => &EN,
+            Language::Ru => &RU,
+        }
+    }
+}
+
+// Language the content is written in, also used for the users with unsupported languages
+pub fn default_language() -> Language {
+    dotenv::var("DEFAULT_LANGUAGE")
+        .ok()
+        .and_then(|code| Language::from_code(&code))
+        .unwrap_or(Language::En)
+}
+
+pub struct Texts {
+    pub start: &'static str,
+    pub main_menu: &'static str,
+    pub main_menu_button: &'static str,
+    pub prev_page: &'static str,
+    pub next_page: &'static str,
+    pub go_back: &'static str,
+    pub category_select_question: fn(&str) -> String,
+    pub category_empty: fn(&str) -> String,
+    pub category_not_available: &'static str,
+    pub question_not_available: &'static str,
+    pub menu_outdated: &'static str,
+    pub subscribe: &'static str,
+    pub unsubscribe: &'static str,
+    pub search_results: &'static str,
+    pub nothing_found: fn(&str) -> String,
+    pub ask_human: &'static str,
+    pub describe_question: &'static str,
+    pub question_not_text: &'static str,
+    pub question_sent: &'static str,
+    pub answer_header: &'static str,
+    pub was_helpful: &'static str,
+    pub helpful: &'static str,
+    pub not_helpful: &'static str,
+    pub add_comment: &'static str,
+    pub write_comment: &'static str,
+    pub comment_not_text: &'static str,
+    pub comment_thanks: &'static str,
+    pub digest_header: &'static str,
+    pub digest_more: fn(usize) -> String,
+    pub select_language: &'static str,
+    pub language_auto: &'static str,
+    pub language_changed: fn(&str) -> String,
+    pub not_authorized: &'static str,
+    pub request_access: &'static str,
+    pub request_pending: &'static str,
+    pub request_denied: &'static str,
+    pub request_sent: &'static str,
+    pub request_approved: &'static str,
+    pub request_already_handled: &'static str,
+    pub already_have_access: &'static str,
+    // the messages below are read by the admins and the support team
+    pub requests_access: fn(&str) -> String,
+    pub approve: &'static str,
+    pub deny: &'static str,
+    pub approved_by: fn(&str) -> String,
+    pub denied_by: fn(&str) -> String,
+    pub decision_already_made: &'static str,
+    pub support_question: fn(i64, &str, &str) -> String,
+    pub support_answered_in_cms: fn(&str) -> String,
+    pub support_answer_not_text: &'static str,
+    pub support_already_answered: &'static str,
+    pub support_answer_sent: &'static str,
+    pub support_answer_not_delivered: fn(&str) -> String,
+}
+
+static EN: Texts = Texts {
+    start: "Type your question or select the category in the menu below",
+    main_menu: "Main menu, please select the category or type your question",
+    main_menu_button: "Main menu",
+    prev_page: "« Prev",
+    next_page: "Next »",
+    go_back: "Go Back",
+    category_select_question: |path| format!("Category {path}, please select the question"),
+    category_empty: |path| format!("Category {path} has no questions yet"),
+    category_not_available: "This category is not available anymore",
+    question_not_available: "This question is not available anymore",
+    menu_outdated: "This menu is outdated, please press /start to open the new one",
+    subscribe: "🔔 Subscribe to updates",
+    unsubscribe: "🔕 Unsubscribe",
+    search_results: "Here is what I found, please select the question",
+    nothing_found: |query| {
+        format!("Nothing found for \"{query}\", please select the category or rephrase")
+    },
+    ask_human: "🙋 Ask a human",
+    describe_question:
+        "Please describe your question, it will be sent to the support team. Press /start to cancel",
+    question_not_text: "Please send the question as a text message",
+    question_sent: "Your question is sent to the support team, the answer will come to this chat",
+    answer_header: "Answer to your question:",
+    was_helpful: "Was this answer helpful?",
+    helpful: "👍 Helpful",
+    not_helpful: "👎 Not helpful",
+    add_comment: "💬 Add a comment",
+    write_comment:
+        "Please write what is wrong or missing in the answer, or press /start to cancel",
+    comment_not_text: "Please send the comment as a text message",
+    comment_thanks: "Thank you, your comment will help us improve the answer",
+    digest_header: "Updates in the categories you are subscribed to:",
+    digest_more: |count| format!("and {count} more"),
+    select_language: "Please select the language",
+    language_auto: "Same as Telegram",
+    language_changed: |name| format!("Language is changed to {name}"),
+    not_authorized: "You are not authorized to use this bot",
+    request_access: "Request access",
+    request_pending: "Your access request is waiting for the approval of the administrators",
+    request_denied: "Your access request was denied",
+    request_sent: "Your access request is sent to the administrators, you will be notified when it is approved",
+    request_approved: "Your access request is approved, press /start to begin",
+    request_already_handled: "Your access request is already handled, press /start",
+    already_have_access: "You already have access, press /start to begin",
+    requests_access: |name| format!("{name} requests access to the bot"),
+    approve: "Approve",
+    deny: "Deny",
+    approved_by: |name| format!("Approved by {name}"),
+    denied_by: |name| format!("Denied by {name}"),
+    decision_already_made: "Request is already handled",
+    support_question: |id, name, question| {
+        format!("Question #{id} from {name}:\n\n{question}\n\nReply to this message to answer")
+    },
+    support_answered_in_cms: |name| format!("Answered in the CMS by {name}"),
+    support_answer_not_text: "Only text answers are supported",
+    support_already_answered: "This question is already answered",
+    support_answer_sent: "The answer is sent to the user",
+    support_answer_not_delivered: |err| {
+        format!("The answer is saved, but could not be delivered to the user: {err}")
+    },
+};
+
+static RU: Texts = Texts {
+    start: "Напишите вопрос или выберите категорию в меню ниже",
+    main_menu: "Главное меню, выберите категорию или напишите вопрос",
+    main_menu_button: "Главное меню",
+    prev_page: "« Назад",
+    next_page: "Далее »",
+    go_back: "Вернуться",
+    category_select_question: |path| format!("Категория {path}, выберите вопрос"),
+    category_empty: |path| format!("В категории {path} пока нет вопросов"),
+    category_not_available: "Эта категория больше недоступна",
+    question_not_available: "Этот вопрос больше недоступен",
+    menu_outdated: "Это меню устарело, нажмите /start, чтобы открыть новое",
+    subscribe: "🔔 Подписаться на обновления",
+    unsubscribe: "🔕 Отписаться",
+    search_results: "Вот что удалось найти, выберите вопрос",
+    nothing_found: |query| {
+        format!("По запросу «{query}» ничего не найдено, выберите категорию или переформулируйте вопрос")
+    },
+    ask_human: "🙋 Спросить у сотрудника",
+    describe_question:
+        "Опишите ваш вопрос, он будет отправлен команде поддержки. Нажмите /start для отмены",
+    question_not_text: "Пожалуйста, отправьте вопрос текстовым сообщением",
+    question_sent: "Ваш вопрос отправлен команде поддержки, ответ придёт в этот чат",
+    answer_header: "Ответ на ваш вопрос:",
+    was_helpful: "Этот ответ был полезен?",
+    helpful: "👍 Полезен",
+    not_helpful: "👎 Не полезен",
+    add_comment: "💬 Оставить комментарий",
+    write_comment:
+        "Напишите, что в ответе неверно или чего не хватает, или нажмите /start для отмены",
+    comment_not_text: "Пожалуйста, отправьте комментарий текстовым сообщением",
+    comment_thanks: "Спасибо, ваш комментарий поможет улучшить ответ",
+    digest_header: "Обновления в категориях, на которые вы подписаны:",
+    digest_more: |count| format!("и ещё {count}"),
+    select_language: "Выберите язык",
+    language_auto: "Как в Telegram",
+    language_changed: |name| format!("Язык изменён на {name}"),
+    not_authorized: "У вас нет доступа к этому боту",
+    request_access: "Запросить доступ",
+    request_pending: "Ваш запрос на доступ ожидает одобрения администраторов",
+    request_denied: "Ваш запрос на доступ отклонён",
+    request_sent:
+        "Ваш запрос на доступ отправлен администраторам, вы получите уведомление, когда его одобрят",
+    request_approved: "Ваш запрос на доступ одобрен, нажмите /start, чтобы начать",
+    request_already_handled: "Ваш запрос на доступ уже рассмотрен, нажмите /start",
+    already_have_access: "У вас уже есть доступ, нажмите /start, чтобы начать",
+    requests_access: |name| format!("{name} запрашивает доступ к боту"),
+    approve: "Одобрить",
+    deny: "Отклонить",
+    approved_by: |name| format!("Одобрено: {name}"),
+    denied_by: |name| format!("Отклонено: {name}"),
+    decision_already_made: "Запрос уже рассмотрен",
+    support_question: |id, name, question| {
+        format!("Вопрос #{id} от {name}:\n\n{question}\n\nОтветьте на это сообщение, чтобы ответить пользователю")
+    },
+    support_answered_in_cms: |name| format!("Ответ дан в CMS: {name}"),
+    support_answer_not_text: "Поддерживаются только текстовые ответы",
+    support_already_answered: "На этот вопрос уже ответили",
+    support_answer_sent: "Ответ отправлен пользователю",
+    support_answer_not_delivered: |err| {
+        format!("Ответ сохранён, но его не удалось доставить пользователю: {err}")
+    },
+};
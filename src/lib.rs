@@ -1,4 +1,7 @@
 pub mod bot;
 pub mod db;
+pub mod i18n;
+pub mod markdown;
 pub mod server;
+pub mod storage;
 pub mod telemetry;
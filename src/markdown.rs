@@ -0,0 +1,491 @@
+// Answers are written in Markdown and sent with the HTML subset supported by Telegram,
+// see https://core.telegram.org/bots/api#html-style
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+
+// Telegram limits the message text to 4096 UTF-16 code units
+pub const MESSAGE_MAX_LENGTH: usize = 4096;
+
+const ALLOWED_LINK_SCHEMES: [&str; 4] = ["http://", "https://", "tg://", "mailto:"];
+
+enum Token {
+    // opening tag with the attributes and the name to close it
+    Open { html: String, name: &'static str },
+    Close(&'static str),
+    // not escaped yet, so it can be split anywhere
+    Text(String),
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn length(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+fn render(tokens: &[Token]) -> String {
+    tokens
+        .iter()
+        .map(|token| match token {
+            Token::Open { html, .. } => html.clone(),
+            Token::Close(name) => format!("</{name}>"),
+            Token::Text(text) => escape(text),
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct Renderer {
+    blocks: Vec<Vec<Token>>,
+    current: Vec<Token>,
+    // nesting of the block elements, the top level block is complete when it gets to 0
+    depth: usize,
+    // new lines to put before the next nested block
+    pending_breaks: usize,
+    // next number of every list, None for the bullet lists
+    lists: Vec<Option<u64>>,
+    // whether the link got the tag, links with unsafe urls are rendered as text
+    links: Vec<bool>,
+    // Telegram does not support nested quotes, so only the outer one gets the tag
+    quotes: usize,
+}
+
+impl Renderer {
+    fn text(&mut self, text: impl Into<String>) {
+        self.current.push(Token::Text(text.into()))
+    }
+
+    fn open(&mut self, name: &'static str) {
+        self.current.push(Token::Open {
+            html: format!("<{name}>"),
+            name,
+        })
+    }
+
+    fn close(&mut self, name: &'static str) {
+        self.current.push(Token::Close(name))
+    }
+
+    fn start_block(&mut self) {
+        if self.depth > 0 && self.pending_breaks > 0 && !self.current.is_empty() {
+            self.text("\n".repeat(self.pending_breaks));
+        }
+        self.pending_breaks = 0;
+        self.depth += 1;
+    }
+
+    fn end_block(&mut self, breaks: usize) {
+        self.depth -= 1;
+        self.pending_breaks = self.pending_breaks.max(breaks);
+        if self.depth == 0 {
+            self.blocks.push(std::mem::take(&mut self.current));
+            self.pending_breaks = 0;
+        }
+    }
+
+    fn start_link(&mut self, url: &str) {
+        let allowed = ALLOWED_LINK_SCHEMES
+            .iter()
+            .any(|scheme| url.to_lowercase().starts_with(scheme));
+        if allowed {
+            self.current.push(Token::Open {
+                html: format!("<a href=\"{}\">", escape(url)),
+                name: "a",
+            });
+        }
+        self.links.push(allowed);
+    }
+
+    fn end_link(&mut self) {
+        if self.links.pop().unwrap_or(false) {
+            self.close("a");
+        }
+    }
+
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => self.text(text.to_string()),
+            Event::Code(code) => {
+                self.open("code");
+                self.text(code.to_string());
+                self.close("code");
+            }
+            // raw html is shown as is, so it can't break the markup
+            Event::Html(html) | Event::InlineHtml(html) => self.text(html.to_string()),
+            Event::SoftBreak | Event::HardBreak => self.text("\n"),
+            Event::Rule => {
+                self.start_block();
+                self.text("———");
+                self.end_block(2);
+            }
+            Event::TaskListMarker(checked) => self.text(if checked { "☑ " } else { "☐ " }),
+            Event::FootnoteReference(label) => self.text(format!("[{label}]")),
+            Event::InlineMath(math) | Event::DisplayMath(math) => self.text(math.to_string()),
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph | Tag::HtmlBlock | Tag::MetadataBlock(_) => self.start_block(),
+            Tag::Heading { .. } => {
+                self.start_block();
+                self.open("b");
+            }
+            Tag::BlockQuote(_) => {
+                self.start_block();
+                if self.quotes == 0 {
+                    self.open("blockquote");
+                }
+                self.quotes += 1;
+            }
+            Tag::CodeBlock(kind) => {
+                self.start_block();
+                self.open("pre");
+                match kind {
+                    CodeBlockKind::Fenced(language) if !language.is_empty() => {
+                        self.current.push(Token::Open {
+                            html: format!("<code class=\"language-{}\">", escape(&language)),
+                            name: "code",
+                        })
+                    }
+                    _ => self.open("code"),
+                }
+            }
+            Tag::List(start) => {
+                // nested list starts on the new line after the text of the item
+                if self.depth > 0 {
+                    self.pending_breaks = self.pending_breaks.max(1);
+                }
+                self.start_block();
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.start_block();
+                let indent = "    ".repeat(self.lists.len().saturating_sub(1));
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => "• ".to_string(),
+                };
+                self.text(format!("{indent}{marker}"));
+            }
+            Tag::FootnoteDefinition(label) => {
+                self.start_block();
+                self.text(format!("[{label}] "));
+            }
+            Tag::DefinitionList
+            | Tag::DefinitionListTitle
+            | Tag::DefinitionListDefinition
+            | Tag::Table(_)
+            | Tag::TableHead
+            | Tag::TableRow => self.start_block(),
+            Tag::TableCell => {}
+            Tag::Emphasis => self.open("i"),
+            Tag::Strong => self.open("b"),
+            Tag::Strikethrough => self.open("s"),
+            Tag::Superscript | Tag::Subscript => {}
+            Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. } => self.start_link(&dest_url),
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph | TagEnd::HtmlBlock | TagEnd::MetadataBlock(_) => self.end_block(2),
+            TagEnd::Heading(_) => {
+                self.close("b");
+                self.end_block(2);
+            }
+            TagEnd::BlockQuote(_) => {
+                self.quotes -= 1;
+                if self.quotes == 0 {
+                    self.close("blockquote");
+                }
+                self.end_block(2);
+            }
+            TagEnd::CodeBlock => {
+                // code block text always ends with the new line
+                if let Some(Token::Text(text)) = self.current.last_mut() {
+                    if text.ends_with('\n') {
+                        text.pop();
+                    }
+                }
+                self.close("code");
+                self.close("pre");
+                self.end_block(2);
+            }
+            TagEnd::List(_) => {
+                self.lists.pop();
+                self.end_block(1);
+            }
+            TagEnd::Item
+            | TagEnd::DefinitionListTitle
+            | TagEnd::DefinitionListDefinition
+            | TagEnd::TableHead
+            | TagEnd::TableRow => self.end_block(1),
+            TagEnd::FootnoteDefinition | TagEnd::DefinitionList | TagEnd::Table => {
+                self.end_block(2)
+            }
+            TagEnd::TableCell => self.text(" "),
+            TagEnd::Emphasis => self.close("i"),
+            TagEnd::Strong => self.close("b"),
+            TagEnd::Strikethrough => self.close("s"),
+            TagEnd::Superscript | TagEnd::Subscript => {}
+            TagEnd::Link | TagEnd::Image => self.end_link(),
+        }
+    }
+}
+
+fn parse(markdown: &str) -> Vec<Vec<Token>> {
+    let mut renderer = Renderer::default();
+    let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    for event in Parser::new_ext(markdown, options) {
+        renderer.event(event);
+    }
+    renderer.blocks
+}
+
+// Byte index to split the text at, so the escaped head fits into `room`.
+// Prefers to split after the last whitespace
+fn split_point(text: &str, room: usize) -> usize {
+    let mut used = 0;
+    let mut last_space = None;
+    for (i, c) in text.char_indices() {
+        used += length(&escape(c.encode_utf8(&mut [0; 4])));
+        if used > room {
+            return last_space.unwrap_or(i);
+        }
+        if c.is_whitespace() {
+            last_space = Some(i + c.len_utf8());
+        }
+    }
+    text.len()
+}
+
+// Splits the block that does not fit into a single message,
+// tags open at the split are closed and opened again in the next part
+fn split_block(tokens: &[Token], limit: usize) -> Vec<String> {
+    let mut parts = vec![];
+    let mut open: Vec<(&str, &'static str)> = vec![];
+    let mut current = String::new();
+    // part that consists only of the reopened tags has no content to flush
+    let mut reopened = 0;
+
+    let closing = |open: &[(&str, &'static str)]| -> String {
+        open.iter()
+            .rev()
+            .map(|(_, name)| format!("</{name}>"))
+            .collect()
+    };
+
+    for token in tokens {
+        match token {
+            Token::Open { html, name } => {
+                let needed = length(html) + length(&closing(&open)) + name.len() + 3;
+                if length(&current) + needed > limit && length(&current) > reopened {
+                    current.push_str(&closing(&open));
+                    parts.push(std::mem::take(&mut current));
+                    current = open.iter().map(|(html, _)| *html).collect();
+                    reopened = length(&current);
+                }
+                current.push_str(html);
+                open.push((html, name));
+            }
+            Token::Close(name) => {
+                current.push_str(&format!("</{name}>"));
+                open.pop();
+            }
+            Token::Text(text) => {
+                let mut rest = text.as_str();
+                while !rest.is_empty() {
+                    let room = limit
+                        .saturating_sub(length(&current))
+                        .saturating_sub(length(&closing(&open)));
+                    let mut at = split_point(rest, room);
+                    if at == 0 && length(&current) <= reopened {
+                        // not even a single character fits, the part is already as small as it gets
+                        at = rest.chars().next().map(|c| c.len_utf8()).unwrap_or(0);
+                    }
+                    current.push_str(&escape(&rest[..at]));
+                    rest = &rest[at..];
+                    if !rest.is_empty() {
+                        current.push_str(&closing(&open));
+                        parts.push(std::mem::take(&mut current));
+                        current = open.iter().map(|(html, _)| *html).collect();
+                        reopened = length(&current);
+                    }
+                }
+            }
+        }
+    }
+    if length(&current) > 0 {
+        parts.push(current);
+    }
+    parts
+}
+
+// Renders the Markdown into the messages, each no longer than `limit`.
+// Messages are split between the paragraphs when possible
+pub fn to_telegram_chunks(markdown: &str, limit: usize) -> Vec<String> {
+    let mut chunks = vec![];
+    let mut current = String::new();
+    for block in parse(markdown) {
+        let html = render(&block);
+        if html.trim().is_empty() {
+            continue;
+        }
+        let separator = if current.is_empty() { 0 } else { 2 };
+        if length(&current) + separator + length(&html) <= limit {
+            if separator > 0 {
+                current.push_str("\n\n");
+            }
+            current.push_str(&html);
+            continue;
+        }
+        if !current.is_empty() {
+            chunks.push(std::mem::take(&mut current));
+        }
+        if length(&html) <= limit {
+            current = html;
+        } else {
+            let mut parts = split_block(&block, limit);
+            current = parts.pop().unwrap_or_default();
+            chunks.extend(parts);
+        }
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render_one(markdown: &str) -> String {
+        let chunks = to_telegram_chunks(markdown, MESSAGE_MAX_LENGTH);
+        assert_eq!(chunks.len(), 1, "{chunks:?}");
+        chunks.into_iter().next().unwrap()
+    }
+
+    // every chunk fits and has its tags balanced
+    fn check_chunks(chunks: &[String], limit: usize) {
+        for chunk in chunks {
+            assert!(length(chunk) <= limit, "{chunk}");
+            let mut open = vec![];
+            for tag in chunk.split('<').skip(1) {
+                let name = tag.split(['>', ' ']).next().unwrap();
+                match name.strip_prefix('/') {
+                    Some(name) => assert_eq!(open.pop(), Some(name), "{chunk}"),
+                    None => open.push(name),
+                }
+            }
+            assert!(open.is_empty(), "{chunk}");
+        }
+    }
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(render_one("a < b && c > d"), "a &lt; b &amp;&amp; c &gt; d");
+        assert_eq!(
+            render_one("<b>bold</b> `<i>`"),
+            "&lt;b&gt;bold&lt;/b&gt; <code>&lt;i&gt;</code>"
+        );
+    }
+
+    #[test]
+    fn renders_inline_markup() {
+        assert_eq!(
+            render_one("*it* **bold** ~~gone~~"),
+            "<i>it</i> <b>bold</b> <s>gone</s>"
+        );
+        assert_eq!(
+            render_one("[site](https://example.com/?a=1&b=\"2\")"),
+            "<a href=\"https://example.com/?a=1&amp;b=&quot;2&quot;\">site</a>"
+        );
+    }
+
+    #[test]
+    fn drops_unsupported_links() {
+        assert_eq!(render_one("[click](javascript:alert(1))"), "click");
+        assert_eq!(render_one("[click](JavaScript:alert(1))"), "click");
+        assert_eq!(render_one("[file](file:///etc/passwd)"), "file");
+        assert_eq!(render_one("[page](/relative)"), "page");
+        assert_eq!(
+            render_one("[mail](mailto:a@b.c)"),
+            "<a href=\"mailto:a@b.c\">mail</a>"
+        );
+    }
+
+    #[test]
+    fn renders_nested_lists() {
+        assert_eq!(
+            render_one("- one\n  1. first\n  2. second\n- two"),
+            "• one\n    1. first\n    2. second\n• two"
+        );
+    }
+
+    #[test]
+    fn renders_nested_quotes() {
+        assert_eq!(
+            render_one("> outer\n>\n> > inner"),
+            "<blockquote>outer\n\ninner</blockquote>"
+        );
+    }
+
+    #[test]
+    fn splits_between_paragraphs() {
+        let paragraph = "word ".repeat(300);
+        let markdown = [paragraph.trim(); 3].join("\n\n");
+        let chunks = to_telegram_chunks(&markdown, MESSAGE_MAX_LENGTH);
+        check_chunks(&chunks, MESSAGE_MAX_LENGTH);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1], paragraph.trim());
+    }
+
+    #[test]
+    fn splits_long_code_block() {
+        let code = "let x = 1;\n".repeat(1000);
+        let chunks = to_telegram_chunks(&format!("```rust\n{code}```"), MESSAGE_MAX_LENGTH);
+        check_chunks(&chunks, MESSAGE_MAX_LENGTH);
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            assert!(chunk.starts_with("<pre><code class=\"language-rust\">"));
+            assert!(chunk.ends_with("</code></pre>"));
+        }
+        let text: String = chunks
+            .iter()
+            .map(|chunk| {
+                chunk
+                    .trim_start_matches("<pre><code class=\"language-rust\">")
+                    .trim_end_matches("</code></pre>")
+            })
+            .collect();
+        assert_eq!(text, code.trim_end());
+    }
+
+    #[test]
+    fn reopens_tags_at_split() {
+        let markdown = format!("> **{}** tail", ["a"; 30].join(" "));
+        let chunks = to_telegram_chunks(&markdown, 50);
+        check_chunks(&chunks, 50);
+        assert!(chunks.len() > 1);
+        assert!(chunks[0].starts_with("<blockquote><b>"));
+        assert!(chunks[1].starts_with("<blockquote><b>"));
+        assert!(chunks.last().unwrap().ends_with(" tail</blockquote>"));
+    }
+
+    #[test]
+    fn counts_escaped_length() {
+        let chunks = to_telegram_chunks(&"&".repeat(100), 50);
+        check_chunks(&chunks, 50);
+        assert_eq!(chunks.concat(), "&amp;".repeat(100));
+    }
+}
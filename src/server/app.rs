@@ -2,37 +2,78 @@ use askama::Template;
 use axum::body::Body;
 use axum::http::header;
 use axum::response::Response;
-use axum::{extract::FromRef, http::StatusCode, response::Html, routing::get, Router};
+use axum::{extract::FromRef, http::StatusCode, middleware, response::Html, routing::get, Router};
 use prometheus::{Encoder, TextEncoder};
-use routes::{category_router, questions_router, users_router};
-use sqlx::SqlitePool;
+use routes::{
+    access_requests_router, api_router, backup_router, broadcasts_router, category_router,
+    feedback_router, groups_router, history_router, login_router, pending_questions_router,
+    questions_csv_router, questions_router, translations_router, trash_router, users_router,
+};
 use std::path::PathBuf;
+use std::sync::Arc;
+use teloxide::Bot;
 use tokio::net::TcpListener;
-use tower_http::services::ServeDir;
 use tower_http::trace::TraceLayer;
 
+use crate::bot::broadcast::resume_broadcasts;
+use crate::db::{Pool, Repositories};
+use crate::storage::BlobStorage;
+
+use super::auth::{require_admin, AuthConfig};
 use super::routes;
+use super::trash::run_purge;
 
 #[derive(FromRef, Clone)]
 pub struct AppState {
-    pool: SqlitePool,
+    pool: Pool,
+    repositories: Repositories,
     static_dir: PathBuf,
+    blobs: Arc<dyn BlobStorage>,
+    auth: Arc<AuthConfig>,
+    // to notify the users about the changes made in the CMS
+    bot: Bot,
 }
 
-pub async fn run_server(pool: SqlitePool, static_dir: PathBuf) -> anyhow::Result<()> {
+pub async fn run_server(
+    pool: Pool,
+    repositories: Repositories,
+    static_dir: PathBuf,
+    blobs: Arc<dyn BlobStorage>,
+) -> anyhow::Result<()> {
     let addr = "0.0.0.0:8080";
     let state = AppState {
         pool,
+        repositories,
         static_dir: static_dir.clone(),
+        blobs: blobs.clone(),
+        auth: Arc::new(AuthConfig::from_env()),
+        bot: Bot::from_env(),
     };
+    resume_broadcasts(state.bot.clone(), state.pool.clone(), blobs.clone()).await?;
+    tokio::spawn(run_purge(state.pool.clone(), blobs));
 
-    let app = Router::new()
+    let cms = Router::new()
         .route("/", get(index))
-        .route("/metrics", get(metrics))
-        .nest_service("/static", ServeDir::new(static_dir))
         .merge(category_router(state.clone()))
         .merge(questions_router(state.clone()))
+        .merge(questions_csv_router(state.clone()))
+        .merge(translations_router(state.clone()))
         .merge(users_router(state.clone()))
+        .merge(groups_router(state.clone()))
+        .merge(access_requests_router(state.clone()))
+        .merge(feedback_router(state.clone()))
+        .merge(pending_questions_router(state.clone()))
+        .merge(broadcasts_router(state.clone()))
+        .merge(history_router(state.clone()))
+        .merge(trash_router(state.clone()))
+        .merge(backup_router(state.clone()))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_admin));
+
+    let app = Router::new()
+        .route("/metrics", get(metrics))
+        .merge(login_router(state.clone()))
+        .merge(api_router(state.clone()))
+        .merge(cms)
         .fallback(|| async {
             tracing::info!("Fallback");
             StatusCode::NOT_FOUND
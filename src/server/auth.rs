@@ -0,0 +1,206 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::bot::access::display_name;
+use crate::db::queries::sessions::get_session_admin;
+use crate::db::{Pool, Repositories};
+
+use super::routes::api::ErrorBody;
+
+pub const SESSION_COOKIE: &str = "qna_session";
+pub const SESSION_TTL_SECONDS: i64 = 7 * 24 * 60 * 60;
+// how long the data signed by the Telegram Login Widget is accepted
+const LOGIN_DATA_TTL_SECONDS: i64 = 24 * 60 * 60;
+
+pub struct AuthConfig {
+    // used to check the signature of the Telegram Login Widget data
+    pub bot_token: SecretString,
+    pub bot_username: String,
+    // static tokens for automation, sent as `Authorization: Bearer <token>`
+    pub api_tokens: Vec<SecretString>,
+}
+
+impl AuthConfig {
+    pub fn from_env() -> Self {
+        let api_tokens = dotenv::var("API_TOKENS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|token| token.trim())
+            .filter(|token| !token.is_empty())
+            .map(|token| SecretString::from(token.to_string()))
+            .collect();
+        AuthConfig {
+            bot_token: SecretString::from(
+                dotenv::var("TELOXIDE_TOKEN").expect("Variable TELOXIDE_TOKEN should be set"),
+            ),
+            bot_username: dotenv::var("BOT_USERNAME").expect("Variable BOT_USERNAME should be set"),
+            api_tokens,
+        }
+    }
+}
+
+// Who made the request, available to the handlers as an extension
+#[derive(Clone, Debug)]
+pub enum Identity {
+    Admin { id: i64 },
+    ApiToken,
+}
+
+impl std::fmt::Display for Identity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Admin { id } => write!(f, "admin {id}"),
+            Self::ApiToken => write!(f, "API token"),
+        }
+    }
+}
+
+impl Identity {
+    // Name shown to the users and in the history of the changes
+    pub async fn name(&self, repos: &Repositories) -> sqlx::Result<String> {
+        Ok(match self {
+            Self::Admin { id } => display_name(&repos.users.get_user(*id).await?),
+            Self::ApiToken => self.to_string(),
+        })
+    }
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+pub fn new_session_token() -> String {
+    hex::encode(rand::random::<[u8; 32]>())
+}
+
+pub fn session_cookie(token: &str, max_age: i64) -> HeaderValue {
+    HeaderValue::from_str(&format!(
+        "{SESSION_COOKIE}={token}; Path=/; HttpOnly; SameSite=Lax; Max-Age={max_age}"
+    ))
+    .expect("Token is a hex string")
+}
+
+pub fn session_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, value)| value)
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+// Checks the data sent by the Telegram Login Widget, see https://core.telegram.org/widgets/login#checking-authorization
+// Returns id of the telegram user
+pub fn verify_login_data(
+    bot_token: &SecretString,
+    mut data: BTreeMap<String, String>,
+    now: i64,
+) -> Result<i64, &'static str> {
+    let hash = data.remove("hash").ok_or("Signature is missing")?;
+    let hash = hex::decode(hash).map_err(|_| "Signature is malformed")?;
+    let check_string = data
+        .iter()
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect::<Vec<String>>()
+        .join("\n");
+    let secret = Sha256::digest(bot_token.expose_secret().as_bytes());
+    let mut mac = Hmac::<Sha256>::new_from_slice(&secret).expect("HMAC accepts keys of any size");
+    mac.update(check_string.as_bytes());
+    mac.verify_slice(&hash)
+        .map_err(|_| "Signature is invalid")?;
+
+    let auth_date: i64 = data
+        .get("auth_date")
+        .and_then(|date| date.parse().ok())
+        .ok_or("Authorization date is missing")?;
+    if now - auth_date > LOGIN_DATA_TTL_SECONDS {
+        return Err("Authorization data is outdated, please log in again");
+    }
+    data.get("id")
+        .and_then(|id| id.parse().ok())
+        .ok_or("User id is missing")
+}
+
+fn is_api_token(auth: &AuthConfig, token: &str) -> bool {
+    // hashes are compared to not leak the token through the comparison time
+    let hash = hash_token(token);
+    auth.api_tokens
+        .iter()
+        .any(|known| hash_token(known.expose_secret()) == hash)
+}
+
+async fn identify(pool: &Pool, auth: &AuthConfig, headers: &HeaderMap) -> Option<Identity> {
+    if let Some(token) = bearer_token(headers) {
+        return is_api_token(auth, token).then_some(Identity::ApiToken);
+    }
+    let token = session_token(headers)?;
+    match get_session_admin(pool, &hash_token(token)).await {
+        Ok(user) => user.map(|user| Identity::Admin { id: user.id }),
+        Err(e) => {
+            tracing::error!("Unable to check session: {e}");
+            None
+        }
+    }
+}
+
+// Lets through only the requests with a valid admin session or API token
+pub async fn require_admin(
+    State(pool): State<Pool>,
+    State(auth): State<Arc<AuthConfig>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    match identify(&pool, &auth, request.headers()).await {
+        Some(identity) => {
+            tracing::debug!("{} {} by {identity}", request.method(), request.uri());
+            request.extensions_mut().insert(identity);
+            next.run(request).await
+        }
+        None if request.headers().contains_key("HX-Request") => {
+            // htmx swaps only the part of the page, so it is asked to do a full redirect
+            (StatusCode::UNAUTHORIZED, [("HX-Redirect", "/login")]).into_response()
+        }
+        None if request.headers().contains_key(header::AUTHORIZATION) => {
+            StatusCode::UNAUTHORIZED.into_response()
+        }
+        None => Redirect::to("/login").into_response(),
+    }
+}
+
+// JSON API is used by the other services, so only the API tokens are accepted there
+pub async fn require_api_token(
+    State(auth): State<Arc<AuthConfig>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    match bearer_token(request.headers()) {
+        Some(token) if is_api_token(&auth, token) => {
+            tracing::debug!("{} {} by API token", request.method(), request.uri());
+            request.extensions_mut().insert(Identity::ApiToken);
+            next.run(request).await
+        }
+        _ => ErrorBody::response(
+            StatusCode::UNAUTHORIZED,
+            "Valid API token should be sent as `Authorization: Bearer <token>`",
+        ),
+    }
+}
@@ -1,3 +1,7 @@
+use anyhow::anyhow;
+use axum::body::Bytes;
+use axum_typed_multipart::{async_trait, FieldMetadata, TryFromChunks, TypedMultipartError};
+use futures_util::stream::Stream;
 use serde::{Deserialize, Deserializer};
 
 // forms send "on" or not including the value for checkbox
@@ -32,3 +36,40 @@ impl TryFrom<String> for Stri64 {
         }
     }
 }
+
+// json-enc sends a single selected option of the multiple select as a string and several as an array
+pub fn deserialize_ids_from_select<'de, D>(deserializer: D) -> Result<Vec<i64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Selected {
+        One(Stri64),
+        Many(Vec<Stri64>),
+    }
+    Ok(match Option::<Selected>::deserialize(deserializer)? {
+        None => vec![],
+        Some(Selected::One(id)) => vec![id.0],
+        Some(Selected::Many(ids)) => ids.into_iter().map(|id| id.0).collect(),
+    })
+}
+
+// same checkbox behaviour, but for multipart forms
+pub struct FormBool(pub bool);
+
+#[async_trait]
+impl TryFromChunks for FormBool {
+    async fn try_from_chunks(
+        chunks: impl Stream<Item = Result<Bytes, TypedMultipartError>> + Send + Sync + Unpin,
+        metadata: FieldMetadata,
+    ) -> Result<Self, TypedMultipartError> {
+        let string = String::try_from_chunks(chunks, metadata).await?;
+        match string.as_str() {
+            "on" => Ok(FormBool(true)),
+            unexpected => Err(TypedMultipartError::Other {
+                source: anyhow!("Unexpected checkbox value {unexpected}"),
+            }),
+        }
+    }
+}
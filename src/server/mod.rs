@@ -1,3 +1,5 @@
 pub mod app;
+mod auth;
 mod deserializers;
 mod routes;
+mod trash;
@@ -0,0 +1,66 @@
+use askama::Template;
+use askama_web::WebTemplate;
+use axum::{
+    extract::{Path, State},
+    routing::{get, post},
+    Router,
+};
+use teloxide::Bot;
+
+use crate::bot::access::notify_about_decision;
+use crate::db::queries::{self, access_requests::PendingUser};
+use crate::db::Pool;
+use crate::server::app::AppState;
+
+use super::{ApiError, ApiResponse};
+
+#[derive(Template, WebTemplate)]
+#[template(path = "users/access_requests.html", escape = "none")]
+struct AccessRequestsPage {
+    users: Vec<PendingUser>,
+}
+
+async fn access_requests_page(State(pool): State<Pool>) -> ApiResponse<AccessRequestsPage> {
+    Ok(AccessRequestsPage {
+        users: queries::access_requests::get_pending_users(&pool).await?,
+    })
+}
+
+// Row of the handled request is removed from the table by returning the empty body
+async fn approve(
+    State(pool): State<Pool>,
+    State(bot): State<Bot>,
+    Path(id): Path<i64>,
+) -> ApiResponse<()> {
+    if !queries::access_requests::approve_access_request(&pool, id).await? {
+        return Err(ApiError::BadRequest(
+            "Request is already handled".to_string(),
+        ));
+    }
+    tracing::info!("Access request of user {id} is approved in the CMS");
+    notify_about_decision(&bot, &pool, id, true).await;
+    Ok(())
+}
+
+async fn deny(
+    State(pool): State<Pool>,
+    State(bot): State<Bot>,
+    Path(id): Path<i64>,
+) -> ApiResponse<()> {
+    if !queries::access_requests::deny_access_request(&pool, id).await? {
+        return Err(ApiError::BadRequest(
+            "Request is already handled".to_string(),
+        ));
+    }
+    tracing::info!("Access request of user {id} is denied in the CMS");
+    notify_about_decision(&bot, &pool, id, false).await;
+    Ok(())
+}
+
+pub fn access_requests_router(state: AppState) -> Router {
+    Router::new()
+        .route("/access-requests", get(access_requests_page))
+        .route("/access-requests/{id}/approve", post(approve))
+        .route("/access-requests/{id}/deny", post(deny))
+        .with_state(state)
+}
@@ -0,0 +1,299 @@
+use axum::{extract::State, http::StatusCode, routing::get, Extension, Json, Router};
+use serde::Deserialize;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+use crate::db::{queries::categories::flatten_tree, Category, Repositories};
+use crate::server::{app::AppState, auth::Identity, routes::categories::check_parent};
+
+use super::{ApiError, ApiJson, ApiPath, ApiQuery, ErrorBody, JsonResponse, Page, Pagination};
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    list_categories,
+    category,
+    create_category,
+    update_category,
+    delete_category
+))]
+pub struct CategoriesApi;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct CategoriesFilter {
+    /// only the direct children of the category
+    parent: Option<i64>,
+    hidden: Option<bool>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct CategoryUpdate {
+    name: String,
+    #[serde(default)]
+    hidden: bool,
+    #[serde(default)]
+    parent: Option<i64>,
+}
+
+impl CategoryUpdate {
+    async fn validate(&self, repos: &Repositories, id: Option<i64>) -> JsonResponse<()> {
+        if self.name.trim().is_empty() {
+            return Err(ApiError::BadRequest("Name can't be empty".to_string()).into());
+        }
+        check_parent(repos, id, self.parent).await?;
+        Ok(())
+    }
+}
+
+/// Categories are listed in the order of the tree, every category is followed by its children
+#[utoipa::path(
+    get,
+    path = "/categories",
+    tag = "categories",
+    params(Pagination, CategoriesFilter),
+    responses(
+        (status = 200, body = Page<Category>),
+        (status = 400, body = ErrorBody),
+    )
+)]
+async fn list_categories(
+    State(repos): State<Repositories>,
+    ApiQuery(pagination): ApiQuery<Pagination>,
+    ApiQuery(filter): ApiQuery<CategoriesFilter>,
+) -> JsonResponse<Json<Page<Category>>> {
+    let categories = flatten_tree(repos.categories.get_all_categories().await?)
+        .into_iter()
+        .map(|(_, category)| category)
+        .filter(|category| {
+            filter
+                .parent
+                .is_none_or(|parent| category.parent == Some(parent))
+        })
+        .filter(|category| filter.hidden.is_none_or(|hidden| category.hidden == hidden))
+        .collect();
+    Page::new(categories, &pagination)
+}
+
+#[utoipa::path(
+    get,
+    path = "/categories/{id}",
+    tag = "categories",
+    params(("id" = i64, Path)),
+    responses(
+        (status = 200, body = Category),
+        (status = 404, body = ErrorBody),
+    )
+)]
+async fn category(
+    State(repos): State<Repositories>,
+    ApiPath(id): ApiPath<i64>,
+) -> JsonResponse<Json<Category>> {
+    Ok(Json(repos.categories.get_category(id).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/categories",
+    tag = "categories",
+    request_body = CategoryUpdate,
+    responses(
+        (status = 201, body = Category),
+        (status = 400, body = ErrorBody),
+    )
+)]
+async fn create_category(
+    State(repos): State<Repositories>,
+    Extension(identity): Extension<Identity>,
+    ApiJson(body): ApiJson<CategoryUpdate>,
+) -> JsonResponse<(StatusCode, Json<Category>)> {
+    body.validate(&repos, None).await?;
+    let id = repos
+        .categories
+        .create_category(
+            body.name.trim(),
+            body.hidden,
+            1,
+            body.parent,
+            &identity.name(&repos).await?,
+        )
+        .await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(repos.categories.get_category(id).await?),
+    ))
+}
+
+#[utoipa::path(
+    put,
+    path = "/categories/{id}",
+    tag = "categories",
+    params(("id" = i64, Path)),
+    request_body = CategoryUpdate,
+    responses(
+        (status = 200, body = Category),
+        (status = 400, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    )
+)]
+async fn update_category(
+    State(repos): State<Repositories>,
+    Extension(identity): Extension<Identity>,
+    ApiPath(id): ApiPath<i64>,
+    ApiJson(body): ApiJson<CategoryUpdate>,
+) -> JsonResponse<Json<Category>> {
+    repos.categories.get_category(id).await?;
+    body.validate(&repos, Some(id)).await?;
+    repos
+        .categories
+        .update_category(
+            id,
+            body.name.trim().to_string(),
+            body.hidden,
+            body.parent,
+            &identity.name(&repos).await?,
+        )
+        .await?;
+    Ok(Json(repos.categories.get_category(id).await?))
+}
+
+/// Category is moved to the trash like in the CMS
+#[utoipa::path(
+    delete,
+    path = "/categories/{id}",
+    tag = "categories",
+    params(("id" = i64, Path)),
+    responses(
+        (status = 204),
+        (status = 404, body = ErrorBody),
+    )
+)]
+async fn delete_category(
+    State(repos): State<Repositories>,
+    Extension(identity): Extension<Identity>,
+    ApiPath(id): ApiPath<i64>,
+) -> JsonResponse<StatusCode> {
+    repos.categories.get_category(id).await?;
+    repos
+        .categories
+        .delete_category(id, &identity.name(&repos).await?)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn categories_router(state: AppState) -> Router {
+    Router::new()
+        .route("/categories", get(list_categories).post(create_category))
+        .route(
+            "/categories/{id}",
+            get(category).put(update_category).delete(delete_category),
+        )
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::response::IntoResponse;
+
+    use super::*;
+
+    fn pagination() -> ApiQuery<Pagination> {
+        ApiQuery(Pagination {
+            page: 1,
+            per_page: 50,
+        })
+    }
+
+    async fn create(repos: &Repositories, name: &str, parent: Option<i64>) -> JsonResponse<i64> {
+        let body = CategoryUpdate {
+            name: name.to_string(),
+            hidden: false,
+            parent,
+        };
+        let (_, Json(category)) = create_category(
+            State(repos.clone()),
+            Extension(Identity::ApiToken),
+            ApiJson(body),
+        )
+        .await?;
+        Ok(category.id)
+    }
+
+    #[tokio::test]
+    async fn lists_categories_in_tree_order() {
+        let repos = Repositories::memory();
+        let second = create(&repos, "Second", None).await.ok().unwrap();
+        let first = create(&repos, "First", None).await.ok().unwrap();
+        let child = create(&repos, "Child", Some(second)).await.ok().unwrap();
+
+        let filter = CategoriesFilter {
+            parent: None,
+            hidden: None,
+        };
+        let Json(page) = list_categories(State(repos.clone()), pagination(), ApiQuery(filter))
+            .await
+            .ok()
+            .unwrap();
+        let ids: Vec<i64> = page.items.iter().map(|c| c.id).collect();
+        assert_eq!(ids, [first, second, child]);
+        assert_eq!(page.total, 3);
+
+        let filter = CategoriesFilter {
+            parent: Some(second),
+            hidden: None,
+        };
+        let Json(page) = list_categories(State(repos), pagination(), ApiQuery(filter))
+            .await
+            .ok()
+            .unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].name, "Child");
+    }
+
+    #[tokio::test]
+    async fn rejects_wrong_categories() {
+        let repos = Repositories::memory();
+        let status = |result: JsonResponse<i64>| result.map(Json).into_response().status();
+        assert_eq!(
+            status(create(&repos, " ", None).await),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            status(create(&repos, "Orphan", Some(42)).await),
+            StatusCode::BAD_REQUEST
+        );
+
+        let parent = create(&repos, "Parent", None).await.ok().unwrap();
+        let child = create(&repos, "Child", Some(parent)).await.ok().unwrap();
+        // the parent can't be moved into its own subtree
+        let body = CategoryUpdate {
+            name: "Parent".to_string(),
+            hidden: false,
+            parent: Some(child),
+        };
+        let response = update_category(
+            State(repos.clone()),
+            Extension(Identity::ApiToken),
+            ApiPath(parent),
+            ApiJson(body),
+        )
+        .await;
+        assert_eq!(response.into_response().status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn deletes_categories() {
+        let repos = Repositories::memory();
+        let id = create(&repos, "Old", None).await.ok().unwrap();
+        let response = delete_category(
+            State(repos.clone()),
+            Extension(Identity::ApiToken),
+            ApiPath(id),
+        )
+        .await;
+        assert_eq!(response.into_response().status(), StatusCode::NO_CONTENT);
+        let response = category(State(repos.clone()), ApiPath(id)).await;
+        assert_eq!(response.into_response().status(), StatusCode::NOT_FOUND);
+        let response =
+            delete_category(State(repos), Extension(Identity::ApiToken), ApiPath(id)).await;
+        assert_eq!(response.into_response().status(), StatusCode::NOT_FOUND);
+    }
+}
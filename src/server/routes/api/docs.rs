@@ -0,0 +1,64 @@
+// OpenAPI document of the JSON API, generated from the handlers and the types they use
+use askama::Template;
+use askama_web::WebTemplate;
+use axum::{routing::get, Json, Router};
+use utoipa::{
+    openapi::{
+        security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+        OpenApi as OpenApiDocument,
+    },
+    Modify, OpenApi,
+};
+
+use super::{categories::CategoriesApi, questions::QuestionsApi, users::UsersApi};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "QnA bot API"),
+    nest(
+        (path = "/api/v1", api = CategoriesApi),
+        (path = "/api/v1", api = QuestionsApi),
+        (path = "/api/v1", api = UsersApi),
+    ),
+    modifiers(&ApiToken),
+    security(("api_token" = [])),
+)]
+struct ApiDoc;
+
+struct ApiToken;
+
+impl Modify for ApiToken {
+    fn modify(&self, openapi: &mut OpenApiDocument) {
+        openapi
+            .components
+            .get_or_insert_default()
+            .add_security_scheme(
+                "api_token",
+                SecurityScheme::Http(
+                    HttpBuilder::new()
+                        .scheme(HttpAuthScheme::Bearer)
+                        .description(Some("One of the tokens from `API_TOKENS`"))
+                        .build(),
+                ),
+            );
+    }
+}
+
+#[derive(Template, WebTemplate)]
+#[template(path = "api_docs.html", escape = "none")]
+struct DocsPage;
+
+async fn openapi_json() -> Json<OpenApiDocument> {
+    Json(ApiDoc::openapi())
+}
+
+async fn docs_page() -> DocsPage {
+    DocsPage
+}
+
+// The document has no secrets, so it's available without the token for the integrators
+pub fn docs_router() -> Router {
+    Router::new()
+        .route("/api/openapi.json", get(openapi_json))
+        .route("/api/docs", get(docs_page))
+}
@@ -0,0 +1,174 @@
+// JSON API for the other services. It shares the queries with the CMS, but the payloads
+// and the errors are JSON and only the API tokens are accepted
+use axum::{
+    extract::{
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+        FromRequest, FromRequestParts, Path, Query,
+    },
+    http::StatusCode,
+    middleware,
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+use axum_typed_multipart::{TypedMultipart, TypedMultipartError};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::server::{app::AppState, auth::require_api_token};
+
+use super::ApiError;
+
+mod categories;
+mod docs;
+mod questions;
+mod users;
+
+const DEFAULT_PER_PAGE: usize = 50;
+const MAX_PER_PAGE: usize = 100;
+
+pub type JsonResponse<T> = Result<T, JsonError>;
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub error: String,
+}
+
+impl ErrorBody {
+    pub fn response(status: StatusCode, error: impl Into<String>) -> Response {
+        (
+            status,
+            Json(ErrorBody {
+                error: error.into(),
+            }),
+        )
+            .into_response()
+    }
+}
+
+// Same errors as in the CMS with the message in the JSON body
+pub struct JsonError(ApiError);
+
+impl<E: Into<ApiError>> From<E> for JsonError {
+    fn from(value: E) -> Self {
+        JsonError(value.into())
+    }
+}
+
+impl IntoResponse for JsonError {
+    fn into_response(self) -> Response {
+        let status = self.0.status();
+        let message = match self.0 {
+            ApiError::BadRequest(message) => message,
+            err => {
+                if status.is_server_error() {
+                    tracing::error!("API request failed: {err:?}");
+                }
+                status.canonical_reason().unwrap_or_default().to_string()
+            }
+        };
+        ErrorBody::response(status, message)
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(value: JsonRejection) -> Self {
+        ApiError::BadRequest(value.body_text())
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(value: PathRejection) -> Self {
+        ApiError::BadRequest(value.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(value: QueryRejection) -> Self {
+        ApiError::BadRequest(value.body_text())
+    }
+}
+
+impl From<TypedMultipartError> for ApiError {
+    fn from(value: TypedMultipartError) -> Self {
+        ApiError::BadRequest(value.to_string())
+    }
+}
+
+// extractors with the rejections in JSON
+#[derive(FromRequest)]
+#[from_request(via(Json), rejection(JsonError))]
+pub struct ApiJson<T>(pub T);
+
+#[derive(FromRequestParts)]
+#[from_request(via(Path), rejection(JsonError))]
+pub struct ApiPath<T>(pub T);
+
+#[derive(FromRequestParts)]
+#[from_request(via(Query), rejection(JsonError))]
+pub struct ApiQuery<T>(pub T);
+
+#[derive(FromRequest)]
+#[from_request(via(TypedMultipart), rejection(JsonError))]
+pub struct ApiMultipart<T>(pub T);
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Pagination {
+    #[serde(default = "first_page")]
+    #[param(default = 1, minimum = 1)]
+    page: usize,
+    #[serde(default = "default_per_page")]
+    #[param(default = 50, minimum = 1, maximum = 100)]
+    per_page: usize,
+}
+
+fn first_page() -> usize {
+    1
+}
+
+fn default_per_page() -> usize {
+    DEFAULT_PER_PAGE
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub page: usize,
+    pub per_page: usize,
+    /// number of the items matching the filters on all the pages
+    pub total: usize,
+}
+
+impl<T> Page<T> {
+    pub fn new(items: Vec<T>, pagination: &Pagination) -> JsonResponse<Json<Page<T>>> {
+        if pagination.page == 0 || !(1..=MAX_PER_PAGE).contains(&pagination.per_page) {
+            return Err(ApiError::BadRequest(format!(
+                "Pages start from 1 and have from 1 to {MAX_PER_PAGE} items"
+            ))
+            .into());
+        }
+        let total = items.len();
+        let items = items
+            .into_iter()
+            .skip((pagination.page - 1) * pagination.per_page)
+            .take(pagination.per_page)
+            .collect();
+        Ok(Json(Page {
+            items,
+            page: pagination.page,
+            per_page: pagination.per_page,
+            total,
+        }))
+    }
+}
+
+pub fn api_router(state: AppState) -> Router {
+    let api = Router::new()
+        .merge(categories::categories_router(state.clone()))
+        .merge(questions::questions_router(state.clone()))
+        .merge(users::users_router(state.clone()))
+        .route_layer(middleware::from_fn_with_state(state, require_api_token));
+    Router::new()
+        .nest("/api/v1", api)
+        .merge(docs::docs_router())
+}
@@ -0,0 +1,637 @@
+use axum::{
+    extract::State, http::StatusCode, response::Response, routing::get, Extension, Json, Router,
+};
+use axum_typed_multipart::{FieldData, TryFromMultipart};
+use serde::Deserialize;
+use std::sync::Arc;
+use tempfile::NamedTempFile;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+use crate::db::{Question, Repositories};
+use crate::server::{app::AppState, auth::Identity, routes::questions::attachment_response};
+use crate::storage::{check_file_name, remove_unused_blobs, store_upload, BlobStorage};
+
+use super::{
+    ApiError, ApiJson, ApiMultipart, ApiPath, ApiQuery, ErrorBody, JsonResponse, Page, Pagination,
+};
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    list_questions,
+    question,
+    create_question,
+    update_question,
+    delete_question,
+    attachments,
+    add_attachment,
+    download_attachment,
+    delete_attachment
+))]
+pub struct QuestionsApi;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct QuestionsFilter {
+    category: Option<i64>,
+    hidden: Option<bool>,
+    /// case insensitive part of the question or the answer
+    search: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct QuestionUpdate {
+    question: String,
+    answer: String,
+    #[serde(default)]
+    category: Option<i64>,
+    #[serde(default)]
+    hidden: bool,
+}
+
+#[derive(TryFromMultipart, ToSchema)]
+struct NewAttachment {
+    #[form_data(limit = "1GiB")]
+    #[schema(value_type = String, format = Binary)]
+    attachment: FieldData<NamedTempFile>,
+}
+
+impl QuestionUpdate {
+    async fn validate(&self, repos: &Repositories) -> JsonResponse<()> {
+        if self.question.trim().is_empty() || self.answer.trim().is_empty() {
+            return Err(
+                ApiError::BadRequest("Question and answer can't be empty".to_string()).into(),
+            );
+        }
+        if let Some(category) = self.category {
+            match repos.categories.get_category(category).await {
+                Err(sqlx::Error::RowNotFound) => {
+                    return Err(
+                        ApiError::BadRequest(format!("Category {category} does not exist")).into(),
+                    )
+                }
+                result => {
+                    result?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl QuestionsFilter {
+    fn matches(&self, question: &Question) -> bool {
+        let category = question.category.as_ref().map(|c| c.id);
+        let search = self
+            .search
+            .as_deref()
+            .map(|search| search.trim().to_lowercase())
+            .unwrap_or_default();
+        self.category.is_none_or(|id| category == Some(id))
+            && self.hidden.is_none_or(|hidden| question.hidden == hidden)
+            && (search.is_empty()
+                || question.question.to_lowercase().contains(&search)
+                || question.answer.to_lowercase().contains(&search))
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/questions",
+    tag = "questions",
+    params(Pagination, QuestionsFilter),
+    responses(
+        (status = 200, body = Page<Question>),
+        (status = 400, body = ErrorBody),
+    )
+)]
+async fn list_questions(
+    State(repos): State<Repositories>,
+    ApiQuery(pagination): ApiQuery<Pagination>,
+    ApiQuery(filter): ApiQuery<QuestionsFilter>,
+) -> JsonResponse<Json<Page<Question>>> {
+    let questions = repos
+        .questions
+        .get_all_questions()
+        .await?
+        .into_iter()
+        .filter(|question| filter.matches(question))
+        .collect();
+    Page::new(questions, &pagination)
+}
+
+#[utoipa::path(
+    get,
+    path = "/questions/{id}",
+    tag = "questions",
+    params(("id" = i64, Path)),
+    responses(
+        (status = 200, body = Question),
+        (status = 404, body = ErrorBody),
+    )
+)]
+async fn question(
+    State(repos): State<Repositories>,
+    ApiPath(id): ApiPath<i64>,
+) -> JsonResponse<Json<Question>> {
+    Ok(Json(repos.questions.get_question_by_id(id).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/questions",
+    tag = "questions",
+    request_body = QuestionUpdate,
+    responses(
+        (status = 201, body = Question),
+        (status = 400, body = ErrorBody),
+    )
+)]
+async fn create_question(
+    State(repos): State<Repositories>,
+    Extension(identity): Extension<Identity>,
+    ApiJson(body): ApiJson<QuestionUpdate>,
+) -> JsonResponse<(StatusCode, Json<Question>)> {
+    body.validate(&repos).await?;
+    let id = repos
+        .questions
+        .create_question(
+            &body.question,
+            &body.answer,
+            body.category,
+            body.hidden,
+            0,
+            &identity.name(&repos).await?,
+        )
+        .await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(repos.questions.get_question_by_id(id).await?),
+    ))
+}
+
+/// Attachments are changed with their own endpoints
+#[utoipa::path(
+    put,
+    path = "/questions/{id}",
+    tag = "questions",
+    params(("id" = i64, Path)),
+    request_body = QuestionUpdate,
+    responses(
+        (status = 200, body = Question),
+        (status = 400, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    )
+)]
+async fn update_question(
+    State(repos): State<Repositories>,
+    Extension(identity): Extension<Identity>,
+    ApiPath(id): ApiPath<i64>,
+    ApiJson(body): ApiJson<QuestionUpdate>,
+) -> JsonResponse<Json<Question>> {
+    repos.questions.get_question_by_id(id).await?;
+    body.validate(&repos).await?;
+    repos
+        .questions
+        .update_question(
+            id,
+            body.category,
+            body.question,
+            body.answer,
+            body.hidden,
+            &identity.name(&repos).await?,
+        )
+        .await?;
+    Ok(Json(repos.questions.get_question_by_id(id).await?))
+}
+
+/// Question is moved to the trash with its attachments like in the CMS
+#[utoipa::path(
+    delete,
+    path = "/questions/{id}",
+    tag = "questions",
+    params(("id" = i64, Path)),
+    responses(
+        (status = 204),
+        (status = 404, body = ErrorBody),
+    )
+)]
+async fn delete_question(
+    State(repos): State<Repositories>,
+    Extension(identity): Extension<Identity>,
+    ApiPath(id): ApiPath<i64>,
+) -> JsonResponse<StatusCode> {
+    repos.questions.get_question_by_id(id).await?;
+    repos
+        .questions
+        .delete_question(id, &identity.name(&repos).await?)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/questions/{id}/attachments",
+    tag = "attachments",
+    params(("id" = i64, Path)),
+    responses(
+        (status = 200, body = Vec<String>),
+        (status = 404, body = ErrorBody),
+    )
+)]
+async fn attachments(
+    State(repos): State<Repositories>,
+    ApiPath(id): ApiPath<i64>,
+) -> JsonResponse<Json<Vec<String>>> {
+    Ok(Json(
+        repos.questions.get_question_by_id(id).await?.attachments,
+    ))
+}
+
+/// File with the same name should be deleted first
+#[utoipa::path(
+    post,
+    path = "/questions/{id}/attachments",
+    tag = "attachments",
+    params(("id" = i64, Path)),
+    request_body(content = NewAttachment, content_type = "multipart/form-data"),
+    responses(
+        (status = 201, body = Vec<String>),
+        (status = 400, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    )
+)]
+async fn add_attachment(
+    State(repos): State<Repositories>,
+    State(blobs): State<Arc<dyn BlobStorage>>,
+    ApiPath(id): ApiPath<i64>,
+    ApiMultipart(form): ApiMultipart<NewAttachment>,
+) -> JsonResponse<(StatusCode, Json<Vec<String>>)> {
+    let file_name = form.attachment.metadata.file_name.unwrap_or_default();
+    check_file_name(&file_name).map_err(ApiError::BadRequest)?;
+    repos.questions.get_question_by_id(id).await?;
+    match repos.questions.get_attachment(id, &file_name).await {
+        Ok(_) => {
+            return Err(
+                ApiError::BadRequest(format!("Attachment \"{file_name}\" already exists")).into(),
+            )
+        }
+        Err(sqlx::Error::RowNotFound) => {}
+        Err(err) => return Err(err.into()),
+    }
+    let upload = store_upload(
+        blobs.as_ref(),
+        file_name,
+        form.attachment.metadata.content_type.as_deref(),
+        form.attachment.contents,
+    )
+    .await?;
+    repos
+        .questions
+        .add_attachment(id, upload.attachment.clone())
+        .await?;
+    upload
+        .keep(repos.questions.as_ref(), blobs.as_ref())
+        .await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(repos.questions.get_question_by_id(id).await?.attachments),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/questions/{id}/attachments/{file_name}",
+    tag = "attachments",
+    params(("id" = i64, Path), ("file_name" = String, Path)),
+    responses(
+        (status = 200, body = Vec<u8>, content_type = "application/octet-stream"),
+        (status = 404, body = ErrorBody),
+    )
+)]
+async fn download_attachment(
+    State(repos): State<Repositories>,
+    State(blobs): State<Arc<dyn BlobStorage>>,
+    ApiPath((id, file_name)): ApiPath<(i64, String)>,
+) -> JsonResponse<Response> {
+    repos.questions.get_question_by_id(id).await?;
+    let attachment = repos.questions.get_attachment(id, &file_name).await?;
+    Ok(attachment_response(blobs.as_ref(), &attachment).await?)
+}
+
+/// Stored file is removed with the last attachment referencing it
+#[utoipa::path(
+    delete,
+    path = "/questions/{id}/attachments/{file_name}",
+    tag = "attachments",
+    params(("id" = i64, Path), ("file_name" = String, Path)),
+    responses(
+        (status = 204),
+        (status = 404, body = ErrorBody),
+    )
+)]
+async fn delete_attachment(
+    State(repos): State<Repositories>,
+    State(blobs): State<Arc<dyn BlobStorage>>,
+    ApiPath((id, file_name)): ApiPath<(i64, String)>,
+) -> JsonResponse<StatusCode> {
+    repos.questions.get_question_by_id(id).await?;
+    if let Some(unused) = repos.questions.delete_attachment(id, &file_name).await? {
+        remove_unused_blobs(repos.questions.as_ref(), blobs.as_ref(), &[unused]).await?;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn questions_router(state: AppState) -> Router {
+    Router::new()
+        .route("/questions", get(list_questions).post(create_question))
+        .route(
+            "/questions/{id}",
+            get(question).put(update_question).delete(delete_question),
+        )
+        .route(
+            "/questions/{id}/attachments",
+            get(attachments).post(add_attachment),
+        )
+        .route(
+            "/questions/{id}/attachments/{file_name}",
+            get(download_attachment).delete(delete_attachment),
+        )
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Bytes;
+    use axum::response::IntoResponse;
+    use axum_typed_multipart::FieldMetadata;
+    use std::io::Write;
+
+    use super::*;
+    use crate::storage::LocalStorage;
+
+    fn pagination() -> ApiQuery<Pagination> {
+        ApiQuery(Pagination {
+            page: 1,
+            per_page: 50,
+        })
+    }
+
+    fn update(question: &str, answer: &str, category: Option<i64>) -> ApiJson<QuestionUpdate> {
+        ApiJson(QuestionUpdate {
+            question: question.to_string(),
+            answer: answer.to_string(),
+            category,
+            hidden: false,
+        })
+    }
+
+    async fn create(repos: &Repositories, question: &str, answer: &str) -> i64 {
+        let (_, Json(question)) = create_question(
+            State(repos.clone()),
+            Extension(Identity::ApiToken),
+            update(question, answer, None),
+        )
+        .await
+        .ok()
+        .unwrap();
+        question.id
+    }
+
+    fn upload(name: &str, content: &str) -> ApiMultipart<NewAttachment> {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+        ApiMultipart(NewAttachment {
+            attachment: FieldData {
+                metadata: FieldMetadata {
+                    file_name: Some(name.to_string()),
+                    ..Default::default()
+                },
+                contents: file,
+            },
+        })
+    }
+
+    #[tokio::test]
+    async fn filters_questions() {
+        let repos = Repositories::memory();
+        let vpn = create(&repos, "How to connect to VPN?", "Use the client").await;
+        create(&repos, "Where is the office?", "Upstairs").await;
+
+        let filter = QuestionsFilter {
+            category: None,
+            hidden: None,
+            search: Some(" vpn ".to_string()),
+        };
+        let Json(page) = list_questions(State(repos.clone()), pagination(), ApiQuery(filter))
+            .await
+            .ok()
+            .unwrap();
+        let ids: Vec<i64> = page.items.iter().map(|q| q.id).collect();
+        assert_eq!(ids, [vpn]);
+
+        let filter = QuestionsFilter {
+            category: None,
+            hidden: Some(true),
+            search: None,
+        };
+        let Json(page) = list_questions(State(repos), pagination(), ApiQuery(filter))
+            .await
+            .ok()
+            .unwrap();
+        assert!(page.items.is_empty());
+    }
+
+    #[tokio::test]
+    async fn validates_questions() {
+        let repos = Repositories::memory();
+        let response = create_question(
+            State(repos.clone()),
+            Extension(Identity::ApiToken),
+            update("", "Answer", None),
+        )
+        .await;
+        assert_eq!(response.into_response().status(), StatusCode::BAD_REQUEST);
+        let response = create_question(
+            State(repos.clone()),
+            Extension(Identity::ApiToken),
+            update("Question", "Answer", Some(42)),
+        )
+        .await;
+        assert_eq!(response.into_response().status(), StatusCode::BAD_REQUEST);
+
+        let id = create(&repos, "Question", "Answer").await;
+        let Json(question) = update_question(
+            State(repos.clone()),
+            Extension(Identity::ApiToken),
+            ApiPath(id),
+            update("Question", "New answer", None),
+        )
+        .await
+        .ok()
+        .unwrap();
+        assert_eq!(question.answer, "New answer");
+        let response = update_question(
+            State(repos),
+            Extension(Identity::ApiToken),
+            ApiPath(id + 1),
+            update("Question", "Answer", None),
+        )
+        .await;
+        assert_eq!(response.into_response().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn stores_attachments_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let blobs: Arc<dyn BlobStorage> = Arc::new(LocalStorage::new(dir.path()));
+        let blobs_count = || std::fs::read_dir(dir.path().join("blobs")).unwrap().count();
+        let repos = Repositories::memory();
+        let first = create(&repos, "First", "Answer").await;
+        let second = create(&repos, "Second", "Answer").await;
+
+        for id in [first, second] {
+            let response = add_attachment(
+                State(repos.clone()),
+                State(blobs.clone()),
+                ApiPath(id),
+                upload("doc.txt", "hello"),
+            )
+            .await;
+            assert_eq!(response.into_response().status(), StatusCode::CREATED);
+        }
+        assert_eq!(blobs_count(), 1);
+        let response = add_attachment(
+            State(repos.clone()),
+            State(blobs.clone()),
+            ApiPath(first),
+            upload("doc.txt", "other"),
+        )
+        .await;
+        assert_eq!(response.into_response().status(), StatusCode::BAD_REQUEST);
+        let response = add_attachment(
+            State(repos.clone()),
+            State(blobs.clone()),
+            ApiPath(first),
+            upload("../doc.txt", "other"),
+        )
+        .await;
+        assert_eq!(response.into_response().status(), StatusCode::BAD_REQUEST);
+
+        let response = download_attachment(
+            State(repos.clone()),
+            State(blobs.clone()),
+            ApiPath((second, "doc.txt".to_string())),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "hello");
+
+        // the blob is removed with the last attachment
+        for id in [first, second] {
+            let response = delete_attachment(
+                State(repos.clone()),
+                State(blobs.clone()),
+                ApiPath((id, "doc.txt".to_string())),
+            )
+            .await;
+            assert_eq!(response.into_response().status(), StatusCode::NO_CONTENT);
+        }
+        assert_eq!(blobs_count(), 0);
+        let Json(names) = attachments(State(repos), ApiPath(first))
+            .await
+            .ok()
+            .unwrap();
+        assert!(names.is_empty());
+    }
+
+    async fn download(repos: &Repositories, blobs: &Arc<dyn BlobStorage>, id: i64) -> Bytes {
+        let response = download_attachment(
+            State(repos.clone()),
+            State(blobs.clone()),
+            ApiPath((id, "doc.txt".to_string())),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+    }
+
+    async fn delete(repos: &Repositories, blobs: &Arc<dyn BlobStorage>, id: i64) {
+        let response = delete_attachment(
+            State(repos.clone()),
+            State(blobs.clone()),
+            ApiPath((id, "doc.txt".to_string())),
+        )
+        .await;
+        assert_eq!(response.into_response().status(), StatusCode::NO_CONTENT);
+    }
+
+    // the content uploaded while its last attachment is deleted stays available
+    #[tokio::test]
+    async fn keeps_blobs_attached_during_deletion() {
+        let dir = tempfile::tempdir().unwrap();
+        let blobs: Arc<dyn BlobStorage> = Arc::new(LocalStorage::new(dir.path()));
+        let repos = Repositories::memory();
+        let first = create(&repos, "First", "Answer").await;
+        let second = create(&repos, "Second", "Answer").await;
+        let third = create(&repos, "Third", "Answer").await;
+        let response = add_attachment(
+            State(repos.clone()),
+            State(blobs.clone()),
+            ApiPath(first),
+            upload("doc.txt", "hello"),
+        )
+        .await;
+        assert_eq!(response.into_response().status(), StatusCode::CREATED);
+
+        // the attachment is inserted before the references are counted, the blob is kept
+        let ApiMultipart(form) = upload("doc.txt", "hello");
+        let stored = store_upload(
+            blobs.as_ref(),
+            "doc.txt".to_string(),
+            None,
+            form.attachment.contents,
+        )
+        .await
+        .unwrap();
+        repos
+            .questions
+            .add_attachment(second, stored.attachment.clone())
+            .await
+            .unwrap();
+        delete(&repos, &blobs, first).await;
+        stored
+            .keep(repos.questions.as_ref(), blobs.as_ref())
+            .await
+            .unwrap();
+        assert_eq!(download(&repos, &blobs, second).await, "hello");
+
+        // the blob is removed before the attachment is inserted, it is put again
+        let ApiMultipart(form) = upload("doc.txt", "hello");
+        let stored = store_upload(
+            blobs.as_ref(),
+            "doc.txt".to_string(),
+            None,
+            form.attachment.contents,
+        )
+        .await
+        .unwrap();
+        delete(&repos, &blobs, second).await;
+        repos
+            .questions
+            .add_attachment(third, stored.attachment.clone())
+            .await
+            .unwrap();
+        stored
+            .keep(repos.questions.as_ref(), blobs.as_ref())
+            .await
+            .unwrap();
+        assert_eq!(download(&repos, &blobs, third).await, "hello");
+    }
+}
@@ -0,0 +1,198 @@
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use serde::Deserialize;
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+use crate::db::{Repositories, User};
+use crate::server::{
+    app::AppState,
+    routes::users::{matches, UserStatus},
+};
+
+use super::{ApiError, ApiJson, ApiPath, ApiQuery, ErrorBody, JsonResponse, Page, Pagination};
+
+#[derive(OpenApi)]
+#[openapi(paths(list_users, user, create_user, update_user, delete_user))]
+pub struct UsersApi;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct UsersFilter {
+    /// id or case insensitive part of the name or the username
+    #[serde(default)]
+    search: String,
+    #[serde(default)]
+    status: UserStatus,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct NewUser {
+    id: i64,
+    #[serde(default)]
+    username: Option<String>,
+    first_name: String,
+    #[serde(default)]
+    last_name: Option<String>,
+    #[serde(default)]
+    is_admin: bool,
+    #[serde(default = "active_default")]
+    #[schema(default = true)]
+    active: bool,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct UserUpdate {
+    #[serde(default)]
+    username: Option<String>,
+    first_name: String,
+    #[serde(default)]
+    last_name: Option<String>,
+    is_admin: bool,
+    active: bool,
+}
+
+fn active_default() -> bool {
+    true
+}
+
+fn check_first_name(first_name: &str) -> JsonResponse<()> {
+    if first_name.trim().is_empty() {
+        return Err(ApiError::BadRequest("First name can't be empty".to_string()).into());
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/users",
+    tag = "users",
+    params(Pagination, UsersFilter),
+    responses(
+        (status = 200, body = Page<User>),
+        (status = 400, body = ErrorBody),
+    )
+)]
+async fn list_users(
+    State(repos): State<Repositories>,
+    ApiQuery(pagination): ApiQuery<Pagination>,
+    ApiQuery(filter): ApiQuery<UsersFilter>,
+) -> JsonResponse<Json<Page<User>>> {
+    let users = repos
+        .users
+        .get_users()
+        .await?
+        .into_iter()
+        .filter(|user| matches(user, &filter.search, filter.status))
+        .collect();
+    Page::new(users, &pagination)
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{id}",
+    tag = "users",
+    params(("id" = i64, Path)),
+    responses(
+        (status = 200, body = User),
+        (status = 404, body = ErrorBody),
+    )
+)]
+async fn user(
+    State(repos): State<Repositories>,
+    ApiPath(id): ApiPath<i64>,
+) -> JsonResponse<Json<User>> {
+    Ok(Json(repos.users.get_user(id).await?))
+}
+
+/// Users are created by the bot on the first message, the API can add them in advance
+#[utoipa::path(
+    post,
+    path = "/users",
+    tag = "users",
+    request_body = NewUser,
+    responses(
+        (status = 201, body = User),
+        (status = 400, body = ErrorBody),
+        (status = 409, body = ErrorBody),
+    )
+)]
+async fn create_user(
+    State(repos): State<Repositories>,
+    ApiJson(user): ApiJson<NewUser>,
+) -> JsonResponse<(StatusCode, Json<User>)> {
+    check_first_name(&user.first_name)?;
+    let user_id = repos
+        .users
+        .create_user(
+            user.id,
+            user.username.as_deref(),
+            &user.first_name,
+            user.last_name.as_deref(),
+            user.is_admin,
+            user.active,
+        )
+        .await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(repos.users.get_user(user_id).await?),
+    ))
+}
+
+#[utoipa::path(
+    put,
+    path = "/users/{id}",
+    tag = "users",
+    params(("id" = i64, Path)),
+    request_body = UserUpdate,
+    responses(
+        (status = 200, body = User),
+        (status = 400, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+    )
+)]
+async fn update_user(
+    State(repos): State<Repositories>,
+    ApiPath(id): ApiPath<i64>,
+    ApiJson(body): ApiJson<UserUpdate>,
+) -> JsonResponse<Json<User>> {
+    check_first_name(&body.first_name)?;
+    repos
+        .users
+        .update_user(User {
+            id,
+            username: body.username,
+            first_name: body.first_name,
+            last_name: body.last_name,
+            is_admin: body.is_admin,
+            active: body.active,
+        })
+        .await?;
+    Ok(Json(repos.users.get_user(id).await?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/users/{id}",
+    tag = "users",
+    params(("id" = i64, Path)),
+    responses(
+        (status = 204),
+        (status = 404, body = ErrorBody),
+    )
+)]
+async fn delete_user(
+    State(repos): State<Repositories>,
+    ApiPath(id): ApiPath<i64>,
+) -> JsonResponse<StatusCode> {
+    repos.users.delete_user(id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn users_router(state: AppState) -> Router {
+    Router::new()
+        .route("/users", get(list_users).post(create_user))
+        .route(
+            "/users/{id}",
+            get(user).put(update_user).delete(delete_user),
+        )
+        .with_state(state)
+}
@@ -0,0 +1,464 @@
+use askama::Template;
+use askama_web::WebTemplate;
+use axum::{
+    body::Body,
+    extract::State,
+    http::header,
+    response::Response,
+    routing::{get, post},
+    Extension, Router,
+};
+use axum_typed_multipart::{FieldData, TryFromMultipart, TypedMultipart};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use tempfile::NamedTempFile;
+use tokio::runtime::Handle;
+use tokio_util::io::{ReaderStream, StreamReader, SyncIoBridge};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::db::queries::attachments::{get_all_attachments, Attachment, NewAttachment};
+use crate::db::queries::snapshot::{get_snapshot, restore_snapshot, Snapshot};
+use crate::db::{Pool, Repositories};
+use crate::server::{app::AppState, auth::Identity, deserializers::FormBool};
+use crate::storage::{
+    check_file_name, keep_blob, mime_type, remove_unused_blobs, store, BlobStorage,
+};
+
+use super::{ApiError, ApiResponse};
+
+const MANIFEST: &str = "manifest.json";
+const FILES_DIR: &str = "files";
+
+#[derive(TryFromMultipart)]
+struct ImportForm {
+    #[form_data(limit = "1GiB")]
+    archive: FieldData<NamedTempFile>,
+    dry_run: Option<FormBool>,
+}
+
+struct EntityDiff {
+    name: &'static str,
+    added: Vec<String>,
+    updated: Vec<String>,
+    deleted: Vec<String>,
+}
+
+#[derive(Template, WebTemplate)]
+#[template(path = "backup/backup.html", escape = "none")]
+struct BackupPage;
+
+#[derive(Template, WebTemplate)]
+#[template(path = "backup/import_result.html", escape = "none")]
+struct ImportResult {
+    dry_run: bool,
+    files: usize,
+    missing_files: Vec<String>,
+    diffs: Vec<EntityDiff>,
+}
+
+fn diff<T: Serialize>(
+    name: &'static str,
+    current: &[T],
+    incoming: &[T],
+    id: impl Fn(&T) -> i64,
+    label: impl Fn(&T) -> String,
+) -> EntityDiff {
+    let current: HashMap<i64, &T> = current.iter().map(|item| (id(item), item)).collect();
+    let incoming_ids: HashSet<i64> = incoming.iter().map(&id).collect();
+    let mut result = EntityDiff {
+        name,
+        added: vec![],
+        updated: vec![],
+        deleted: vec![],
+    };
+    for item in incoming {
+        match current.get(&id(item)) {
+            None => result.added.push(label(item)),
+            Some(existing) => {
+                if serde_json::to_value(existing).ok() != serde_json::to_value(item).ok() {
+                    result.updated.push(label(item))
+                }
+            }
+        }
+    }
+    for (item_id, item) in current {
+        if !incoming_ids.contains(&item_id) {
+            result.deleted.push(label(item));
+        }
+    }
+    result
+}
+
+// Archive contains manifest.json with the database content and files/<question_id>/<file name>
+// with the attachments of the question. The archive is written on the blocking thread,
+// blobs are read from the storage with the handle of the runtime
+fn write_archive(
+    snapshot: &Snapshot,
+    attachments: &[Attachment],
+    blobs: &dyn BlobStorage,
+    runtime: Handle,
+) -> std::io::Result<NamedTempFile> {
+    let file = NamedTempFile::new()?;
+    let mut zip = ZipWriter::new(file.reopen()?);
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .large_file(true);
+
+    zip.start_file(MANIFEST, options)?;
+    serde_json::to_writer_pretty(&mut zip, snapshot)?;
+
+    // the questions in the trash are not exported
+    let ids: HashSet<i64> = snapshot.questions.iter().map(|q| q.id).collect();
+    for attachment in attachments.iter().filter(|a| ids.contains(&a.question_id)) {
+        // missing blobs are reported as the missing files on import
+        let mut blob = match runtime.block_on(blobs.get(&attachment.sha256)) {
+            Ok(blob) => SyncIoBridge::new_with_handle(StreamReader::new(blob), runtime.clone()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        zip.start_file(
+            format!("{FILES_DIR}/{}/{}", attachment.question_id, attachment.name),
+            options,
+        )?;
+        std::io::copy(&mut blob, &mut zip)?;
+    }
+    zip.finish()?;
+    Ok(file)
+}
+
+// Reads the manifest and, when `extract_to` is set, unpacks the files of the questions there.
+// Returns the snapshot and the paths of the files found in the archive
+fn read_archive<R: Read + Seek>(
+    reader: R,
+    extract_to: Option<&Path>,
+) -> Result<(Snapshot, HashSet<PathBuf>), ApiError> {
+    let mut zip = ZipArchive::new(reader)
+        .map_err(|e| ApiError::BadRequest(format!("Unable to read the archive: {e}")))?;
+
+    let snapshot: Snapshot = match zip.by_name(MANIFEST) {
+        Ok(manifest) => serde_json::from_reader(manifest)
+            .map_err(|e| ApiError::BadRequest(format!("Invalid {MANIFEST}: {e}")))?,
+        Err(_) => {
+            return Err(ApiError::BadRequest(format!(
+                "Archive does not contain {MANIFEST}"
+            )))
+        }
+    };
+
+    let mut files = HashSet::new();
+    for i in 0..zip.len() {
+        let mut entry = zip
+            .by_index(i)
+            .map_err(|e| ApiError::BadRequest(format!("Unable to read the archive: {e}")))?;
+        if !entry.is_file() {
+            continue;
+        }
+        // enclosed_name protects from the paths escaping the directory
+        let path = match entry.enclosed_name() {
+            Some(path) => path,
+            None => continue,
+        };
+        let components: Vec<&str> = path
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(part) => part.to_str(),
+                _ => None,
+            })
+            .collect();
+        let relative = match components.as_slice() {
+            [FILES_DIR, id, name] if id.parse::<i64>().is_ok() => Path::new(id).join(name),
+            _ => continue,
+        };
+        if let Some(dir) = extract_to {
+            let target = dir.join(&relative);
+            std::fs::create_dir_all(target.parent().expect("Path always has a parent"))?;
+            std::io::copy(&mut entry, &mut File::create(target)?)?;
+        }
+        files.insert(relative);
+    }
+    Ok((snapshot, files))
+}
+
+// Names of the attachments become the paths in the unpacked archive
+fn check_file_names(snapshot: &Snapshot) -> ApiResponse<()> {
+    for question in snapshot.questions.iter() {
+        for name in question.attachments.iter() {
+            check_file_name(name).map_err(|e| {
+                ApiError::BadRequest(format!("{e} in question {} of {MANIFEST}", question.id))
+            })?;
+        }
+    }
+    Ok(())
+}
+
+// Copies the unpacked files of the questions to the blobs, the ones missing in the archive
+// are skipped. Only the files found by `read_archive` are read, whatever the manifest says
+async fn store_files(
+    blobs: &dyn BlobStorage,
+    imported: &Path,
+    files: &HashSet<PathBuf>,
+    snapshot: &Snapshot,
+) -> std::io::Result<Vec<(i64, NewAttachment)>> {
+    let mut attachments = vec![];
+    for question in snapshot.questions.iter() {
+        for name in question.attachments.iter() {
+            let relative = Path::new(&question.id.to_string()).join(name);
+            if !files.contains(&relative) {
+                continue;
+            }
+            let path = imported.join(relative);
+            let (sha256, size) = store(blobs, &path).await?;
+            let attachment = NewAttachment {
+                name: name.clone(),
+                mime_type: mime_type(name, None),
+                sha256,
+                size,
+            };
+            attachments.push((question.id, attachment));
+        }
+    }
+    Ok(attachments)
+}
+
+async fn backup_page() -> BackupPage {
+    BackupPage
+}
+
+async fn export(
+    State(pool): State<Pool>,
+    State(blobs): State<Arc<dyn BlobStorage>>,
+) -> ApiResponse<Response> {
+    let snapshot = get_snapshot(&pool).await?;
+    let attachments = get_all_attachments(&pool).await?;
+    let runtime = Handle::current();
+    let archive = tokio::task::spawn_blocking(move || {
+        write_archive(&snapshot, &attachments, blobs.as_ref(), runtime)
+    })
+    .await
+    .expect("Archive writer panicked")?;
+    // temporary file is deleted on drop, opened handle keeps the content readable
+    let file = tokio::fs::File::open(archive.path()).await?;
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, "application/zip")
+        .header(
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"qna-backup.zip\"",
+        )
+        .body(Body::from_stream(ReaderStream::new(file)))
+        .expect("Valid response"))
+}
+
+async fn import(
+    State(pool): State<Pool>,
+    State(repos): State<Repositories>,
+    State(static_dir): State<PathBuf>,
+    State(blobs): State<Arc<dyn BlobStorage>>,
+    Extension(identity): Extension<Identity>,
+    TypedMultipart(form): TypedMultipart<ImportForm>,
+) -> ApiResponse<ImportResult> {
+    let dry_run = form.dry_run.map(|v| v.0).unwrap_or(false);
+    let archive = form.archive.contents;
+    // files are unpacked next to the local blobs, so they can be linked instead of copied
+    let staging = tempfile::tempdir_in(&static_dir)?;
+    let extract_to = (!dry_run).then(|| staging.path().to_owned());
+    let (snapshot, files) =
+        tokio::task::spawn_blocking(move || read_archive(archive.reopen()?, extract_to.as_deref()))
+            .await
+            .expect("Archive reader panicked")?;
+    check_file_names(&snapshot)?;
+
+    let missing_files: Vec<String> = snapshot
+        .questions
+        .iter()
+        .flat_map(|q| {
+            q.attachments
+                .iter()
+                .filter(|name| !files.contains(&Path::new(&q.id.to_string()).join(name)))
+                .map(|name| format!("{}/{}", q.id, name))
+                .collect::<Vec<String>>()
+        })
+        .collect();
+
+    let current = get_snapshot(&pool).await?;
+    let diffs = vec![
+        diff(
+            "Categories",
+            &current.categories,
+            &snapshot.categories,
+            |c| c.id,
+            |c| c.name.clone(),
+        ),
+        diff(
+            "Questions",
+            &current.questions,
+            &snapshot.questions,
+            |q| q.id,
+            |q| q.question.clone(),
+        ),
+        diff(
+            "Users",
+            &current.users,
+            &snapshot.users,
+            |u| u.id,
+            |u| match &u.username {
+                Some(username) => format!("{} (@{})", u.first_name, username),
+                None => u.first_name.clone(),
+            },
+        ),
+        diff(
+            "Groups",
+            &current.groups,
+            &snapshot.groups,
+            |g| g.id,
+            |g| g.name.clone(),
+        ),
+    ];
+
+    if !dry_run {
+        // questions missing from the snapshot are moved to the trash with their files
+        let attachments = store_files(blobs.as_ref(), staging.path(), &files, &snapshot).await?;
+        let stored: Vec<(String, PathBuf)> = attachments
+            .iter()
+            .map(|(id, a)| {
+                let path = staging.path().join(id.to_string()).join(&a.name);
+                (a.sha256.clone(), path)
+            })
+            .collect();
+        let unused =
+            restore_snapshot(&pool, snapshot, attachments, &identity.name(&repos).await?).await?;
+        for (sha256, path) in stored {
+            keep_blob(repos.questions.as_ref(), blobs.as_ref(), &sha256, &path).await?;
+        }
+        remove_unused_blobs(repos.questions.as_ref(), blobs.as_ref(), &unused).await?;
+    }
+
+    Ok(ImportResult {
+        dry_run,
+        files: files.len(),
+        missing_files,
+        diffs,
+    })
+}
+
+pub fn backup_router(state: AppState) -> Router {
+    Router::new()
+        .route("/backup", get(backup_page))
+        .route("/export", get(export))
+        .route("/import", post(import))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+    use std::io::Write;
+
+    use super::*;
+    use crate::db::queries::questions::create_question;
+    use crate::db::{establish_connection, run_migrations};
+    use crate::storage::LocalStorage;
+
+    struct Instance {
+        dir: tempfile::TempDir,
+        pool: Pool,
+        blobs: Arc<dyn BlobStorage>,
+    }
+
+    impl Instance {
+        async fn new() -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let url = format!("sqlite:{}?mode=rwc", dir.path().join("db.sqlite").display());
+            let pool = establish_connection(&url).await.unwrap();
+            run_migrations(&pool).await.unwrap();
+            let blobs: Arc<dyn BlobStorage> = Arc::new(LocalStorage::new(dir.path()));
+            Self { dir, pool, blobs }
+        }
+
+        // archive of the current content with the given attachments and files
+        async fn archive(&self, attachments: &[&str], files: &[(&str, &str)]) -> NamedTempFile {
+            let mut snapshot = get_snapshot(&self.pool).await.unwrap();
+            snapshot.questions[0].attachments = attachments.iter().map(|a| a.to_string()).collect();
+            let file = NamedTempFile::new().unwrap();
+            let mut zip = ZipWriter::new(file.reopen().unwrap());
+            zip.start_file(MANIFEST, SimpleFileOptions::default())
+                .unwrap();
+            serde_json::to_writer(&mut zip, &snapshot).unwrap();
+            for (path, content) in files {
+                zip.start_file(*path, SimpleFileOptions::default()).unwrap();
+                zip.write_all(content.as_bytes()).unwrap();
+            }
+            zip.finish().unwrap();
+            file
+        }
+
+        async fn import(&self, archive: NamedTempFile) -> StatusCode {
+            let form = ImportForm {
+                archive: FieldData {
+                    metadata: Default::default(),
+                    contents: archive,
+                },
+                dry_run: None,
+            };
+            import(
+                State(self.pool.clone()),
+                State(Repositories::sql(self.pool.clone())),
+                State(self.dir.path().to_owned()),
+                State(self.blobs.clone()),
+                Extension(Identity::ApiToken),
+                TypedMultipart(form),
+            )
+            .await
+            .into_response()
+            .status()
+        }
+
+        fn blobs_count(&self) -> usize {
+            std::fs::read_dir(self.dir.path().join("blobs")).map_or(0, |dir| dir.count())
+        }
+    }
+
+    #[tokio::test]
+    async fn imports_files_of_the_archive() {
+        let instance = Instance::new().await;
+        let id = create_question(&instance.pool, "Q", "A", None, false, 0, "test")
+            .await
+            .unwrap();
+        let archive = instance
+            .archive(
+                &["doc.txt", "lost.txt"],
+                &[(&format!("files/{id}/doc.txt"), "hello")],
+            )
+            .await;
+        assert_eq!(instance.import(archive).await, StatusCode::OK);
+        let attachments = get_all_attachments(&instance.pool).await.unwrap();
+        let names: Vec<&str> = attachments.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["doc.txt"]);
+        assert_eq!(instance.blobs_count(), 1);
+    }
+
+    // names of the manifest can't point to the files outside the archive
+    #[tokio::test]
+    async fn rejects_hostile_manifest() {
+        let instance = Instance::new().await;
+        create_question(&instance.pool, "Q", "A", None, false, 0, "test")
+            .await
+            .unwrap();
+        let outside = instance.dir.path().join("secret.txt");
+        std::fs::write(&outside, "secret").unwrap();
+        let relative = "../../../../".to_string() + outside.to_str().unwrap();
+        for name in [relative.as_str(), outside.to_str().unwrap(), "..", ""] {
+            let archive = instance.archive(&[name], &[]).await;
+            assert_eq!(instance.import(archive).await, StatusCode::BAD_REQUEST);
+        }
+        assert!(get_all_attachments(&instance.pool)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(instance.blobs_count(), 0);
+    }
+}
@@ -0,0 +1,203 @@
+use askama::Template;
+use askama_web::WebTemplate;
+use axum::{
+    extract::{Path, Query, State},
+    routing::get,
+    Extension, Router,
+};
+use axum_typed_multipart::{FieldData, TryFromMultipart, TypedMultipart};
+use serde::Deserialize;
+use std::sync::Arc;
+use teloxide::Bot;
+use tempfile::NamedTempFile;
+
+use crate::bot::broadcast::spawn_broadcast;
+use crate::db::{
+    queries::{
+        self,
+        broadcasts::{Audience, BroadcastStats},
+        groups::Group,
+    },
+    Category, Pool, Repositories,
+};
+use crate::server::{app::AppState, auth::Identity};
+use crate::storage::{check_file_name, store_upload, BlobStorage};
+
+use super::{ApiError, ApiResponse};
+
+#[derive(TryFromMultipart)]
+struct NewBroadcast {
+    text: String,
+    // "all", "group:<id>" or "category:<id>"
+    audience: String,
+    #[form_data(limit = "1GiB")]
+    attachments: Vec<FieldData<NamedTempFile>>,
+}
+
+#[derive(Deserialize)]
+struct RecipientsQuery {
+    audience: String,
+}
+
+#[derive(Template, WebTemplate)]
+#[template(path = "broadcasts/broadcast_row.html", escape = "none")]
+struct BroadcastRow {
+    stats: BroadcastStats,
+}
+
+#[derive(Template, WebTemplate)]
+#[template(path = "broadcasts/broadcasts.html", escape = "none")]
+struct BroadcastsPage {
+    groups: Vec<Group>,
+    categories: Vec<Category>,
+    recipients: usize,
+    broadcasts: Vec<BroadcastRow>,
+}
+
+fn parse_audience(audience: &str) -> ApiResponse<Audience> {
+    match audience.split_once(':') {
+        None if audience == "all" => Ok(Audience::AllUsers),
+        Some(("group", id)) => id
+            .parse()
+            .map(Audience::Group)
+            .map_err(|_| ApiError::BadRequest(format!("Wrong group id {id}"))),
+        Some(("category", id)) => id
+            .parse()
+            .map(Audience::CategorySubscribers)
+            .map_err(|_| ApiError::BadRequest(format!("Wrong category id {id}"))),
+        _ => Err(ApiError::BadRequest(format!("Unknown audience {audience}"))),
+    }
+}
+
+async fn describe_audience(
+    pool: &Pool,
+    repos: &Repositories,
+    audience: &Audience,
+) -> ApiResponse<String> {
+    Ok(match audience {
+        Audience::AllUsers => "All active users".to_string(),
+        Audience::Group(id) => format!(
+            "Group {}",
+            queries::groups::get_group(pool, *id).await?.name
+        ),
+        Audience::CategorySubscribers(id) => format!(
+            "Subscribers of category {}",
+            repos.categories.get_category(*id).await?.name
+        ),
+    })
+}
+
+async fn broadcasts_page(
+    State(pool): State<Pool>,
+    State(repos): State<Repositories>,
+) -> ApiResponse<BroadcastsPage> {
+    Ok(BroadcastsPage {
+        groups: queries::groups::get_groups(&pool).await?,
+        categories: repos.categories.get_all_categories().await?,
+        recipients: queries::broadcasts::get_recipients(&pool, &Audience::AllUsers)
+            .await?
+            .len(),
+        broadcasts: queries::broadcasts::get_broadcasts(&pool)
+            .await?
+            .into_iter()
+            .map(|stats| BroadcastRow { stats })
+            .collect(),
+    })
+}
+
+async fn recipients(
+    State(pool): State<Pool>,
+    Query(query): Query<RecipientsQuery>,
+) -> ApiResponse<String> {
+    let audience = parse_audience(&query.audience)?;
+    let count = queries::broadcasts::get_recipients(&pool, &audience)
+        .await?
+        .len();
+    Ok(format!("{count} recipients"))
+}
+
+async fn get_broadcast(State(pool): State<Pool>, Path(id): Path<i64>) -> ApiResponse<BroadcastRow> {
+    Ok(BroadcastRow {
+        stats: queries::broadcasts::get_broadcast(&pool, id).await?,
+    })
+}
+
+// Name the file is sent with. Only the last component is kept from the path some browsers send
+fn attachment_name(name: &str) -> ApiResponse<String> {
+    let name = std::path::Path::new(name)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| ApiError::BadRequest(format!("Wrong attachment name \"{name}\"")))?;
+    check_file_name(name).map_err(ApiError::BadRequest)?;
+    Ok(name.to_string())
+}
+
+async fn create_broadcast(
+    State(pool): State<Pool>,
+    State(repos): State<Repositories>,
+    State(blobs): State<Arc<dyn BlobStorage>>,
+    State(bot): State<Bot>,
+    Extension(identity): Extension<Identity>,
+    TypedMultipart(form): TypedMultipart<NewBroadcast>,
+) -> ApiResponse<BroadcastRow> {
+    let mut uploads = vec![];
+    // browsers send the empty file when nothing is selected
+    for attachment in form.attachments {
+        match attachment.metadata.file_name.as_deref() {
+            Some(name) if !name.is_empty() => uploads.push((
+                attachment_name(name)?,
+                attachment.metadata.content_type,
+                attachment.contents,
+            )),
+            _ => {}
+        }
+    }
+    if form.text.trim().is_empty() && uploads.is_empty() {
+        return Err(ApiError::BadRequest(
+            "Announcement should have the text or the attachments".to_string(),
+        ));
+    }
+    let audience = parse_audience(&form.audience)?;
+    let recipients = queries::broadcasts::get_recipients(&pool, &audience).await?;
+    if recipients.is_empty() {
+        return Err(ApiError::BadRequest("There are no recipients".to_string()));
+    }
+    let created_by = identity.name(&repos).await?;
+
+    let mut stored = vec![];
+    for (name, content_type, contents) in uploads {
+        stored.push(store_upload(blobs.as_ref(), name, content_type.as_deref(), contents).await?);
+    }
+    let attachments: Vec<_> = stored.iter().map(|u| u.attachment.clone()).collect();
+    let id = queries::broadcasts::create_broadcast(
+        &pool,
+        &form.text,
+        &attachments,
+        &describe_audience(&pool, &repos, &audience).await?,
+        &created_by,
+        &recipients,
+    )
+    .await?;
+    for upload in stored {
+        upload
+            .keep(repos.questions.as_ref(), blobs.as_ref())
+            .await?;
+    }
+    tracing::info!(
+        "Broadcast {id} to {} users is created by {identity}",
+        recipients.len()
+    );
+    spawn_broadcast(bot, pool.clone(), blobs, id);
+
+    Ok(BroadcastRow {
+        stats: queries::broadcasts::get_broadcast(&pool, id).await?,
+    })
+}
+
+pub fn broadcasts_router(state: AppState) -> Router {
+    Router::new()
+        .route("/broadcasts", get(broadcasts_page).post(create_broadcast))
+        .route("/broadcasts/recipients", get(recipients))
+        .route("/broadcasts/{id}", get(get_broadcast))
+        .with_state(state)
+}
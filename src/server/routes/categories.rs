@@ -2,25 +2,22 @@ use askama::Template;
 use askama_web::WebTemplate;
 use axum::{
     extract::{Path, State},
-    http::StatusCode,
     routing::{delete, get, post},
-    Json, Router,
+    Extension, Json, Router,
 };
 use serde::Deserialize;
+use serde_aux::prelude::deserialize_option_number_from_string;
+use std::collections::HashSet;
 
 use deserializers::deserialize_bool_from_checkbox;
 use deserializers::Stri64;
-use sqlx::SqlitePool;
 
 use crate::{
-    db::{
-        queries::categories::{self, get_all_categories, get_category},
-        Category, Reorder,
-    },
-    server::{app::AppState, deserializers},
+    db::{queries::categories::flatten_tree, Category, Reorder, Repositories},
+    server::{app::AppState, auth::Identity, deserializers},
 };
 
-use super::ApiResponse;
+use super::{ApiError, ApiResponse};
 
 #[derive(Deserialize)]
 struct NewCategory {
@@ -28,6 +25,9 @@ struct NewCategory {
     #[serde(default)]
     #[serde(deserialize_with = "deserialize_bool_from_checkbox")]
     hidden: Option<bool>,
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_option_number_from_string")]
+    parent: Option<i64>,
 }
 
 #[derive(Deserialize)]
@@ -36,6 +36,9 @@ struct CategoryUpdate {
     #[serde(default)]
     #[serde(deserialize_with = "deserialize_bool_from_checkbox")]
     hidden: Option<bool>,
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_option_number_from_string")]
+    parent: Option<i64>,
 }
 
 // a hack to deserialize array of strings to Vec<i64>, since this is how it's get encoded and
@@ -45,98 +48,190 @@ struct OrderingBody {
     row_id: Vec<Stri64>,
 }
 
+// category in the parent select, name is prefixed to display the tree
+struct ParentOption {
+    id: i64,
+    label: String,
+}
+
 #[derive(Template, WebTemplate)]
 #[template(path = "categories/category_row.html", escape = "none")]
 struct CategoryRow {
     category: Category,
+    depth: usize,
 }
 
 #[derive(Template, WebTemplate)]
 #[template(path = "categories/category_row_edit.html", escape = "none")]
 struct CategoryRowEdit {
     category: Category,
+    depth: usize,
+    parents: Vec<ParentOption>,
 }
 
 #[derive(Template, WebTemplate)]
 #[template(path = "categories/categories.html", escape = "none")]
 struct CatgeoriesPage {
     categories: Vec<CategoryRow>,
+    parents: Vec<ParentOption>,
 }
 
 #[derive(Template, WebTemplate)]
 #[template(path = "categories/categories_reordering.html", escape = "none")]
 struct CatgeoriesReorderingPage {
-    categories: Vec<Category>,
+    categories: Vec<CategoryRow>,
 }
 
-async fn get_categories(State(pool): State<SqlitePool>) -> ApiResponse<CatgeoriesPage> {
-    let categories = get_all_categories(&pool).await?;
+fn parent_options(tree: &[(usize, Category)], excluded: &HashSet<i64>) -> Vec<ParentOption> {
+    tree.iter()
+        .filter(|(_, category)| !excluded.contains(&category.id))
+        .map(|(depth, category)| ParentOption {
+            id: category.id,
+            label: format!("{}{}", "— ".repeat(*depth), category.name),
+        })
+        .collect()
+}
+
+async fn render_categories_page(repos: &Repositories) -> ApiResponse<CatgeoriesPage> {
+    let tree = flatten_tree(repos.categories.get_all_categories().await?);
     Ok(CatgeoriesPage {
-        categories: categories
+        parents: parent_options(&tree, &HashSet::new()),
+        categories: tree
             .into_iter()
-            .map(|c| CategoryRow { category: c })
+            .map(|(depth, category)| CategoryRow { category, depth })
             .collect(),
     })
 }
 
-async fn category(State(pool): State<SqlitePool>, Path(id): Path<i64>) -> ApiResponse<CategoryRow> {
-    let c = get_category(&pool, id).await?;
-    Ok(CategoryRow { category: c })
+async fn category_depth(repos: &Repositories, id: i64) -> sqlx::Result<usize> {
+    Ok(repos.categories.get_category_path(id).await?.len() - 1)
+}
+
+// Parent should exist and can't be the category itself or one of its children
+pub(super) async fn check_parent(
+    repos: &Repositories,
+    id: Option<i64>,
+    parent: Option<i64>,
+) -> ApiResponse<()> {
+    let Some(parent) = parent else {
+        return Ok(());
+    };
+    if let Some(id) = id {
+        if repos
+            .categories
+            .get_subtree_ids(id)
+            .await?
+            .contains(&parent)
+        {
+            return Err(ApiError::BadRequest(
+                "Category can't be moved inside itself".to_string(),
+            ));
+        }
+    }
+    match repos.categories.get_category(parent).await {
+        Err(sqlx::Error::RowNotFound) => Err(ApiError::BadRequest(format!(
+            "Parent category {parent} does not exist"
+        ))),
+        result => result.map(|_| ()).map_err(ApiError::from),
+    }
+}
+
+async fn get_categories(State(repos): State<Repositories>) -> ApiResponse<CatgeoriesPage> {
+    render_categories_page(&repos).await
+}
+
+async fn category(
+    State(repos): State<Repositories>,
+    Path(id): Path<i64>,
+) -> ApiResponse<CategoryRow> {
+    let c = repos.categories.get_category(id).await?;
+    Ok(CategoryRow {
+        category: c,
+        depth: category_depth(&repos, id).await?,
+    })
 }
 
 async fn edit_category(
-    State(pool): State<SqlitePool>,
+    State(repos): State<Repositories>,
     Path(id): Path<i64>,
 ) -> ApiResponse<CategoryRowEdit> {
-    let c = get_category(&pool, id).await?;
-    Ok(CategoryRowEdit { category: c })
+    let c = repos.categories.get_category(id).await?;
+    // category can't be moved inside itself
+    let subtree = repos.categories.get_subtree_ids(id).await?;
+    let tree = flatten_tree(repos.categories.get_all_categories().await?);
+    Ok(CategoryRowEdit {
+        category: c,
+        depth: category_depth(&repos, id).await?,
+        parents: parent_options(&tree, &subtree),
+    })
 }
 
 async fn create_category(
-    State(pool): State<SqlitePool>,
+    State(repos): State<Repositories>,
+    Extension(identity): Extension<Identity>,
     Json(new_category): Json<NewCategory>,
-) -> ApiResponse<CategoryRow> {
-    let id = categories::create_category(
-        &pool,
-        new_category.name.as_str(),
-        new_category.hidden.unwrap_or(false),
-        1,
-    )
-    .await?;
+) -> ApiResponse<CatgeoriesPage> {
+    check_parent(&repos, None, new_category.parent).await?;
+    repos
+        .categories
+        .create_category(
+            new_category.name.as_str(),
+            new_category.hidden.unwrap_or(false),
+            1,
+            new_category.parent,
+            &identity.name(&repos).await?,
+        )
+        .await?;
 
-    Ok(CategoryRow {
-        category: categories::get_category(&pool, id).await?,
-    })
+    render_categories_page(&repos).await
 }
 
 async fn update_category(
-    State(pool): State<SqlitePool>,
+    State(repos): State<Repositories>,
+    Extension(identity): Extension<Identity>,
     Path(id): Path<i64>,
     Json(category): Json<CategoryUpdate>,
-) -> ApiResponse<CategoryRow> {
-    categories::update_category(&pool, id, category.name, category.hidden.unwrap_or(false)).await?;
-    Ok(CategoryRow {
-        category: categories::get_category(&pool, id).await?,
-    })
+) -> ApiResponse<CatgeoriesPage> {
+    check_parent(&repos, Some(id), category.parent).await?;
+    repos
+        .categories
+        .update_category(
+            id,
+            category.name,
+            category.hidden.unwrap_or(false),
+            category.parent,
+            &identity.name(&repos).await?,
+        )
+        .await?;
+    render_categories_page(&repos).await
 }
 
 async fn delete_category(
-    State(pool): State<SqlitePool>,
+    State(repos): State<Repositories>,
+    Extension(identity): Extension<Identity>,
     Path(id): Path<i64>,
-) -> ApiResponse<StatusCode> {
-    categories::delete_category(&pool, id).await?;
-    Ok(StatusCode::OK)
+) -> ApiResponse<CatgeoriesPage> {
+    repos
+        .categories
+        .delete_category(id, &identity.name(&repos).await?)
+        .await?;
+    render_categories_page(&repos).await
 }
 
 async fn render_reordering_page(
-    State(pool): State<SqlitePool>,
+    State(repos): State<Repositories>,
 ) -> ApiResponse<CatgeoriesReorderingPage> {
-    let categories = categories::get_all_categories(&pool).await?;
-    Ok(CatgeoriesReorderingPage { categories })
+    let categories = repos.categories.get_all_categories().await?;
+    Ok(CatgeoriesReorderingPage {
+        categories: flatten_tree(categories)
+            .into_iter()
+            .map(|(depth, category)| CategoryRow { category, depth })
+            .collect(),
+    })
 }
 
 async fn reorder(
-    State(pool): State<SqlitePool>,
+    State(repos): State<Repositories>,
     Json(body): Json<OrderingBody>,
 ) -> ApiResponse<CatgeoriesPage> {
     let ordering: Vec<Reorder> = body
@@ -149,15 +244,9 @@ async fn reorder(
         })
         .collect();
 
-    categories::reorder_categories(&pool, ordering).await?;
+    repos.categories.reorder_categories(ordering).await?;
 
-    let categories = categories::get_all_categories(&pool).await?;
-    Ok(CatgeoriesPage {
-        categories: categories
-            .into_iter()
-            .map(|c| CategoryRow { category: c })
-            .collect(),
-    })
+    render_categories_page(&repos).await
 }
 
 pub fn category_router(state: AppState) -> Router {
@@ -165,10 +254,10 @@ pub fn category_router(state: AppState) -> Router {
         .route("/categories", get(get_categories))
         .route("/categories/new", post(create_category))
         .route(
-            "/categories/:id",
+            "/categories/{id}",
             delete(delete_category).put(update_category).get(category),
         )
-        .route("/categories/:id/edit", get(edit_category))
+        .route("/categories/{id}/edit", get(edit_category))
         .route(
             "/categories/order",
             get(render_reordering_page).post(reorder),
@@ -0,0 +1,57 @@
+use askama::Template;
+use askama_web::WebTemplate;
+use axum::{
+    extract::{Path, State},
+    routing::get,
+    Router,
+};
+
+use crate::db::queries::{
+    self,
+    feedback::{FeedbackComment, FeedbackStats},
+};
+use crate::db::{Pool, Repositories};
+use crate::server::app::AppState;
+
+use super::ApiResponse;
+
+#[derive(Template, WebTemplate)]
+#[template(path = "feedback/feedback.html", escape = "none")]
+struct FeedbackPage {
+    questions: Vec<FeedbackStats>,
+}
+
+#[derive(Template, WebTemplate)]
+#[template(path = "feedback/comments_modal.html", escape = "none")]
+struct CommentsModal {
+    question: String,
+    comments: Vec<FeedbackComment>,
+}
+
+async fn feedback_page(State(pool): State<Pool>) -> ApiResponse<FeedbackPage> {
+    Ok(FeedbackPage {
+        questions: queries::feedback::get_feedback_report(&pool).await?,
+    })
+}
+
+async fn comments(
+    State(pool): State<Pool>,
+    State(repos): State<Repositories>,
+    Path(question_id): Path<i64>,
+) -> ApiResponse<CommentsModal> {
+    Ok(CommentsModal {
+        question: repos
+            .questions
+            .get_question_by_id(question_id)
+            .await?
+            .question,
+        comments: queries::feedback::get_feedback_comments(&pool, question_id).await?,
+    })
+}
+
+pub fn feedback_router(state: AppState) -> Router {
+    Router::new()
+        .route("/feedback", get(feedback_page))
+        .route("/feedback/{question_id}/comments", get(comments))
+        .with_state(state)
+}
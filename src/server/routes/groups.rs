@@ -0,0 +1,209 @@
+use askama::Template;
+use askama_web::WebTemplate;
+use axum::{
+    extract::{Path, State},
+    routing::get,
+    Json, Router,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::db::Repositories;
+use crate::{
+    db::queries::{categories::flatten_tree, groups::Group},
+    server::{app::AppState, deserializers::deserialize_ids_from_select},
+};
+
+use super::{ApiError, ApiResponse};
+
+#[derive(Deserialize)]
+struct NewGroup {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct GroupUpdate {
+    name: String,
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_ids_from_select")]
+    users: Vec<i64>,
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_ids_from_select")]
+    categories: Vec<i64>,
+}
+
+// user or category in the multiple select
+struct MemberOption {
+    id: i64,
+    label: String,
+    selected: bool,
+}
+
+// names of users and categories to display the groups, categories are in the tree order
+struct Labels {
+    users: Vec<(i64, String)>,
+    categories: Vec<(i64, String, usize)>,
+}
+
+#[derive(Template, WebTemplate)]
+#[template(path = "groups/group_row.html", escape = "none")]
+struct GroupRow {
+    group: Group,
+    users: Vec<String>,
+    categories: Vec<String>,
+}
+
+#[derive(Template, WebTemplate)]
+#[template(path = "groups/group_row_edit.html", escape = "none")]
+struct GroupRowEdit {
+    group: Group,
+    users: Vec<MemberOption>,
+    categories: Vec<MemberOption>,
+}
+
+#[derive(Template, WebTemplate)]
+#[template(path = "groups/groups.html", escape = "none")]
+struct GroupsPage {
+    groups: Vec<GroupRow>,
+}
+
+async fn load_labels(repos: &Repositories) -> sqlx::Result<Labels> {
+    let users = repos
+        .users
+        .get_users()
+        .await?
+        .into_iter()
+        .map(|user| {
+            let label = match user.username {
+                Some(username) => format!("{} (@{})", user.first_name, username),
+                None => user.first_name,
+            };
+            (user.id, label)
+        })
+        .collect();
+    let categories = flatten_tree(repos.categories.get_all_categories().await?)
+        .into_iter()
+        .map(|(depth, category)| (category.id, category.name, depth))
+        .collect();
+    Ok(Labels { users, categories })
+}
+
+fn make_row(group: Group, labels: &Labels) -> GroupRow {
+    let users: HashMap<i64, &String> = labels.users.iter().map(|(id, l)| (*id, l)).collect();
+    let categories: HashMap<i64, &String> = labels
+        .categories
+        .iter()
+        .map(|(id, name, _)| (*id, name))
+        .collect();
+    GroupRow {
+        users: group
+            .users
+            .iter()
+            .filter_map(|id| users.get(id).map(|l| l.to_string()))
+            .collect(),
+        categories: group
+            .categories
+            .iter()
+            .filter_map(|id| categories.get(id).map(|l| l.to_string()))
+            .collect(),
+        group,
+    }
+}
+
+fn make_options(all: impl Iterator<Item = (i64, String)>, selected: &[i64]) -> Vec<MemberOption> {
+    all.map(|(id, label)| MemberOption {
+        id,
+        label,
+        selected: selected.contains(&id),
+    })
+    .collect()
+}
+
+async fn groups_page(State(repos): State<Repositories>) -> ApiResponse<GroupsPage> {
+    let labels = load_labels(&repos).await?;
+    Ok(GroupsPage {
+        groups: repos
+            .groups
+            .get_groups()
+            .await?
+            .into_iter()
+            .map(|group| make_row(group, &labels))
+            .collect(),
+    })
+}
+
+async fn create_group(
+    State(repos): State<Repositories>,
+    Json(form): Json<NewGroup>,
+) -> ApiResponse<GroupRow> {
+    let name = form.name.trim();
+    if name.is_empty() {
+        return Err(ApiError::BadRequest("Name should not be empty".to_string()));
+    }
+    let id = repos.groups.create_group(name).await?;
+    let labels = load_labels(&repos).await?;
+    Ok(make_row(repos.groups.get_group(id).await?, &labels))
+}
+
+async fn group(State(repos): State<Repositories>, Path(id): Path<i64>) -> ApiResponse<GroupRow> {
+    let labels = load_labels(&repos).await?;
+    Ok(make_row(repos.groups.get_group(id).await?, &labels))
+}
+
+async fn edit_group(
+    State(repos): State<Repositories>,
+    Path(id): Path<i64>,
+) -> ApiResponse<GroupRowEdit> {
+    let group = repos.groups.get_group(id).await?;
+    let labels = load_labels(&repos).await?;
+    Ok(GroupRowEdit {
+        users: make_options(labels.users.into_iter(), &group.users),
+        // name is prefixed to display the tree
+        categories: make_options(
+            labels
+                .categories
+                .into_iter()
+                .map(|(id, name, depth)| (id, format!("{}{}", "— ".repeat(depth), name))),
+            &group.categories,
+        ),
+        group,
+    })
+}
+
+async fn update_group(
+    State(repos): State<Repositories>,
+    Path(id): Path<i64>,
+    Json(form): Json<GroupUpdate>,
+) -> ApiResponse<GroupRow> {
+    let name = form.name.trim();
+    if name.is_empty() {
+        return Err(ApiError::BadRequest("Name should not be empty".to_string()));
+    }
+    repos
+        .groups
+        .update_group(&Group {
+            id,
+            name: name.to_string(),
+            users: form.users,
+            categories: form.categories,
+        })
+        .await?;
+    let labels = load_labels(&repos).await?;
+    Ok(make_row(repos.groups.get_group(id).await?, &labels))
+}
+
+async fn delete_group(State(repos): State<Repositories>, Path(id): Path<i64>) -> ApiResponse<()> {
+    repos.groups.delete_group(id).await?;
+    Ok(())
+}
+
+pub fn groups_router(state: AppState) -> Router {
+    Router::new()
+        .route("/groups", get(groups_page).post(create_group))
+        .route("/groups/{id}/edit", get(edit_group))
+        .route(
+            "/groups/{id}",
+            get(group).put(update_group).delete(delete_group),
+        )
+        .with_state(state)
+}
@@ -0,0 +1,239 @@
+use askama::Template;
+use askama_web::WebTemplate;
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    routing::{get, post},
+    Extension, Router,
+};
+use serde_json::Value;
+use similar::{ChangeTag, TextDiff};
+use std::collections::HashMap;
+
+use crate::db::queries::revisions::{self, get_state, Entity, Revision};
+use crate::db::{Pool, Repositories};
+use crate::server::{app::AppState, auth::Identity};
+
+use super::ApiResponse;
+
+// how many changes are shown on the history page
+const LATEST_REVISIONS: i64 = 100;
+
+enum DiffPart {
+    Equal(String),
+    Insert(String),
+    Delete(String),
+}
+
+struct FieldChange {
+    field: &'static str,
+    parts: Vec<DiffPart>,
+}
+
+#[derive(Template, WebTemplate)]
+#[template(path = "history/revision.html", escape = "none")]
+struct RevisionView {
+    revision: Revision,
+    title: String,
+    changes: Vec<FieldChange>,
+    // the item already has the values of the revision
+    current: bool,
+}
+
+#[derive(Template, WebTemplate)]
+#[template(path = "history/history_modal.html", escape = "none")]
+struct HistoryModal {
+    title: String,
+    revisions: Vec<RevisionView>,
+}
+
+#[derive(Template, WebTemplate)]
+#[template(path = "history/history.html", escape = "none")]
+struct HistoryPage {
+    revisions: Vec<RevisionView>,
+}
+
+fn fields(entity: Entity) -> &'static [&'static str] {
+    match entity {
+        Entity::Question => &["question", "answer", "category", "hidden"],
+        Entity::Category => &["name", "parent", "hidden"],
+    }
+}
+
+// categories are stored as ids, so they are shown by the name when they still exist
+fn field_text(field: &str, value: &Value, categories: &HashMap<i64, String>) -> String {
+    match (field, value) {
+        ("category" | "parent", Value::Number(id)) => {
+            let id = id.as_i64().unwrap_or_default();
+            categories
+                .get(&id)
+                .cloned()
+                .unwrap_or_else(|| format!("#{id}"))
+        }
+        (_, Value::Null) => "None".to_string(),
+        (_, Value::String(text)) => text.clone(),
+        (_, value) => value.to_string(),
+    }
+}
+
+fn diff(old: &str, new: &str) -> Vec<DiffPart> {
+    let mut parts: Vec<DiffPart> = vec![];
+    for change in TextDiff::from_words(old, new).iter_all_changes() {
+        let text = change.value();
+        // consecutive words with the same tag are joined to a single span
+        match (parts.last_mut(), change.tag()) {
+            (Some(DiffPart::Equal(last)), ChangeTag::Equal)
+            | (Some(DiffPart::Insert(last)), ChangeTag::Insert)
+            | (Some(DiffPart::Delete(last)), ChangeTag::Delete) => last.push_str(text),
+            (_, ChangeTag::Equal) => parts.push(DiffPart::Equal(text.to_string())),
+            (_, ChangeTag::Insert) => parts.push(DiffPart::Insert(text.to_string())),
+            (_, ChangeTag::Delete) => parts.push(DiffPart::Delete(text.to_string())),
+        }
+    }
+    parts
+}
+
+async fn revision_view(
+    pool: &Pool,
+    revision: Revision,
+    categories: &HashMap<i64, String>,
+) -> ApiResponse<RevisionView> {
+    let parse = |value: &Option<String>| {
+        value
+            .as_deref()
+            .and_then(|value| serde_json::from_str::<Value>(value).ok())
+    };
+    let old = parse(&revision.old_value);
+    let new = parse(&revision.new_value);
+    let text = |state: &Option<Value>, field: &str| {
+        state
+            .as_ref()
+            .map(|state| field_text(field, &state[field], categories))
+            .unwrap_or_default()
+    };
+
+    let title_field = fields(revision.entity)[0];
+    let title = match &new {
+        Some(_) => text(&new, title_field),
+        None => text(&old, title_field),
+    };
+    let changes = fields(revision.entity)
+        .iter()
+        .filter_map(|field| {
+            let (old, new) = (text(&old, field), text(&new, field));
+            (old != new).then(|| FieldChange {
+                field,
+                parts: diff(&old, &new),
+            })
+        })
+        .collect();
+    // only reads, the transaction is rolled back when dropped
+    let mut transaction = pool.begin().await?;
+    let current = get_state(&mut transaction, revision.entity, revision.entity_id).await?;
+    Ok(RevisionView {
+        current: current.as_deref() == revision.restorable_value(),
+        revision,
+        title,
+        changes,
+    })
+}
+
+async fn revision_views(
+    pool: &Pool,
+    repos: &Repositories,
+    revisions: Vec<Revision>,
+) -> ApiResponse<Vec<RevisionView>> {
+    let categories = repos
+        .categories
+        .get_all_categories()
+        .await?
+        .into_iter()
+        .map(|category| (category.id, category.name))
+        .collect();
+    let mut views = vec![];
+    for revision in revisions {
+        views.push(revision_view(pool, revision, &categories).await?);
+    }
+    Ok(views)
+}
+
+async fn history_page(
+    State(pool): State<Pool>,
+    State(repos): State<Repositories>,
+) -> ApiResponse<HistoryPage> {
+    let revisions = revisions::get_latest_revisions(&pool, LATEST_REVISIONS).await?;
+    Ok(HistoryPage {
+        revisions: revision_views(&pool, &repos, revisions).await?,
+    })
+}
+
+async fn item_history(
+    pool: &Pool,
+    repos: &Repositories,
+    entity: Entity,
+    id: i64,
+) -> ApiResponse<HistoryModal> {
+    let revisions = revision_views(
+        pool,
+        repos,
+        revisions::get_revisions(pool, entity, id).await?,
+    )
+    .await?;
+    // deleted items have the history too, so the title is taken from it
+    let title = match revisions.first() {
+        Some(view) => format!("History of the {entity} \"{}\"", view.title),
+        None => format!("History of the {entity} {id}"),
+    };
+    Ok(HistoryModal { title, revisions })
+}
+
+async fn question_history(
+    State(pool): State<Pool>,
+    State(repos): State<Repositories>,
+    Path(id): Path<i64>,
+) -> ApiResponse<HistoryModal> {
+    item_history(&pool, &repos, Entity::Question, id).await
+}
+
+async fn category_history(
+    State(pool): State<Pool>,
+    State(repos): State<Repositories>,
+    Path(id): Path<i64>,
+) -> ApiResponse<HistoryModal> {
+    item_history(&pool, &repos, Entity::Category, id).await
+}
+
+async fn revision(
+    State(pool): State<Pool>,
+    State(repos): State<Repositories>,
+    Path(id): Path<i64>,
+) -> ApiResponse<HistoryModal> {
+    let revision = revisions::get_revision(&pool, id).await?;
+    let revisions = revision_views(&pool, &repos, vec![revision]).await?;
+    Ok(HistoryModal {
+        title: format!("Change {id}"),
+        revisions,
+    })
+}
+
+// The restored item can be on the page in any form, so the whole page is reloaded
+async fn restore_revision(
+    State(pool): State<Pool>,
+    State(repos): State<Repositories>,
+    Extension(identity): Extension<Identity>,
+    Path(id): Path<i64>,
+) -> ApiResponse<impl IntoResponse> {
+    revisions::restore_revision(&pool, id, &identity.name(&repos).await?).await?;
+    tracing::info!("Revision {id} is restored by {identity}");
+    Ok([("HX-Refresh", "true")])
+}
+
+pub fn history_router(state: AppState) -> Router {
+    Router::new()
+        .route("/history", get(history_page))
+        .route("/questions/{id}/history", get(question_history))
+        .route("/categories/{id}/history", get(category_history))
+        .route("/revisions/{id}", get(revision))
+        .route("/revisions/{id}/restore", post(restore_revision))
+        .with_state(state)
+}
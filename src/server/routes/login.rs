@@ -0,0 +1,105 @@
+use askama::Template;
+use askama_web::WebTemplate;
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Router,
+};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::db::{queries, Pool, Repositories};
+use crate::server::{
+    app::AppState,
+    auth::{
+        hash_token, new_session_token, session_cookie, session_token, verify_login_data,
+        AuthConfig, SESSION_TTL_SECONDS,
+    },
+};
+
+use super::ApiResponse;
+
+#[derive(Template, WebTemplate)]
+#[template(path = "login.html", escape = "none")]
+struct LoginPage {
+    bot_username: String,
+    error: Option<&'static str>,
+}
+
+async fn login_page(State(auth): State<Arc<AuthConfig>>) -> LoginPage {
+    LoginPage {
+        bot_username: auth.bot_username.clone(),
+        error: None,
+    }
+}
+
+// Telegram Login Widget redirects here with the signed user data in the query
+async fn telegram_callback(
+    State(pool): State<Pool>,
+    State(repos): State<Repositories>,
+    State(auth): State<Arc<AuthConfig>>,
+    Query(data): Query<BTreeMap<String, String>>,
+) -> ApiResponse<Response> {
+    let refuse = |error| {
+        (
+            StatusCode::FORBIDDEN,
+            LoginPage {
+                bot_username: auth.bot_username.clone(),
+                error: Some(error),
+            },
+        )
+            .into_response()
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs() as i64;
+    let user_id = match verify_login_data(&auth.bot_token, data, now) {
+        Ok(id) => id,
+        Err(error) => return Ok(refuse(error)),
+    };
+    let is_admin = match repos.users.get_user(user_id).await {
+        Ok(user) => user.is_admin && user.active,
+        Err(sqlx::Error::RowNotFound) => false,
+        Err(e) => return Err(e.into()),
+    };
+    if !is_admin {
+        return Ok(refuse("You are not an administrator of the bot"));
+    }
+
+    queries::sessions::delete_expired_sessions(&pool).await?;
+    let token = new_session_token();
+    queries::sessions::create_session(&pool, &hash_token(&token), user_id, SESSION_TTL_SECONDS)
+        .await?;
+    tracing::info!("User {user_id} logged in to the CMS");
+    Ok((
+        [(
+            header::SET_COOKIE,
+            session_cookie(&token, SESSION_TTL_SECONDS),
+        )],
+        Redirect::to("/"),
+    )
+        .into_response())
+}
+
+async fn logout(State(pool): State<Pool>, headers: HeaderMap) -> ApiResponse<Response> {
+    if let Some(token) = session_token(&headers) {
+        queries::sessions::delete_session(&pool, &hash_token(token)).await?;
+    }
+    Ok((
+        [(header::SET_COOKIE, session_cookie("", 0))],
+        Redirect::to("/login"),
+    )
+        .into_response())
+}
+
+pub fn login_router(state: AppState) -> Router {
+    Router::new()
+        .route("/login", get(login_page))
+        .route("/auth/telegram", get(telegram_callback))
+        .route("/logout", post(logout))
+        .with_state(state)
+}
@@ -1,11 +1,35 @@
+pub mod access_requests;
+pub mod api;
+pub mod backup;
+pub mod broadcasts;
 pub mod categories;
+pub mod feedback;
+pub mod groups;
+pub mod history;
+pub mod login;
+pub mod pending_questions;
 pub mod questions;
+pub mod questions_csv;
+pub mod translations;
+pub mod trash;
 pub mod users;
 
+pub use access_requests::access_requests_router;
+pub use api::api_router;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
+pub use backup::backup_router;
+pub use broadcasts::broadcasts_router;
 pub use categories::category_router;
+pub use feedback::feedback_router;
+pub use groups::groups_router;
+pub use history::history_router;
+pub use login::login_router;
+pub use pending_questions::pending_questions_router;
 pub use questions::questions_router;
+pub use questions_csv::questions_csv_router;
+pub use translations::translations_router;
+pub use trash::trash_router;
 pub use users::users_router;
 
 pub type ApiResponse<T> = Result<T, ApiError>;
@@ -14,6 +38,7 @@ pub type ApiResponse<T> = Result<T, ApiError>;
 pub enum ApiError {
     SqlxError(sqlx::Error),
     IoError(std::io::Error),
+    BadRequest(String),
 }
 
 impl From<sqlx::Error> for ApiError {
@@ -28,18 +53,28 @@ impl From<std::io::Error> for ApiError {
     }
 }
 
-impl IntoResponse for ApiError {
-    fn into_response(self) -> Response {
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
         match self {
             Self::SqlxError(err) => match err {
                 sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+                sqlx::Error::Database(e) if e.is_unique_violation() => StatusCode::CONFLICT,
                 _ => StatusCode::INTERNAL_SERVER_ERROR,
             },
             Self::IoError(err) => match err.kind() {
                 std::io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
                 _ => StatusCode::INTERNAL_SERVER_ERROR,
             },
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::BadRequest(message) => (StatusCode::BAD_REQUEST, message).into_response(),
+            err => err.status().into_response(),
         }
-        .into_response()
     }
 }
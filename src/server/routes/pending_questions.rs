@@ -0,0 +1,163 @@
+use askama::Template;
+use askama_web::WebTemplate;
+use axum::{
+    extract::{Path, State},
+    routing::get,
+    Extension, Json, Router,
+};
+use serde::Deserialize;
+use serde_aux::prelude::deserialize_option_number_from_string;
+use teloxide::Bot;
+
+use crate::bot::support;
+use crate::db::{
+    queries::{self, pending_questions::PendingQuestion},
+    Category, Pool, Repositories,
+};
+use crate::server::{app::AppState, auth::Identity, deserializers::deserialize_bool_from_checkbox};
+
+use super::{ApiError, ApiResponse};
+
+#[derive(Deserialize)]
+struct PendingQuestionAnswer {
+    question: String,
+    answer: String,
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_option_number_from_string")]
+    category: Option<i64>,
+    // adds the question with the answer to the questions of the bot
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_bool_from_checkbox")]
+    promote: Option<bool>,
+}
+
+#[derive(Template, WebTemplate)]
+#[template(path = "pending_questions/pending_question_row.html", escape = "none")]
+struct PendingQuestionRow {
+    question: PendingQuestion,
+}
+
+#[derive(Template, WebTemplate)]
+#[template(
+    path = "pending_questions/pending_question_row_edit.html",
+    escape = "none"
+)]
+struct PendingQuestionRowEdit {
+    question: PendingQuestion,
+    categories: Vec<Category>,
+}
+
+#[derive(Template, WebTemplate)]
+#[template(path = "pending_questions/pending_questions.html", escape = "none")]
+struct PendingQuestionsPage {
+    questions: Vec<PendingQuestionRow>,
+}
+
+async fn pending_questions_page(State(pool): State<Pool>) -> ApiResponse<PendingQuestionsPage> {
+    Ok(PendingQuestionsPage {
+        questions: queries::pending_questions::get_pending_questions(&pool)
+            .await?
+            .into_iter()
+            .map(|question| PendingQuestionRow { question })
+            .collect(),
+    })
+}
+
+async fn get_pending_question(
+    State(pool): State<Pool>,
+    Path(id): Path<i64>,
+) -> ApiResponse<PendingQuestionRow> {
+    Ok(PendingQuestionRow {
+        question: queries::pending_questions::get_pending_question(&pool, id).await?,
+    })
+}
+
+async fn edit_pending_question(
+    State(pool): State<Pool>,
+    State(repos): State<Repositories>,
+    Path(id): Path<i64>,
+) -> ApiResponse<PendingQuestionRowEdit> {
+    Ok(PendingQuestionRowEdit {
+        question: queries::pending_questions::get_pending_question(&pool, id).await?,
+        categories: repos.categories.get_all_categories().await?,
+    })
+}
+
+// Sends the answer to the user, when the question is not answered yet,
+// and adds it to the questions of the bot when asked to
+async fn answer_pending_question(
+    State(pool): State<Pool>,
+    State(repos): State<Repositories>,
+    State(bot): State<Bot>,
+    Extension(identity): Extension<Identity>,
+    Path(id): Path<i64>,
+    Json(form): Json<PendingQuestionAnswer>,
+) -> ApiResponse<PendingQuestionRow> {
+    let mut question = queries::pending_questions::get_pending_question(&pool, id).await?;
+    let promote = form.promote.unwrap_or(false);
+    if form.answer.trim().is_empty() {
+        return Err(ApiError::BadRequest("Answer can't be empty".to_string()));
+    }
+    if question.answer.is_some() && !promote {
+        return Err(ApiError::BadRequest(
+            "The question is already answered".to_string(),
+        ));
+    }
+    if promote && question.question_id.is_some() {
+        return Err(ApiError::BadRequest(
+            "The question is already added to the questions".to_string(),
+        ));
+    }
+
+    if question.answer.is_none() {
+        let by = identity.name(&repos).await?;
+        if !queries::pending_questions::answer_pending_question(&pool, id, &form.answer, &by)
+            .await?
+        {
+            return Err(ApiError::BadRequest(
+                "The question is already answered".to_string(),
+            ));
+        }
+        question = queries::pending_questions::get_pending_question(&pool, id).await?;
+        tracing::info!("Question {id} is answered in the CMS by {identity}");
+        if let Err(err) = support::deliver_answer(&bot, &pool, &question).await {
+            tracing::warn!("Unable to deliver the answer to question {id}: {err}");
+        }
+        support::notify_answered(&bot, &question, &by).await;
+    }
+    if promote {
+        let question_id = repos
+            .questions
+            .create_question(
+                &form.question,
+                &form.answer,
+                form.category,
+                false,
+                0,
+                &identity.name(&repos).await?,
+            )
+            .await?;
+        queries::pending_questions::set_promoted_question(&pool, id, question_id).await?;
+    }
+    Ok(PendingQuestionRow {
+        question: queries::pending_questions::get_pending_question(&pool, id).await?,
+    })
+}
+
+async fn delete_pending_question(State(pool): State<Pool>, Path(id): Path<i64>) -> ApiResponse<()> {
+    queries::pending_questions::delete_pending_question(&pool, id).await?;
+    Ok(())
+}
+
+pub fn pending_questions_router(state: AppState) -> Router {
+    Router::new()
+        .route("/pending-questions", get(pending_questions_page))
+        .route("/pending-questions/{id}/edit", get(edit_pending_question))
+        .route(
+            "/pending-questions/{id}",
+            get(get_pending_question)
+                .put(answer_pending_question)
+                .delete(delete_pending_question),
+        )
+        .with_state(state)
+}
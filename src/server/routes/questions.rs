@@ -1,33 +1,32 @@
-use anyhow::anyhow;
 use askama::Template;
 use askama_web::WebTemplate;
-use axum_typed_multipart::async_trait;
 
 use axum::{
-    body::Bytes,
+    body::Body,
     extract::{Path, Query, State},
-    http::{HeaderMap, StatusCode},
-    response::IntoResponse,
-    routing::get,
-    Json, Router,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Extension, Json, Router,
 };
-use axum_typed_multipart::{
-    FieldData, FieldMetadata, TryFromChunks, TryFromMultipart, TypedMultipart, TypedMultipartError,
-};
-use futures_util::stream::Stream;
+use axum_typed_multipart::{FieldData, FieldMetadata, TryFromMultipart, TypedMultipart};
 use serde::Deserialize;
 use serde_aux::prelude::deserialize_option_number_from_string;
-use sqlx::SqlitePool;
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::sync::Arc;
 use tempfile::NamedTempFile;
 
-use crate::db::{queries, Category, Question, Reorder};
+use crate::db::queries::attachments::Attachment;
+use crate::db::{Category, Question, Reorder, Repositories};
+use crate::markdown;
 use crate::server::{
     app::AppState,
-    deserializers::{deserialize_bool_from_checkbox, Stri64},
+    auth::Identity,
+    deserializers::{deserialize_bool_from_checkbox, FormBool, Stri64},
 };
+use crate::storage::{check_file_name, remove_unused_blobs, store_upload, BlobStorage};
 
-use super::ApiResponse;
+use super::{ApiError, ApiResponse};
 
 #[derive(Deserialize)]
 struct OrderingBody {
@@ -59,6 +58,13 @@ struct QuestionUpdate {
     hidden: Option<bool>,
 }
 
+#[derive(Deserialize)]
+struct AnswerPreviewBody {
+    // broadcasts are previewed the same way
+    #[serde(alias = "text")]
+    answer: String,
+}
+
 #[derive(Deserialize)]
 struct QuestionsQuery {
     category: Option<i64>,
@@ -70,24 +76,6 @@ struct NewAttachments {
     attachment: FieldData<NamedTempFile>,
 }
 
-struct FormBool(bool);
-
-#[async_trait]
-impl TryFromChunks for FormBool {
-    async fn try_from_chunks(
-        chunks: impl Stream<Item = Result<Bytes, TypedMultipartError>> + Send + Sync + Unpin,
-        metadata: FieldMetadata,
-    ) -> Result<Self, TypedMultipartError> {
-        let string = String::try_from_chunks(chunks, metadata).await?;
-        match string.as_str() {
-            "on" => Ok(FormBool(true)),
-            unexpected => Err(TypedMultipartError::Other {
-                source: anyhow!("Unexpected checkbox value {unexpected}"),
-            }),
-        }
-    }
-}
-
 #[derive(Template, WebTemplate)]
 #[template(path = "questions/question_row.html", escape = "none")]
 struct QuestionRow {
@@ -129,6 +117,13 @@ struct AttachmentRow {
     name: String,
 }
 
+// Answer as it is going to be sent by the bot, split into the messages
+#[derive(Template, WebTemplate)]
+#[template(path = "questions/answer_preview.html", escape = "none")]
+struct AnswerPreview {
+    messages: Vec<String>,
+}
+
 #[derive(Template, WebTemplate)]
 #[template(path = "questions/questions_reordering.html", escape = "none")]
 struct QuestionsReordering {
@@ -138,25 +133,25 @@ struct QuestionsReordering {
 }
 
 async fn get_questions_for_category(
-    pool: &SqlitePool,
+    repos: &Repositories,
     category: Option<i64>,
 ) -> sqlx::Result<Vec<Question>> {
     let questions = match category {
-        Some(id) => queries::questions::get_questions_by_category_id(pool, id).await?,
-        None => queries::questions::get_all_questions(pool).await?,
+        Some(id) => repos.questions.get_questions_by_category_id(id).await?,
+        None => repos.questions.get_all_questions().await?,
     };
     Ok(questions)
 }
 
 async fn questions_page(
-    State(pool): State<SqlitePool>,
+    State(repos): State<Repositories>,
     Query(QuestionsQuery { category }): Query<QuestionsQuery>,
 ) -> ApiResponse<QuestionsPage> {
-    let categories = queries::categories::get_all_categories(&pool).await?;
+    let categories = repos.categories.get_all_categories().await?;
     let table = QuestionsTable {
         categories: categories.clone(),
         selected: category.unwrap_or(-1),
-        questions: get_questions_for_category(&pool, category)
+        questions: get_questions_for_category(&repos, category)
             .await?
             .into_iter()
             .map(|c| QuestionRow { question: c })
@@ -166,22 +161,22 @@ async fn questions_page(
 }
 
 async fn get_question(
-    State(pool): State<SqlitePool>,
+    State(repos): State<Repositories>,
     Path(id): Path<i64>,
 ) -> ApiResponse<QuestionRow> {
     Ok(QuestionRow {
-        question: queries::questions::get_question_by_id(&pool, id).await?,
+        question: repos.questions.get_question_by_id(id).await?,
     })
 }
 
 async fn questions_table(
-    State(pool): State<SqlitePool>,
+    State(repos): State<Repositories>,
     Query(QuestionsQuery { category }): Query<QuestionsQuery>,
 ) -> ApiResponse<QuestionsTable> {
     Ok(QuestionsTable {
-        categories: queries::categories::get_all_categories(&pool).await?,
+        categories: repos.categories.get_all_categories().await?,
         selected: category.unwrap_or(-1),
-        questions: get_questions_for_category(&pool, category)
+        questions: get_questions_for_category(&repos, category)
             .await?
             .into_iter()
             .map(|c| QuestionRow { question: c })
@@ -190,102 +185,150 @@ async fn questions_table(
 }
 
 async fn questions_reordering_table(
-    State(pool): State<SqlitePool>,
+    State(repos): State<Repositories>,
     Query(QuestionsQuery { category }): Query<QuestionsQuery>,
 ) -> ApiResponse<QuestionsReordering> {
     Ok(QuestionsReordering {
-        questions: get_questions_for_category(&pool, category).await?,
-        categories: queries::categories::get_all_categories(&pool).await?,
+        questions: get_questions_for_category(&repos, category).await?,
+        categories: repos.categories.get_all_categories().await?,
         selected: category.unwrap_or(-1),
     })
 }
 
+// the default is used only when the browser sends no name
+fn attachment_name(metadata: &FieldMetadata, default: &str) -> String {
+    metadata
+        .file_name
+        .clone()
+        .unwrap_or_else(|| default.to_owned())
+}
+
 async fn create_question(
-    State(pool): State<SqlitePool>,
-    State(static_dir): State<PathBuf>,
+    State(repos): State<Repositories>,
+    State(blobs): State<Arc<dyn BlobStorage>>,
+    Extension(identity): Extension<Identity>,
     TypedMultipart(form): TypedMultipart<NewQuestion>,
 ) -> ApiResponse<QuestionRow> {
-    let info: Vec<(String, NamedTempFile)> = form
-        .attachments
-        .into_iter()
-        .map(|a| {
-            let file_name = a.metadata.file_name.unwrap_or("random_name".to_owned());
-            (file_name, a.contents)
-        })
-        .collect();
+    let mut names = HashSet::new();
+    for attachment in form.attachments.iter() {
+        let name = attachment_name(&attachment.metadata, "random_name");
+        check_file_name(&name).map_err(ApiError::BadRequest)?;
+        if !names.insert(name.clone()) {
+            return Err(ApiError::BadRequest(format!(
+                "Attachment \"{name}\" is added twice"
+            )));
+        }
+    }
 
-    let id = queries::questions::create_question(
-        &pool,
-        &form.question,
-        &form.answer,
-        form.category,
-        info.iter().map(|(a, _)| a.as_str()).collect(),
-        form.hidden.map(|v| v.0).unwrap_or(false),
-        0,
-    )
-    .await?;
-    for (name, contents) in info.into_iter() {
-        let question_dir = static_dir.join(id.to_string());
-        std::fs::create_dir_all(&question_dir)?;
-        std::fs::copy(contents.path(), question_dir.join(name))?;
-        std::fs::remove_file(contents.path())?;
+    let id = repos
+        .questions
+        .create_question(
+            &form.question,
+            &form.answer,
+            form.category,
+            form.hidden.map(|v| v.0).unwrap_or(false),
+            0,
+            &identity.name(&repos).await?,
+        )
+        .await?;
+    for attachment in form.attachments.into_iter() {
+        let name = attachment_name(&attachment.metadata, "random_name");
+        let upload = store_upload(
+            blobs.as_ref(),
+            name,
+            attachment.metadata.content_type.as_deref(),
+            attachment.contents,
+        )
+        .await?;
+        repos
+            .questions
+            .add_attachment(id, upload.attachment.clone())
+            .await?;
+        upload
+            .keep(repos.questions.as_ref(), blobs.as_ref())
+            .await?;
     }
 
     Ok(QuestionRow {
-        question: queries::questions::get_question_by_id(&pool, id).await?,
+        question: repos.questions.get_question_by_id(id).await?,
     })
 }
 
 async fn edit_question(
-    State(pool): State<SqlitePool>,
+    State(repos): State<Repositories>,
     Path(id): Path<i64>,
 ) -> ApiResponse<QuestionRowEdit> {
-    let question = queries::questions::get_question_by_id(&pool, id).await?;
+    let question = repos.questions.get_question_by_id(id).await?;
     Ok(QuestionRowEdit {
-        categories: queries::categories::get_all_categories(&pool).await?,
+        categories: repos.categories.get_all_categories().await?,
         question,
     })
 }
 
 async fn update_question(
-    State(pool): State<SqlitePool>,
+    State(repos): State<Repositories>,
+    Extension(identity): Extension<Identity>,
     Path(id): Path<i64>,
     Json(form): Json<QuestionUpdate>,
 ) -> ApiResponse<QuestionRow> {
-    let question = queries::questions::get_question_by_id(&pool, id).await?;
-    queries::questions::update_question(
-        &pool,
-        id,
-        form.category,
-        form.question,
-        form.answer,
-        question.attachments.iter().map(|a| a.as_str()).collect(),
-        form.hidden.unwrap_or(false),
-    )
-    .await?;
-    let question = queries::questions::get_question_by_id(&pool, id).await?;
+    repos
+        .questions
+        .update_question(
+            id,
+            form.category,
+            form.question,
+            form.answer,
+            form.hidden.unwrap_or(false),
+            &identity.name(&repos).await?,
+        )
+        .await?;
+    let question = repos.questions.get_question_by_id(id).await?;
     Ok(QuestionRow { question })
 }
 
+async fn preview_answer(Json(body): Json<AnswerPreviewBody>) -> AnswerPreview {
+    AnswerPreview {
+        messages: markdown::to_telegram_chunks(&body.answer, markdown::MESSAGE_MAX_LENGTH),
+    }
+}
+
+// Question is moved to the trash with its attachments
 async fn delete_question(
-    State(pool): State<SqlitePool>,
-    State(static_dir): State<PathBuf>,
+    State(repos): State<Repositories>,
+    Extension(identity): Extension<Identity>,
     Path(id): Path<i64>,
 ) -> ApiResponse<StatusCode> {
-    queries::questions::delete_question(&pool, id).await?;
-    if let Err(e) = std::fs::remove_dir_all(static_dir.join(id.to_string())) {
-        match e.kind() {
-            std::io::ErrorKind::NotFound => return Ok(StatusCode::OK),
-            _ => return Ok(StatusCode::INTERNAL_SERVER_ERROR),
-        }
-    }
+    repos
+        .questions
+        .delete_question(id, &identity.name(&repos).await?)
+        .await?;
     Ok(StatusCode::OK)
 }
 
+// Content of the attachment with its original name, the blob is named by the hash
+pub(super) async fn attachment_response(
+    blobs: &dyn BlobStorage,
+    attachment: &Attachment,
+) -> std::io::Result<Response> {
+    let blob = blobs.get(&attachment.sha256).await?;
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, &attachment.mime_type)
+        .header(header::CONTENT_LENGTH, attachment.size)
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"{}\"",
+                attachment.name.replace(['"', '\r', '\n'], "")
+            ),
+        )
+        .body(Body::from_stream(blob))
+        .expect("Valid response"))
+}
+
 // Small hack to cause redirect on client side so download modal will be displayed
 async fn download_attachment(Path((id, file_name)): Path<(i64, String)>) -> impl IntoResponse {
     let mut headers = HeaderMap::new();
-    let val = match format!("/static/{}/{}", id, file_name).parse() {
+    let val = match format!("/questions/{}/files/{}", id, file_name).parse() {
         Ok(v) => v,
         Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, format!("{}", err)).into_response(),
     };
@@ -293,90 +336,91 @@ async fn download_attachment(Path((id, file_name)): Path<(i64, String)>) -> impl
     headers.into_response()
 }
 
+async fn attachment_file(
+    State(blobs): State<Arc<dyn BlobStorage>>,
+    State(repos): State<Repositories>,
+    Path((id, file_name)): Path<(i64, String)>,
+) -> ApiResponse<Response> {
+    let attachment = repos.questions.get_attachment(id, &file_name).await?;
+    Ok(attachment_response(blobs.as_ref(), &attachment).await?)
+}
+
 async fn attachments(
-    State(pool): State<SqlitePool>,
+    State(repos): State<Repositories>,
     Path(id): Path<i64>,
 ) -> ApiResponse<Attachments> {
-    let question = queries::questions::get_question_by_id(&pool, id).await?;
+    repos.questions.get_question_by_id(id).await?;
     Ok(Attachments {
         id,
-        attachments: question
-            .attachments
+        attachments: repos
+            .questions
+            .get_attachments(id)
+            .await?
             .into_iter()
             .map(|a| AttachmentRow {
                 question_id: id,
-                name: a,
+                name: a.name,
             })
             .collect(),
     })
 }
 
+// The blob is removed with the last attachment referencing it
 async fn delete_attachment(
-    State(static_dir): State<PathBuf>,
-    State(pool): State<SqlitePool>,
+    State(blobs): State<Arc<dyn BlobStorage>>,
+    State(repos): State<Repositories>,
     Path((id, file_name)): Path<(i64, String)>,
 ) -> ApiResponse<StatusCode> {
-    let mut question = queries::questions::get_question_by_id(&pool, id).await?;
-    question.attachments.sort();
-    let idx = match question.attachments.binary_search(&file_name) {
-        Ok(idx) => idx,
-        Err(_) => return Ok(StatusCode::OK),
-    };
-    question.attachments.remove(idx);
-    queries::questions::update_question(
-        &pool,
-        id,
-        question.category.map(|c| c.id),
-        question.question,
-        question.answer,
-        question.attachments.iter().map(|a| a.as_str()).collect(),
-        question.hidden,
-    )
-    .await?;
-
-    std::fs::remove_file(static_dir.join(id.to_string()).join(file_name))?;
+    match repos.questions.delete_attachment(id, &file_name).await {
+        Ok(Some(unused)) => {
+            remove_unused_blobs(repos.questions.as_ref(), blobs.as_ref(), &[unused]).await?
+        }
+        Ok(None) | Err(sqlx::Error::RowNotFound) => {}
+        Err(err) => return Err(err.into()),
+    }
     Ok(StatusCode::OK)
 }
 
 async fn add_attachment(
-    State(static_dir): State<PathBuf>,
-    State(pool): State<SqlitePool>,
+    State(blobs): State<Arc<dyn BlobStorage>>,
+    State(repos): State<Repositories>,
     Path(id): Path<i64>,
     TypedMultipart(form): TypedMultipart<NewAttachments>,
 ) -> ApiResponse<AttachmentRow> {
-    let file_name = form
-        .attachment
-        .metadata
-        .file_name
-        .unwrap_or("random".to_owned());
-    let mut question = queries::questions::get_question_by_id(&pool, id).await?;
-    question.attachments.push(file_name.clone());
-    queries::questions::update_question(
-        &pool,
-        id,
-        question.category.map(|c| c.id),
-        question.question,
-        question.answer,
-        question.attachments.iter().map(|a| a.as_str()).collect(),
-        question.hidden,
+    let file_name = attachment_name(&form.attachment.metadata, "random");
+    check_file_name(&file_name).map_err(ApiError::BadRequest)?;
+    repos.questions.get_question_by_id(id).await?;
+    match repos.questions.get_attachment(id, &file_name).await {
+        Ok(_) => {
+            return Err(ApiError::BadRequest(format!(
+                "Attachment \"{file_name}\" already exists"
+            )))
+        }
+        Err(sqlx::Error::RowNotFound) => {}
+        Err(err) => return Err(err.into()),
+    }
+    let upload = store_upload(
+        blobs.as_ref(),
+        file_name,
+        form.attachment.metadata.content_type.as_deref(),
+        form.attachment.contents,
     )
     .await?;
-    let question_dir = static_dir.join(id.to_string());
-    std::fs::create_dir_all(&question_dir)?;
-
-    std::fs::copy(
-        form.attachment.contents.path(),
-        question_dir.join(&file_name),
-    )?;
-    std::fs::remove_file(form.attachment.contents.path())?;
+    let attachment = repos
+        .questions
+        .add_attachment(id, upload.attachment.clone())
+        .await?;
+    upload
+        .keep(repos.questions.as_ref(), blobs.as_ref())
+        .await?;
     Ok(AttachmentRow {
         question_id: id,
-        name: file_name,
+        name: attachment.name,
     })
 }
 
 async fn reorder(
-    State(pool): State<SqlitePool>,
+    State(repos): State<Repositories>,
     Json(body): Json<OrderingBody>,
 ) -> ApiResponse<QuestionsPage> {
     let ordering: Vec<Reorder> = body
@@ -389,12 +433,12 @@ async fn reorder(
         })
         .collect();
 
-    queries::questions::reorder_questions(&pool, ordering).await?;
-    let categories = queries::categories::get_all_categories(&pool).await?;
+    repos.questions.reorder_questions(ordering).await?;
+    let categories = repos.categories.get_all_categories().await?;
     let table = QuestionsTable {
         categories: categories.clone(),
         selected: body.category.unwrap_or(-1),
-        questions: get_questions_for_category(&pool, body.category)
+        questions: get_questions_for_category(&repos, body.category)
             .await?
             .into_iter()
             .map(|c| QuestionRow { question: c })
@@ -411,20 +455,22 @@ pub fn questions_router(state: AppState) -> Router {
             "/questions/order",
             get(questions_reordering_table).post(reorder),
         )
-        .route("/questions/:id/edit", get(edit_question))
+        .route("/questions/preview", post(preview_answer))
+        .route("/questions/{id}/edit", get(edit_question))
         .route(
-            "/questions/:id",
+            "/questions/{id}",
             get(get_question)
                 .delete(delete_question)
                 .put(update_question),
         )
         .route(
-            "/questions/:id/attachments",
+            "/questions/{id}/attachments",
             get(attachments).post(add_attachment),
         )
         .route(
-            "/questions/:id/attachments/:file_name",
+            "/questions/{id}/attachments/{file_name}",
             get(download_attachment).delete(delete_attachment),
         )
+        .route("/questions/{id}/files/{file_name}", get(attachment_file))
         .with_state(state)
 }
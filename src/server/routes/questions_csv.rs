@@ -0,0 +1,296 @@
+use askama::Template;
+use askama_web::WebTemplate;
+use axum::{
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Router,
+};
+use axum_typed_multipart::{FieldData, TryFromMultipart, TypedMultipart};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tempfile::NamedTempFile;
+
+use crate::db::{queries, Category, Pool, Repositories};
+use crate::server::{app::AppState, auth::Identity};
+
+use super::ApiResponse;
+
+// nested categories are written as the path, e.g. "HR / Vacations". The spaces are the part
+// of the separator, so the names like "CI/CD" are kept as they are
+const CATEGORY_SEPARATOR: &str = " / ";
+
+#[derive(TryFromMultipart)]
+struct CsvUpload {
+    #[form_data(limit = "64MiB")]
+    file: FieldData<NamedTempFile>,
+}
+
+// everything is read as text, so the errors can be reported with the column name
+#[derive(Deserialize)]
+struct CsvRow {
+    category: String,
+    question: String,
+    answer: String,
+    hidden: String,
+    ordering: String,
+}
+
+#[derive(Serialize)]
+struct CsvQuestion<'a> {
+    category: String,
+    question: &'a str,
+    answer: &'a str,
+    hidden: bool,
+    ordering: i64,
+}
+
+struct ValidRow {
+    category: Vec<String>,
+    question: String,
+    answer: String,
+    hidden: bool,
+    ordering: i64,
+}
+
+struct RowError {
+    line: u64,
+    message: String,
+}
+
+#[derive(Template, WebTemplate)]
+#[template(path = "questions/csv_import.html", escape = "none")]
+struct CsvImportPage;
+
+#[derive(Template, WebTemplate)]
+#[template(path = "questions/csv_import_result.html", escape = "none")]
+struct CsvImportResult {
+    errors: Vec<RowError>,
+    created: usize,
+    updated: usize,
+}
+
+fn category_path(id: i64, categories: &HashMap<i64, Category>) -> String {
+    let mut path = vec![];
+    let mut current = categories.get(&id);
+    // the limit protects from looping forever on broken data
+    while let Some(category) = current.filter(|_| path.len() < categories.len()) {
+        path.push(category.name.as_str());
+        current = category.parent.and_then(|parent| categories.get(&parent));
+    }
+    path.reverse();
+    path.join(CATEGORY_SEPARATOR)
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.trim().to_lowercase().as_str() {
+        "" | "false" | "0" | "no" => Ok(false),
+        "true" | "1" | "yes" => Ok(true),
+        other => Err(format!(
+            "Column hidden: expected true or false, got \"{other}\""
+        )),
+    }
+}
+
+fn validate_row(row: CsvRow) -> Result<ValidRow, String> {
+    let question = row.question.trim().to_string();
+    if question.is_empty() {
+        return Err("Column question: should not be empty".to_string());
+    }
+    if row.answer.trim().is_empty() {
+        return Err("Column answer: should not be empty".to_string());
+    }
+    let ordering = match row.ordering.trim() {
+        "" => 0,
+        value => value
+            .parse()
+            .map_err(|_| format!("Column ordering: expected a number, got \"{value}\""))?,
+    };
+    let category: Vec<String> = row
+        .category
+        .split(CATEGORY_SEPARATOR)
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+    Ok(ValidRow {
+        category,
+        question,
+        answer: row.answer,
+        hidden: parse_bool(&row.hidden)?,
+        ordering,
+    })
+}
+
+fn parse_csv(file: &NamedTempFile) -> Result<Vec<ValidRow>, Vec<RowError>> {
+    let mut reader = match csv::Reader::from_path(file.path()) {
+        Ok(reader) => reader,
+        Err(e) => {
+            return Err(vec![RowError {
+                line: 1,
+                message: e.to_string(),
+            }])
+        }
+    };
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(e) => {
+            return Err(vec![RowError {
+                line: 1,
+                message: e.to_string(),
+            }])
+        }
+    };
+
+    let mut rows = vec![];
+    let mut errors = vec![];
+    let mut seen = HashSet::new();
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                errors.push(RowError {
+                    line: e.position().map(|p| p.line()).unwrap_or_default(),
+                    message: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        let row = record
+            .deserialize::<CsvRow>(Some(&headers))
+            .map_err(|e| e.to_string())
+            .and_then(validate_row);
+        match row {
+            Ok(row) => {
+                if !seen.insert((row.category.clone(), row.question.clone())) {
+                    errors.push(RowError {
+                        line,
+                        message: "Same question in the same category is already in the file"
+                            .to_string(),
+                    });
+                    continue;
+                }
+                rows.push(row)
+            }
+            Err(message) => errors.push(RowError { line, message }),
+        }
+    }
+    if errors.is_empty() {
+        Ok(rows)
+    } else {
+        Err(errors)
+    }
+}
+
+async fn import_page() -> CsvImportPage {
+    CsvImportPage
+}
+
+async fn export_csv(State(repos): State<Repositories>) -> ApiResponse<Response> {
+    let categories: HashMap<i64, Category> = repos
+        .categories
+        .get_all_categories()
+        .await?
+        .into_iter()
+        .map(|c| (c.id, c))
+        .collect();
+    let questions = repos.questions.get_all_questions().await?;
+
+    let mut writer = csv::Writer::from_writer(vec![]);
+    for question in questions.iter() {
+        writer
+            .serialize(CsvQuestion {
+                category: question
+                    .category
+                    .as_ref()
+                    .map(|c| category_path(c.id, &categories))
+                    .unwrap_or_default(),
+                question: &question.question,
+                answer: &question.answer,
+                hidden: question.hidden,
+                ordering: question.ordering,
+            })
+            .map_err(std::io::Error::from)?;
+    }
+    let data = writer.into_inner().map_err(|e| e.into_error())?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"questions.csv\"",
+            ),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+// Rows are checked before anything is written, so a file with errors changes nothing
+async fn import_csv(
+    State(pool): State<Pool>,
+    State(repos): State<Repositories>,
+    Extension(identity): Extension<Identity>,
+    TypedMultipart(form): TypedMultipart<CsvUpload>,
+) -> ApiResponse<CsvImportResult> {
+    let rows = match parse_csv(&form.file.contents) {
+        Ok(rows) => rows,
+        Err(errors) => {
+            return Ok(CsvImportResult {
+                errors,
+                created: 0,
+                updated: 0,
+            })
+        }
+    };
+
+    let author = identity.name(&repos).await?;
+    let mut created = 0;
+    let mut updated = 0;
+    let mut transaction = pool.begin().await?;
+    for row in rows {
+        let category = if row.category.is_empty() {
+            None
+        } else {
+            let path: Vec<&str> = row.category.iter().map(|name| name.as_str()).collect();
+            Some(
+                queries::categories::get_or_create_category_by_path(
+                    &mut transaction,
+                    &path,
+                    &author,
+                )
+                .await?,
+            )
+        };
+        let is_new = queries::questions::upsert_question(
+            &mut transaction,
+            category,
+            &row.question,
+            &row.answer,
+            row.hidden,
+            row.ordering,
+            &author,
+        )
+        .await?;
+        if is_new {
+            created += 1;
+        } else {
+            updated += 1;
+        }
+    }
+    transaction.commit().await?;
+
+    Ok(CsvImportResult {
+        errors: vec![],
+        created,
+        updated,
+    })
+}
+
+pub fn questions_csv_router(state: AppState) -> Router {
+    Router::new()
+        .route("/questions/import", get(import_page).post(import_csv))
+        .route("/questions/export.csv", get(export_csv))
+        .with_state(state)
+}
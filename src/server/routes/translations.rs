@@ -0,0 +1,229 @@
+use askama::Template;
+use askama_web::WebTemplate;
+use axum::{
+    extract::{Path, State},
+    routing::{get, put},
+    Json, Router,
+};
+use serde::Deserialize;
+
+use crate::db::{Pool, Repositories};
+use crate::{
+    db::{queries::translations, Category, Question},
+    i18n::{default_language, Language},
+    server::app::AppState,
+};
+
+use super::{ApiError, ApiResponse};
+
+#[derive(Deserialize)]
+struct QuestionTranslationBody {
+    question: String,
+    answer: String,
+}
+
+#[derive(Deserialize)]
+struct CategoryTranslationBody {
+    name: String,
+}
+
+// Default text on the left and the translation to the language on the right
+#[derive(Template, WebTemplate)]
+#[template(path = "questions/translation_form.html", escape = "none")]
+struct QuestionTranslationForm {
+    question: Question,
+    language: Language,
+    default_language: Language,
+    translation: Option<translations::QuestionTranslation>,
+}
+
+#[derive(Template, WebTemplate)]
+#[template(path = "questions/translations_modal.html", escape = "none")]
+struct QuestionTranslations {
+    question: String,
+    forms: Vec<QuestionTranslationForm>,
+}
+
+#[derive(Template, WebTemplate)]
+#[template(path = "categories/translation_form.html", escape = "none")]
+struct CategoryTranslationForm {
+    category: Category,
+    language: Language,
+    default_language: Language,
+    translation: Option<translations::CategoryTranslation>,
+}
+
+#[derive(Template, WebTemplate)]
+#[template(path = "categories/translations_modal.html", escape = "none")]
+struct CategoryTranslations {
+    category: String,
+    forms: Vec<CategoryTranslationForm>,
+}
+
+// content in the default language is edited on the pages of the questions and the categories
+fn check_not_default(language: Language) -> ApiResponse<()> {
+    if language == default_language() {
+        return Err(ApiError::BadRequest(format!(
+            "{} is the default language, edit the content itself",
+            language.name()
+        )));
+    }
+    Ok(())
+}
+
+async fn question_translation_form(
+    pool: &Pool,
+    repos: &Repositories,
+    id: i64,
+    language: Language,
+) -> ApiResponse<QuestionTranslationForm> {
+    let question = repos.questions.get_question_by_id(id).await?;
+    let translation = translations::get_question_translations(pool, id)
+        .await?
+        .into_iter()
+        .find(|translation| translation.language == language.code());
+    Ok(QuestionTranslationForm {
+        question,
+        language,
+        default_language: default_language(),
+        translation,
+    })
+}
+
+async fn category_translation_form(
+    pool: &Pool,
+    repos: &Repositories,
+    id: i64,
+    language: Language,
+) -> ApiResponse<CategoryTranslationForm> {
+    let category = repos.categories.get_category(id).await?;
+    let translation = translations::get_category_translations(pool, id)
+        .await?
+        .into_iter()
+        .find(|translation| translation.language == language.code());
+    Ok(CategoryTranslationForm {
+        category,
+        language,
+        default_language: default_language(),
+        translation,
+    })
+}
+
+fn other_languages() -> impl Iterator<Item = Language> {
+    let default = default_language();
+    Language::ALL
+        .into_iter()
+        .filter(move |language| *language != default)
+}
+
+async fn question_translations(
+    State(pool): State<Pool>,
+    State(repos): State<Repositories>,
+    Path(id): Path<i64>,
+) -> ApiResponse<QuestionTranslations> {
+    let question = repos.questions.get_question_by_id(id).await?;
+    let mut forms = vec![];
+    for language in other_languages() {
+        forms.push(question_translation_form(&pool, &repos, id, language).await?);
+    }
+    Ok(QuestionTranslations {
+        question: question.question,
+        forms,
+    })
+}
+
+// Translation with the empty question and answer is removed
+async fn update_question_translation(
+    State(pool): State<Pool>,
+    State(repos): State<Repositories>,
+    Path((id, language)): Path<(i64, Language)>,
+    Json(body): Json<QuestionTranslationBody>,
+) -> ApiResponse<QuestionTranslationForm> {
+    check_not_default(language)?;
+    repos.questions.get_question_by_id(id).await?;
+    let question = body.question.trim();
+    let answer = body.answer.trim();
+    match (question.is_empty(), answer.is_empty()) {
+        (true, true) => {
+            translations::delete_question_translation(&pool, id, language.code()).await?
+        }
+        (false, false) => {
+            translations::set_question_translation(&pool, id, language.code(), question, answer)
+                .await?
+        }
+        _ => {
+            return Err(ApiError::BadRequest(
+                "Both the question and the answer should be translated".to_string(),
+            ))
+        }
+    }
+    question_translation_form(&pool, &repos, id, language).await
+}
+
+async fn delete_question_translation(
+    State(pool): State<Pool>,
+    State(repos): State<Repositories>,
+    Path((id, language)): Path<(i64, Language)>,
+) -> ApiResponse<QuestionTranslationForm> {
+    check_not_default(language)?;
+    translations::delete_question_translation(&pool, id, language.code()).await?;
+    question_translation_form(&pool, &repos, id, language).await
+}
+
+async fn category_translations(
+    State(pool): State<Pool>,
+    State(repos): State<Repositories>,
+    Path(id): Path<i64>,
+) -> ApiResponse<CategoryTranslations> {
+    let category = repos.categories.get_category(id).await?;
+    let mut forms = vec![];
+    for language in other_languages() {
+        forms.push(category_translation_form(&pool, &repos, id, language).await?);
+    }
+    Ok(CategoryTranslations {
+        category: category.name,
+        forms,
+    })
+}
+
+async fn update_category_translation(
+    State(pool): State<Pool>,
+    State(repos): State<Repositories>,
+    Path((id, language)): Path<(i64, Language)>,
+    Json(body): Json<CategoryTranslationBody>,
+) -> ApiResponse<CategoryTranslationForm> {
+    check_not_default(language)?;
+    repos.categories.get_category(id).await?;
+    let name = body.name.trim();
+    if name.is_empty() {
+        translations::delete_category_translation(&pool, id, language.code()).await?;
+    } else {
+        translations::set_category_translation(&pool, id, language.code(), name).await?;
+    }
+    category_translation_form(&pool, &repos, id, language).await
+}
+
+async fn delete_category_translation(
+    State(pool): State<Pool>,
+    State(repos): State<Repositories>,
+    Path((id, language)): Path<(i64, Language)>,
+) -> ApiResponse<CategoryTranslationForm> {
+    check_not_default(language)?;
+    translations::delete_category_translation(&pool, id, language.code()).await?;
+    category_translation_form(&pool, &repos, id, language).await
+}
+
+pub fn translations_router(state: AppState) -> Router {
+    Router::new()
+        .route("/questions/{id}/translations", get(question_translations))
+        .route(
+            "/questions/{id}/translations/{language}",
+            put(update_question_translation).delete(delete_question_translation),
+        )
+        .route("/categories/{id}/translations", get(category_translations))
+        .route(
+            "/categories/{id}/translations/{language}",
+            put(update_category_translation).delete(delete_category_translation),
+        )
+        .with_state(state)
+}
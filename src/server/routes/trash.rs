@@ -0,0 +1,86 @@
+use askama::Template;
+use askama_web::WebTemplate;
+use axum::{
+    extract::{Path, State},
+    routing::{delete, get, post},
+    Extension, Router,
+};
+use std::sync::Arc;
+
+use crate::db::queries::trash::{self, DeletedCategory, DeletedQuestion};
+use crate::db::{Pool, Repositories};
+use crate::server::{app::AppState, auth::Identity, trash::retention_days};
+use crate::storage::{remove_unused_blobs, BlobStorage};
+
+use super::ApiResponse;
+
+#[derive(Template, WebTemplate)]
+#[template(path = "trash/trash.html", escape = "none")]
+struct TrashPage {
+    questions: Vec<DeletedQuestion>,
+    categories: Vec<DeletedCategory>,
+    retention_days: Option<i64>,
+}
+
+async fn trash_page(State(pool): State<Pool>) -> ApiResponse<TrashPage> {
+    Ok(TrashPage {
+        questions: trash::get_deleted_questions(&pool).await?,
+        categories: trash::get_deleted_categories(&pool).await?,
+        retention_days: retention_days(),
+    })
+}
+
+async fn restore_question(
+    State(pool): State<Pool>,
+    State(repos): State<Repositories>,
+    Extension(identity): Extension<Identity>,
+    Path(id): Path<i64>,
+) -> ApiResponse<()> {
+    trash::restore_question(&pool, id, &identity.name(&repos).await?).await?;
+    tracing::info!("Question {id} is restored from the trash by {identity}");
+    Ok(())
+}
+
+async fn purge_question(
+    State(pool): State<Pool>,
+    State(repos): State<Repositories>,
+    State(blobs): State<Arc<dyn BlobStorage>>,
+    Extension(identity): Extension<Identity>,
+    Path(id): Path<i64>,
+) -> ApiResponse<()> {
+    let unused = trash::purge_question(&pool, id).await?;
+    remove_unused_blobs(repos.questions.as_ref(), blobs.as_ref(), &unused).await?;
+    tracing::info!("Question {id} is purged from the trash by {identity}");
+    Ok(())
+}
+
+async fn restore_category(
+    State(pool): State<Pool>,
+    State(repos): State<Repositories>,
+    Extension(identity): Extension<Identity>,
+    Path(id): Path<i64>,
+) -> ApiResponse<()> {
+    trash::restore_category(&pool, id, &identity.name(&repos).await?).await?;
+    tracing::info!("Category {id} is restored from the trash by {identity}");
+    Ok(())
+}
+
+async fn purge_category(
+    State(pool): State<Pool>,
+    Extension(identity): Extension<Identity>,
+    Path(id): Path<i64>,
+) -> ApiResponse<()> {
+    trash::purge_category(&pool, id).await?;
+    tracing::info!("Category {id} is purged from the trash by {identity}");
+    Ok(())
+}
+
+pub fn trash_router(state: AppState) -> Router {
+    Router::new()
+        .route("/trash", get(trash_page))
+        .route("/trash/questions/{id}", delete(purge_question))
+        .route("/trash/questions/{id}/restore", post(restore_question))
+        .route("/trash/categories/{id}", delete(purge_category))
+        .route("/trash/categories/{id}/restore", post(restore_category))
+        .with_state(state)
+}
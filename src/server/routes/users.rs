@@ -1,55 +1,215 @@
+use askama::Template;
+use askama_web::WebTemplate;
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::IntoResponse,
-    routing::{delete, get},
-    Json, Router,
+    extract::{Path, Query, State},
+    routing::get,
+    Extension, Json, Router,
 };
-use sqlx::SqlitePool;
+use serde::Deserialize;
+use utoipa::ToSchema;
 
 use crate::{
-    db::{queries, User},
-    server::app::AppState,
+    db::{Repositories, User},
+    server::{app::AppState, auth::Identity, deserializers::deserialize_bool_from_checkbox},
 };
 
-async fn get_users(State(pool): State<SqlitePool>) -> Json<Vec<User>> {
-    Json(queries::users::get_users(&pool).await.unwrap())
+use super::{ApiError, ApiResponse};
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum UserStatus {
+    #[default]
+    All,
+    Active,
+    Inactive,
+    Admins,
 }
 
-async fn create_user(State(pool): State<SqlitePool>, Json(user): Json<User>) -> Json<User> {
-    let user_id = queries::users::create_user(
-        &pool,
-        user.id,
-        user.username.as_deref(),
-        &user.first_name,
-        user.last_name.as_deref(),
-        user.is_admin,
-        user.active,
-    )
-    .await
-    .unwrap();
-    let new_user = queries::users::get_user(&pool, user_id).await.unwrap();
-    Json(new_user)
+#[derive(Deserialize)]
+struct UsersQuery {
+    #[serde(default)]
+    search: String,
+    #[serde(default)]
+    status: UserStatus,
 }
 
-async fn update_user(State(pool): State<SqlitePool>, Json(user): Json<User>) -> Json<User> {
-    let user_id = user.id;
-    queries::users::update_user(&pool, user).await.unwrap();
-    let updated_user = queries::users::get_user(&pool, user_id).await.unwrap();
-    Json(updated_user)
+#[derive(Deserialize)]
+struct UserUpdate {
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_bool_from_checkbox")]
+    active: Option<bool>,
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_bool_from_checkbox")]
+    is_admin: Option<bool>,
+}
+
+#[derive(Template, WebTemplate)]
+#[template(path = "users/user_row.html", escape = "none")]
+struct UserRow {
+    user: User,
+}
+
+#[derive(Template, WebTemplate)]
+#[template(path = "users/user_row_edit.html", escape = "none")]
+struct UserRowEdit {
+    user: User,
+}
+
+#[derive(Template, WebTemplate)]
+#[template(path = "users/users_table.html", escape = "none")]
+struct UsersTable {
+    users: Vec<UserRow>,
+}
+
+#[derive(Template, WebTemplate)]
+#[template(path = "users/users.html", escape = "none")]
+struct UsersPage {
+    table: UsersTable,
+}
+
+pub(super) fn matches(user: &User, search: &str, status: UserStatus) -> bool {
+    let status_matches = match status {
+        UserStatus::All => true,
+        UserStatus::Active => user.active,
+        UserStatus::Inactive => !user.active,
+        UserStatus::Admins => user.is_admin,
+    };
+    let search = search.trim().to_lowercase();
+    let search_matches = search.is_empty()
+        || user.id.to_string() == search
+        || [
+            Some(&user.first_name),
+            user.last_name.as_ref(),
+            user.username.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .any(|field| field.to_lowercase().contains(&search));
+    status_matches && search_matches
+}
+
+async fn render_users_table(
+    repos: &Repositories,
+    search: &str,
+    status: UserStatus,
+) -> ApiResponse<UsersTable> {
+    Ok(UsersTable {
+        users: repos
+            .users
+            .get_users()
+            .await?
+            .into_iter()
+            .filter(|user| matches(user, search, status))
+            .map(|user| UserRow { user })
+            .collect(),
+    })
+}
+
+// admin should not be able to lock themselves out of the CMS
+fn check_not_self(identity: &Identity, id: i64) -> ApiResponse<()> {
+    match identity {
+        Identity::Admin { id: admin_id } if *admin_id == id => Err(ApiError::BadRequest(
+            "You can't deactivate, demote or delete yourself".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+async fn users_page(State(repos): State<Repositories>) -> ApiResponse<UsersPage> {
+    Ok(UsersPage {
+        table: render_users_table(&repos, "", UserStatus::All).await?,
+    })
+}
+
+async fn users_table(
+    State(repos): State<Repositories>,
+    Query(query): Query<UsersQuery>,
+) -> ApiResponse<UsersTable> {
+    render_users_table(&repos, &query.search, query.status).await
+}
+
+async fn get_user(State(repos): State<Repositories>, Path(id): Path<i64>) -> ApiResponse<UserRow> {
+    Ok(UserRow {
+        user: repos.users.get_user(id).await?,
+    })
+}
+
+async fn edit_user(
+    State(repos): State<Repositories>,
+    Path(id): Path<i64>,
+) -> ApiResponse<UserRowEdit> {
+    Ok(UserRowEdit {
+        user: repos.users.get_user(id).await?,
+    })
+}
+
+async fn update_user_row(
+    State(repos): State<Repositories>,
+    Extension(identity): Extension<Identity>,
+    Path(id): Path<i64>,
+    Json(form): Json<UserUpdate>,
+) -> ApiResponse<UserRow> {
+    let mut user = repos.users.get_user(id).await?;
+    user.active = form.active.unwrap_or(false);
+    user.is_admin = form.is_admin.unwrap_or(false);
+    if !user.active || !user.is_admin {
+        check_not_self(&identity, id)?;
+    }
+    repos.users.update_user(user).await?;
+    Ok(UserRow {
+        user: repos.users.get_user(id).await?,
+    })
 }
 
 async fn delete_user(
-    State(pool): State<SqlitePool>,
-    Path(user_id): Path<i64>,
-) -> impl IntoResponse {
-    queries::users::delete_user(&pool, user_id).await.unwrap();
-    StatusCode::OK
+    State(repos): State<Repositories>,
+    Extension(identity): Extension<Identity>,
+    Path(id): Path<i64>,
+) -> ApiResponse<()> {
+    check_not_self(&identity, id)?;
+    repos.users.delete_user(id).await?;
+    Ok(())
+}
+
+async fn create_user(
+    State(repos): State<Repositories>,
+    Json(user): Json<User>,
+) -> ApiResponse<Json<User>> {
+    let user_id = repos
+        .users
+        .create_user(
+            user.id,
+            user.username.as_deref(),
+            &user.first_name,
+            user.last_name.as_deref(),
+            user.is_admin,
+            user.active,
+        )
+        .await?;
+    Ok(Json(repos.users.get_user(user_id).await?))
+}
+
+async fn update_user(
+    State(repos): State<Repositories>,
+    Extension(identity): Extension<Identity>,
+    Json(user): Json<User>,
+) -> ApiResponse<Json<User>> {
+    let user_id = user.id;
+    if !user.active || !user.is_admin {
+        check_not_self(&identity, user_id)?;
+    }
+    repos.users.update_user(user).await?;
+    Ok(Json(repos.users.get_user(user_id).await?))
 }
 
 pub fn users_router(state: AppState) -> Router {
     Router::new()
-        .route("/users", get(get_users).post(create_user).put(update_user))
-        .route("/users/:id", delete(delete_user))
+        .route("/users", get(users_page).post(create_user).put(update_user))
+        .route("/users/table", get(users_table))
+        .route("/users/{id}/edit", get(edit_user))
+        .route(
+            "/users/{id}",
+            get(get_user).put(update_user_row).delete(delete_user),
+        )
         .with_state(state)
 }
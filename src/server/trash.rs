@@ -0,0 +1,49 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::db::queries::trash::purge_expired;
+use crate::db::repositories::SqlRepository;
+use crate::db::Pool;
+use crate::storage::{remove_unused_blobs, BlobStorage};
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const DEFAULT_RETENTION_DAYS: i64 = 30;
+
+// How long the deleted items stay in the trash, 0 keeps them until they are purged by hand
+pub fn retention_days() -> Option<i64> {
+    let days = dotenv::var("TRASH_RETENTION_DAYS")
+        .ok()
+        .and_then(|days| days.parse().ok())
+        .unwrap_or(DEFAULT_RETENTION_DAYS);
+    (days > 0).then_some(days)
+}
+
+async fn purge_expired_items(
+    pool: &Pool,
+    blobs: &dyn BlobStorage,
+    days: i64,
+) -> anyhow::Result<()> {
+    let purged = purge_expired(pool, days).await?;
+    let questions = SqlRepository::new(pool.clone());
+    remove_unused_blobs(&questions, blobs, &purged.blobs).await?;
+    if !purged.questions.is_empty() || !purged.categories.is_empty() {
+        tracing::info!(
+            "Purged {} questions and {} categories deleted more than {days} days ago",
+            purged.questions.len(),
+            purged.categories.len()
+        );
+    }
+    Ok(())
+}
+
+// Empties the trash from the items deleted before the retention period
+pub async fn run_purge(pool: Pool, blobs: Arc<dyn BlobStorage>) {
+    loop {
+        if let Some(days) = retention_days() {
+            if let Err(err) = purge_expired_items(&pool, blobs.as_ref(), days).await {
+                tracing::error!("Unable to purge the trash: {err:#}");
+            }
+        }
+        tokio::time::sleep(PURGE_INTERVAL).await;
+    }
+}
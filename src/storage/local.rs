@@ -0,0 +1,67 @@
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+use tokio_util::io::ReaderStream;
+
+use super::{BlobStorage, BlobStream};
+
+const BLOBS_DIR: &str = "blobs";
+
+// Blobs in STATIC_DIR/blobs, named by the hash
+pub struct LocalStorage {
+    dir: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(static_dir: &Path) -> Self {
+        Self {
+            dir: static_dir.join(BLOBS_DIR),
+        }
+    }
+
+    fn path(&self, sha256: &str) -> PathBuf {
+        self.dir.join(sha256)
+    }
+}
+
+// The blob appears at once, so the partially copied content is never referenced
+fn copy_blob(path: &Path, target: &Path) -> std::io::Result<()> {
+    let mut file = NamedTempFile::new_in(target.parent().expect("Blob always has a parent"))?;
+    std::io::copy(&mut File::open(path)?, &mut file)?;
+    file.persist(target)?;
+    Ok(())
+}
+
+#[async_trait]
+impl BlobStorage for LocalStorage {
+    async fn exists(&self, sha256: &str) -> std::io::Result<bool> {
+        tokio::fs::try_exists(self.path(sha256)).await
+    }
+
+    // the file is linked, or copied when it is on another file system
+    async fn put(&self, sha256: &str, path: &Path) -> std::io::Result<()> {
+        let target = self.path(sha256);
+        tokio::fs::create_dir_all(&self.dir).await?;
+        if tokio::fs::hard_link(path, &target).await.is_err() {
+            let path = path.to_owned();
+            tokio::task::spawn_blocking(move || copy_blob(&path, &target))
+                .await
+                .expect("Copying panicked")?;
+        }
+        Ok(())
+    }
+
+    async fn get(&self, sha256: &str) -> std::io::Result<BlobStream> {
+        let file = tokio::fs::File::open(self.path(sha256)).await?;
+        Ok(ReaderStream::new(file).boxed())
+    }
+
+    async fn delete(&self, sha256: &str) -> std::io::Result<()> {
+        match tokio::fs::remove_file(self.path(sha256)).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,246 @@
+mod local;
+mod s3;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::stream::BoxStream;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+use tempfile::NamedTempFile;
+
+use crate::db::queries::attachments::{
+    delete_unhashed_attachment, get_unhashed_attachments, set_attachment_blob, NewAttachment,
+};
+use crate::db::queries::broadcasts::{
+    delete_unhashed_broadcast_attachment, get_unhashed_broadcast_attachments,
+    set_broadcast_attachment_blob,
+};
+use crate::db::repositories::QuestionRepository;
+use crate::db::Pool;
+
+pub use local::LocalStorage;
+pub use s3::S3Storage;
+
+pub type BlobStream = BoxStream<'static, std::io::Result<Bytes>>;
+
+// Content of the attachments is stored by its hash, so the file attached
+// to several questions is stored once. Blobs are never changed, only added and removed
+#[async_trait]
+pub trait BlobStorage: Send + Sync {
+    async fn exists(&self, sha256: &str) -> std::io::Result<bool>;
+    // Copies the content of the local file to the storage, the file itself is kept
+    async fn put(&self, sha256: &str, path: &Path) -> std::io::Result<()>;
+    async fn get(&self, sha256: &str) -> std::io::Result<BlobStream>;
+    // Missing blob is not an error
+    async fn delete(&self, sha256: &str) -> std::io::Result<()>;
+}
+
+// Blobs are kept in the S3 bucket when S3_BUCKET is set, in STATIC_DIR otherwise
+pub fn from_env(static_dir: &Path) -> anyhow::Result<Arc<dyn BlobStorage>> {
+    match dotenv::var("S3_BUCKET") {
+        Ok(bucket) if !bucket.is_empty() => {
+            tracing::info!("Attachments are stored in the bucket {bucket}");
+            Ok(Arc::new(S3Storage::from_env(&bucket)?))
+        }
+        _ => Ok(Arc::new(LocalStorage::new(static_dir))),
+    }
+}
+
+// Type sent by the browser, or the one guessed by the extension when it is missing or invalid
+pub fn mime_type(name: &str, content_type: Option<&str>) -> String {
+    match content_type.and_then(|t| t.parse::<mime_guess::Mime>().ok()) {
+        Some(mime) if mime != mime_guess::mime::APPLICATION_OCTET_STREAM => mime.to_string(),
+        _ => mime_guess::from_path(name)
+            .first_or_octet_stream()
+            .to_string(),
+    }
+}
+
+// Names are the paths of the files in the backup archive, so they can't leave the directory
+pub fn check_file_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(format!("Invalid file name \"{name}\""));
+    }
+    Ok(())
+}
+
+fn hash_file(path: &Path) -> std::io::Result<(String, i64)> {
+    let mut hasher = Sha256::new();
+    let size = std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok((hex::encode(hasher.finalize()), size as i64))
+}
+
+async fn hash(path: &Path) -> std::io::Result<(String, i64)> {
+    let path = path.to_owned();
+    tokio::task::spawn_blocking(move || hash_file(&path))
+        .await
+        .expect("Hashing panicked")
+}
+
+// Copies the file to the blobs when its content is new. Returns the hash and the size of the content
+pub async fn store(blobs: &dyn BlobStorage, path: &Path) -> std::io::Result<(String, i64)> {
+    let (sha256, size) = hash(path).await?;
+    if !blobs.exists(&sha256).await? {
+        blobs.put(&sha256, path).await?;
+    }
+    Ok((sha256, size))
+}
+
+// Called with the stored file after the attachment referencing it is inserted. The blob could be
+// removed in between with the last other attachment, then it is put again. Blobs are removed
+// under the same lock after their references are counted, so the new attachment either is
+// counted there or finds the blob missing here
+pub async fn keep_blob(
+    questions: &dyn QuestionRepository,
+    blobs: &dyn BlobStorage,
+    sha256: &str,
+    path: &Path,
+) -> std::io::Result<()> {
+    let _lock = questions
+        .lock_blob(sha256)
+        .await
+        .map_err(std::io::Error::other)?;
+    if !blobs.exists(sha256).await? {
+        tracing::warn!("Blob {sha256} was removed while being attached, it is stored again");
+        blobs.put(sha256, path).await?;
+    }
+    Ok(())
+}
+
+// Uploaded file stored in the blobs. The file is kept until the attachment is inserted
+pub struct Upload {
+    pub attachment: NewAttachment,
+    file: NamedTempFile,
+}
+
+impl Upload {
+    pub async fn keep(
+        self,
+        questions: &dyn QuestionRepository,
+        blobs: &dyn BlobStorage,
+    ) -> std::io::Result<()> {
+        keep_blob(questions, blobs, &self.attachment.sha256, self.file.path()).await
+    }
+}
+
+// Stores the uploaded file as the attachment with the given name
+pub async fn store_upload(
+    blobs: &dyn BlobStorage,
+    name: String,
+    content_type: Option<&str>,
+    file: NamedTempFile,
+) -> std::io::Result<Upload> {
+    let (sha256, size) = store(blobs, file.path()).await?;
+    let attachment = NewAttachment {
+        mime_type: mime_type(&name, content_type),
+        name,
+        sha256,
+        size,
+    };
+    Ok(Upload { attachment, file })
+}
+
+// Removes the blobs left without the attachments. References are counted again under the lock,
+// so the blob attached again after the deletion is kept
+pub async fn remove_unused_blobs(
+    questions: &dyn QuestionRepository,
+    blobs: &dyn BlobStorage,
+    hashes: &[String],
+) -> std::io::Result<()> {
+    for sha256 in hashes {
+        let _lock = questions
+            .lock_blob(sha256)
+            .await
+            .map_err(std::io::Error::other)?;
+        let unused = questions
+            .unused_blobs(vec![sha256.clone()])
+            .await
+            .map_err(std::io::Error::other)?;
+        if !unused.is_empty() {
+            blobs.delete(sha256).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn is_file(path: &Path) -> bool {
+    tokio::fs::metadata(path).await.is_ok_and(|m| m.is_file())
+}
+
+// Attachments were stored at STATIC_DIR/<question id>/<name> before the blobs. Their files
+// are copied to the blobs first, so the interrupted migration is continued on the next start
+pub async fn migrate_question_files(
+    pool: &Pool,
+    static_dir: &Path,
+    blobs: &dyn BlobStorage,
+) -> anyhow::Result<()> {
+    let unhashed = get_unhashed_attachments(pool).await?;
+    if unhashed.is_empty() {
+        return Ok(());
+    }
+    tracing::info!("Moving {} attachments to the blobs", unhashed.len());
+    for attachment in unhashed {
+        let question_dir = static_dir.join(attachment.question_id.to_string());
+        let path = question_dir.join(&attachment.name);
+        if !is_file(&path).await {
+            tracing::warn!("File {path:?} is not found, the attachment is removed");
+            delete_unhashed_attachment(pool, attachment.id).await?;
+            continue;
+        }
+        let (sha256, size) = store(blobs, &path).await?;
+        let blob = NewAttachment {
+            mime_type: mime_type(&attachment.name, None),
+            name: attachment.name,
+            sha256,
+            size,
+        };
+        set_attachment_blob(pool, attachment.id, &blob).await?;
+        tokio::fs::remove_file(&path).await?;
+        // the directory is left when there are files of the other attachments
+        let _ = tokio::fs::remove_dir(question_dir).await;
+    }
+    Ok(())
+}
+
+// Attachments of the broadcasts were stored at STATIC_DIR/broadcasts/<broadcast id>/<name>
+// before the blobs, they are moved the same way
+pub async fn migrate_broadcast_files(
+    pool: &Pool,
+    static_dir: &Path,
+    blobs: &dyn BlobStorage,
+) -> anyhow::Result<()> {
+    let unhashed = get_unhashed_broadcast_attachments(pool).await?;
+    if unhashed.is_empty() {
+        return Ok(());
+    }
+    tracing::info!(
+        "Moving {} broadcast attachments to the blobs",
+        unhashed.len()
+    );
+    let broadcasts_dir = static_dir.join("broadcasts");
+    for attachment in unhashed {
+        let broadcast_dir = broadcasts_dir.join(attachment.broadcast_id.to_string());
+        let path = broadcast_dir.join(&attachment.name);
+        // names were not checked before, so the ones pointing out of the directory are skipped
+        let plain_name = Path::new(&attachment.name).file_name() == Some(attachment.name.as_ref());
+        if !plain_name || !is_file(&path).await {
+            tracing::warn!("File {path:?} is not found, the attachment is removed");
+            delete_unhashed_broadcast_attachment(pool, attachment.id).await?;
+            continue;
+        }
+        let (sha256, size) = store(blobs, &path).await?;
+        let blob = NewAttachment {
+            mime_type: mime_type(&attachment.name, None),
+            name: attachment.name,
+            sha256,
+            size,
+        };
+        set_broadcast_attachment_blob(pool, attachment.id, &blob).await?;
+        tokio::fs::remove_file(&path).await?;
+        let _ = tokio::fs::remove_dir(broadcast_dir).await;
+    }
+    let _ = tokio::fs::remove_dir(broadcasts_dir).await;
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use async_trait::async_trait;
+use futures_util::{StreamExt, TryStreamExt};
+use object_store::{aws::AmazonS3Builder, buffered::BufWriter, path::Path as ObjectPath};
+use object_store::{Error, ObjectStore};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+
+use super::{BlobStorage, BlobStream};
+
+const BLOBS_PREFIX: &str = "blobs";
+
+// Blobs in the bucket of S3 or a compatible storage like MinIO, named blobs/<hash>
+pub struct S3Storage {
+    store: Arc<dyn ObjectStore>,
+}
+
+impl S3Storage {
+    // Credentials, region and endpoint are read from the AWS_* variables
+    pub fn from_env(bucket: &str) -> anyhow::Result<Self> {
+        let store = AmazonS3Builder::from_env()
+            .with_bucket_name(bucket)
+            .build()?;
+        Ok(Self {
+            store: Arc::new(store),
+        })
+    }
+
+    fn path(sha256: &str) -> ObjectPath {
+        ObjectPath::from(format!("{BLOBS_PREFIX}/{sha256}"))
+    }
+}
+
+#[async_trait]
+impl BlobStorage for S3Storage {
+    async fn exists(&self, sha256: &str) -> std::io::Result<bool> {
+        match self.store.head(&Self::path(sha256)).await {
+            Ok(_) => Ok(true),
+            Err(Error::NotFound { .. }) => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    // large files are uploaded in parts
+    async fn put(&self, sha256: &str, path: &Path) -> std::io::Result<()> {
+        let mut file = tokio::fs::File::open(path).await?;
+        let mut writer = BufWriter::new(self.store.clone(), Self::path(sha256));
+        tokio::io::copy(&mut file, &mut writer).await?;
+        writer.shutdown().await
+    }
+
+    async fn get(&self, sha256: &str) -> std::io::Result<BlobStream> {
+        let result = self.store.get(&Self::path(sha256)).await?;
+        Ok(result.into_stream().map_err(std::io::Error::from).boxed())
+    }
+
+    async fn delete(&self, sha256: &str) -> std::io::Result<()> {
+        match self.store.delete(&Self::path(sha256)).await {
+            Ok(()) | Err(Error::NotFound { .. }) => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
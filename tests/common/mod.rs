@@ -0,0 +1,70 @@
+// The Postgres queries are not checked at compile time, so they are run when TEST_POSTGRES_URL
+// is set to the server where the test can create its own database
+#![allow(dead_code)]
+
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sqlx::postgres::{PgConnectOptions, PgPool};
+use sqlx::{Connection, Executor, PgConnection};
+use tempfile::TempDir;
+use udv_qna_bot::db::{establish_connection, run_migrations, Pool};
+
+// Migrated database in the directory removed with it
+pub async fn sqlite_pool() -> (TempDir, Pool) {
+    let dir = tempfile::tempdir().unwrap();
+    let url = format!("sqlite:{}?mode=rwc", dir.path().join("db.sqlite").display());
+    let pool = establish_connection(&url).await.unwrap();
+    run_migrations(&pool).await.unwrap();
+    (dir, pool)
+}
+
+// Database with the unique name on the test server, dropped when the test passes
+pub struct TestDatabase {
+    server_url: String,
+    name: String,
+}
+
+impl TestDatabase {
+    pub async fn create(server_url: &str) -> (Self, Pool) {
+        let suffix = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let name = format!("qna_test_{}_{suffix}", std::process::id());
+        let mut server = PgConnection::connect(server_url).await.unwrap();
+        server
+            .execute(format!("CREATE DATABASE {name} TEMPLATE template0 ENCODING 'UTF8'").as_str())
+            .await
+            .unwrap();
+        let options = PgConnectOptions::from_str(server_url)
+            .unwrap()
+            .database(&name);
+        let pool = Pool::Postgres(PgPool::connect_with(options).await.unwrap());
+        run_migrations(&pool).await.unwrap();
+        let database = TestDatabase {
+            server_url: server_url.to_string(),
+            name,
+        };
+        (database, pool)
+    }
+
+    pub async fn drop(self, pool: Pool) {
+        if let Pool::Postgres(pool) = pool {
+            pool.close().await;
+        }
+        let mut server = PgConnection::connect(&self.server_url).await.unwrap();
+        server
+            .execute(format!("DROP DATABASE {}", self.name).as_str())
+            .await
+            .unwrap();
+    }
+}
+
+pub fn postgres_url() -> Option<String> {
+    let url = std::env::var("TEST_POSTGRES_URL").ok();
+    if url.is_none() {
+        eprintln!("TEST_POSTGRES_URL is not set, the Postgres queries are not checked");
+    }
+    url
+}
@@ -0,0 +1,502 @@
+// Runs the queries used outside of the repositories against both databases
+mod common;
+
+use std::future::Future;
+
+use common::{postgres_url, sqlite_pool, TestDatabase};
+use udv_qna_bot::db::queries::attachments::NewAttachment;
+use udv_qna_bot::db::queries::broadcasts::{self, Audience, DeliveryStatus};
+use udv_qna_bot::db::queries::revisions::{self, Entity};
+use udv_qna_bot::db::queries::{snapshot, subscriptions, trash, users};
+use udv_qna_bot::db::{Pool, Repositories};
+
+const AUTHOR: &str = "test";
+
+// Runs the check against SQLite, and against Postgres when TEST_POSTGRES_URL is set
+async fn on_both<F, Fut>(check: F)
+where
+    F: Fn(Pool) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let (_dir, pool) = sqlite_pool().await;
+    check(pool).await;
+    if let Some(server_url) = postgres_url() {
+        let (database, pool) = TestDatabase::create(&server_url).await;
+        check(pool.clone()).await;
+        database.drop(pool).await;
+    }
+}
+
+// Runs the statement written the same way for both databases
+async fn execute(pool: &Pool, sql: &str) {
+    match pool {
+        Pool::Sqlite(pool) => {
+            sqlx::query(sql).execute(pool).await.unwrap();
+        }
+        Pool::Postgres(pool) => {
+            sqlx::query(sql).execute(pool).await.unwrap();
+        }
+    }
+}
+
+fn new_attachment(name: &str, sha256: &str) -> NewAttachment {
+    NewAttachment {
+        name: name.to_string(),
+        sha256: sha256.to_string(),
+        mime_type: "text/plain".to_string(),
+        size: 5,
+    }
+}
+
+// Users 1 and 2 are active, user 3 is not
+async fn create_users(repos: &Repositories) {
+    for (id, active) in [(1, true), (2, true), (3, false)] {
+        repos
+            .users
+            .create_user(id, None, "User", None, false, active)
+            .await
+            .unwrap();
+    }
+}
+
+async fn category_of(repos: &Repositories, question: i64) -> Option<i64> {
+    let question = repos.questions.get_question_by_id(question).await.unwrap();
+    question.category.map(|c| c.id)
+}
+
+async fn check_category_restore(pool: Pool) {
+    let repos = Repositories::sql(pool.clone());
+    let (categories, questions) = (&repos.categories, &repos.questions);
+    let parent = categories
+        .create_category("Parent", false, 0, None, AUTHOR)
+        .await
+        .unwrap();
+    let child = categories
+        .create_category("Child", false, 0, Some(parent), AUTHOR)
+        .await
+        .unwrap();
+    let other = categories
+        .create_category("Other", false, 1, None, AUTHOR)
+        .await
+        .unwrap();
+    let kept = questions
+        .create_question("Kept", "Answer", Some(parent), false, 0, AUTHOR)
+        .await
+        .unwrap();
+    let moved = questions
+        .create_question("Moved", "Answer", Some(parent), false, 1, AUTHOR)
+        .await
+        .unwrap();
+
+    categories.delete_category(parent, AUTHOR).await.unwrap();
+    assert_eq!(categories.get_category(child).await.unwrap().parent, None);
+    assert_eq!(category_of(&repos, kept).await, None);
+    // the question moved while the category is in the trash stays where it is
+    questions
+        .update_question(
+            moved,
+            Some(other),
+            "Moved".to_string(),
+            "Answer".to_string(),
+            false,
+            AUTHOR,
+        )
+        .await
+        .unwrap();
+
+    trash::restore_category(&pool, parent, AUTHOR)
+        .await
+        .unwrap();
+    assert_eq!(
+        categories.get_category(child).await.unwrap().parent,
+        Some(parent)
+    );
+    assert_eq!(category_of(&repos, kept).await, Some(parent));
+    assert_eq!(category_of(&repos, moved).await, Some(other));
+
+    // the detached items are forgotten after the restore
+    categories.delete_category(child, AUTHOR).await.unwrap();
+    trash::restore_category(&pool, child, AUTHOR).await.unwrap();
+    assert_eq!(category_of(&repos, kept).await, Some(parent));
+}
+
+#[tokio::test]
+async fn restores_category_with_content() {
+    on_both(check_category_restore).await;
+}
+
+async fn check_digest(pool: Pool) {
+    let repos = Repositories::sql(pool.clone());
+    create_users(&repos).await;
+    let parent = repos
+        .categories
+        .create_category("Parent", false, 0, None, AUTHOR)
+        .await
+        .unwrap();
+    let child = repos
+        .categories
+        .create_category("Child", false, 0, Some(parent), AUTHOR)
+        .await
+        .unwrap();
+    for user_id in [1, 2, 3] {
+        subscriptions::subscribe(&pool, user_id, parent)
+            .await
+            .unwrap();
+    }
+    // user 2 can't see the child category
+    let group = repos.groups.create_group("Staff").await.unwrap();
+    let mut staff = repos.groups.get_group(group).await.unwrap();
+    staff.users = vec![1, 3];
+    staff.categories = vec![child];
+    repos.groups.update_group(&staff).await.unwrap();
+
+    let question = repos
+        .questions
+        .create_question("Question", "Answer", Some(child), false, 0, AUTHOR)
+        .await
+        .unwrap();
+    let pending = subscriptions::get_pending_changes(&pool)
+        .await
+        .unwrap()
+        .unwrap();
+    let digest = subscriptions::take_digest(&pool, pending.now, true)
+        .await
+        .unwrap();
+    let items: Vec<(i64, i64, bool)> = digest
+        .iter()
+        .map(|item| (item.user_id, item.question_id, item.created))
+        .collect();
+    assert_eq!(items, [(1, question, true)]);
+    assert_eq!(digest[0].category_id, child);
+
+    // the changes are taken only once
+    assert!(subscriptions::get_pending_changes(&pool)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(subscriptions::take_digest(&pool, pending.now, false)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn takes_digest() {
+    on_both(check_digest).await;
+}
+
+async fn check_purge(pool: Pool) {
+    let repos = Repositories::sql(pool.clone());
+    let category = repos
+        .categories
+        .create_category("Category", false, 0, None, AUTHOR)
+        .await
+        .unwrap();
+    let question = repos
+        .questions
+        .create_question("Question", "Answer", Some(category), false, 0, AUTHOR)
+        .await
+        .unwrap();
+    repos
+        .questions
+        .add_attachment(question, new_attachment("a.txt", "aaaa"))
+        .await
+        .unwrap();
+    repos
+        .questions
+        .delete_question(question, AUTHOR)
+        .await
+        .unwrap();
+    repos
+        .categories
+        .delete_category(category, AUTHOR)
+        .await
+        .unwrap();
+
+    let purged = trash::purge_expired(&pool, 30).await.unwrap();
+    assert!(purged.questions.is_empty() && purged.categories.is_empty());
+    assert_eq!(trash::get_deleted_questions(&pool).await.unwrap().len(), 1);
+
+    execute(
+        &pool,
+        "UPDATE questions SET deleted_at = '2000-01-01 00:00:00' WHERE deleted_at IS NOT NULL",
+    )
+    .await;
+    execute(
+        &pool,
+        "UPDATE categories SET deleted_at = '2000-01-01 00:00:00' WHERE deleted_at IS NOT NULL",
+    )
+    .await;
+    let purged = trash::purge_expired(&pool, 30).await.unwrap();
+    assert_eq!(purged.questions, [question]);
+    assert_eq!(purged.categories, [category]);
+    assert_eq!(purged.blobs, ["aaaa"]);
+    assert!(trash::get_deleted_questions(&pool)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(trash::get_deleted_categories(&pool)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn purges_expired_items() {
+    on_both(check_purge).await;
+}
+
+async fn check_snapshot_restore(pool: Pool) {
+    let repos = Repositories::sql(pool.clone());
+    create_users(&repos).await;
+    let category = repos
+        .categories
+        .create_category("Category", false, 0, None, AUTHOR)
+        .await
+        .unwrap();
+    let question = repos
+        .questions
+        .create_question("Question", "Answer", Some(category), false, 0, AUTHOR)
+        .await
+        .unwrap();
+    repos
+        .questions
+        .add_attachment(question, new_attachment("a.txt", "aaaa"))
+        .await
+        .unwrap();
+    let group = repos.groups.create_group("Staff").await.unwrap();
+    let mut staff = repos.groups.get_group(group).await.unwrap();
+    staff.users = vec![1];
+    staff.categories = vec![category];
+    repos.groups.update_group(&staff).await.unwrap();
+    let taken = snapshot::get_snapshot(&pool).await.unwrap();
+
+    repos
+        .questions
+        .update_question(
+            question,
+            Some(category),
+            "Changed".to_string(),
+            "Changed".to_string(),
+            false,
+            AUTHOR,
+        )
+        .await
+        .unwrap();
+    repos
+        .categories
+        .create_category("Added", false, 1, None, AUTHOR)
+        .await
+        .unwrap();
+    repos.groups.delete_group(group).await.unwrap();
+    repos.groups.create_group("Added").await.unwrap();
+
+    let unused = snapshot::restore_snapshot(
+        &pool,
+        taken,
+        vec![(question, new_attachment("b.txt", "bbbb"))],
+        AUTHOR,
+    )
+    .await
+    .unwrap();
+    assert_eq!(unused, ["aaaa"]);
+    let restored = repos.questions.get_question_by_id(question).await.unwrap();
+    assert_eq!(restored.question, "Question");
+    assert_eq!(restored.attachments, ["b.txt"]);
+    let names: Vec<String> = repos
+        .categories
+        .get_all_categories()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|c| c.name)
+        .collect();
+    assert_eq!(names, ["Category"]);
+    let groups = repos.groups.get_groups().await.unwrap();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(
+        (groups[0].id, groups[0].users.as_slice()),
+        (group, [1].as_slice())
+    );
+    assert_eq!(groups[0].categories, [category]);
+
+    // the ids continue after the imported ones
+    let added = repos
+        .categories
+        .create_category("Added", false, 1, None, AUTHOR)
+        .await
+        .unwrap();
+    assert!(added > category);
+    assert!(repos.groups.create_group("Added").await.unwrap() > group);
+}
+
+#[tokio::test]
+async fn restores_snapshot() {
+    on_both(check_snapshot_restore).await;
+}
+
+async fn check_revision_restore(pool: Pool) {
+    let repos = Repositories::sql(pool.clone());
+    let category = repos
+        .categories
+        .create_category("Category", false, 0, None, AUTHOR)
+        .await
+        .unwrap();
+    let question = repos
+        .questions
+        .create_question("Question", "First", Some(category), false, 0, AUTHOR)
+        .await
+        .unwrap();
+    repos
+        .questions
+        .update_question(
+            question,
+            Some(category),
+            "Question".to_string(),
+            "Second".to_string(),
+            false,
+            AUTHOR,
+        )
+        .await
+        .unwrap();
+    let history = revisions::get_revisions(&pool, Entity::Question, question)
+        .await
+        .unwrap();
+    assert_eq!(history.len(), 2);
+    let (updated, created) = (history[0].id, history[1].id);
+
+    revisions::restore_revision(&pool, created, AUTHOR)
+        .await
+        .unwrap();
+    let restored = repos.questions.get_question_by_id(question).await.unwrap();
+    assert_eq!(restored.answer, "First");
+    let history = revisions::get_revisions(&pool, Entity::Question, question)
+        .await
+        .unwrap();
+    assert_eq!(history[0].restored_from, Some(created));
+
+    // the question is brought back from the trash, without the deleted category
+    repos
+        .questions
+        .delete_question(question, AUTHOR)
+        .await
+        .unwrap();
+    repos
+        .categories
+        .delete_category(category, AUTHOR)
+        .await
+        .unwrap();
+    revisions::restore_revision(&pool, updated, AUTHOR)
+        .await
+        .unwrap();
+    let restored = repos.questions.get_question_by_id(question).await.unwrap();
+    assert_eq!(restored.answer, "Second");
+    assert!(restored.category.is_none());
+    assert!(trash::get_deleted_questions(&pool)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // the purged category is created again
+    let history = revisions::get_revisions(&pool, Entity::Category, category)
+        .await
+        .unwrap();
+    let created = history.last().unwrap().id;
+    trash::purge_category(&pool, category).await.unwrap();
+    revisions::restore_revision(&pool, created, AUTHOR)
+        .await
+        .unwrap();
+    assert_eq!(
+        repos.categories.get_category(category).await.unwrap().name,
+        "Category"
+    );
+}
+
+#[tokio::test]
+async fn restores_revision() {
+    on_both(check_revision_restore).await;
+}
+
+async fn check_broadcast_deliveries(pool: Pool) {
+    let repos = Repositories::sql(pool.clone());
+    create_users(&repos).await;
+    repos
+        .users
+        .create_user(4, None, "User", None, false, true)
+        .await
+        .unwrap();
+    users::set_user_blocked(&pool, 4, true).await.unwrap();
+    let recipients = broadcasts::get_recipients(&pool, &Audience::AllUsers)
+        .await
+        .unwrap();
+    assert_eq!(recipients, [1, 2]);
+    let group = repos.groups.create_group("Staff").await.unwrap();
+    let mut staff = repos.groups.get_group(group).await.unwrap();
+    staff.users = vec![2, 3, 4];
+    repos.groups.update_group(&staff).await.unwrap();
+    assert_eq!(
+        broadcasts::get_recipients(&pool, &Audience::Group(group))
+            .await
+            .unwrap(),
+        [2]
+    );
+
+    let id = broadcasts::create_broadcast(
+        &pool,
+        "Hello",
+        &[new_attachment("a.txt", "aaaa")],
+        "All users",
+        AUTHOR,
+        &recipients,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        broadcasts::get_unfinished_broadcasts(&pool).await.unwrap(),
+        [id]
+    );
+    assert_eq!(
+        broadcasts::get_pending_deliveries(&pool, id).await.unwrap(),
+        [1, 2]
+    );
+
+    broadcasts::set_delivery_status(&pool, id, 1, DeliveryStatus::Sent, None)
+        .await
+        .unwrap();
+    assert_eq!(
+        broadcasts::get_pending_deliveries(&pool, id).await.unwrap(),
+        [2]
+    );
+    broadcasts::set_delivery_status(&pool, id, 2, DeliveryStatus::Blocked, Some("Blocked"))
+        .await
+        .unwrap();
+    assert!(broadcasts::get_pending_deliveries(&pool, id)
+        .await
+        .unwrap()
+        .is_empty());
+
+    broadcasts::finish_broadcast(&pool, id).await.unwrap();
+    broadcasts::finish_broadcast(&pool, id).await.unwrap();
+    assert!(broadcasts::get_unfinished_broadcasts(&pool)
+        .await
+        .unwrap()
+        .is_empty());
+    let stats = broadcasts::get_broadcast(&pool, id).await.unwrap();
+    assert_eq!(
+        (stats.pending, stats.sent, stats.blocked, stats.failed),
+        (0, 1, 1, 0)
+    );
+    assert!(stats.broadcast.finished_at.is_some());
+    let names: Vec<String> = stats
+        .broadcast
+        .attachments
+        .iter()
+        .map(|a| a.name.clone())
+        .collect();
+    assert_eq!(names, ["a.txt"]);
+}
+
+#[tokio::test]
+async fn tracks_broadcast_deliveries() {
+    on_both(check_broadcast_deliveries).await;
+}
@@ -0,0 +1,434 @@
+// Runs the repositories against both databases and the in-memory implementation
+mod common;
+
+use std::time::Duration;
+
+use common::{postgres_url, sqlite_pool, TestDatabase};
+use udv_qna_bot::db::queries::attachments::NewAttachment;
+use udv_qna_bot::db::{Pool, Reorder, Repositories};
+
+const AUTHOR: &str = "test";
+
+fn new_attachment(name: &str, sha256: &str) -> NewAttachment {
+    NewAttachment {
+        name: name.to_string(),
+        sha256: sha256.to_string(),
+        mime_type: "text/plain".to_string(),
+        size: 5,
+    }
+}
+
+async fn check_users(repos: &Repositories) {
+    repos
+        .users
+        .create_user(1, Some("admin"), "Ann", Some("Smith"), true, true)
+        .await
+        .unwrap();
+    repos
+        .users
+        .create_user(2, None, "Bob", None, false, false)
+        .await
+        .unwrap();
+
+    let mut bob = repos.users.get_user(2).await.unwrap();
+    assert_eq!((bob.first_name.as_str(), bob.username), ("Bob", None));
+    bob.active = true;
+    bob.username = Some("bob".to_string());
+    repos.users.update_user(bob).await.unwrap();
+    let bob = repos.users.get_user(2).await.unwrap();
+    assert!(bob.active);
+    assert_eq!(bob.username.as_deref(), Some("bob"));
+
+    let ids = |users: Vec<udv_qna_bot::db::User>| users.iter().map(|u| u.id).collect::<Vec<_>>();
+    assert_eq!(ids(repos.users.get_users().await.unwrap()), [1, 2]);
+    assert_eq!(ids(repos.users.get_admins().await.unwrap()), [1]);
+
+    repos
+        .users
+        .create_user(3, None, "Eve", None, false, true)
+        .await
+        .unwrap();
+    repos.users.delete_user(3).await.unwrap();
+    assert!(matches!(
+        repos.users.get_user(3).await,
+        Err(sqlx::Error::RowNotFound)
+    ));
+}
+
+// Returns the ids of the parent and the child categories
+async fn check_categories(repos: &Repositories) -> (i64, i64) {
+    let categories = &repos.categories;
+    let parent = categories
+        .create_category("Parent", false, 0, None, AUTHOR)
+        .await
+        .unwrap();
+    let child = categories
+        .create_category("Child", false, 0, Some(parent), AUTHOR)
+        .await
+        .unwrap();
+    let hidden = categories
+        .create_category("Hidden", true, 1, None, AUTHOR)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        categories.get_category(child).await.unwrap().parent,
+        Some(parent)
+    );
+    assert_eq!(categories.get_all_categories().await.unwrap().len(), 3);
+    let path: Vec<i64> = categories
+        .get_category_path(child)
+        .await
+        .unwrap()
+        .iter()
+        .map(|c| c.id)
+        .collect();
+    assert_eq!(path, [parent, child]);
+    let subtree = categories.get_subtree_ids(parent).await.unwrap();
+    assert_eq!(subtree, [parent, child].into_iter().collect());
+
+    let public: Vec<i64> = categories
+        .get_public_categories(2)
+        .await
+        .unwrap()
+        .iter()
+        .map(|c| c.id)
+        .collect();
+    assert!(public.contains(&parent) && public.contains(&child));
+    assert!(!public.contains(&hidden));
+    let top: Vec<i64> = categories
+        .get_public_subcategories(2, None)
+        .await
+        .unwrap()
+        .iter()
+        .map(|c| c.id)
+        .collect();
+    assert_eq!(top, [parent]);
+    assert!(categories.get_public_category(2, hidden).await.is_err());
+
+    categories
+        .update_category(hidden, "Renamed".to_string(), false, None, AUTHOR)
+        .await
+        .unwrap();
+    let renamed = categories.get_category(hidden).await.unwrap();
+    assert_eq!((renamed.name.as_str(), renamed.hidden), ("Renamed", false));
+    categories
+        .reorder_categories(vec![Reorder {
+            id: hidden,
+            ordering: 5,
+        }])
+        .await
+        .unwrap();
+    assert_eq!(categories.get_category(hidden).await.unwrap().ordering, 5);
+
+    categories.delete_category(hidden, AUTHOR).await.unwrap();
+    assert!(categories
+        .get_all_categories()
+        .await
+        .unwrap()
+        .iter()
+        .all(|c| c.id != hidden));
+    (parent, child)
+}
+
+async fn public_ids(repos: &Repositories, user_id: i64) -> Vec<i64> {
+    repos
+        .categories
+        .get_public_categories(user_id)
+        .await
+        .unwrap()
+        .iter()
+        .map(|c| c.id)
+        .collect()
+}
+
+// Restricting the parent hides the whole subtree, restrictions on the path add up
+async fn check_groups(repos: &Repositories, parent: i64, child: i64) {
+    let groups = &repos.groups;
+    let staff = groups.create_group("Staff").await.unwrap();
+    assert!(groups.create_group("Staff").await.is_err());
+    let mut group = groups.get_group(staff).await.unwrap();
+    group.users = vec![1, 1];
+    group.categories = vec![parent];
+    groups.update_group(&group).await.unwrap();
+    assert_eq!(groups.get_group(staff).await.unwrap().users, [1]);
+
+    let public = public_ids(repos, 2).await;
+    assert!(!public.contains(&parent) && !public.contains(&child));
+    assert!(repos
+        .categories
+        .get_public_category(2, child)
+        .await
+        .is_err());
+    let public = public_ids(repos, 1).await;
+    assert!(public.contains(&parent) && public.contains(&child));
+
+    let managers = groups.create_group("Managers").await.unwrap();
+    let mut group = groups.get_group(managers).await.unwrap();
+    group.users = vec![2];
+    group.categories = vec![child];
+    groups.update_group(&group).await.unwrap();
+    let public = public_ids(repos, 1).await;
+    assert!(public.contains(&parent) && !public.contains(&child));
+
+    let mut group = groups.get_group(staff).await.unwrap();
+    group.users = vec![1, 2];
+    groups.update_group(&group).await.unwrap();
+    let public = public_ids(repos, 2).await;
+    assert!(public.contains(&parent) && public.contains(&child));
+
+    group.users = vec![1, 404];
+    assert!(groups.update_group(&group).await.is_err());
+    let names: Vec<String> = groups
+        .get_groups()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|g| g.name)
+        .collect();
+    assert_eq!(names, ["Managers", "Staff"]);
+
+    groups.delete_group(staff).await.unwrap();
+    groups.delete_group(managers).await.unwrap();
+    assert!(matches!(
+        groups.delete_group(staff).await,
+        Err(sqlx::Error::RowNotFound)
+    ));
+    let public = public_ids(repos, 1).await;
+    assert!(public.contains(&parent) && public.contains(&child));
+}
+
+async fn check_questions(repos: &Repositories, category: i64) {
+    let questions = &repos.questions;
+    let first = questions
+        .create_question(
+            "How to reset the password?",
+            "Ask **IT**",
+            Some(category),
+            false,
+            0,
+            AUTHOR,
+        )
+        .await
+        .unwrap();
+    let second = questions
+        .create_question(
+            "Where is the office?",
+            "Upstairs",
+            Some(category),
+            false,
+            1,
+            AUTHOR,
+        )
+        .await
+        .unwrap();
+    let hidden = questions
+        .create_question("Secret", "Hidden answer", Some(category), true, 2, AUTHOR)
+        .await
+        .unwrap();
+
+    let question = questions.get_question_by_id(first).await.unwrap();
+    assert_eq!(question.answer, "Ask **IT**");
+    assert_eq!(question.category.map(|c| c.id), Some(category));
+    assert_eq!(questions.get_all_questions().await.unwrap().len(), 3);
+    assert_eq!(
+        questions
+            .get_questions_by_category_id(category)
+            .await
+            .unwrap()
+            .len(),
+        3
+    );
+    let public: Vec<i64> = questions
+        .get_public_questions_for_public_category(2, category)
+        .await
+        .unwrap()
+        .iter()
+        .map(|q| q.id)
+        .collect();
+    assert_eq!(public, [first, second]);
+    assert!(questions.get_public_question(2, hidden).await.is_err());
+    let found: Vec<i64> = questions
+        .search_public_questions(2, "en", "password", 10)
+        .await
+        .unwrap()
+        .iter()
+        .map(|q| q.id)
+        .collect();
+    assert_eq!(found, [first]);
+
+    questions
+        .update_question(
+            second,
+            Some(category),
+            "Where is the new office?".to_string(),
+            "Downstairs".to_string(),
+            false,
+            AUTHOR,
+        )
+        .await
+        .unwrap();
+    let updated = questions.get_question_by_id(second).await.unwrap();
+    assert_eq!(updated.question, "Where is the new office?");
+    questions
+        .reorder_questions(vec![
+            Reorder {
+                id: first,
+                ordering: 1,
+            },
+            Reorder {
+                id: second,
+                ordering: 0,
+            },
+        ])
+        .await
+        .unwrap();
+    let ordered: Vec<i64> = questions
+        .get_public_questions_for_public_category(2, category)
+        .await
+        .unwrap()
+        .iter()
+        .map(|q| q.id)
+        .collect();
+    assert_eq!(ordered, [second, first]);
+
+    questions.delete_question(hidden, AUTHOR).await.unwrap();
+    assert_eq!(questions.get_all_questions().await.unwrap().len(), 2);
+
+    check_attachments(repos, first, second).await;
+}
+
+async fn check_attachments(repos: &Repositories, first: i64, second: i64) {
+    let questions = &repos.questions;
+    let added = questions
+        .add_attachment(first, new_attachment("a.txt", "aaaa"))
+        .await
+        .unwrap();
+    questions
+        .add_attachment(first, new_attachment("b.txt", "bbbb"))
+        .await
+        .unwrap();
+    questions
+        .add_attachment(second, new_attachment("a.txt", "aaaa"))
+        .await
+        .unwrap();
+    assert!(questions
+        .add_attachment(first, new_attachment("a.txt", "cccc"))
+        .await
+        .is_err());
+
+    let names: Vec<String> = questions
+        .get_attachments(first)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|a| a.name)
+        .collect();
+    assert_eq!(names, ["a.txt", "b.txt"]);
+    assert_eq!(
+        questions
+            .get_question_by_id(first)
+            .await
+            .unwrap()
+            .attachments,
+        ["a.txt", "b.txt"]
+    );
+
+    questions
+        .set_telegram_file_id(&added, Some("file-id"))
+        .await
+        .unwrap();
+    let attachment = questions.get_attachment(first, "a.txt").await.unwrap();
+    assert_eq!(attachment.sha256, "aaaa");
+    assert_eq!(attachment.telegram_file_id.as_deref(), Some("file-id"));
+
+    // the blob is removed with the last attachment referencing it
+    let unused = questions.delete_attachment(first, "a.txt").await.unwrap();
+    assert_eq!(unused, None);
+    let unused = questions.delete_attachment(second, "a.txt").await.unwrap();
+    assert_eq!(unused.as_deref(), Some("aaaa"));
+    assert!(questions.delete_attachment(second, "a.txt").await.is_err());
+    let unused = questions
+        .unused_blobs(vec!["aaaa".to_string(), "bbbb".to_string()])
+        .await
+        .unwrap();
+    assert_eq!(unused, ["aaaa"]);
+}
+
+async fn check_repositories(repos: Repositories) {
+    check_users(&repos).await;
+    let (parent, child) = check_categories(&repos).await;
+    check_groups(&repos, parent, child).await;
+    check_questions(&repos, child).await;
+}
+
+#[tokio::test]
+async fn sqlite_queries() {
+    let (_dir, pool) = sqlite_pool().await;
+    check_repositories(Repositories::sql(pool)).await;
+}
+
+// the in-memory implementation behaves like the databases
+#[tokio::test]
+async fn memory_repositories() {
+    check_repositories(Repositories::memory()).await;
+}
+
+#[tokio::test]
+async fn postgres_queries() {
+    let Some(server_url) = postgres_url() else {
+        return;
+    };
+    let (database, pool) = TestDatabase::create(&server_url).await;
+    check_repositories(Repositories::sql(pool.clone())).await;
+    database.drop(pool).await;
+}
+
+#[tokio::test]
+async fn postgres_lock() {
+    let Some(server_url) = postgres_url() else {
+        return;
+    };
+    let (database, pool) = TestDatabase::create(&server_url).await;
+    let lock = pool.lock(1).await.unwrap();
+    let other = pool.clone();
+    let waiting = tokio::spawn(async move { other.lock(1).await.map(|_| ()) });
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(!waiting.is_finished());
+    drop(lock);
+    tokio::time::timeout(Duration::from_secs(5), waiting)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    database.drop(pool).await;
+}
+
+#[tokio::test]
+async fn postgres_blob_lock() {
+    let Some(server_url) = postgres_url() else {
+        return;
+    };
+    let (database, pool) = TestDatabase::create(&server_url).await;
+    // the other instance has its own local locks, only the advisory lock is shared
+    let lock = pool.lock_blob("aaaa").await.unwrap();
+    let Pool::Postgres(pg) = &pool else {
+        unreachable!()
+    };
+    let mut other = pg.begin().await.unwrap();
+    let waiting = tokio::spawn(async move {
+        sqlx::query("SELECT pg_advisory_xact_lock(1, hashtext('aaaa'))")
+            .execute(&mut *other)
+            .await
+            .map(|_| ())
+    });
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(!waiting.is_finished());
+    drop(lock);
+    tokio::time::timeout(Duration::from_secs(5), waiting)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    database.drop(pool).await;
+}
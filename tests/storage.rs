@@ -0,0 +1,79 @@
+// Runs the blob storages through the same checks. S3 is checked when TEST_S3_BUCKET is set
+// to the bucket the test can write to, the credentials and the endpoint are read from AWS_*
+use std::io::{ErrorKind, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures_util::TryStreamExt;
+use tempfile::NamedTempFile;
+use udv_qna_bot::storage::{BlobStorage, LocalStorage, S3Storage};
+
+// larger than the part of the multipart upload
+const LARGE_SIZE: usize = 12 * 1024 * 1024;
+
+fn local_file(content: &[u8]) -> NamedTempFile {
+    let mut file = NamedTempFile::new().unwrap();
+    file.write_all(content).unwrap();
+    file
+}
+
+async fn read(blobs: &dyn BlobStorage, sha256: &str) -> Vec<u8> {
+    let chunks: Vec<_> = blobs
+        .get(sha256)
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    chunks.concat()
+}
+
+// names are unique, so the runs sharing the bucket don't see each other's blobs
+async fn check_storage(blobs: &dyn BlobStorage) {
+    let suffix = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let small = format!("small-{}-{suffix}", std::process::id());
+    let large = format!("large-{}-{suffix}", std::process::id());
+
+    assert!(!blobs.exists(&small).await.unwrap());
+    let missing = blobs.get(&small).await.err().unwrap();
+    assert_eq!(missing.kind(), ErrorKind::NotFound);
+    // missing blob is not an error
+    blobs.delete(&small).await.unwrap();
+
+    let file = local_file(b"hello");
+    blobs.put(&small, file.path()).await.unwrap();
+    // the local file is kept and the same content can be put again
+    assert!(file.path().is_file());
+    blobs.put(&small, file.path()).await.unwrap();
+    assert!(blobs.exists(&small).await.unwrap());
+    assert_eq!(read(blobs, &small).await, b"hello");
+
+    let content: Vec<u8> = (0..LARGE_SIZE).map(|i| (i % 251) as u8).collect();
+    blobs
+        .put(&large, local_file(&content).path())
+        .await
+        .unwrap();
+    assert_eq!(read(blobs, &large).await, content);
+
+    for sha256 in [&small, &large] {
+        blobs.delete(sha256).await.unwrap();
+        assert!(!blobs.exists(sha256).await.unwrap());
+    }
+}
+
+#[tokio::test]
+async fn local_storage() {
+    let dir = tempfile::tempdir().unwrap();
+    check_storage(&LocalStorage::new(dir.path())).await;
+}
+
+#[tokio::test]
+async fn s3_storage() {
+    let Ok(bucket) = std::env::var("TEST_S3_BUCKET") else {
+        eprintln!("TEST_S3_BUCKET is not set, the S3 storage is not checked");
+        return;
+    };
+    check_storage(&S3Storage::from_env(&bucket).unwrap()).await;
+}